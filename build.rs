@@ -1,24 +1,71 @@
-extern crate vergen;
-use std::process::Command;
-use vergen::{generate_cargo_keys, ConstantsFlags};
-
-fn main() {
-    let output = Command::new("git")
-        .arg("status")
-        .arg("--short")
-        .output()
-        .expect("Could not determine if workspace is dirty");
-
-    if !output.status.success() {
-        panic!(
-            "Command 'git status --short' executed with failing error code, {}",
-            output.status
-        );
-    }
-    println!(
-        "cargo:rustc-env=BUILD_GIT_WORKSPACE_IS_DIRTY={}",
-        output.stdout.len() > 0 || output.stderr.len() > 0
-    );
-    // Generate the 'cargo:' key output
-    generate_cargo_keys(ConstantsFlags::all()).expect("Unable to generate the cargo keys!");
-}
+extern crate vergen;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use vergen::{generate_cargo_keys, ConstantsFlags};
+
+fn main() {
+    let output = Command::new("git")
+        .arg("status")
+        .arg("--short")
+        .output()
+        .expect("Could not determine if workspace is dirty");
+
+    if !output.status.success() {
+        panic!(
+            "Command 'git status --short' executed with failing error code, {}",
+            output.status
+        );
+    }
+    println!(
+        "cargo:rustc-env=BUILD_GIT_WORKSPACE_IS_DIRTY={}",
+        !output.stdout.is_empty() || !output.stderr.is_empty()
+    );
+    // Generate the 'cargo:' key output
+    generate_cargo_keys(ConstantsFlags::all()).expect("Unable to generate the cargo keys!");
+    generate_assets();
+}
+
+/// Lists the files in `static/` with their contents and an entity tag, so
+/// they are compiled into the executable.
+fn generate_assets() {
+    let static_path = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("static");
+    let mut files = Vec::new();
+    collect_files(&static_path, &mut files);
+    files.sort();
+    let mut code = String::from("pub static FILES: &[(&str, &[u8], &str)] = &[\n");
+    for file in files {
+        let name = file
+            .strip_prefix(&static_path)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_str().unwrap())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut hasher = DefaultHasher::new();
+        std::fs::read(&file)
+            .expect("Could not read a static file")
+            .hash(&mut hasher);
+        code.push_str(&format!(
+            "    ({:?}, include_bytes!({:?}), \"\\\"{:016x}\\\"\"),\n",
+            name,
+            file,
+            hasher.finish()
+        ));
+    }
+    code.push_str("];\n");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("assets.rs"), code).expect("Could not write the asset list");
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(directory).expect("Could not read the static directory") {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
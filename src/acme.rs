@@ -0,0 +1,471 @@
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use crate::tls::Tls;
+use actix::prelude::*;
+use actix_web::client::Client;
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509Builder, X509NameBuilder, X509Req, X509};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+/// Certificates are renewed this many days before they expire.
+const RENEW_DAYS: i32 = 30;
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+
+/// Obtains and renews the certificate of `STS_RS_ACME_DOMAIN` from an ACME
+/// provider like Let's Encrypt, answering its HTTP-01 challenges. The
+/// account key, key and certificate are kept in the `acme` directory of the
+/// configuration.
+pub struct Acme {
+    directory_url: String,
+    domain: String,
+    email: Option<String>,
+    dir: PathBuf,
+    /// Key authorizations of the pending challenges by their token.
+    challenges: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+}
+
+impl Acme {
+    pub fn from_env(config_dir: &Path) -> Option<Acme> {
+        let domain = std::env::var("STS_RS_ACME_DOMAIN").ok()?;
+        Some(Acme {
+            directory_url: env_or_default("STS_RS_ACME_DIRECTORY", LETS_ENCRYPT),
+            domain,
+            email: std::env::var("STS_RS_ACME_EMAIL").ok(),
+            dir: config_dir.join("acme"),
+            challenges: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn key_file(&self) -> PathBuf {
+        self.dir.join(format!("{}.key.pem", self.domain))
+    }
+
+    pub fn cert_file(&self) -> PathBuf {
+        self.dir.join(format!("{}.cert.pem", self.domain))
+    }
+
+    /// The response to the HTTP-01 challenge with the token.
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.challenges.lock().unpoisoned().get(token).cloned()
+    }
+
+    /// Until the first certificate is obtained the listener uses a self
+    /// signed one.
+    pub fn ensure_certificate(&self) -> std::io::Result<()> {
+        if self.cert_file().exists() && self.key_file().exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let key = new_key();
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_nid(Nid::COMMONNAME, &self.domain)?;
+        let name = name.build();
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+        builder.set_not_after(&*Asn1Time::days_from_now(1)?)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+        write_private(&self.key_file(), &key.private_key_to_pem_pkcs8()?)?;
+        std::fs::write(self.cert_file(), builder.build().to_pem()?)
+    }
+
+    /// Whether the certificate expires within `RENEW_DAYS` or was not
+    /// issued for the domain yet.
+    pub fn needs_renewal(&self) -> bool {
+        let certificate = match std::fs::read(self.cert_file())
+            .ok()
+            .and_then(|pem| X509::from_pem(&pem).ok())
+        {
+            Some(certificate) => certificate,
+            None => return true,
+        };
+        let self_signed =
+            certificate.issuer_name().to_der().ok() == certificate.subject_name().to_der().ok();
+        let days_left = Asn1Time::days_from_now(0)
+            .and_then(|now| now.diff(certificate.not_after()))
+            .map_or(0, |diff| diff.days);
+        self_signed || days_left < RENEW_DAYS
+    }
+
+    /// Orders a certificate and stores it with its key.
+    pub async fn obtain(&self) -> Result<(), String> {
+        let mut session = Session::start(self).await?;
+        let new_order = session.directory.new_order.clone();
+        let (order_url, mut order) = session
+            .post(
+                &new_order,
+                Some(json!({"identifiers": [{"type": "dns", "value": self.domain}]})),
+            )
+            .await
+            .and_then(|(location, body)| Ok((location, parse::<Order>(&body)?)))?;
+        let order_url = order_url.ok_or("the order has no location")?;
+        for authorization_url in &order.authorizations {
+            self.authorize(&mut session, authorization_url).await?;
+        }
+        let key = new_key();
+        let csr = self.signing_request(&key).map_err(|err| err.to_string())?;
+        session
+            .post(&order.finalize, Some(json!({ "csr": base64url(&csr) })))
+            .await?;
+        for _ in 0..POLL_ATTEMPTS {
+            order = parse(&session.post(&order_url, None).await?.1)?;
+            if order.status != "processing" && order.status != "ready" {
+                break;
+            }
+            actix_rt::time::delay_for(POLL_INTERVAL).await;
+        }
+        let certificate_url = match (order.status.as_str(), order.certificate) {
+            ("valid", Some(certificate_url)) => certificate_url,
+            (status, _) => return Err(format!("the order is {}", status)),
+        };
+        let (_, chain) = session.post(&certificate_url, None).await?;
+        write_private(
+            &self.key_file(),
+            &key.private_key_to_pem_pkcs8()
+                .map_err(|err| err.to_string())?,
+        )
+        .and_then(|_| std::fs::write(self.cert_file(), chain))
+        .map_err(|err| format!("storing the certificate failed, {}", err))
+    }
+
+    async fn authorize(&self, session: &mut Session, url: &str) -> Result<(), String> {
+        let authorization: Authorization = parse(&session.post(url, None).await?.1)?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.kind == "http-01")
+            .ok_or("the provider offers no HTTP-01 challenge")?;
+        let key_authorization = format!("{}.{}", challenge.token, session.thumbprint());
+        self.challenges
+            .lock()
+            .unpoisoned()
+            .insert(challenge.token.clone(), key_authorization);
+        let result = async {
+            session.post(&challenge.url, Some(json!({}))).await?;
+            for _ in 0..POLL_ATTEMPTS {
+                actix_rt::time::delay_for(POLL_INTERVAL).await;
+                let authorization: Authorization = parse(&session.post(url, None).await?.1)?;
+                match authorization.status.as_str() {
+                    "valid" => return Ok(()),
+                    "pending" => {}
+                    status => return Err(format!("the challenge is {}", status)),
+                }
+            }
+            Err("the challenge was not checked in time".to_owned())
+        }
+        .await;
+        self.challenges.lock().unpoisoned().remove(&challenge.token);
+        result
+    }
+
+    fn signing_request(&self, key: &PKey<Private>) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_nid(Nid::COMMONNAME, &self.domain)?;
+        let mut builder = X509Req::builder()?;
+        builder.set_subject_name(&name.build())?;
+        builder.set_pubkey(key)?;
+        let mut extensions = openssl::stack::Stack::new()?;
+        extensions.push(
+            SubjectAlternativeName::new()
+                .dns(&self.domain)
+                .build(&builder.x509v3_context(None))?,
+        )?;
+        builder.add_extensions(&extensions)?;
+        builder.sign(key, MessageDigest::sha256())?;
+        builder.build().to_der()
+    }
+}
+
+/// Requests to the provider, signed with the account key.
+struct Session {
+    directory: Directory,
+    account_key: EcKey<Private>,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Session {
+    async fn start(acme: &Acme) -> Result<Session, String> {
+        let directory = Client::default()
+            .get(&acme.directory_url)
+            .send()
+            .await
+            .map_err(|err| format!("fetching {} failed, {}", acme.directory_url, err))?
+            .json::<Directory>()
+            .await
+            .map_err(|err| format!("reading {} failed, {}", acme.directory_url, err))?;
+        let mut session = Session {
+            directory,
+            account_key: account_key(&acme.dir)?,
+            account_url: None,
+            nonce: None,
+        };
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = &acme.email {
+            account["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let new_account = session.directory.new_account.clone();
+        let (account_url, _) = session.post(&new_account, Some(account)).await?;
+        session.account_url = Some(account_url.ok_or("the account has no location")?);
+        Ok(session)
+    }
+
+    /// The public account key, with its members in the order the
+    /// thumbprint requires.
+    fn jwk(&self) -> String {
+        let group = self.account_key.group();
+        let mut x = openssl::bn::BigNum::new().unwrap();
+        let mut y = openssl::bn::BigNum::new().unwrap();
+        self.account_key
+            .public_key()
+            .affine_coordinates_gfp(group, &mut x, &mut y, &mut BigNumContext::new().unwrap())
+            .unwrap();
+        format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            base64url(&x.to_vec_padded(32).unwrap()),
+            base64url(&y.to_vec_padded(32).unwrap())
+        )
+    }
+
+    /// The digest of the account key, part of the answer to challenges.
+    fn thumbprint(&self) -> String {
+        base64url(&openssl::sha::sha256(self.jwk().as_bytes()))
+    }
+
+    async fn nonce(&mut self) -> Result<String, String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = Client::default()
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|err| format!("fetching a nonce failed, {}", err))?;
+        replay_nonce(response.headers()).ok_or_else(|| "the provider sent no nonce".to_owned())
+    }
+
+    /// Posts the payload, or fetches the resource when there is none.
+    /// Returns the location and body of the response.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<Value>,
+    ) -> Result<(Option<String>, Vec<u8>), String> {
+        let mut retried = false;
+        loop {
+            let nonce = self.nonce().await?;
+            let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+            match &self.account_url {
+                Some(account_url) => protected["kid"] = json!(account_url),
+                None => protected["jwk"] = serde_json::from_str(&self.jwk()).unwrap(),
+            }
+            let protected = base64url(protected.to_string().as_bytes());
+            let payload = payload.as_ref().map_or_else(String::new, |payload| {
+                base64url(payload.to_string().as_bytes())
+            });
+            let signature = self.sign(format!("{}.{}", protected, payload).as_bytes())?;
+            let body =
+                json!({ "protected": protected, "payload": payload, "signature": signature });
+            let mut response = Client::default()
+                .post(url)
+                .header("Content-Type", "application/jose+json")
+                .send_body(body.to_string())
+                .await
+                .map_err(|err| format!("posting to {} failed, {}", url, err))?;
+            self.nonce = replay_nonce(response.headers());
+            let location = response
+                .headers()
+                .get("Location")
+                .and_then(|location| location.to_str().ok())
+                .map(str::to_owned);
+            let body = response
+                .body()
+                .limit(1 << 20)
+                .await
+                .map_err(|err| format!("reading {} failed, {}", url, err))?
+                .to_vec();
+            if response.status().is_success() {
+                return Ok((location, body));
+            }
+            let problem: Value = serde_json::from_slice(&body).unwrap_or_default();
+            // A nonce can go stale, the provider then hands out a new one.
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(format!(
+                "{} refused the request with {}, {}",
+                url,
+                response.status(),
+                problem["detail"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<String, String> {
+        let signature = EcdsaSig::sign(&openssl::sha::sha256(data), &self.account_key)
+            .map_err(|err| err.to_string())?;
+        let mut raw = signature.r().to_vec_padded(32).unwrap();
+        raw.extend(signature.s().to_vec_padded(32).unwrap());
+        Ok(base64url(&raw))
+    }
+}
+
+/// Renews the certificate when it is due and has the listener use it.
+pub struct Renewer {
+    pub acme: Arc<Acme>,
+    pub tls: Arc<Tls>,
+    pub renewing: bool,
+}
+
+struct Renew;
+
+impl Message for Renew {
+    type Result = ();
+}
+
+impl Actor for Renewer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.notify(Renew);
+        ctx.run_interval(CHECK_INTERVAL, |_, ctx| ctx.notify(Renew));
+    }
+}
+
+impl Handler<Renew> for Renewer {
+    type Result = ();
+
+    fn handle(&mut self, _: Renew, ctx: &mut Context<Self>) -> Self::Result {
+        if self.renewing || !self.acme.needs_renewal() {
+            return;
+        }
+        self.renewing = true;
+        info!("Obtaining a certificate for {}", self.acme.domain());
+        let acme = self.acme.clone();
+        ctx.spawn(
+            actix::fut::wrap_future::<_, Self>(async move { acme.obtain().await }).map(
+                |result, act, ctx| {
+                    act.renewing = false;
+                    match result.and_then(|_| act.tls.reload()) {
+                        Ok(()) => info!("Using the new certificate of {}", act.acme.domain()),
+                        Err(err) => {
+                            warn!(
+                                "Obtaining a certificate for {} failed, {}; retrying in {:?}.",
+                                act.acme.domain(),
+                                err,
+                                RETRY_INTERVAL
+                            );
+                            ctx.run_later(RETRY_INTERVAL, |_, ctx| ctx.notify(Renew));
+                        }
+                    }
+                },
+            ),
+        );
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|err| format!("the provider sent {}", err))
+}
+
+fn replay_nonce(headers: &actix_web::http::HeaderMap) -> Option<String> {
+    headers
+        .get("Replay-Nonce")
+        .and_then(|nonce| nonce.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// The account key is made on the first order and kept.
+fn account_key(dir: &Path) -> Result<EcKey<Private>, String> {
+    let file = dir.join("account.key.pem");
+    if let Ok(pem) = std::fs::read(&file) {
+        return EcKey::private_key_from_pem(&pem)
+            .map_err(|err| format!("could not read {:?}, {}", file, err));
+    }
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = EcKey::generate(&group).unwrap();
+    std::fs::create_dir_all(dir)
+        .and_then(|_| write_private(&file, &key.private_key_to_pem().unwrap()))
+        .map_err(|err| format!("could not store {:?}, {}", file, err))?;
+    Ok(key)
+}
+
+fn new_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+/// Keys are only readable by the user running the server.
+fn write_private(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(file)?.write_all(contents)
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    openssl::base64::encode_block(bytes)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
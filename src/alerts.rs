@@ -0,0 +1,279 @@
+//! Alert rules of series, evaluated in the background. An alert fires while
+//! the condition of its rule holds and is resolved once it no longer does.
+
+use crate::email::{AlertMail, Mailer};
+use crate::error::Unpoisoned;
+use crate::history::History;
+use crate::plot::Snapshot;
+use crate::smtp;
+use crate::webhooks::{Event, Webhooks};
+use crate::{env_or_default, Series, SeriesMap};
+use actix::prelude::*;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A rule of a series, like `{"name": "too hot", "condition": "above",
+/// "value": 30, "minutes": 10}`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: Condition,
+    /// Addresses mailed when the alert fires or is resolved.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub email: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum Condition {
+    /// The values of the last `minutes` are all above `value`.
+    Above { value: f64, minutes: u32 },
+    /// The values of the last `minutes` are all below `value`.
+    Below { value: f64, minutes: u32 },
+    /// No value was added for `minutes`.
+    NoData { minutes: u32 },
+    /// The values changed by more than `rate` per minute over the last
+    /// `minutes`, up or down.
+    RateOfChange { rate: f64, minutes: u32 },
+}
+
+impl AlertRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("alert rules should have a name".to_owned());
+        }
+        let (value, minutes) = match self.condition {
+            Condition::Above { value, minutes }
+            | Condition::Below { value, minutes }
+            | Condition::RateOfChange {
+                rate: value,
+                minutes,
+            } => (value, minutes),
+            Condition::NoData { minutes } => (0.0, minutes),
+        };
+        if !value.is_finite() {
+            return Err(format!("alert {} should have a finite value", self.name));
+        }
+        if minutes == 0 {
+            return Err(format!("alert {} should last a minute or more", self.name));
+        }
+        self.email
+            .iter()
+            .try_for_each(|address| smtp::validate_address(address))
+            .map_err(|err| format!("alert {} mails {}", self.name, err))
+    }
+
+    /// Whether the condition holds at `now`. Values that came in later than
+    /// `now` count as if they were in time.
+    fn holds(&self, data: &History, now: i64) -> bool {
+        let minutes = match self.condition {
+            Condition::Above { minutes, .. }
+            | Condition::Below { minutes, .. }
+            | Condition::NoData { minutes }
+            | Condition::RateOfChange { minutes, .. } => minutes,
+        };
+        let start = now - i64::from(minutes) * 60;
+        // A series younger than the rule has not held a value that long.
+        let covered = data.time_range().is_some_and(|(first, _)| first <= start);
+        let window = || data.between(start, i64::MAX);
+        match self.condition {
+            Condition::Above { value, .. } => {
+                let window = window();
+                covered && !window.is_empty() && window.values().iter().all(|v| *v > value)
+            }
+            Condition::Below { value, .. } => {
+                let window = window();
+                covered && !window.is_empty() && window.values().iter().all(|v| *v < value)
+            }
+            Condition::NoData { .. } => data.latest().is_none_or(|datum| datum.timeStamp < start),
+            Condition::RateOfChange { rate, .. } => {
+                let window = window();
+                match (window.first(), window.last()) {
+                    (Some(first), Some(last)) if last.timeStamp > first.timeStamp => {
+                        let elapsed = (last.timeStamp - first.timeStamp) as f64 / 60.0;
+                        ((last.value - first.value) / elapsed).abs() > rate
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// Rules of a series have different names.
+pub fn validate_rules(rules: &[AlertRule]) -> Result<(), String> {
+    rules.iter().try_for_each(AlertRule::validate)?;
+    for (index, rule) in rules.iter().enumerate() {
+        if rules[..index].iter().any(|other| other.name == rule.name) {
+            return Err(format!("there are two alert rules named {}", rule.name));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Ok,
+    Firing,
+}
+
+/// The state of a rule of a series, as `GET /api/v1/alerts` lists it.
+#[derive(Serialize, Clone, Debug)]
+pub struct Alert {
+    pub series: String,
+    pub rule: String,
+    pub state: AlertState,
+    /// When the alert started firing or was last resolved, or when its rule
+    /// was first evaluated.
+    pub since: i64,
+    /// The latest value of the series.
+    pub value: Option<f64>,
+}
+
+impl Alert {
+    /// The plot of the day before the alert to this day, for mails and
+    /// chats. None when the series has no values then.
+    pub fn snapshot(&self, serie: &Series) -> Option<Snapshot> {
+        Snapshot::of(&self.series, serie, (self.since - 24 * 60 * 60, i64::MAX))
+    }
+}
+
+/// The alerts of every rule as they were last evaluated.
+#[derive(Default)]
+pub struct Alerts {
+    alerts: Mutex<BTreeMap<(String, String), Alert>>,
+}
+
+impl Alerts {
+    /// Ordered by series and rule.
+    pub fn list(&self) -> Vec<Alert> {
+        self.alerts.lock().unpoisoned().values().cloned().collect()
+    }
+
+    /// The names of the rules of a series that are firing.
+    pub fn firing(&self, series_name: &str) -> Vec<String> {
+        self.alerts
+            .lock()
+            .unpoisoned()
+            .values()
+            .filter(|alert| alert.series == series_name && alert.state == AlertState::Firing)
+            .map(|alert| alert.rule.clone())
+            .collect()
+    }
+
+    /// Replaces the alerts with those of `evaluated`, keeping when the ones
+    /// whose state did not change got it. Returns the alerts that fired or
+    /// were resolved.
+    fn update(&self, evaluated: Vec<Alert>) -> Vec<Alert> {
+        let mut alerts = self.alerts.lock().unpoisoned();
+        let mut changed = Vec::new();
+        let mut updated = BTreeMap::new();
+        for mut alert in evaluated {
+            let key = (alert.series.clone(), alert.rule.clone());
+            match alerts.get(&key) {
+                Some(previous) if previous.state == alert.state => alert.since = previous.since,
+                Some(_) => changed.push(alert.clone()),
+                None if alert.state == AlertState::Firing => changed.push(alert.clone()),
+                None => {}
+            }
+            updated.insert(key, alert);
+        }
+        *alerts = updated;
+        changed
+    }
+}
+
+/// Evaluates the rules of every series each `STS_RS_ALERT_INTERVAL`
+/// seconds. Archived series take no values, so their rules are left out.
+pub struct Evaluator {
+    series: SeriesMap,
+    alerts: Arc<Alerts>,
+    interval: Duration,
+    webhooks: Option<Addr<Webhooks>>,
+    mailer: Option<Addr<Mailer>>,
+}
+
+impl Evaluator {
+    pub fn from_env(
+        series: SeriesMap,
+        alerts: Arc<Alerts>,
+        webhooks: Option<Addr<Webhooks>>,
+        mailer: Option<Addr<Mailer>>,
+    ) -> Evaluator {
+        let interval = env_or_default("STS_RS_ALERT_INTERVAL", "60")
+            .parse::<u64>()
+            .expect("STS_RS_ALERT_INTERVAL should be a whole number of seconds");
+        Evaluator {
+            series,
+            alerts,
+            interval: Duration::from_secs(interval.max(1)),
+            webhooks,
+            mailer,
+        }
+    }
+
+    fn evaluate(&mut self) {
+        let now = Utc::now().timestamp();
+        let mut evaluated = Vec::new();
+        let mut recipients = HashMap::new();
+        for (name, serie) in self.series.read().unpoisoned().iter() {
+            let serie = Series::lock(serie);
+            if serie.meta.archived {
+                continue;
+            }
+            for rule in &serie.meta.alerts {
+                if !rule.email.is_empty() {
+                    recipients.insert((name.clone(), rule.name.clone()), rule.email.clone());
+                }
+                evaluated.push(Alert {
+                    series: name.clone(),
+                    rule: rule.name.clone(),
+                    state: match rule.holds(&serie.data, now) {
+                        true => AlertState::Firing,
+                        false => AlertState::Ok,
+                    },
+                    since: now,
+                    value: serie.data.latest().map(|datum| datum.value),
+                });
+            }
+        }
+        for alert in self.alerts.update(evaluated) {
+            let key = (alert.series.clone(), alert.rule.clone());
+            if let (Some(mailer), Some(to)) = (&self.mailer, recipients.remove(&key)) {
+                mailer.do_send(AlertMail {
+                    alert: alert.clone(),
+                    to,
+                });
+            }
+            let event = match alert.state {
+                AlertState::Firing => {
+                    info!("Alert {} of series {} fires.", alert.rule, alert.series);
+                    Event::AlertFired(alert)
+                }
+                AlertState::Ok => {
+                    info!(
+                        "Alert {} of series {} is resolved.",
+                        alert.rule, alert.series
+                    );
+                    Event::AlertResolved(alert)
+                }
+            };
+            if let Some(webhooks) = &self.webhooks {
+                webhooks.do_send(event);
+            }
+        }
+    }
+}
+
+impl Actor for Evaluator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.interval, |this, _| this.evaluate());
+    }
+}
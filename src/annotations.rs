@@ -0,0 +1,52 @@
+use crate::error::{Context, Error};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An event marked on the plots of a series, like a firmware update.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Annotation {
+    #[serde(rename = "timeStamp")]
+    pub time_stamp: i64,
+    pub label: String,
+}
+
+impl Annotation {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.label.trim().is_empty() {
+            return Err("an annotation should have a label".to_owned());
+        }
+        if self.label.contains(['\n', '\r']) {
+            return Err("an annotation label should be a single line".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// The annotations of a series are stored next to the data file as
+/// `<series>.events.json`.
+pub fn annotations_file(data_file: &Path) -> PathBuf {
+    data_file.with_extension("events.json")
+}
+
+pub fn read_annotations(annotations_file: &Path) -> Vec<Annotation> {
+    match File::open(annotations_file) {
+        Ok(file) => serde_json::from_reader(file).unwrap_or_else(|err| {
+            warn!(
+                "Ignoring unreadable annotations {:?}, {}",
+                annotations_file, err
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn write_annotations(annotations_file: &Path, annotations: &[Annotation]) -> Result<(), Error> {
+    let file = File::create(annotations_file)
+        .context(|| format!("creating {:?} failed", annotations_file))?;
+    serde_json::to_writer_pretty(file, annotations)
+        .map_err(io::Error::from)
+        .context(|| format!("writing {:?} failed", annotations_file))
+}
@@ -0,0 +1,44 @@
+use crate::Datum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Archived series keep their data gzip compressed as `<series>.csv.gz`.
+pub fn archived_file(data_file: &Path) -> PathBuf {
+    data_file.with_extension("csv.gz")
+}
+
+/// Replaces the data file by its compressed counterpart.
+pub fn compress(data_file: &Path) -> io::Result<()> {
+    let archived = archived_file(data_file);
+    let temporary = archived.with_extension("gz.tmp");
+    let mut encoder = GzEncoder::new(File::create(&temporary)?, Compression::best());
+    io::copy(&mut File::open(data_file)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::rename(&temporary, &archived)?;
+    std::fs::remove_file(data_file)
+}
+
+/// Restores the plain data file of an archived series.
+pub fn decompress(data_file: &Path) -> io::Result<()> {
+    let archived = archived_file(data_file);
+    let temporary = data_file.with_extension("csv.tmp");
+    let mut decoder = GzDecoder::new(File::open(&archived)?);
+    io::copy(&mut decoder, &mut File::create(&temporary)?)?;
+    std::fs::rename(&temporary, data_file)?;
+    std::fs::remove_file(archived)
+}
+
+pub fn read_archived(data_file: &Path) -> io::Result<Vec<Datum>> {
+    let mut contents = Vec::new();
+    GzDecoder::new(File::open(archived_file(data_file))?).read_to_end(&mut contents)?;
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(contents.as_slice())
+        .deserialize()
+        .filter_map(Result::ok)
+        .collect())
+}
@@ -0,0 +1,24 @@
+//! The files of `static/`, compiled into the executable by the build script
+//! so the server runs from any working directory.
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// The contents and entity tag of a static file.
+pub fn get(name: &str) -> Option<(&'static [u8], &'static str)> {
+    FILES
+        .iter()
+        .find(|(file_name, _, _)| *file_name == name)
+        .map(|(_, contents, etag)| (*contents, *etag))
+}
+
+pub fn content_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("webmanifest") => "application/manifest+json",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
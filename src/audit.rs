@@ -0,0 +1,100 @@
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// Every change made through the API, appended as a line of JSON to
+/// `STS_RS_AUDIT_LOG`, by default `audit.log` in the data directory. Set it
+/// empty to keep no audit log.
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// Unix timestamp of the change.
+    pub time: i64,
+    /// `key:<digest>`, `cert:<identity>`, `user:<name>` or `anonymous`.
+    pub who: String,
+    /// The address the change came from.
+    pub address: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Unix timestamps of the first and last change to return.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub who: Option<String>,
+    /// Only changes to paths starting with this, like `/api/v1/series/`.
+    pub path: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl AuditLog {
+    pub fn from_env(data_path: &Path) -> Option<AuditLog> {
+        let path = env_or_default(
+            "STS_RS_AUDIT_LOG",
+            data_path.join("audit.log").to_str().unwrap(),
+        );
+        if path.is_empty() {
+            return None;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap_or_else(|err| panic!("Could not open the audit log {}, {}", path, err));
+        Some(AuditLog {
+            path: PathBuf::from(path),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, entry: &AuditEntry) {
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+        if let Err(err) = self.file.lock().unpoisoned().write_all(line.as_bytes()) {
+            warn!("Could not write to the audit log {:?}, {}", self.path, err);
+        }
+    }
+
+    /// The latest changes matching the query, the most recent first.
+    pub fn query(&self, query: &AuditQuery) -> std::io::Result<Vec<AuditEntry>> {
+        let matches = |entry: &AuditEntry| {
+            query.from.is_none_or(|from| entry.time >= from)
+                && query.to.is_none_or(|to| entry.time <= to)
+                && query.who.as_ref().is_none_or(|who| entry.who == *who)
+                && query
+                    .path
+                    .as_ref()
+                    .is_none_or(|path| entry.path.starts_with(path.as_str()))
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let mut entries = VecDeque::new();
+        for entry in BufReader::new(File::open(&self.path)?)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+            .filter(matches)
+        {
+            entries.push_front(entry);
+            entries.truncate(limit);
+        }
+        Ok(entries.into())
+    }
+}
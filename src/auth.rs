@@ -0,0 +1,306 @@
+use crate::client_certs::ClientIdentity;
+use crate::env_or_default;
+use crate::metadata::SeriesMeta;
+use crate::namespaces;
+use crate::secrets;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use subtle::ConstantTimeEq;
+
+/// What a key, user or client certificate may do, every role may do what
+/// the ones before it may.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Views the pages, data and plots.
+    Reader,
+    /// Adds values and annotations as well.
+    Writer,
+    /// Deletes, renames and configures series and dashboards as well.
+    Admin,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Role> {
+        match value {
+            "reader" => Some(Role::Reader),
+            "writer" => Some(Role::Writer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Reader => "reader",
+            Role::Writer => "writer",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// Who makes a request and the role they have, kept with the request once it
+/// is authorized for the routes that hide private series. The principal is
+/// named like in the audit log, `user:<name>`, `key:<digest>` or
+/// `cert:<identity>`.
+#[derive(Clone, Debug, Default)]
+pub struct Caller {
+    pub principal: String,
+    pub role: Option<Role>,
+}
+
+impl Caller {
+    /// Requests that were not authorized are made by nobody in particular.
+    pub fn of_request(req: &HttpRequest) -> Caller {
+        req.extensions()
+            .get::<Caller>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Admins read every series, others the public ones and the private ones
+    /// they are one of the readers of.
+    pub fn may_read(&self, meta: &SeriesMeta) -> bool {
+        !meta.private || self.may_read_private(&meta.readers)
+    }
+
+    pub fn may_read_private(&self, readers: &[String]) -> bool {
+        self.role == Some(Role::Admin) || readers.contains(&self.principal)
+    }
+}
+
+/// The routes fall in groups by the role they need. Signing in and the
+/// static files need none. The routes of a namespace need what they need
+/// outside it.
+pub fn required_role(req: &ServiceRequest) -> Option<Role> {
+    let path = namespaces::strip(req.path()).1;
+    let method = req.method();
+    if path.starts_with("/login")
+        || path == "/logout"
+        || path == "/favicon.ico"
+        || path == "/healthz"
+        || path == "/readyz"
+        || path.starts_with("/static/")
+        || path.starts_with("/.well-known/acme-challenge/")
+        || *method == Method::OPTIONS
+    {
+        return None;
+    }
+    if path == "/api/v1/audit" || path.starts_with("/admin/") {
+        return Some(Role::Admin);
+    }
+    if [Method::GET, Method::HEAD].contains(method) {
+        return Some(Role::Reader);
+    }
+    let adds_values = path
+        .strip_prefix('/')
+        .is_some_and(|name| !name.is_empty() && !name.contains('/'));
+    let adds_annotation = path.starts_with("/api/v1/series/") && path.ends_with("/annotations");
+    let pushes_metrics =
+        path.starts_with("/metrics/job") && [Method::POST, Method::PUT].contains(method);
+    if *method == Method::POST && (adds_values || adds_annotation) || pushes_metrics {
+        Some(Role::Writer)
+    } else {
+        Some(Role::Admin)
+    }
+}
+
+/// The keys that allow changing series, configured with `STS_RS_API_KEYS`
+/// as a comma separated list or with `STS_RS_API_KEYS_FILE`, one key per
+/// line. Lines of `cert:<identity>` rather than a key allow the client
+/// certificate of that identity.
+pub struct ApiKeys {
+    keys: Vec<ApiKey>,
+}
+
+struct ApiKey {
+    key: String,
+    role: Role,
+    /// The namespace the key is limited to, keys without one may use every
+    /// namespace and the series outside them.
+    namespace: Option<String>,
+    /// Names of the series this key may add values to, a trailing `*`
+    /// matches every series starting with what comes before it. Keys without
+    /// series may change everything.
+    series: Vec<String>,
+}
+
+impl ApiKey {
+    /// A line of the keys file, the key followed by the series it is
+    /// limited to, like `3f9a1c greenhouse-* barn-temperature` or
+    /// `cert:sensor-12 greenhouse-*`, or by its role like `3f9a1c
+    /// role:writer`, or by its namespace like `3f9a1c ns:garden`. Keys
+    /// limited to series are writers, others admins.
+    fn parse(line: &str) -> ApiKey {
+        let mut words = line.split_whitespace();
+        let key = words.next().unwrap_or_default().to_owned();
+        let mut role = None;
+        let mut namespace = None;
+        let mut series = Vec::new();
+        for word in words {
+            if let Some(name) = word.strip_prefix("role:") {
+                role = Some(Role::parse(name).unwrap_or_else(|| {
+                    panic!("{} is not a role, use reader, writer or admin", name)
+                }))
+            } else if let Some(name) = word.strip_prefix("ns:") {
+                namespace = Some(name.to_owned());
+            } else {
+                series.push(word.to_owned());
+            }
+        }
+        let role = match role {
+            Some(role) => role,
+            None if series.is_empty() => Role::Admin,
+            None => Role::Writer,
+        };
+        ApiKey {
+            key,
+            role,
+            namespace,
+            series,
+        }
+    }
+
+    fn client(&self) -> Option<&str> {
+        self.key.strip_prefix("cert:")
+    }
+
+    /// A key limited to a namespace only uses its routes, a key limited to
+    /// series only adds values, with `POST /<series>`.
+    fn allows(&self, req: &ServiceRequest) -> bool {
+        let (namespace, path) = namespaces::strip(req.path());
+        if self.namespace.is_some() && namespace != self.namespace.as_deref() {
+            return false;
+        }
+        if self.series.is_empty() {
+            return true;
+        }
+        let series_name = match path.strip_prefix('/') {
+            Some(name)
+                if *req.method() == Method::POST && !name.is_empty() && !name.contains('/') =>
+            {
+                percent_decode(name)
+            }
+            _ => return false,
+        };
+        self.series
+            .iter()
+            .any(|pattern| series_matches(pattern, &series_name))
+    }
+}
+
+impl ApiKeys {
+    pub fn from_env() -> ApiKeys {
+        let mut keys = env_or_default("STS_RS_API_KEYS", "")
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(ApiKey::parse)
+            .collect::<Vec<_>>();
+        let keys_file = std::env::var("STS_RS_API_KEYS_FILE")
+            .ok()
+            .map(PathBuf::from)
+            .or_else(|| secrets::credential("STS_RS_API_KEYS_FILE"));
+        if let Some(keys_file) = keys_file {
+            let contents = std::fs::read_to_string(&keys_file)
+                .unwrap_or_else(|err| panic!("Could not read {:?}, {}", keys_file, err));
+            keys.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(ApiKey::parse),
+            );
+        }
+        ApiKeys { keys }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// The role of the key the request carries as `Authorization: Bearer
+    /// <key>` or `X-Api-Key: <key>`, or of the identity of its client
+    /// certificate. Fails with `UNAUTHORIZED` for an unknown key and with
+    /// `FORBIDDEN` when the key is limited to other series.
+    pub fn role(&self, req: &ServiceRequest) -> Result<Option<Role>, StatusCode> {
+        let headers = req.headers();
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| {
+                headers
+                    .get("X-Api-Key")
+                    .and_then(|value| value.to_str().ok())
+            });
+        let found = match (given, ClientIdentity::of_request(req)) {
+            // Every key is compared, so the time taken does not tell which
+            // one came close.
+            (Some(given), _) => self.keys.iter().fold(None, |found, key| {
+                if key.client().is_none()
+                    && constant_time_eq(key.key.as_bytes(), given.trim().as_bytes())
+                {
+                    Some(key)
+                } else {
+                    found
+                }
+            }),
+            (None, Some(identity)) => self
+                .keys
+                .iter()
+                .find(|key| key.client() == Some(identity.as_str())),
+            (None, None) => None,
+        };
+        match (found, given) {
+            (Some(key), _) if key.allows(req) => Ok(Some(key.role)),
+            (Some(_), _) => Err(StatusCode::FORBIDDEN),
+            (None, Some(_)) => Err(StatusCode::UNAUTHORIZED),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// A trailing `*` matches every series starting with what comes before it.
+pub fn series_matches(pattern: &str, series_name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => series_name.starts_with(prefix),
+        None => pattern == series_name,
+    }
+}
+
+/// Compares without stopping at the first difference, only the length of
+/// the keys can be told from the time taken.
+fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    lhs.len() == rhs.len() && bool::from(lhs.ct_eq(rhs))
+}
+
+/// Series names in paths may be percent-encoded, like `%C2%B0C`.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], value.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,289 @@
+use crate::error::Unpoisoned;
+use crate::plot::Plotter;
+use crate::reload::Reloadable;
+use crate::trash::Trash;
+use crate::{BackgroundActor, Datum, Series, WriteCsv};
+use actix::prelude::*;
+use actix_web::client::Client;
+use chrono::Utc;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_millis(10);
+/// How long values still on their way may take once the run is over.
+const GRACE: Duration = Duration::from_secs(10);
+
+/// A run of `sts-rs bench`, adding values to `series` series named
+/// `bench-<n>` at `rate` values a second for `duration`.
+pub struct Bench {
+    pub series: usize,
+    pub rate: f64,
+    pub duration: Duration,
+    pub target: Target,
+}
+
+/// Where the values go: `POST /<series>` of a running server, or the actor
+/// writing the data files and plots, in a temporary data directory.
+pub enum Target {
+    Http {
+        url: String,
+        api_key: Option<String>,
+        insecure: bool,
+    },
+    Direct,
+}
+
+enum Ingest {
+    Http {
+        client: Client,
+        url: String,
+        api_key: Option<String>,
+    },
+    Direct {
+        background_actor: Addr<BackgroundActor>,
+        series: crate::SeriesMap,
+    },
+}
+
+#[derive(Default)]
+struct Outcome {
+    latencies: Vec<Duration>,
+    failures: usize,
+    first_failure: Option<String>,
+    finished: Option<Instant>,
+}
+
+impl Bench {
+    /// Values are sent at the rate whether or not the ones before are
+    /// answered, so a slow server shows in the latencies.
+    pub async fn run(self) -> Result<(), String> {
+        let directory = std::env::temp_dir().join(format!("sts-rs-bench-{}", std::process::id()));
+        let direct = matches!(self.target, Target::Direct);
+        let ingest = Rc::new(match self.target {
+            Target::Http {
+                url,
+                api_key,
+                insecure,
+            } => {
+                println!("Adding values to {}", url);
+                Ingest::Http {
+                    client: crate::client(insecure),
+                    url: url.trim_end_matches('/').to_owned(),
+                    api_key,
+                }
+            }
+            Target::Direct => {
+                let (data_path, image_path) = (directory.join("data"), directory.join("images"));
+                for path in &[&data_path, &image_path] {
+                    std::fs::create_dir_all(path)
+                        .map_err(|err| format!("Could not create {:?}, {}", path, err))?;
+                }
+                println!("Adding values directly in {:?}", data_path);
+                let series = Arc::new(RwLock::new(HashMap::new()));
+                Ingest::Direct {
+                    background_actor: BackgroundActor::new(
+                        data_path.clone(),
+                        image_path,
+                        series.clone(),
+                        None,
+                        Arc::new(Reloadable::new(None)),
+                        Arc::new(Trash::from_env(&data_path)),
+                        Plotter::from_env(),
+                    )
+                    .start(),
+                    series,
+                }
+            }
+        });
+        let outcome = Rc::new(RefCell::new(Outcome::default()));
+        let started = Instant::now();
+        let first_time_stamp = Utc::now().timestamp();
+        let mut ticks = actix_rt::time::interval(TICK);
+        let mut sent = 0;
+        while started.elapsed() < self.duration {
+            ticks.tick().await;
+            let due = (started
+                .elapsed()
+                .as_secs_f64()
+                .min(self.duration.as_secs_f64())
+                * self.rate) as usize;
+            while sent < due {
+                let series_name = format!("bench-{}", sent % self.series);
+                // Every series gets a value per second of its own.
+                let datum = Datum {
+                    timeStamp: first_time_stamp + (sent / self.series) as i64,
+                    value: (sent % 100) as f64,
+                };
+                let (ingest, outcome) = (ingest.clone(), outcome.clone());
+                actix_rt::spawn(async move {
+                    let sent_at = Instant::now();
+                    let added = ingest.add(series_name, datum).await;
+                    let mut outcome = outcome.borrow_mut();
+                    match added {
+                        Ok(()) => outcome.latencies.push(sent_at.elapsed()),
+                        Err(err) => {
+                            outcome.failures += 1;
+                            outcome.first_failure.get_or_insert(err);
+                        }
+                    }
+                    outcome.finished = Some(Instant::now());
+                });
+                sent += 1;
+            }
+        }
+        let waiting = Instant::now();
+        while {
+            let outcome = outcome.borrow();
+            outcome.latencies.len() + outcome.failures < sent && waiting.elapsed() < GRACE
+        } {
+            actix_rt::time::delay_for(TICK).await;
+        }
+        if direct {
+            let _ = std::fs::remove_dir_all(&directory);
+        }
+        let outcome = outcome.borrow();
+        report(sent, self.series, started, &outcome);
+        match (outcome.failures, &outcome.first_failure) {
+            (0, _) => Ok(()),
+            (failures, first) => Err(format!(
+                "{} of {} values failed, the first with {}",
+                failures,
+                sent,
+                first.as_deref().unwrap_or_default()
+            )),
+        }
+    }
+}
+
+impl Ingest {
+    async fn add(&self, series_name: String, datum: Datum) -> Result<(), String> {
+        match self {
+            Ingest::Http {
+                client,
+                url,
+                api_key,
+            } => {
+                let mut request = client.post(format!("{}/{}", url, series_name));
+                if let Some(api_key) = api_key {
+                    request = request.header("X-Api-Key", api_key.as_str());
+                }
+                match request.send_json(&datum).await {
+                    Ok(response) if response.status().is_success() => Ok(()),
+                    Ok(response) => Err(format!("status code {}", response.status())),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            // Like `POST /<series>` without the checks, the actor taking the
+            // value is waited for rather than only queued.
+            Ingest::Direct {
+                background_actor,
+                series,
+            } => {
+                let written = loop {
+                    let all_series = series.read().unpoisoned();
+                    if let Some(serie) = all_series.get(&series_name) {
+                        let mut serie = Series::lock(serie);
+                        serie.append(datum, Utc::now());
+                        break background_actor.send(WriteCsv { series_name, datum });
+                    }
+                    drop(all_series);
+                    if let Entry::Vacant(entry) =
+                        series.write().unpoisoned().entry(series_name.clone())
+                    {
+                        entry.insert(Mutex::new(Series::new(datum, Utc::now())));
+                        break background_actor.send(WriteCsv { series_name, datum });
+                    }
+                };
+                written.await.map_err(|err| err.to_string())
+            }
+        }
+    }
+}
+
+fn report(sent: usize, series: usize, started: Instant, outcome: &Outcome) {
+    let elapsed = outcome
+        .finished
+        .map_or(Duration::from_secs(0), |finished| finished - started);
+    let added = outcome.latencies.len();
+    println!(
+        "Added {} of {} values to {} series in {:.1} s, {:.1} values a second, {} failed",
+        added,
+        sent,
+        series,
+        elapsed.as_secs_f64(),
+        added as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        outcome.failures
+    );
+    let mut latencies = outcome.latencies.clone();
+    latencies.sort();
+    let percentile = |percent: usize| match latencies.len() {
+        0 => 0.0,
+        count => latencies[(count * percent / 100).min(count - 1)].as_secs_f64() * 1000.0,
+    };
+    println!(
+        "Latency p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        percentile(100)
+    );
+}
+
+/// Rates like `500/s`, `600/m` or a number of values a second.
+pub fn parse_rate(rate: &str) -> Result<f64, String> {
+    let (count, per) = match rate.split_once('/') {
+        Some((count, "s")) => (count, 1.0),
+        Some((count, "m")) => (count, 60.0),
+        Some(_) => {
+            return Err(format!(
+                "{} should be per second or minute, like 500/s",
+                rate
+            ))
+        }
+        None => (rate, 1.0),
+    };
+    count
+        .parse::<f64>()
+        .ok()
+        .filter(|count| count.is_finite() && *count > 0.0)
+        .map(|count| count / per)
+        .ok_or_else(|| format!("{} is not a rate like 500/s", rate))
+}
+
+/// Durations like `60s`, `5m`, `1h` or a number of seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let (count, unit) = match duration.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(format!("{} is not a duration like 60s or 5m", duration)),
+    };
+    count
+        .parse::<u64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .map(|count| Duration::from_secs(count * seconds))
+        .ok_or_else(|| format!("{} is not a duration like 60s or 5m", duration))
+}
+
+/// The URL of the server configured here, its HTTPS listener unless only
+/// plain HTTP is configured.
+pub fn default_url() -> String {
+    let https_address = crate::env_or_default("STS_RS_HTTPS_ADDRESS", crate::DEFAULT_HTTPS_ADDRESS);
+    if https_address.is_empty() {
+        format!(
+            "http://{}",
+            crate::env_or_default("STS_RS_HTTP_ADDRESS", "")
+        )
+    } else {
+        format!("https://{}", https_address)
+    }
+}
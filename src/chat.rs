@@ -0,0 +1,192 @@
+//! Alerts posted to chats: Slack and Discord through their incoming
+//! webhooks and Telegram through a bot. Messages follow
+//! `STS_RS_CHAT_TEMPLATE` and link to the page of the series under
+//! `STS_RS_PUBLIC_URL`, on Discord and Telegram a plot of the day before
+//! the alert is attached.
+
+use crate::alerts::{Alert, AlertState};
+use crate::namespaces::Namespace;
+use crate::webhooks::Post;
+use crate::{
+    env_or_default, namespaces, secrets, series_names, show_value, show_value_with_unit, Series,
+};
+use bytes::Bytes;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use serde_json::json;
+
+const DEFAULT_TEMPLATE: &str =
+    "Alert {{rule}} of {{series}} {{event}}, the latest value is {{value}}. {{link}}";
+/// Telegram takes captions of photos this long at most.
+const MAX_CAPTION: usize = 1024;
+
+pub enum Chat {
+    Slack {
+        url: String,
+    },
+    Discord {
+        url: String,
+    },
+    /// A bot posting to the chat, group or channel of `chat_id`, `api` is
+    /// the url of the Bot API with the token of the bot.
+    Telegram {
+        api: String,
+        chat_id: String,
+    },
+}
+
+impl Chat {
+    /// The chats of `STS_RS_SLACK_WEBHOOK_URL`, `STS_RS_DISCORD_WEBHOOK_URL`
+    /// and `STS_RS_TELEGRAM_BOT_TOKEN`, secrets as they grant posting.
+    pub fn from_env() -> Vec<Chat> {
+        let mut chats = Vec::new();
+        if let Some(url) = secrets::secret("STS_RS_SLACK_WEBHOOK_URL") {
+            chats.push(Chat::Slack { url });
+        }
+        if let Some(url) = secrets::secret("STS_RS_DISCORD_WEBHOOK_URL") {
+            chats.push(Chat::Discord { url });
+        }
+        if let Some(token) = secrets::secret("STS_RS_TELEGRAM_BOT_TOKEN") {
+            let chat_id = std::env::var("STS_RS_TELEGRAM_CHAT_ID")
+                .expect("STS_RS_TELEGRAM_BOT_TOKEN needs STS_RS_TELEGRAM_CHAT_ID");
+            let api = env_or_default("STS_RS_TELEGRAM_API", "https://api.telegram.org");
+            chats.push(Chat::Telegram {
+                api: format!("{}/bot{}", api.trim_end_matches('/'), token),
+                chat_id,
+            });
+        }
+        chats
+    }
+
+    /// How the chat is named in the log, its url is a secret.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Chat::Slack { .. } => "Slack",
+            Chat::Discord { .. } => "Discord",
+            Chat::Telegram { .. } => "Telegram",
+        }
+    }
+
+    /// Incoming webhooks of Slack cannot upload files.
+    pub fn takes_plots(&self) -> bool {
+        !matches!(self, Chat::Slack { .. })
+    }
+
+    /// The post of a message, with the PNG of a plot when there is one.
+    pub fn post(&self, text: &str, png: Option<&[u8]>) -> Post {
+        match (self, png) {
+            (Chat::Slack { url }, _) => Post::json(url.clone(), &json!({ "text": text })),
+            (Chat::Discord { url }, None) => Post::json(url.clone(), &json!({ "content": text })),
+            (Chat::Discord { url }, Some(png)) => multipart(
+                url.clone(),
+                &[("payload_json", json!({ "content": text }).to_string())],
+                ("files[0]", png),
+            ),
+            (Chat::Telegram { api, chat_id }, None) => Post::json(
+                format!("{}/sendMessage", api),
+                &json!({ "chat_id": chat_id, "text": text }),
+            ),
+            (Chat::Telegram { api, chat_id }, Some(png)) => multipart(
+                format!("{}/sendPhoto", api),
+                &[
+                    ("chat_id", chat_id.clone()),
+                    ("caption", text.chars().take(MAX_CAPTION).collect()),
+                ],
+                ("photo", png),
+            ),
+        }
+    }
+}
+
+/// The messages of alerts, `STS_RS_CHAT_TEMPLATE` with its placeholders
+/// like `{{series}}` filled in.
+pub struct Messages {
+    template: String,
+    /// The url the server is reached at, for links to series.
+    public_url: Option<String>,
+    timezone: Tz,
+}
+
+impl Messages {
+    pub fn from_env(timezone: Tz) -> Messages {
+        Messages {
+            template: env_or_default("STS_RS_CHAT_TEMPLATE", DEFAULT_TEMPLATE),
+            public_url: std::env::var("STS_RS_PUBLIC_URL")
+                .ok()
+                .map(|url| url.trim_end_matches('/').to_owned())
+                .filter(|url| !url.is_empty()),
+            timezone,
+        }
+    }
+
+    /// The message of `alert` of `serie`, which may be gone meanwhile.
+    pub fn text(&self, alert: &Alert, serie: Option<&Series>) -> String {
+        let value = match (alert.value, serie) {
+            (Some(value), Some(serie)) => show_value_with_unit(value, &serie.meta),
+            (Some(value), None) => show_value(value, None),
+            (None, _) => "unknown".to_owned(),
+        };
+        let (state, event) = match alert.state {
+            AlertState::Firing => ("firing", "fires"),
+            AlertState::Ok => ("ok", "is resolved"),
+        };
+        let time = match self.timezone.timestamp_opt(alert.since, 0).single() {
+            Some(time) => time.format("%Y-%m-%d %H:%M %Z").to_string(),
+            None => alert.since.to_string(),
+        };
+        let link = self.public_url.as_ref().map_or(String::new(), |url| {
+            format!(
+                "{}{}/series/{}",
+                url,
+                Namespace::of_series(&alert.series).base(),
+                series_names::file_stem(namespaces::split(&alert.series).1)
+            )
+        });
+        [
+            ("series", alert.series.clone()),
+            ("rule", alert.rule.clone()),
+            ("state", state.to_owned()),
+            ("event", event.to_owned()),
+            ("value", value),
+            ("time", time),
+            ("link", link),
+        ]
+        .iter()
+        .fold(self.template.clone(), |text, (name, value)| {
+            text.replace(&format!("{{{{{}}}}}", name), value)
+        })
+        .trim()
+        .to_owned()
+    }
+}
+
+/// A form of `fields` and the PNG `file` named `plot.png`.
+fn multipart(url: String, fields: &[(&str, String)], (name, file): (&str, &[u8])) -> Post {
+    let mut bytes = [0; 12];
+    getrandom::getrandom(&mut bytes).expect("no random numbers for a form boundary");
+    let boundary = format!("sts-rs-{}", hex::encode(bytes));
+    let mut body = Vec::with_capacity(file.len() + 512);
+    for (field, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, field, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"plot.png\"\r\nContent-Type: image/png\r\n\r\n",
+            boundary, name
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    Post {
+        url,
+        content_type: format!("multipart/form-data; boundary={}", boundary),
+        body: Bytes::from(body),
+    }
+}
@@ -0,0 +1,242 @@
+use crate::bench::{self, Bench, Target};
+use crate::config::{self, Config};
+use crate::logging;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+const DEFAULT_HTTP_ADDRESS: &str = "127.0.0.1:8080";
+
+/// The arguments of `sts-rs`. Without a subcommand it serves.
+pub fn app() -> App<'static, 'static> {
+    App::new(crate::PACKAGE_NAME)
+        .version(crate::VERSION)
+        .about("Records time series and serves plots of them")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .global(true)
+                .help("The configuration file, instead of ~/.config/sts-rs/config.toml"),
+        )
+        .arg(
+            Arg::with_name("bind")
+                .long("bind")
+                .value_name("ADDRESS")
+                .global(true)
+                .help("Address to listen on, like 0.0.0.0:8443"),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .long("data-dir")
+                .value_name("DIRECTORY")
+                .global(true)
+                .help("Directory holding the data files"),
+        )
+        .arg(
+            Arg::with_name("no-tls")
+                .long("no-tls")
+                .global(true)
+                .help("Only listen for plain HTTP, behind a reverse proxy"),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .global(true)
+                .help("error, warn, info, debug or trace, or filters like RUST_LOG takes"),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .global(true)
+                .help("Log lines as text or as JSON objects"),
+        )
+        .subcommand(SubCommand::with_name("serve").about("Serves the series, the default"))
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports RRD or whisper archives as series, with the server stopped")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["rrd", "whisper"])
+                        .required(true),
+                )
+                .arg(Arg::with_name("files").multiple(true).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("check").about(
+                "Reports problems in the configuration and data files without changing them",
+            ),
+        )
+        .subcommand(SubCommand::with_name("compact").about(
+            "Quarantines broken rows, sorts rows and drops duplicate rows, with the server stopped",
+        ))
+        .subcommand(
+            SubCommand::with_name("replot")
+                .about("Renders the plots of the series again, without serving")
+                .arg(
+                    Arg::with_name("series")
+                        .long("series")
+                        .value_name("NAME")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("A series to plot, all of them when not given"),
+                )
+                .arg(
+                    Arg::with_name("range")
+                        .long("range")
+                        .value_name("WINDOW")
+                        .help("How far back the plots go, like 24h, 30d or all"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Adds values at a steady rate and reports throughput and latency")
+                .arg(
+                    Arg::with_name("series")
+                        .long("series")
+                        .value_name("COUNT")
+                        .default_value("10")
+                        .help("Number of series, named bench-<n>"),
+                )
+                .arg(
+                    Arg::with_name("rate")
+                        .long("rate")
+                        .value_name("RATE")
+                        .default_value("100/s")
+                        .help("Values added, like 500/s or 600/m"),
+                )
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .value_name("DURATION")
+                        .default_value("10s")
+                        .help("How long to add values, like 60s or 5m"),
+                )
+                .arg(
+                    Arg::with_name("url")
+                        .long("url")
+                        .value_name("URL")
+                        .conflicts_with("direct")
+                        .help("The server to add values to, the configured one by default"),
+                )
+                .arg(
+                    Arg::with_name("api-key")
+                        .long("api-key")
+                        .value_name("KEY")
+                        .help("Key sent along to a server that requires one"),
+                )
+                .arg(
+                    Arg::with_name("insecure")
+                        .long("insecure")
+                        .help("Accept any certificate of the server"),
+                )
+                .arg(
+                    Arg::with_name("direct")
+                        .long("direct")
+                        .help("Write values and plots in a temporary directory, without HTTP"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("hash-password")
+                .about("Prints the hash of the password read from standard input"),
+        )
+}
+
+/// Puts the options in the environment, where they take precedence over
+/// the variables and the configuration file, and then reads that file.
+pub fn apply(matches: &ArgMatches) -> Result<Option<Config>, String> {
+    let value = |option: &str| value(matches, option);
+    let flag = |option: &str| flag(matches, option);
+    if let Some(config) = value("config") {
+        std::env::set_var("STS_RS_CONFIG", config);
+    }
+    if let Some(data_dir) = value("data-dir") {
+        std::env::set_var("STS_RS_DATA_PATH", data_dir);
+    }
+    if let Some(log_format) = value("log-format") {
+        std::env::set_var("STS_RS_LOG_FORMAT", log_format);
+    }
+    // Builds without the tls feature only listen for plain HTTP.
+    let no_tls = flag("no-tls") || cfg!(not(feature = "tls"));
+    match (value("bind"), no_tls) {
+        (Some(bind), true) => std::env::set_var("STS_RS_HTTP_ADDRESS", bind),
+        (Some(bind), false) => std::env::set_var("STS_RS_HTTPS_ADDRESS", bind),
+        (None, _) => (),
+    }
+    if flag("no-tls") {
+        std::env::set_var("STS_RS_HTTPS_ADDRESS", "");
+    }
+    let file = config::load()?;
+    let http_address = std::env::var("STS_RS_HTTP_ADDRESS").ok();
+    if no_tls && http_address.is_none_or(|address| address.is_empty()) {
+        std::env::set_var("STS_RS_HTTP_ADDRESS", DEFAULT_HTTP_ADDRESS);
+    }
+    Ok(file)
+}
+
+/// The run `sts-rs bench` was asked for.
+pub fn bench(matches: &ArgMatches) -> Result<Bench, String> {
+    let series = matches
+        .value_of("series")
+        .unwrap_or_default()
+        .parse::<usize>()
+        .ok()
+        .filter(|series| *series > 0)
+        .ok_or("--series should be a number of series")?;
+    let target = if matches.is_present("direct") {
+        Target::Direct
+    } else {
+        Target::Http {
+            url: matches
+                .value_of("url")
+                .map_or_else(bench::default_url, str::to_owned),
+            api_key: matches.value_of("api-key").map(str::to_owned),
+            insecure: matches.is_present("insecure"),
+        }
+    };
+    Ok(Bench {
+        series,
+        rate: bench::parse_rate(matches.value_of("rate").unwrap_or_default())
+            .map_err(|err| format!("--rate {}", err))?,
+        duration: bench::parse_duration(matches.value_of("duration").unwrap_or_default())
+            .map_err(|err| format!("--duration {}", err))?,
+        target,
+    })
+}
+
+/// Logs at `--log-level`, or else as `RUST_LOG` says, in the format of
+/// `STS_RS_LOG_FORMAT`. Called once the configuration file is read.
+pub fn init_logging(matches: &ArgMatches) -> Result<(), String> {
+    let format = std::env::var("STS_RS_LOG_FORMAT").unwrap_or_else(|_| "text".to_owned());
+    logging::init(value(matches, "log-level"), &format)?;
+    let name = matches.subcommand_name().unwrap_or("serve");
+    // The benchmark goes to the configured server.
+    if name != "serve"
+        && name != "bench"
+        && (value(matches, "bind").is_some() || flag(matches, "no-tls"))
+    {
+        warn!("--bind and --no-tls only matter when serving");
+    }
+    Ok(())
+}
+
+/// Global options can also be given after the subcommand.
+fn value<'a>(matches: &'a ArgMatches, option: &str) -> Option<&'a str> {
+    matches
+        .subcommand()
+        .1
+        .and_then(|subcommand| subcommand.value_of(option))
+        .or_else(|| matches.value_of(option))
+}
+
+fn flag(matches: &ArgMatches, option: &str) -> bool {
+    matches.is_present(option)
+        || matches
+            .subcommand()
+            .1
+            .is_some_and(|subcommand| subcommand.is_present(option))
+}
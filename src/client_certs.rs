@@ -0,0 +1,99 @@
+use crate::env_or_default;
+use actix_web::dev::ServiceRequest;
+use actix_web::HttpMessage;
+#[cfg(feature = "tls")]
+use openssl::nid::Nid;
+#[cfg(feature = "tls")]
+use openssl::ssl::{SslAcceptorBuilder, SslVerifyMode};
+#[cfg(feature = "tls")]
+use openssl::x509::X509Ref;
+
+/// Verifying client certificates on the TLS listener, against the
+/// certificate authorities in `STS_RS_CLIENT_CA`. With
+/// `STS_RS_CLIENT_CERTS=required` connections without a certificate are
+/// refused, by default they are let through without an identity.
+pub struct ClientCerts {
+    ca_file: String,
+    required: bool,
+}
+
+/// Who the verified certificate of the connection belongs to, stored with
+/// every request on that connection.
+#[derive(Clone)]
+pub struct ClientIdentity(pub Option<String>);
+
+impl ClientCerts {
+    pub fn from_env() -> Option<ClientCerts> {
+        let ca_file = std::env::var("STS_RS_CLIENT_CA").ok()?;
+        let required = match env_or_default("STS_RS_CLIENT_CERTS", "optional").as_str() {
+            "optional" => false,
+            "required" => true,
+            other => panic!(
+                "STS_RS_CLIENT_CERTS should be optional or required, not {}",
+                other
+            ),
+        };
+        Some(ClientCerts { ca_file, required })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} client certificates of {}",
+            if self.required {
+                "Requiring"
+            } else {
+                "Accepting"
+            },
+            self.ca_file
+        )
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn configure(&self, builder: &mut SslAcceptorBuilder) -> Result<(), String> {
+        builder
+            .set_ca_file(&self.ca_file)
+            .map_err(|err| format!("Could not read {}, {}", self.ca_file, err))?;
+        let mut mode = SslVerifyMode::PEER;
+        if self.required {
+            mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+        }
+        builder.set_verify(mode);
+        // Resuming sessions with client certificates needs a context.
+        builder
+            .set_session_id_context(b"sts-rs")
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl ClientIdentity {
+    /// The common name of the certificate, or its first DNS name or email
+    /// address when it has none. Only verified certificates get this far.
+    #[cfg(feature = "tls")]
+    pub fn of_certificate(certificate: Option<&X509Ref>) -> ClientIdentity {
+        let certificate = match certificate {
+            Some(certificate) => certificate,
+            None => return ClientIdentity(None),
+        };
+        let common_name = certificate
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .and_then(|entry| entry.data().as_utf8().ok())
+            .map(|name| name.to_string());
+        let alt_name = || {
+            certificate.subject_alt_names().and_then(|names| {
+                names
+                    .iter()
+                    .find_map(|name| name.dnsname().or_else(|| name.email()))
+                    .map(str::to_owned)
+            })
+        };
+        ClientIdentity(common_name.or_else(alt_name))
+    }
+
+    pub fn of_request(req: &ServiceRequest) -> Option<String> {
+        req.extensions()
+            .get::<ClientIdentity>()
+            .and_then(|identity| identity.0.clone())
+    }
+}
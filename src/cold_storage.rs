@@ -0,0 +1,200 @@
+use crate::error::{Context, Error};
+use crate::s3::{ObjectStore, ObjectStoreError};
+use crate::secrets;
+use crate::{env_or_default, read_csv_data, rewrite_data_file, to_csv, Datum};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_COLD_AFTER_DAYS: &str = "30";
+
+/// A day worth of observations of a single series that has been moved to
+/// object storage.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ColdPartition {
+    pub day: String,
+    pub first: i64,
+    pub last: i64,
+    pub count: usize,
+}
+
+impl ColdPartition {
+    pub fn overlaps(&self, from: i64, to: i64) -> bool {
+        self.first <= to && self.last >= from
+    }
+}
+
+/// Offloads observations older than `cold_after_days` to S3-compatible
+/// object storage, one object per series per UTC day.
+pub struct ColdStorage {
+    store: ObjectStore,
+    prefix: String,
+    cold_after_days: i64,
+}
+
+impl ColdStorage {
+    /// Cold storage is enabled by setting `STS_RS_S3_ENDPOINT`.
+    pub fn from_env() -> Option<ColdStorage> {
+        let endpoint = std::env::var("STS_RS_S3_ENDPOINT").ok()?;
+        let cold_after_days = env_or_default("STS_RS_COLD_AFTER_DAYS", DEFAULT_COLD_AFTER_DAYS)
+            .parse::<i64>()
+            .expect("STS_RS_COLD_AFTER_DAYS should be a whole number of days");
+        Some(ColdStorage {
+            store: ObjectStore::new(
+                &endpoint,
+                &env_or_default("STS_RS_S3_BUCKET", "sts-rs"),
+                &env_or_default("STS_RS_S3_REGION", "us-east-1"),
+                &secrets::secret("STS_RS_S3_ACCESS_KEY").unwrap_or_default(),
+                &secrets::secret("STS_RS_S3_SECRET_KEY").unwrap_or_default(),
+            ),
+            prefix: env_or_default("STS_RS_S3_PREFIX", ""),
+            cold_after_days,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "data older than {} days, object prefix '{}'",
+            self.cold_after_days, self.prefix
+        )
+    }
+
+    /// Observations with a time stamp before the returned value are cold.
+    pub fn cutoff(&self, now: DateTime<Utc>) -> i64 {
+        (now - Duration::days(self.cold_after_days))
+            .date()
+            .and_hms(0, 0, 0)
+            .timestamp()
+    }
+
+    /// Moves all observations before `cutoff` from `data_file` to object
+    /// storage, merging with partitions that were offloaded earlier. Returns
+    /// the updated partition list and the number of observations moved.
+    pub async fn offload(
+        &self,
+        series_name: &str,
+        data_file: &Path,
+        mut partitions: Vec<ColdPartition>,
+        cutoff: i64,
+    ) -> Result<(Vec<ColdPartition>, usize), String> {
+        let (data, _) = read_csv_data(data_file).map_err(|err| err.to_string())?;
+        let (cold, hot): (Vec<Datum>, Vec<Datum>) =
+            data.into_iter().partition(|datum| datum.timeStamp < cutoff);
+        if cold.is_empty() {
+            return Ok((partitions, 0));
+        }
+        let mut days: BTreeMap<String, Vec<Datum>> = BTreeMap::new();
+        for datum in &cold {
+            days.entry(day_of(datum.timeStamp))
+                .or_default()
+                .push(*datum);
+        }
+        for (day, mut day_data) in days {
+            let key = self.key(series_name, &day);
+            if let Some(existing) = partitions.iter().position(|p| p.day == day) {
+                let mut previous = self
+                    .fetch_partition(&key)
+                    .await
+                    .map_err(|err| format!("fetching {} failed, {}", key, err))?;
+                previous.append(&mut day_data);
+                day_data = previous;
+                partitions.remove(existing);
+            }
+            day_data.sort_by_key(|datum| datum.timeStamp);
+            let csv = to_csv(day_data.iter().copied())
+                .map_err(|err| format!("encoding {} failed, {}", key, err))?;
+            self.store
+                .put_object(&key, csv)
+                .await
+                .map_err(|err| format!("storing {} failed, {}", key, err))?;
+            partitions.push(ColdPartition {
+                day,
+                first: day_data.first().unwrap().timeStamp,
+                last: day_data.last().unwrap().timeStamp,
+                count: day_data.len(),
+            });
+        }
+        partitions.sort_by(|lhs, rhs| lhs.day.cmp(&rhs.day));
+        write_index(&index_file(data_file), &partitions).map_err(|err| err.to_string())?;
+        rewrite_data_file(data_file, &hot).map_err(|err| err.to_string())?;
+        Ok((partitions, cold.len()))
+    }
+
+    /// Retrieves the observations of the given partitions from object storage.
+    pub async fn fetch(
+        &self,
+        series_name: &str,
+        partitions: &[ColdPartition],
+    ) -> Result<Vec<Datum>, ObjectStoreError> {
+        let mut result = Vec::new();
+        for partition in partitions {
+            let mut data = self
+                .fetch_partition(&self.key(series_name, &partition.day))
+                .await?;
+            result.append(&mut data);
+        }
+        Ok(result)
+    }
+
+    /// Removes the given partitions from object storage.
+    pub async fn delete(
+        &self,
+        series_name: &str,
+        partitions: &[ColdPartition],
+    ) -> Result<(), ObjectStoreError> {
+        for partition in partitions {
+            self.store
+                .delete_object(&self.key(series_name, &partition.day))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_partition(&self, key: &str) -> Result<Vec<Datum>, ObjectStoreError> {
+        match self.store.get_object(key).await? {
+            Some(bytes) => Ok(csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(bytes.as_ref())
+                .deserialize()
+                .filter_map(Result::ok)
+                .collect()),
+            None => {
+                warn!("Cold partition {} is missing from object storage", key);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn key(&self, series_name: &str, day: &str) -> String {
+        format!("{}{}/{}.csv", self.prefix, series_name, day)
+    }
+}
+
+/// The partition index is kept next to the data file as `<series>.cold.json`.
+pub fn index_file(data_file: &Path) -> PathBuf {
+    data_file.with_extension("cold.json")
+}
+
+pub fn read_index(index_file: &Path) -> Vec<ColdPartition> {
+    match File::open(index_file) {
+        Ok(file) => serde_json::from_reader(file).unwrap_or_else(|err| {
+            warn!("Ignoring unreadable cold index {:?}, {}", index_file, err);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn write_index(index_file: &Path, partitions: &[ColdPartition]) -> Result<(), Error> {
+    let file = File::create(index_file).context(|| format!("creating {:?} failed", index_file))?;
+    serde_json::to_writer(file, partitions)
+        .map_err(io::Error::from)
+        .context(|| format!("writing {:?} failed", index_file))
+}
+
+fn day_of(time_stamp: i64) -> String {
+    Utc.timestamp(time_stamp, 0).format("%Y-%m-%d").to_string()
+}
@@ -0,0 +1,175 @@
+use crate::Datum;
+use std::iter::FromIterator;
+use std::ops::Range;
+
+/// Values as a column of time stamps and a column of values, the way
+/// queries and plots get them from the history of a series. It takes no
+/// padding and lets aggregations run over the values alone. While the
+/// values are in order of time stamp, a range of time stamps is found by
+/// binary search.
+#[derive(Clone, Debug)]
+pub struct Columns {
+    time_stamps: Vec<i64>,
+    values: Vec<f64>,
+    in_order: bool,
+}
+
+impl Default for Columns {
+    fn default() -> Columns {
+        Columns::with_capacity(0)
+    }
+}
+
+impl Columns {
+    pub fn with_capacity(capacity: usize) -> Columns {
+        Columns {
+            time_stamps: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+            in_order: true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.time_stamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time_stamps.is_empty()
+    }
+
+    pub fn time_stamps(&self) -> &[i64] {
+        &self.time_stamps
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    pub fn push(&mut self, datum: Datum) {
+        self.in_order &= self
+            .time_stamps
+            .last()
+            .is_none_or(|last| *last <= datum.timeStamp);
+        self.time_stamps.push(datum.timeStamp);
+        self.values.push(datum.value);
+    }
+
+    pub fn get(&self, index: usize) -> Option<Datum> {
+        Some(Datum {
+            timeStamp: *self.time_stamps.get(index)?,
+            value: self.values[index],
+        })
+    }
+
+    pub fn first(&self) -> Option<Datum> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<Datum> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// The value with the highest time stamp, the last one added of those.
+    pub fn latest(&self) -> Option<Datum> {
+        if self.in_order {
+            return self.last();
+        }
+        let index = (0..self.len()).max_by_key(|index| self.time_stamps[*index])?;
+        self.get(index)
+    }
+
+    pub fn into_data(self) -> impl Iterator<Item = Datum> {
+        self.time_stamps
+            .into_iter()
+            .zip(self.values)
+            .map(|(time_stamp, value)| Datum {
+                timeStamp: time_stamp,
+                value,
+            })
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Datum> + ExactSizeIterator + '_ {
+        self.time_stamps
+            .iter()
+            .zip(&self.values)
+            .map(|(time_stamp, value)| Datum {
+                timeStamp: *time_stamp,
+                value: *value,
+            })
+    }
+
+    /// The indices of the values from `from` up to and including `to`, the
+    /// values have to be in order.
+    pub fn range(&self, from: i64, to: i64) -> Range<usize> {
+        debug_assert!(self.in_order);
+        let start = self
+            .time_stamps
+            .partition_point(|time_stamp| *time_stamp < from);
+        let end = self
+            .time_stamps
+            .partition_point(|time_stamp| *time_stamp <= to);
+        start..end.max(start)
+    }
+
+    /// A copy of the values in `range`.
+    pub fn slice(&self, range: Range<usize>) -> Columns {
+        Columns {
+            time_stamps: self.time_stamps[range.clone()].to_vec(),
+            values: self.values[range].to_vec(),
+            in_order: self.in_order,
+        }
+    }
+
+    /// The values from `from` up to and including `to`, in order.
+    pub fn between(&self, from: i64, to: i64) -> Columns {
+        if self.in_order {
+            return self.slice(self.range(from, to));
+        }
+        let mut between = self
+            .iter()
+            .filter(|datum| datum.timeStamp >= from && datum.timeStamp <= to)
+            .collect::<Columns>();
+        between.sort();
+        between
+    }
+
+    /// Replaces every value, like to convert them to another unit.
+    pub fn map_values(&mut self, f: impl Fn(f64) -> f64) {
+        self.values.iter_mut().for_each(|value| *value = f(*value));
+    }
+
+    /// Sorts by time stamp, values with the same time stamp keep their order.
+    pub fn sort(&mut self) {
+        if self.in_order {
+            return;
+        }
+        let mut order = (0..self.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| self.time_stamps[*index]);
+        self.time_stamps = order.iter().map(|index| self.time_stamps[*index]).collect();
+        self.values = order.iter().map(|index| self.values[*index]).collect();
+        self.in_order = true;
+    }
+}
+
+impl Extend<Datum> for Columns {
+    fn extend<I: IntoIterator<Item = Datum>>(&mut self, data: I) {
+        for datum in data {
+            self.push(datum);
+        }
+    }
+}
+
+impl FromIterator<Datum> for Columns {
+    fn from_iter<I: IntoIterator<Item = Datum>>(data: I) -> Columns {
+        let data = data.into_iter();
+        let mut columns = Columns::with_capacity(data.size_hint().0);
+        columns.extend(data);
+        columns
+    }
+}
+
+impl From<Vec<Datum>> for Columns {
+    fn from(data: Vec<Datum>) -> Columns {
+        data.into_iter().collect()
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use toml::Value;
+
+/// The configuration file that was read, it is read again on reload.
+pub struct Config {
+    pub file: PathBuf,
+    /// The variables that were set before the file was read.
+    environment: HashSet<String>,
+    /// The variables the file set, the ones it no longer sets are removed
+    /// on reload.
+    from_file: Mutex<Vec<String>>,
+}
+
+/// Settings from the configuration file, `STS_RS_CONFIG` or else
+/// `~/.config/sts-rs/config.toml`. Every key stands for the environment
+/// variable of the same name, so
+///
+/// ```toml
+/// data_path = "/var/lib/sts-rs"
+///
+/// [tls]
+/// key = "/etc/sts-rs/key.pem"
+/// ```
+///
+/// sets `STS_RS_DATA_PATH` and `STS_RS_TLS_KEY`. Lists become comma
+/// separated values. Variables that are set already are kept, so the
+/// environment overrides the file.
+pub fn load() -> Result<Option<Config>, String> {
+    let (file, required) = match std::env::var_os("STS_RS_CONFIG") {
+        Some(file) => (PathBuf::from(file), true),
+        None => match dirs::config_dir() {
+            Some(directory) => (directory.join("sts-rs").join("config.toml"), false),
+            None => return Ok(None),
+        },
+    };
+    if !required && !file.exists() {
+        return Ok(None);
+    }
+    let config = Config {
+        file,
+        environment: std::env::vars_os()
+            .filter_map(|(variable, _)| variable.into_string().ok())
+            .collect(),
+        from_file: Mutex::new(Vec::new()),
+    };
+    config.reload()?;
+    Ok(Some(config))
+}
+
+impl Config {
+    /// Sets the variables of the file again. Nothing changes when the file
+    /// can not be read.
+    pub fn reload(&self) -> Result<(), String> {
+        let file = &self.file;
+        let contents = std::fs::read_to_string(file)
+            .map_err(|err| format!("Could not read {:?}, {}", file, err))?;
+        let settings = contents
+            .parse::<Value>()
+            .map_err(|err| format!("{:?} is not valid TOML, {}", file, err))?;
+        let mut variables = Vec::new();
+        collect("STS_RS", &settings, &mut variables)
+            .map_err(|err| format!("{:?} has {}", file, err))?;
+        let mut from_file = self.from_file.lock().unwrap();
+        for variable in from_file.drain(..) {
+            std::env::remove_var(variable);
+        }
+        for (variable, value) in variables {
+            if !self.environment.contains(&variable) {
+                std::env::set_var(&variable, value);
+                from_file.push(variable);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect(
+    prefix: &str,
+    value: &Value,
+    variables: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                let name = format!("{}_{}", prefix, key.to_uppercase().replace('-', "_"));
+                collect(&name, value, variables)?;
+            }
+        }
+        Value::Array(values) => {
+            let values = values
+                .iter()
+                .map(|value| scalar(prefix, value))
+                .collect::<Result<Vec<_>, _>>()?;
+            variables.push((prefix.to_owned(), values.join(",")));
+        }
+        value => variables.push((prefix.to_owned(), scalar(prefix, value)?)),
+    }
+    Ok(())
+}
+
+fn scalar(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        Value::Datetime(value) => Ok(value.to_string()),
+        _ => Err(format!("a nested list or table for {}", name)),
+    }
+}
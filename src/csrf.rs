@@ -0,0 +1,75 @@
+use actix_session::{Session, UserSession};
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, Method};
+use subtle::ConstantTimeEq;
+
+/// The header scripts send the token of their page in.
+const TOKEN_HEADER: &str = "X-Csrf-Token";
+
+/// Browsers tell where a request comes from, so a page on another site can
+/// not make the browser of a visitor change series. Clients other than
+/// browsers send neither header and are let through.
+pub fn is_cross_site(req: &ServiceRequest) -> bool {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        return false;
+    }
+    let headers = req.headers();
+    if let Some(site) = headers.get("Sec-Fetch-Site") {
+        return !matches!(site.to_str(), Ok("same-origin") | Ok("none"));
+    }
+    match headers.get(header::ORIGIN).map(|origin| origin.to_str()) {
+        Some(Ok(origin)) => match origin.split_once("://") {
+            Some((_, host)) => host != req.connection_info().host(),
+            None => true,
+        },
+        Some(Err(_)) => true,
+        None => false,
+    }
+}
+
+/// The token of the session, created on first use, that the pages put in
+/// their forms and send along with the changes they make.
+pub fn token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>("csrf") {
+        return token;
+    }
+    let mut bytes = [0; 32];
+    getrandom::getrandom(&mut bytes).unwrap();
+    let token = hex::encode(bytes);
+    if let Err(err) = session.set("csrf", &token) {
+        warn!("Could not store the CSRF token, {}", err);
+    }
+    token
+}
+
+pub fn token_matches(session: &Session, token: &str) -> bool {
+    match session.get::<String>("csrf") {
+        Ok(Some(expected)) => {
+            expected.len() == token.len() && bool::from(expected.as_bytes().ct_eq(token.as_bytes()))
+        }
+        _ => false,
+    }
+}
+
+/// Changes that rely on the sign in cookie need the token of the session,
+/// the cookie is sent along whichever page makes the request. API keys and
+/// passwords in headers are not, so those requests need no token. The sign
+/// in form checks the token itself.
+pub fn lacks_token(req: &ServiceRequest) -> bool {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) || req.path() == "/login"
+    {
+        return false;
+    }
+    let headers = req.headers();
+    if headers.contains_key(header::AUTHORIZATION) || headers.contains_key("X-Api-Key") {
+        return false;
+    }
+    let session = req.get_session();
+    if !matches!(session.get::<String>("user"), Ok(Some(_))) {
+        return false;
+    }
+    match headers.get(TOKEN_HEADER).map(|token| token.to_str()) {
+        Some(Ok(token)) => !token_matches(&session, token),
+        _ => true,
+    }
+}
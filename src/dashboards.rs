@@ -0,0 +1,124 @@
+use crate::plot::Window;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A named selection of series shown together, stored as
+/// `<data>/.dashboards/<slug>.json`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Dashboard {
+    pub title: String,
+    pub series: Vec<String>,
+    /// Number of plots per row, as many as fit when not set.
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// Defaults to the window of the pre-rendered plots.
+    #[serde(default)]
+    pub window: Option<Window>,
+    /// Seconds between reloads, 0 does not reload.
+    #[serde(default)]
+    pub refresh: Option<u32>,
+}
+
+impl Dashboard {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("a dashboard should have a title".to_owned());
+        }
+        if self.series.is_empty() {
+            return Err("a dashboard should show one or more series".to_owned());
+        }
+        match self.columns {
+            Some(columns) if !(1..=12).contains(&columns) => {
+                Err("columns should be between 1 and 12".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Slugs name dashboards in urls and files, so they are limited to lower
+/// case letters, digits and dashes.
+pub fn validate_slug(slug: &str) -> Result<(), String> {
+    if slug.is_empty()
+        || slug.len() > 64
+        || !slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(format!(
+            "{} should be at most 64 lower case letters, digits and dashes",
+            slug
+        ));
+    }
+    Ok(())
+}
+
+pub struct Dashboards {
+    directory: PathBuf,
+}
+
+impl Dashboards {
+    pub fn new(data_path: &Path) -> Dashboards {
+        Dashboards {
+            directory: data_path.join(".dashboards"),
+        }
+    }
+
+    fn file(&self, slug: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", slug))
+    }
+
+    /// The slugs and dashboards, ordered by slug.
+    pub fn list(&self) -> Vec<(String, Dashboard)> {
+        let mut dashboards = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|entry| {
+                    let slug = entry.file_name().into_string().ok()?;
+                    let slug = slug.strip_suffix(".json")?.to_owned();
+                    let dashboard = self.get(&slug)?;
+                    Some((slug, dashboard))
+                })
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        dashboards.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        dashboards
+    }
+
+    pub fn get(&self, slug: &str) -> Option<Dashboard> {
+        let file = File::open(self.file(slug)).ok()?;
+        serde_json::from_reader(file)
+            .map_err(|err| warn!("Ignoring unreadable dashboard {}, {}", slug, err))
+            .ok()
+    }
+
+    pub fn save(&self, slug: &str, dashboard: &Dashboard) -> io::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        let file = File::create(self.file(slug))?;
+        serde_json::to_writer_pretty(file, dashboard).map_err(io::Error::from)
+    }
+
+    pub fn delete(&self, slug: &str) -> io::Result<()> {
+        std::fs::remove_file(self.file(slug))
+    }
+
+    /// Follows a renamed series on the dashboards showing it.
+    pub fn rename_series(&self, series_name: &str, new_name: &str) -> io::Result<()> {
+        for (slug, mut dashboard) in self.list() {
+            if dashboard.series.iter().any(|name| name == series_name) {
+                for name in dashboard
+                    .series
+                    .iter_mut()
+                    .filter(|name| *name == series_name)
+                {
+                    *name = new_name.to_owned();
+                }
+                self.save(&slug, &dashboard)?;
+            }
+        }
+        Ok(())
+    }
+}
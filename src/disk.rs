@@ -0,0 +1,117 @@
+use crate::trash;
+use crate::{directory_size, env_or_default, format_bytes};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The space the data takes and what is left of its volume. A warning is
+/// raised when less than `STS_RS_DISK_WARNING_PERCENT` of the volume is
+/// free, 10 by default.
+pub struct Disk {
+    data_path: PathBuf,
+    warning_percent: f64,
+}
+
+/// What `/admin/disk` reports, sizes are in bytes.
+#[derive(Serialize)]
+pub struct DiskUsage {
+    /// The files of every series, its values, metadata and annotations.
+    pub series: BTreeMap<String, u64>,
+    /// Everything in the data directory, including the trash, dashboards
+    /// and cached plots.
+    pub data_bytes: u64,
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+    pub warning: Option<String>,
+}
+
+/// The summary in the footer of the index page.
+pub struct Summary {
+    pub data_bytes: u64,
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+    pub warning: Option<String>,
+}
+
+impl Disk {
+    pub fn from_env(data_path: &Path) -> Disk {
+        let warning_percent = env_or_default("STS_RS_DISK_WARNING_PERCENT", "10")
+            .parse::<f64>()
+            .ok()
+            .filter(|percent| (0.0..=100.0).contains(percent))
+            .expect("STS_RS_DISK_WARNING_PERCENT should be a percentage from 0 to 100");
+        Disk {
+            data_path: data_path.to_path_buf(),
+            warning_percent,
+        }
+    }
+
+    pub fn usage<'a>(&self, series: impl Iterator<Item = &'a str>) -> io::Result<DiskUsage> {
+        let summary = self.summary()?;
+        let series = series
+            .map(|name| {
+                let bytes = trash::series_files(&self.data_path, name)
+                    .iter()
+                    .filter_map(|file| file.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .sum();
+                (name.to_owned(), bytes)
+            })
+            .collect();
+        Ok(DiskUsage {
+            series,
+            data_bytes: summary.data_bytes,
+            free_bytes: summary.free_bytes,
+            total_bytes: summary.total_bytes,
+            warning: summary.warning,
+        })
+    }
+
+    pub fn summary(&self) -> io::Result<Summary> {
+        let (free_bytes, total_bytes) = self.space()?;
+        Ok(Summary {
+            data_bytes: directory_size(&self.data_path),
+            free_bytes,
+            total_bytes,
+            warning: self.warning(free_bytes, total_bytes),
+        })
+    }
+
+    /// Only looks at the free space, so it is cheap enough for every
+    /// readiness probe.
+    pub fn check(&self) -> Result<(), String> {
+        let (free_bytes, total_bytes) = self
+            .space()
+            .map_err(|err| format!("the free space is unknown, {}", err))?;
+        match self.warning(free_bytes, total_bytes) {
+            Some(warning) => Err(warning),
+            None => Ok(()),
+        }
+    }
+
+    /// The bytes free and in total on the volume.
+    pub fn space(&self) -> io::Result<(u64, u64)> {
+        Ok((
+            fs2::available_space(&self.data_path)?,
+            fs2::total_space(&self.data_path)?,
+        ))
+    }
+
+    fn warning(&self, free_bytes: u64, total_bytes: u64) -> Option<String> {
+        if total_bytes == 0 {
+            return None;
+        }
+        let free_percent = free_bytes as f64 * 100.0 / total_bytes as f64;
+        if free_percent >= self.warning_percent {
+            return None;
+        }
+        Some(format!(
+            "only {:.1}% of the volume of {:?} is free, {} of {}",
+            free_percent,
+            self.data_path,
+            format_bytes(free_bytes),
+            format_bytes(total_bytes)
+        ))
+    }
+}
@@ -0,0 +1,319 @@
+//! Mails about alerts, to the addresses of their rules, and summaries of
+//! all series each day or week, through the server of `STS_RS_SMTP_HOST`.
+
+use crate::alerts::{Alert, AlertState, Alerts};
+use crate::error::Unpoisoned;
+use crate::plot::Snapshot;
+use crate::smtp::{self, Attachment, Mail, Smtp};
+use crate::stats;
+use crate::{env_or_default, show_value_with_unit, Series, SeriesMap};
+use actix::prelude::*;
+use actix_web::web;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::sync::Arc;
+
+/// Summaries attach plots of this many series at most.
+const MAX_SUMMARY_PLOTS: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Daily => "day",
+            Period::Weekly => "week",
+        }
+    }
+}
+
+/// An alert that fired or was resolved, for the addresses of its rule.
+pub struct AlertMail {
+    pub alert: Alert,
+    pub to: Vec<String>,
+}
+
+impl Message for AlertMail {
+    type Result = ();
+}
+
+pub struct Mailer {
+    smtp: Arc<Smtp>,
+    series: SeriesMap,
+    alerts: Arc<Alerts>,
+    /// Summaries are sent at midnight, weekly ones on Monday.
+    summary: Option<(Period, Vec<String>)>,
+    timezone: Tz,
+}
+
+impl Mailer {
+    pub fn new(smtp: Smtp, series: SeriesMap, alerts: Arc<Alerts>, timezone: Tz) -> Mailer {
+        let period = match env_or_default("STS_RS_SMTP_SUMMARY", "").as_str() {
+            "" => None,
+            "daily" => Some(Period::Daily),
+            "weekly" => Some(Period::Weekly),
+            other => panic!(
+                "STS_RS_SMTP_SUMMARY should be daily or weekly, not {}",
+                other
+            ),
+        };
+        let summary = period.map(|period| {
+            let to = addresses("STS_RS_SMTP_SUMMARY_TO");
+            if to.is_empty() {
+                panic!("STS_RS_SMTP_SUMMARY needs the addresses of STS_RS_SMTP_SUMMARY_TO");
+            }
+            (period, to)
+        });
+        Mailer {
+            smtp: Arc::new(smtp),
+            series,
+            alerts,
+            summary,
+            timezone,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.summary {
+            Some((period, to)) => format!(
+                "Mailing through {}, a summary each {} to {}",
+                self.smtp.describe(),
+                period.name(),
+                to.join(", ")
+            ),
+            None => format!("Mailing through {}", self.smtp.describe()),
+        }
+    }
+
+    /// Renders the plots and sends the mail on a blocking thread.
+    fn send(&self, mut mail: Mail, plots: Vec<Snapshot>, ctx: &mut Context<Self>) {
+        let smtp = self.smtp.clone();
+        let subject = mail.subject.clone();
+        let timezone = self.timezone;
+        let sent = web::block(move || {
+            mail.attachments
+                .extend(plots.iter().filter_map(|plot| attachment(plot, timezone)));
+            smtp.send(&mail)
+        });
+        ctx.spawn(
+            actix::fut::wrap_future(sent).map(move |sent, _: &mut Mailer, _| match sent {
+                Ok(()) => info!("Mailed {}.", subject),
+                Err(err) => warn!("Mailing {} failed, {}", subject, err),
+            }),
+        );
+    }
+
+    fn schedule_summary(&self, ctx: &mut Context<Self>) {
+        let period = match &self.summary {
+            Some((period, _)) => *period,
+            None => return,
+        };
+        let now = Utc::now();
+        let today = now.with_timezone(&self.timezone).naive_local().date();
+        let days = match period {
+            Period::Daily => 1,
+            Period::Weekly => 7 - i64::from(today.weekday().num_days_from_monday()),
+        };
+        let midnight = (today + Duration::days(days)).and_hms(0, 0, 0);
+        let next = self
+            .timezone
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map_or(now + Duration::days(days), |next| next.with_timezone(&Utc));
+        let delay = (next - now).to_std().unwrap_or_default();
+        ctx.run_later(delay, move |act, ctx| {
+            let length = match period {
+                Period::Daily => Duration::days(1),
+                Period::Weekly => Duration::weeks(1),
+            };
+            let (mail, plots) = act.summary(next - length, next);
+            act.send(mail, plots, ctx);
+            act.schedule_summary(ctx);
+        });
+    }
+
+    /// The latest value and the statistics of every series over the period,
+    /// with the series that changed most and the firing alerts first.
+    /// Archived and private series are left out.
+    fn summary(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> (Mail, Vec<Snapshot>) {
+        let (period, recipients) = self.summary.as_ref().unwrap();
+        let (start, end) = (from.timestamp(), to.timestamp());
+        let mut rows = Vec::new();
+        let mut notable = Vec::new();
+        let mut plots = Vec::new();
+        let series = self.series.read().unpoisoned();
+        let mut names = series.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let serie = Series::lock(&series[name]);
+            if serie.meta.archived || serie.meta.private {
+                continue;
+            }
+            let data = serie.data.between(start, end);
+            let show = |value: f64| show_value_with_unit(value, &serie.meta);
+            let summary = match stats::summarize(&data) {
+                Some(summary) => summary,
+                None => {
+                    rows.push(format!("{}: no values", name));
+                    continue;
+                }
+            };
+            let (first, last) = (data.first().unwrap(), data.last().unwrap());
+            rows.push(format!(
+                "{}: {} latest, {} to {}, {} on average, {} values",
+                name,
+                show(last.value),
+                show(summary.min),
+                show(summary.max),
+                show(summary.mean),
+                summary.count
+            ));
+            // Changes well beyond the spread of the values stand out.
+            let change = last.value - first.value;
+            if summary.std_dev > 0.0 && change.abs() > 2.0 * summary.std_dev {
+                notable.push(format!(
+                    "{}: {} to {}, {} {}",
+                    name,
+                    show(first.value),
+                    show(last.value),
+                    if change > 0.0 { "up" } else { "down" },
+                    show(change.abs())
+                ));
+            }
+            if plots.len() < MAX_SUMMARY_PLOTS {
+                plots.extend(Snapshot::of(name, &serie, (start, end)));
+            }
+        }
+        let firing = self
+            .alerts
+            .list()
+            .into_iter()
+            .filter(|alert| alert.state == AlertState::Firing)
+            .filter(|alert| {
+                series
+                    .get(&alert.series)
+                    .is_some_and(|serie| !Series::lock(serie).meta.private)
+            })
+            .map(|alert| {
+                format!(
+                    "{}: {} since {}",
+                    alert.series,
+                    alert.rule,
+                    self.time(alert.since)
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut text = format!(
+            "Summary of the {} from {} to {}.\n",
+            period.name(),
+            self.time(start),
+            self.time(end)
+        );
+        for (title, lines) in [
+            ("Notable changes", &notable),
+            ("Firing alerts", &firing),
+            ("Series", &rows),
+        ] {
+            if !lines.is_empty() {
+                text.push_str(&format!("\n{}\n\n{}\n", title, lines.join("\n")));
+            }
+        }
+        let mail = Mail {
+            to: recipients.clone(),
+            subject: format!("Summary of the {} to {}", period.name(), self.time(end)),
+            text,
+            attachments: Vec::new(),
+        };
+        (mail, plots)
+    }
+
+    fn time(&self, time_stamp: i64) -> String {
+        match self.timezone.timestamp_opt(time_stamp, 0).single() {
+            Some(time) => time.format("%Y-%m-%d %H:%M %Z").to_string(),
+            None => time_stamp.to_string(),
+        }
+    }
+}
+
+impl Actor for Mailer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.schedule_summary(ctx);
+    }
+}
+
+impl Handler<AlertMail> for Mailer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AlertMail, ctx: &mut Context<Self>) {
+        let alert = msg.alert;
+        let (subject, happened) = match alert.state {
+            AlertState::Firing => (
+                format!("Alert {} of {} fires", alert.rule, alert.series),
+                "fired",
+            ),
+            AlertState::Ok => (
+                format!("Alert {} of {} is resolved", alert.rule, alert.series),
+                "was resolved",
+            ),
+        };
+        let mut text = format!(
+            "Alert {} of series {} {} at {}.\n",
+            alert.rule,
+            alert.series,
+            happened,
+            self.time(alert.since)
+        );
+        let mut plots = Vec::new();
+        if let Some(serie) = self.series.read().unpoisoned().get(&alert.series) {
+            let serie = Series::lock(serie);
+            if let Some(value) = alert.value {
+                text.push_str(&format!(
+                    "The latest value is {}.\n",
+                    show_value_with_unit(value, &serie.meta)
+                ));
+            }
+            plots.extend(alert.snapshot(&serie));
+        }
+        let mail = Mail {
+            to: msg.to,
+            subject,
+            text,
+            attachments: Vec::new(),
+        };
+        self.send(mail, plots, ctx);
+    }
+}
+
+/// A PNG of the plot, none when it does not render.
+fn attachment(plot: &Snapshot, timezone: Tz) -> Option<Attachment> {
+    match plot.render_png(timezone) {
+        Ok(data) => Some(Attachment {
+            file_name: format!("{}.png", plot.name.replace('/', "-")),
+            content_type: "image/png",
+            data,
+        }),
+        Err(err) => {
+            warn!("Plotting series {} for a mail failed, {}", plot.name, err);
+            None
+        }
+    }
+}
+
+/// A comma separated list of addresses.
+fn addresses(key: &str) -> Vec<String> {
+    env_or_default(key, "")
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            smtp::validate_address(address).unwrap_or_else(|err| panic!("{} in {}", err, key));
+            address.to_owned()
+        })
+        .collect()
+}
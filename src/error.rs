@@ -0,0 +1,229 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{LockResult, PoisonError};
+
+/// What went wrong handling a request or storing values. Handlers return
+/// it to answer with its status code and a JSON body like
+/// `{"error": {"code": "invalid", "message": "...", "field": "value"}}`,
+/// `field` is the part of the request that is wrong or `null`. Server
+/// errors are logged with their context and answered without it.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{message}")]
+    Invalid {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    Unprocessable {
+        field: Option<String>,
+        message: String,
+    },
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    TooLarge(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// A series reached its size limit.
+    #[error("{0}")]
+    Full(String),
+    #[error("{context}, {source}")]
+    Storage {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("row {row} of {file:?} is not a time stamp and a value, {reason}")]
+    MalformedRow {
+        file: PathBuf,
+        row: usize,
+        reason: String,
+    },
+    /// A server this one relies on failed, like cold storage or the sign
+    /// in provider.
+    #[error("{0}")]
+    Upstream(String),
+    #[error("the background actor is not running, {0}")]
+    Actor(#[from] actix::MailboxError),
+    #[error("rendering a page failed, {0}")]
+    Render(#[from] askama::Error),
+    /// Anything else the server could not do, with what it was doing.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl Error {
+    /// A request that can not be right, like a value that does not parse.
+    pub fn invalid(message: impl Into<String>) -> Error {
+        Error::Invalid {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    /// A request that is well-formed but not accepted, like one beyond the
+    /// request limits.
+    pub fn unprocessable(message: impl Into<String>) -> Error {
+        Error::Unprocessable {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    /// Tells which field of the body or query parameter is wrong.
+    pub fn for_field(self, name: &str) -> Error {
+        match self {
+            Error::Invalid { message, .. } => Error::Invalid {
+                field: Some(name.to_owned()),
+                message,
+            },
+            Error::Unprocessable { message, .. } => Error::Unprocessable {
+                field: Some(name.to_owned()),
+                message,
+            },
+            other => other,
+        }
+    }
+
+    /// A body or query that does not deserialize, serde names the field
+    /// when one is missing, unknown or repeated.
+    pub fn deserialize(err: impl std::fmt::Display) -> Error {
+        let message = err.to_string();
+        let field = ["missing field `", "unknown field `", "duplicate field `"]
+            .iter()
+            .find_map(|prefix| message.get(message.find(prefix)? + prefix.len()..))
+            .and_then(|rest| rest.split('`').next())
+            .map(str::to_owned);
+        Error::Invalid { field, message }
+    }
+
+    fn field(&self) -> Option<&str> {
+        match self {
+            Error::Invalid { field, .. } | Error::Unprocessable { field, .. } => field.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// A failed file operation, missing and clashing files are the fault of
+    /// the request.
+    pub fn io(context: String, source: io::Error) -> Error {
+        match source.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(source.to_string()),
+            io::ErrorKind::AlreadyExists | io::ErrorKind::InvalidInput => {
+                Error::Conflict(source.to_string())
+            }
+            _ => Error::Storage { context, source },
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Error::Invalid { .. } => "invalid",
+            Error::Unprocessable { .. } => "unprocessable",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::TooLarge(_) => "too_large",
+            Error::UnsupportedMediaType(_) => "unsupported_media_type",
+            Error::TooManyRequests(_) => "too_many_requests",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::Full(_) => "storage_full",
+            Error::Storage { source, .. } if source.kind() == io::ErrorKind::StorageFull => {
+                "storage_full"
+            }
+            Error::Storage { .. } | Error::MalformedRow { .. } => "storage",
+            Error::Upstream(_) => "upstream",
+            Error::Actor(_) => "unavailable",
+            Error::Render(_) | Error::Internal(_) => "internal",
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Invalid { .. } => StatusCode::BAD_REQUEST,
+            Error::Unprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::QuotaExceeded(_) => StatusCode::FORBIDDEN,
+            Error::Full(_) => StatusCode::INSUFFICIENT_STORAGE,
+            Error::Storage { source, .. } if source.kind() == io::ErrorKind::StorageFull => {
+                StatusCode::INSUFFICIENT_STORAGE
+            }
+            Error::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Error::Actor(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Storage { .. }
+            | Error::MalformedRow { .. }
+            | Error::Render(_)
+            | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // Reaching a size limit and which upstream failed are told, other
+        // server errors hide what failed.
+        let message =
+            if status.is_server_error() && !matches!(self, Error::Full(_) | Error::Upstream(_)) {
+                warn!("{}", self);
+                status.canonical_reason().unwrap_or_default().to_lowercase()
+            } else {
+                self.to_string()
+            };
+        HttpResponse::build(status).json(serde_json::json!({
+            "error": { "code": self.code(), "message": message, "field": self.field() }
+        }))
+    }
+}
+
+impl From<Error> for HttpResponse {
+    fn from(err: Error) -> HttpResponse {
+        err.error_response()
+    }
+}
+
+/// Adds what was being done to I/O errors.
+pub trait Context<T> {
+    fn context(self, context: impl FnOnce() -> String) -> Result<T, Error>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, context: impl FnOnce() -> String) -> Result<T, Error> {
+        self.map_err(|source| Error::Storage {
+            context: context(),
+            source,
+        })
+    }
+}
+
+/// Locks are used again after a thread panicked while holding one. Series
+/// change a whole value at a time, so what the thread left is consistent.
+pub trait Unpoisoned<T> {
+    fn unpoisoned(self) -> T;
+}
+
+impl<T> Unpoisoned<T> for LockResult<T> {
+    fn unpoisoned(self) -> T {
+        self.unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::auth::Caller;
+use crate::cold_storage::ColdPartition;
+use crate::columns::Columns;
+use crate::error::{Context as _, Error, Unpoisoned};
+use crate::metadata;
+use crate::units::Conversion;
+use crate::{series_data, to_csv, AppState, DataFormat, Series};
+use actix_web::{web, HttpResponse};
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+/// Values encoded into one chunk of the response.
+const CHUNK: usize = 4096;
+
+/// The values of a series between `from` and `to` as they are sent, a
+/// cold partition or a chunk of the values in memory at a time, so memory
+/// stays flat however long the series is.
+struct Export {
+    state: web::Data<AppState>,
+    series_name: String,
+    from: i64,
+    to: i64,
+    format: DataFormat,
+    /// Converts the values, rounded to the precision of the series.
+    conversion: Option<(Conversion, Option<u32>)>,
+    cold: VecDeque<ColdPartition>,
+    hot: Hot,
+    started: bool,
+    finished: bool,
+}
+
+enum Hot {
+    /// The values in memory are in order, the next chunk starts at this
+    /// time stamp. Values rotated out or added meanwhile are no problem.
+    From(i64),
+    /// Values that were added out of order are sorted in a copy first,
+    /// like the values for plots, the next chunk starts at this index.
+    Sorted(Columns, usize),
+    Done,
+}
+
+/// Streams `GET /api/v1/series/{name}/data` as JSON or CSV. The response
+/// starts before the cold partitions are fetched, a partition that can not
+/// be fetched ends it early. With a `unit` the values are converted to it.
+/// Private series the caller may not read are not found.
+pub async fn response(
+    state: web::Data<AppState>,
+    caller: Caller,
+    series_name: String,
+    (from, to): (Option<i64>, Option<i64>),
+    format: DataFormat,
+    unit: Option<String>,
+) -> HttpResponse {
+    let (start, end) = (from.unwrap_or(i64::MIN), to.unwrap_or(i64::MAX));
+    let mut conversion = None;
+    let in_order = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => {
+            if let Some(unit) = &unit {
+                match Conversion::new(serie.meta.unit.as_deref(), unit) {
+                    Ok(converted) => conversion = Some((converted, serie.meta.precision)),
+                    Err(err) => return Error::invalid(err).for_field("unit").into(),
+                }
+            }
+            let cold = overlapping(&state, &serie.cold_partitions, start, end);
+            let after_cold = cold.iter().map(|partition| partition.last).max();
+            let in_order = serie.data.in_order();
+            let first_hot = in_order
+                .then(|| serie.data.chunk(start, end, 1).first())
+                .flatten()
+                .map(|datum| datum.timeStamp);
+            match (in_order, first_hot, after_cold) {
+                (false, _, _) => None,
+                (true, Some(first), Some(last)) if first < last => None,
+                (true, _, _) => Some(cold),
+            }
+        }
+        None => return Error::NotFound(format!("no series {}", series_name)).into(),
+    };
+    let (cold, hot) = match in_order {
+        Some(cold) => (cold, Hot::From(start)),
+        None => match series_data(&state, &series_name, from, to).await {
+            Ok(data) => (VecDeque::new(), Hot::Sorted(data, 0)),
+            Err(response) => return response,
+        },
+    };
+    let export = Export {
+        state,
+        series_name,
+        from: start,
+        to: end,
+        format,
+        conversion,
+        cold,
+        hot,
+        started: false,
+        finished: false,
+    };
+    let mut response = HttpResponse::Ok();
+    match format {
+        DataFormat::Json => response.content_type("application/json"),
+        DataFormat::Csv => response.content_type("text/csv").header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.csv\"", export.series_name),
+        ),
+    };
+    response.streaming(futures::stream::unfold(export, |mut export| async move {
+        let chunk = export.next_chunk().await?;
+        Some((chunk, export))
+    }))
+}
+
+fn overlapping(
+    state: &AppState,
+    partitions: &[ColdPartition],
+    from: i64,
+    to: i64,
+) -> VecDeque<ColdPartition> {
+    match state.cold_storage {
+        Some(_) => partitions
+            .iter()
+            .filter(|partition| partition.overlaps(from, to))
+            .cloned()
+            .collect(),
+        None => VecDeque::new(),
+    }
+}
+
+impl Export {
+    async fn next_chunk(&mut self) -> Option<Result<Bytes, actix_web::Error>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let data = match self.cold.pop_front() {
+                Some(partition) => match self.fetch(partition).await {
+                    Ok(data) => data,
+                    Err(err) => {
+                        self.finished = true;
+                        return Some(Err(err));
+                    }
+                },
+                None => self.hot_chunk(),
+            };
+            if !data.is_empty() {
+                let encoded = self.encode(data);
+                self.finished = encoded.is_err();
+                return Some(encoded);
+            }
+            if self.cold.is_empty() && matches!(self.hot, Hot::Done) {
+                self.finished = true;
+                return match (self.format, self.started) {
+                    (DataFormat::Json, true) => Some(Ok(Bytes::from_static(b"]"))),
+                    (DataFormat::Json, false) => Some(Ok(Bytes::from_static(b"[]"))),
+                    (DataFormat::Csv, _) => None,
+                };
+            }
+        }
+    }
+
+    async fn fetch(&self, partition: ColdPartition) -> Result<Columns, actix_web::Error> {
+        let cold_storage = self.state.cold_storage.as_ref().unwrap();
+        match cold_storage.fetch(&self.series_name, &[partition]).await {
+            Ok(data) => Ok(data
+                .into_iter()
+                .filter(|datum| datum.timeStamp >= self.from && datum.timeStamp <= self.to)
+                .collect()),
+            Err(err) => {
+                warn!(
+                    "Fetching cold data for series {} failed, {}",
+                    self.series_name, err
+                );
+                Err(Error::Upstream("cold storage is unavailable".to_owned()).into())
+            }
+        }
+    }
+
+    /// The next values in memory, all the values of the last time stamp in
+    /// the chunk are in it so the next chunk can start after that.
+    fn hot_chunk(&mut self) -> Columns {
+        let next = match &mut self.hot {
+            Hot::From(next) => *next,
+            Hot::Sorted(data, next) => {
+                let chunk = data.slice(*next..(*next + CHUNK).min(data.len()));
+                *next += chunk.len();
+                if chunk.is_empty() {
+                    self.hot = Hot::Done;
+                }
+                return chunk;
+            }
+            Hot::Done => return Columns::default(),
+        };
+        let to = self.to;
+        let chunk = match self
+            .state
+            .series
+            .read()
+            .unpoisoned()
+            .get(&self.series_name)
+            .map(Series::lock)
+        {
+            Some(serie) => serie.data.chunk(next, to, CHUNK),
+            None => Columns::default(),
+        };
+        self.hot = match chunk.last().and_then(|last| last.timeStamp.checked_add(1)) {
+            Some(next) if chunk.len() >= CHUNK => Hot::From(next),
+            _ => Hot::Done,
+        };
+        chunk
+    }
+
+    fn encode(&mut self, mut data: Columns) -> Result<Bytes, actix_web::Error> {
+        if let Some((conversion, precision)) = self.conversion {
+            data.map_values(|value| metadata::round(conversion.apply(value), precision));
+        }
+        match self.format {
+            DataFormat::Csv => to_csv(data.iter())
+                .map(Bytes::from)
+                .context(|| "encoding values as CSV failed".to_owned())
+                .map_err(actix_web::Error::from),
+            DataFormat::Json => {
+                let mut encoded = Vec::with_capacity(data.len() * 40);
+                for datum in data.iter() {
+                    encoded.push(if self.started { b',' } else { b'[' });
+                    serde_json::to_writer(&mut encoded, &datum)
+                        .map_err(std::io::Error::from)
+                        .context(|| "encoding values as JSON failed".to_owned())?;
+                    self.started = true;
+                }
+                Ok(Bytes::from(encoded))
+            }
+        }
+    }
+}
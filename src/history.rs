@@ -0,0 +1,361 @@
+use crate::columns::Columns;
+use crate::Datum;
+use std::iter::FromIterator;
+
+/// Values per block, full blocks are compressed.
+const BLOCK: usize = 1024;
+
+/// The values of a series in memory. Every full block of values is
+/// compressed like Gorilla does, time stamps as the difference between
+/// consecutive differences and values as the bits that differ from the
+/// value before, which takes some 10 times less memory for values measured
+/// at a regular interval. Queries and plots get the blocks in their range
+/// decompressed, the lowest and highest time stamp of every block tell
+/// which blocks that are.
+pub struct History {
+    sealed: Vec<Sealed>,
+    open: Columns,
+    len: usize,
+    last: Option<Datum>,
+    time_range: Option<(i64, i64)>,
+    in_order: bool,
+}
+
+/// A compressed block of values.
+struct Sealed {
+    min: i64,
+    max: i64,
+    count: usize,
+    bits: Box<[u64]>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            sealed: Vec::new(),
+            open: Columns::with_capacity(BLOCK),
+            len: 0,
+            last: None,
+            time_range: None,
+            in_order: true,
+        }
+    }
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether every time stamp is at least the one before it.
+    pub fn in_order(&self) -> bool {
+        self.in_order
+    }
+
+    pub fn push(&mut self, datum: Datum) {
+        self.in_order &= self
+            .last
+            .is_none_or(|last| last.timeStamp <= datum.timeStamp);
+        self.open.push(datum);
+        self.last = Some(datum);
+        self.time_range = Some(match self.time_range {
+            Some((first, last)) => (first.min(datum.timeStamp), last.max(datum.timeStamp)),
+            None => (datum.timeStamp, datum.timeStamp),
+        });
+        self.len += 1;
+        if self.open.len() == BLOCK {
+            let open = std::mem::replace(&mut self.open, Columns::with_capacity(BLOCK));
+            self.sealed.push(Sealed::compress(&open));
+        }
+    }
+
+    /// The lowest and the highest time stamp.
+    pub fn time_range(&self) -> Option<(i64, i64)> {
+        self.time_range
+    }
+
+    /// The value with the highest time stamp, the last one added of those.
+    pub fn latest(&self) -> Option<Datum> {
+        if self.in_order {
+            return self.last;
+        }
+        let sealed = self.sealed.iter().max_by_key(|sealed| sealed.max);
+        match (sealed, self.open.latest()) {
+            (Some(sealed), Some(open)) if open.timeStamp >= sealed.max => Some(open),
+            (Some(sealed), _) => sealed.decompress().latest(),
+            (None, open) => open,
+        }
+    }
+
+    /// All values in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = Datum> + '_ {
+        self.sealed
+            .iter()
+            .flat_map(|sealed| sealed.decompress().into_data())
+            .chain(self.open.iter())
+    }
+
+    /// The blocks with values from `from` up to and including `to`,
+    /// decompressed.
+    fn blocks(&self, from: i64, to: i64) -> impl Iterator<Item = Columns> + '_ {
+        // Blocks of values in order are in order themselves.
+        let first = match self.in_order {
+            true => self.sealed.partition_point(|sealed| sealed.max < from),
+            false => 0,
+        };
+        self.sealed[first..]
+            .iter()
+            .filter(move |sealed| sealed.max >= from && sealed.min <= to)
+            .map(Sealed::decompress)
+            .chain(std::iter::once(self.open.clone()))
+    }
+
+    /// The values from `from` up to and including `to`, in order.
+    pub fn between(&self, from: i64, to: i64) -> Columns {
+        let mut between = self.values_between(from, to).collect::<Columns>();
+        between.sort();
+        between
+    }
+
+    fn values_between(&self, from: i64, to: i64) -> impl Iterator<Item = Datum> + '_ {
+        self.blocks(from, to)
+            .flat_map(move |block| block.between(from, to).into_data())
+    }
+
+    /// The first `count` values from `from` up to and including `to`, in
+    /// order, and the rest of the values with the time stamp of the last
+    /// of those.
+    pub fn chunk(&self, from: i64, to: i64, count: usize) -> Columns {
+        // Blocks after the chunk are not decompressed.
+        match self.in_order {
+            true => first_values(self.values_between(from, to), count),
+            false => first_values(self.between(from, to).into_data(), count),
+        }
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(Datum) -> bool) {
+        *self = self.iter().filter(|datum| keep(*datum)).collect();
+    }
+}
+
+fn first_values(data: impl Iterator<Item = Datum>, count: usize) -> Columns {
+    let mut chunk = Columns::default();
+    for datum in data {
+        match chunk.last() {
+            Some(last) if chunk.len() >= count && last.timeStamp != datum.timeStamp => break,
+            _ => chunk.push(datum),
+        }
+    }
+    chunk
+}
+
+impl Extend<Datum> for History {
+    fn extend<I: IntoIterator<Item = Datum>>(&mut self, data: I) {
+        for datum in data {
+            self.push(datum);
+        }
+    }
+}
+
+impl FromIterator<Datum> for History {
+    fn from_iter<I: IntoIterator<Item = Datum>>(data: I) -> History {
+        let mut history = History::default();
+        history.extend(data);
+        history
+    }
+}
+
+impl From<Vec<Datum>> for History {
+    fn from(data: Vec<Datum>) -> History {
+        data.into_iter().collect()
+    }
+}
+
+impl Sealed {
+    fn compress(block: &Columns) -> Sealed {
+        let mut bits = BitWriter::default();
+        let (mut time_stamp, mut delta) = (0i64, 0i64);
+        let (mut value, mut window) = (0u64, None);
+        for (index, datum) in block.iter().enumerate() {
+            if index == 0 {
+                bits.write(datum.timeStamp as u64, 64);
+                bits.write(datum.value.to_bits(), 64);
+            } else {
+                let next_delta = datum.timeStamp.wrapping_sub(time_stamp);
+                write_delta_of_delta(&mut bits, next_delta.wrapping_sub(delta));
+                delta = next_delta;
+                window = write_xor(&mut bits, datum.value.to_bits() ^ value, window);
+            }
+            time_stamp = datum.timeStamp;
+            value = datum.value.to_bits();
+        }
+        Sealed {
+            min: *block.time_stamps().iter().min().unwrap(),
+            max: *block.time_stamps().iter().max().unwrap(),
+            count: block.len(),
+            bits: bits.words.into_boxed_slice(),
+        }
+    }
+
+    fn decompress(&self) -> Columns {
+        let mut bits = BitReader {
+            words: &self.bits,
+            position: 0,
+        };
+        let mut block = Columns::with_capacity(self.count);
+        let mut time_stamp = bits.read(64) as i64;
+        let mut value = bits.read(64);
+        let (mut delta, mut window) = (0i64, (0, 0));
+        for index in 0..self.count {
+            if index > 0 {
+                delta = delta.wrapping_add(read_delta_of_delta(&mut bits));
+                time_stamp = time_stamp.wrapping_add(delta);
+                value ^= read_xor(&mut bits, &mut window);
+            }
+            block.push(Datum {
+                timeStamp: time_stamp,
+                value: f64::from_bits(value),
+            });
+        }
+        block
+    }
+}
+
+/// The prefix, its size and the size of differences of differences that
+/// fit the size, larger ones take 64 bits after `1111`.
+const DELTA_OF_DELTA_SIZES: [(u64, u32, u32); 3] = [(0b10, 2, 7), (0b110, 3, 9), (0b1110, 4, 12)];
+
+fn write_delta_of_delta(bits: &mut BitWriter, delta_of_delta: i64) {
+    if delta_of_delta == 0 {
+        bits.write(0, 1);
+        return;
+    }
+    for (prefix, prefix_size, size) in DELTA_OF_DELTA_SIZES {
+        let limit = 1 << (size - 1);
+        if (-limit..limit).contains(&delta_of_delta) {
+            bits.write(prefix, prefix_size);
+            bits.write(delta_of_delta as u64, size);
+            return;
+        }
+    }
+    bits.write(0b1111, 4);
+    bits.write(delta_of_delta as u64, 64);
+}
+
+fn read_delta_of_delta(bits: &mut BitReader) -> i64 {
+    if bits.read(1) == 0 {
+        return 0;
+    }
+    for (_, _, size) in DELTA_OF_DELTA_SIZES {
+        if bits.read(1) == 0 {
+            return sign_extend(bits.read(size), size);
+        }
+    }
+    bits.read(64) as i64
+}
+
+/// Writes the bits that differ from the value before, within the window of
+/// meaningful bits of the value before when they fit. Returns the window of
+/// leading and meaningful bits for the next value.
+fn write_xor(bits: &mut BitWriter, xor: u64, window: Option<(u32, u32)>) -> Option<(u32, u32)> {
+    if xor == 0 {
+        bits.write(0, 1);
+        return window;
+    }
+    let leading = xor.leading_zeros().min(31);
+    let trailing = xor.trailing_zeros();
+    match window {
+        Some((window_leading, window_size))
+            if leading >= window_leading && trailing >= 64 - window_leading - window_size =>
+        {
+            bits.write(0b10, 2);
+            bits.write(xor >> (64 - window_leading - window_size), window_size);
+            window
+        }
+        _ => {
+            let size = 64 - leading - trailing;
+            bits.write(0b11, 2);
+            bits.write(leading as u64, 5);
+            bits.write(size as u64 % 64, 6);
+            bits.write(xor >> trailing, size);
+            Some((leading, size))
+        }
+    }
+}
+
+fn read_xor(bits: &mut BitReader, window: &mut (u32, u32)) -> u64 {
+    if bits.read(1) == 0 {
+        return 0;
+    }
+    if bits.read(1) == 1 {
+        let leading = bits.read(5) as u32;
+        let size = match bits.read(6) as u32 {
+            0 => 64,
+            size => size,
+        };
+        *window = (leading, size);
+    }
+    let (leading, size) = *window;
+    bits.read(size) << (64 - leading - size)
+}
+
+/// The number of `size` bits in `value` as a signed number.
+fn sign_extend(value: u64, size: u32) -> i64 {
+    let shift = 64 - size;
+    ((value << shift) as i64) >> shift
+}
+
+#[derive(Default)]
+struct BitWriter {
+    words: Vec<u64>,
+    position: usize,
+}
+
+impl BitWriter {
+    /// Writes the lowest `size` bits of `value`, the highest first.
+    fn write(&mut self, value: u64, size: u32) {
+        if size == 0 {
+            return;
+        }
+        let value = match size {
+            64 => value,
+            _ => value & ((1 << size) - 1),
+        };
+        let used = (self.position % 64) as u32;
+        if used == 0 {
+            self.words.push(0);
+        }
+        let free = 64 - used;
+        let last = self.words.last_mut().unwrap();
+        if size <= free {
+            *last |= value << (free - size);
+        } else {
+            *last |= value >> (size - free);
+            self.words.push(value << (64 - (size - free)));
+        }
+        self.position += size as usize;
+    }
+}
+
+struct BitReader<'a> {
+    words: &'a [u64],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, size: u32) -> u64 {
+        if size == 0 {
+            return 0;
+        }
+        let word = self.position / 64;
+        let used = (self.position % 64) as u32;
+        let both = ((self.words[word] as u128) << 64)
+            | self.words.get(word + 1).copied().unwrap_or(0) as u128;
+        self.position += size as usize;
+        ((both << used) >> (128 - size)) as u64
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::Error;
+use crate::{read_csv_data, rewrite_data_file, series_names, Datum};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::path::Path;
+use std::process::Command;
+
+pub enum Format {
+    Rrd,
+    Whisper,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "rrd" => Some(Format::Rrd),
+            "whisper" => Some(Format::Whisper),
+            _ => None,
+        }
+    }
+}
+
+/// Runs `sts-rs import --format rrd|whisper <files>...`, converting every
+/// archive into one or more series in `data_path`. Existing series are
+/// merged with the imported values. The server should not be running while
+/// importing.
+pub fn run(format: Format, files: &[&str], data_path: &Path) -> Result<(), String> {
+    for file in files {
+        let path = Path::new(file);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("cannot derive a series name from {}", file))?;
+        let series = match format {
+            Format::Rrd => read_rrd(path)?,
+            Format::Whisper => vec![(String::new(), read_whisper(path)?)],
+        };
+        let single = series.len() == 1;
+        for (data_source, data) in series {
+            let series_name = if single {
+                stem.to_owned()
+            } else {
+                format!("{}_{}", stem, data_source)
+            };
+            series_names::validate(&series_name)?;
+            let count = data.len();
+            merge_into_series(&series_names::data_file(data_path, &series_name), data)
+                .map_err(|err| err.to_string())?;
+            info!(
+                "Imported {} values from {} into series {}",
+                count, file, series_name
+            );
+        }
+    }
+    Ok(())
+}
+
+fn merge_into_series(data_file: &Path, imported: Vec<Datum>) -> Result<(), Error> {
+    let mut merged = BTreeMap::new();
+    if data_file.exists() {
+        let (existing, _) = read_csv_data(data_file)?;
+        merged.extend(existing.into_iter().map(|datum| (datum.timeStamp, datum)));
+    }
+    merged.extend(imported.into_iter().map(|datum| (datum.timeStamp, datum)));
+    rewrite_data_file(data_file, &merged.into_values().collect::<Vec<_>>())
+}
+
+/// Reads a Graphite whisper file. Archives are combined from fine to coarse,
+/// a coarser archive only contributes points older than the finer ones.
+fn read_whisper(path: &Path) -> Result<Vec<Datum>, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("reading {:?} failed, {}", path, err))?;
+    let u32_at = |offset: usize| -> Result<u32, String> {
+        bytes
+            .get(offset..offset + 4)
+            .map(|slice| u32::from_be_bytes(slice.try_into().unwrap()))
+            .ok_or_else(|| format!("{:?} is truncated", path))
+    };
+    let archive_count = u32_at(12)? as usize;
+    let mut archives = (0..archive_count)
+        .map(|index| {
+            let info = 16 + index * 12;
+            Ok((
+                u32_at(info)? as usize,
+                u32_at(info + 4)?,
+                u32_at(info + 8)? as usize,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    archives.sort_by_key(|(_, seconds_per_point, _)| *seconds_per_point);
+    let mut result: Vec<Datum> = Vec::new();
+    for (offset, _, points) in archives {
+        let oldest = result.first().map_or(i64::MAX, |datum| datum.timeStamp);
+        let mut archive = Vec::new();
+        for point in 0..points {
+            let start = offset + point * 12;
+            let interval = u32_at(start)? as i64;
+            let value = bytes
+                .get(start + 4..start + 12)
+                .map(|slice| f64::from_be_bytes(slice.try_into().unwrap()))
+                .ok_or_else(|| format!("{:?} is truncated", path))?;
+            if interval != 0 && interval < oldest && value.is_finite() {
+                archive.push(Datum {
+                    timeStamp: interval,
+                    value,
+                });
+            }
+        }
+        archive.append(&mut result);
+        result = archive;
+        result.sort_by_key(|datum| datum.timeStamp);
+        result.dedup_by_key(|datum| datum.timeStamp);
+    }
+    Ok(result)
+}
+
+/// Reads a RRDtool database by means of the `rrdtool` command, combining the
+/// AVERAGE archives from fine to coarse. Returns the values per data source.
+fn read_rrd(path: &Path) -> Result<Vec<(String, Vec<Datum>)>, String> {
+    let file = path.to_string_lossy();
+    let info = rrdtool(&["info", &file])?;
+    let value_of = |key: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(&format!("{} = ", key)))
+            .map(|value| value.trim_matches('"').to_owned())
+    };
+    let step = value_of("step")
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or_else(|| format!("{} has no step", file))?;
+    let last_update = value_of("last_update")
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or_else(|| format!("{} has no last_update", file))?;
+    let mut archives = Vec::new();
+    for index in 0.. {
+        let cf = match value_of(&format!("rra[{}].cf", index)) {
+            Some(cf) => cf,
+            None => break,
+        };
+        let pdp_per_row = value_of(&format!("rra[{}].pdp_per_row", index))
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(1);
+        let rows = value_of(&format!("rra[{}].rows", index))
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(0);
+        if cf == "AVERAGE" {
+            archives.push((step * pdp_per_row, rows));
+        }
+    }
+    archives.sort_unstable();
+    let mut result: BTreeMap<String, BTreeMap<i64, Datum>> = BTreeMap::new();
+    for (resolution, rows) in archives {
+        let end = last_update - last_update % resolution;
+        let start = end - rows * resolution;
+        let output = rrdtool(&[
+            "fetch",
+            &file,
+            "AVERAGE",
+            "-r",
+            &resolution.to_string(),
+            "-s",
+            &start.to_string(),
+            "-e",
+            &end.to_string(),
+        ])?;
+        let mut lines = output.lines();
+        let data_sources = lines
+            .next()
+            .map(|header| {
+                header
+                    .split_whitespace()
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let oldest = data_sources
+            .iter()
+            .map(|data_source| {
+                result
+                    .get(data_source)
+                    .and_then(|series| series.keys().next().copied())
+                    .unwrap_or(i64::MAX)
+            })
+            .collect::<Vec<_>>();
+        for line in lines {
+            let (time_stamp, values) = match line.split_once(':') {
+                Some((time_stamp, values)) => (time_stamp.trim().parse::<i64>(), values),
+                None => continue,
+            };
+            let time_stamp = match time_stamp {
+                Ok(time_stamp) => time_stamp,
+                Err(_) => continue,
+            };
+            for (index, value) in values.split_whitespace().enumerate() {
+                match (data_sources.get(index), value.parse::<f64>()) {
+                    (Some(data_source), Ok(value))
+                        if value.is_finite() && time_stamp < oldest[index] =>
+                    {
+                        result.entry(data_source.clone()).or_default().insert(
+                            time_stamp,
+                            Datum {
+                                timeStamp: time_stamp,
+                                value,
+                            },
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(result
+        .into_iter()
+        .map(|(data_source, data)| (data_source, data.into_values().collect()))
+        .collect())
+}
+
+fn rrdtool(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("rrdtool")
+        .args(args)
+        .output()
+        .map_err(|err| format!("running rrdtool failed, {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "rrdtool {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
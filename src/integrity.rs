@@ -0,0 +1,391 @@
+use crate::annotations::{self, Annotation};
+use crate::cold_storage::{self, ColdPartition};
+use crate::metadata::{self, SeriesMeta};
+use crate::namespaces::Namespaces;
+use crate::{archive, series_names, Datum};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Outcome of checking a single data file.
+pub struct IntegrityReport {
+    pub rows: usize,
+    pub problems: BTreeMap<&'static str, usize>,
+    pub quarantine_file: PathBuf,
+    /// Whether rows came before a row with a later time stamp, like values
+    /// added afterwards for an earlier time. They are sorted when parsed.
+    pub out_of_order: bool,
+}
+
+impl IntegrityReport {
+    pub fn quarantined(&self) -> usize {
+        self.problems.values().sum()
+    }
+
+    pub fn summary(&self) -> String {
+        self.problems
+            .iter()
+            .map(|(problem, count)| format!("{} {}", count, problem))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Parses `data_file`, moving rows that cannot be part of a series to
+/// `<series>.quarantine` so they can be inspected and fixed by hand. The data
+/// file is rewritten without those rows, rows that are not valid UTF-8
+/// included, so one damaged row does not cost the whole series.
+pub fn check_data_file(data_file: &Path) -> io::Result<(Vec<Datum>, IntegrityReport)> {
+    let contents = std::fs::read(data_file)?;
+    let (data, bad_lines, report) = parse(data_file, &contents);
+    if !bad_lines.is_empty() {
+        let mut quarantine = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&report.quarantine_file)?;
+        for (_, line) in &bad_lines {
+            quarantine.write_all(line)?;
+            quarantine.write_all(b"\n")?;
+        }
+        quarantine.sync_all()?;
+        let temporary = data_file.with_extension("csv.tmp");
+        let mut bad_rows = bad_lines.iter().map(|(row, _)| *row).peekable();
+        let mut cleaned = Vec::with_capacity(contents.len());
+        for (row, line) in rows(&contents).enumerate() {
+            if bad_rows.next_if_eq(&row).is_none() {
+                cleaned.extend_from_slice(line);
+                cleaned.push(b'\n');
+            }
+        }
+        std::fs::write(&temporary, cleaned)?;
+        std::fs::rename(&temporary, data_file)?;
+    }
+    Ok((data, report))
+}
+
+/// The rows that cannot be part of a series, with their index.
+type BadLines<'a> = Vec<(usize, &'a [u8])>;
+
+/// The values of the rows that can be part of a series, sorted by their
+/// time stamp, the other rows with their index, and what is wrong with
+/// them. Data files are mostly good, so only the bad rows are collected.
+fn parse<'a>(data_file: &Path, contents: &'a [u8]) -> (Vec<Datum>, BadLines<'a>, IntegrityReport) {
+    let mut report = IntegrityReport {
+        rows: 0,
+        problems: BTreeMap::new(),
+        quarantine_file: data_file.with_extension("quarantine"),
+        out_of_order: false,
+    };
+    let mut data = Vec::with_capacity(contents.iter().filter(|byte| **byte == b'\n').count());
+    let mut bad_lines = Vec::new();
+    for (row, line) in rows(contents).enumerate() {
+        report.rows += 1;
+        let parsed = std::str::from_utf8(line)
+            .map_err(|_| "rows that are not valid UTF-8")
+            .and_then(parse_row);
+        match parsed {
+            Ok(datum) => data.push(datum),
+            Err(problem) => {
+                *report.problems.entry(problem).or_insert(0) += 1;
+                bad_lines.push((row, line));
+            }
+        }
+    }
+    report.out_of_order = data
+        .windows(2)
+        .any(|pair| pair[0].timeStamp > pair[1].timeStamp);
+    if report.out_of_order {
+        // Stable, so values of the same time keep the order they came in.
+        data.sort_by_key(|datum| datum.timeStamp);
+    }
+    (data, bad_lines, report)
+}
+
+/// The lines of a data file without their line ending, leaving out blank
+/// ones. They are split as bytes, so a line that is not valid UTF-8 can be
+/// quarantined on its own.
+fn rows(contents: &[u8]) -> impl Iterator<Item = &[u8]> {
+    contents
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| std::str::from_utf8(line).map_or(true, |line| !line.trim().is_empty()))
+}
+
+/// Runs `sts-rs check`, reporting the data files that cannot be read, with
+/// rows that would be quarantined, that are not in time stamp order or that
+/// repeat the time stamp of the row before, metadata, annotations and cold
+/// storage indexes that do not parse, files that are not named after a valid
+/// series and plots of series that do not exist, without changing anything. Archived series and the series of namespaces
+/// are checked as well.
+pub fn check(data_path: &Path, image_path: &Path, namespaces: &Namespaces) -> Result<(), String> {
+    let mut problems = 0;
+    let mut stems = HashSet::new();
+    let mut data_files = Vec::new();
+    for (namespace, directory) in directories(data_path, namespaces) {
+        data_files.extend(
+            series_data_files(&directory)?
+                .into_iter()
+                .map(|data_file| (namespace, data_file)),
+        );
+    }
+    for (namespace, data_file) in data_files {
+        let name = match series_name(namespace, &data_file) {
+            Ok(name) => name,
+            Err(err) => {
+                println!("{:?}: {}", data_file, err);
+                problems += 1;
+                continue;
+            }
+        };
+        stems.insert(series_names::file_stem(&name));
+        let contents = match read_data(&data_file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                println!("{}: could not read the data, {}", name, err);
+                problems += 1;
+                continue;
+            }
+        };
+        let (data, _, report) = parse(&data_file, &contents);
+        let mut findings = Vec::new();
+        if report.quarantined() > 0 {
+            findings.push(format!(
+                "{} of {} rows would be quarantined, {}",
+                report.quarantined(),
+                report.rows,
+                report.summary()
+            ));
+        }
+        if report.out_of_order {
+            findings.push("rows are not in time stamp order".to_owned());
+        }
+        let repeated = data
+            .windows(2)
+            .filter(|pair| pair[0].timeStamp == pair[1].timeStamp)
+            .count();
+        if repeated > 0 {
+            findings.push(format!(
+                "{} rows repeat the time stamp of the row before",
+                repeated
+            ));
+        }
+        findings.extend(check_json(
+            &metadata::meta_file(&data_file),
+            |meta: &SeriesMeta| meta.validate(),
+        ));
+        findings.extend(check_json(
+            &annotations::annotations_file(&data_file),
+            |annotations: &Vec<Annotation>| annotations.iter().try_for_each(Annotation::validate),
+        ));
+        findings.extend(check_json(
+            &cold_storage::index_file(&data_file),
+            |_: &Vec<ColdPartition>| Ok(()),
+        ));
+        if findings.is_empty() {
+            println!("{}: {} values", name, data.len());
+        } else {
+            for finding in findings {
+                println!("{}: {}", name, finding);
+            }
+            problems += 1;
+        }
+    }
+    for plot in orphaned_plots(image_path, namespaces, &stems)? {
+        println!("{:?}: plot of a series that does not exist", plot);
+        problems += 1;
+    }
+    match problems {
+        0 => Ok(()),
+        _ => Err(format!("Found problems with {} series or plots", problems)),
+    }
+}
+
+/// What is wrong with the JSON file next to a data file, when it exists.
+fn check_json<T: DeserializeOwned>(
+    file: &Path,
+    validate: impl Fn(&T) -> Result<(), String>,
+) -> Option<String> {
+    let contents = std::fs::read_to_string(file).ok()?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    match serde_json::from_str::<T>(&contents) {
+        Ok(value) => validate(&value)
+            .err()
+            .map(|err| format!("{} is not valid, {}", name, err)),
+        Err(err) => Some(format!("{} does not parse, {}", name, err)),
+    }
+}
+
+/// The rows of a data file, or of its compressed counterpart when the
+/// series is archived.
+fn read_data(data_file: &Path) -> io::Result<Vec<u8>> {
+    if data_file.exists() {
+        return std::fs::read(data_file);
+    }
+    let mut contents = Vec::new();
+    flate2::read::GzDecoder::new(File::open(archive::archived_file(data_file))?)
+        .read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The pre-rendered plots in `image_path` and in the subdirectories of the
+/// namespaces whose series has no data file.
+fn orphaned_plots(
+    image_path: &Path,
+    namespaces: &Namespaces,
+    stems: &HashSet<String>,
+) -> Result<Vec<PathBuf>, String> {
+    let mut plots = Vec::new();
+    for (namespace, directory) in directories(image_path, namespaces) {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("Could not read {:?}, {}", directory, err)),
+        };
+        plots.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension() == Some("svg".as_ref()))
+                .filter(|path| {
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let stem = match namespace {
+                        Some(namespace) => format!("{}/{}", namespace, stem),
+                        None => stem.into_owned(),
+                    };
+                    !stems.contains(&stem)
+                }),
+        );
+    }
+    plots.sort();
+    Ok(plots)
+}
+
+/// Runs `sts-rs compact`, quarantining the rows that can not be part of a
+/// series, sorting the rows by time stamp and dropping rows that repeat the
+/// one before, for the series outside and inside namespaces. The server
+/// should not be running while compacting.
+pub fn compact(data_path: &Path, namespaces: &Namespaces) -> Result<(), String> {
+    let mut files = Vec::new();
+    for (namespace, directory) in directories(data_path, namespaces) {
+        files.extend(
+            data_files(&directory)?
+                .into_iter()
+                .map(|data_file| (namespace, data_file)),
+        );
+    }
+    for (namespace, data_file) in files {
+        let name = match series_name(namespace, &data_file) {
+            Ok(name) => name,
+            Err(err) => {
+                warn!("Not compacting {:?}, {}", data_file, err);
+                continue;
+            }
+        };
+        let (mut data, report) = check_data_file(&data_file)
+            .map_err(|err| format!("Could not compact {:?}, {}", data_file, err))?;
+        let rows = data.len();
+        data.dedup_by(|datum, previous| {
+            datum.timeStamp == previous.timeStamp && datum.value == previous.value
+        });
+        if data.len() < rows || report.out_of_order {
+            crate::rewrite_data_file(&data_file, &data)
+                .map_err(|err| format!("Could not compact {:?}, {}", data_file, err))?;
+        }
+        info!(
+            "Compacted {}, quarantined {} and dropped {} duplicate rows, {} remain",
+            name,
+            report.quarantined(),
+            rows - data.len(),
+            data.len()
+        );
+    }
+    Ok(())
+}
+
+/// The data directory, or the image directory, and the subdirectories of
+/// the namespaces in it, with the namespace each holds the series of.
+fn directories<'a>(path: &Path, namespaces: &'a Namespaces) -> Vec<(Option<&'a str>, PathBuf)> {
+    std::iter::once((None, path.to_owned()))
+        .chain(
+            namespaces
+                .names()
+                .map(|namespace| (Some(namespace), path.join(namespace))),
+        )
+        .collect()
+}
+
+/// The entries of a directory, none when a namespace has no directory yet.
+fn read_dir(directory: &Path) -> Result<Vec<std::fs::DirEntry>, String> {
+    match std::fs::read_dir(directory) {
+        Ok(entries) => Ok(entries.flatten().collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("Could not read {:?}, {}", directory, err)),
+    }
+}
+
+/// The data files of all series in a directory, for archived series the
+/// name the data file has once they are restored.
+fn series_data_files(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = read_dir(directory)?;
+    let mut files = entries
+        .into_iter()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?;
+            let stem = file_name
+                .strip_suffix(".csv.gz")
+                .or_else(|| file_name.strip_suffix(".csv"))?;
+            Some(path.with_file_name(format!("{}.csv", stem)))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The uncompressed data files in a directory, archived series are left
+/// alone.
+fn data_files(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = read_dir(directory)?
+        .into_iter()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension() == Some("csv".as_ref()))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// The series of a data file in the directory of `namespace`, when the file
+/// is named after a valid series.
+fn series_name(namespace: Option<&str>, data_file: &Path) -> Result<String, String> {
+    let stem = data_file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = series_names::from_file_stem(&stem);
+    series_names::validate(&name)?;
+    Ok(match namespace {
+        Some(namespace) => format!("{}/{}", namespace, name),
+        None => name,
+    })
+}
+
+fn parse_row(line: &str) -> Result<Datum, &'static str> {
+    let (time_stamp, value) = match line.split_once(',') {
+        Some((time_stamp, value)) if !value.contains(',') => (time_stamp.trim(), value.trim()),
+        _ => return Err("rows with a wrong column count"),
+    };
+    let time_stamp = time_stamp
+        .parse::<i64>()
+        .map_err(|_| "rows with an unparsable time stamp")?;
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| "rows with an unparsable value")?;
+    if !value.is_finite() {
+        return Err("rows with a non-finite value");
+    }
+    Ok(Datum {
+        timeStamp: time_stamp,
+        value,
+    })
+}
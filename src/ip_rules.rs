@@ -0,0 +1,121 @@
+use crate::env_or_default;
+use std::net::IpAddr;
+
+/// The addresses changes may come from. `STS_RS_WRITE_ALLOW` and
+/// `STS_RS_WRITE_DENY` are comma separated networks like
+/// `192.168.1.0/24,fd00::/8`, a denied address is refused even when it is
+/// also allowed. Reading is not restricted.
+pub struct IpRules {
+    allow: Vec<Network>,
+    deny: Vec<Network>,
+}
+
+struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpRules {
+    pub fn from_env() -> Option<IpRules> {
+        let allow = networks("STS_RS_WRITE_ALLOW");
+        let deny = networks("STS_RS_WRITE_DENY");
+        if allow.is_empty() && deny.is_empty() {
+            return None;
+        }
+        Some(IpRules { allow, deny })
+    }
+
+    pub fn describe(&self) -> String {
+        let list = |networks: &[Network]| {
+            networks
+                .iter()
+                .map(|network| format!("{}/{}", network.address, network.prefix))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (self.allow.is_empty(), self.deny.is_empty()) {
+            (false, true) => format!("Allowing changes only from {}", list(&self.allow)),
+            (true, false) => format!("Refusing changes from {}", list(&self.deny)),
+            _ => format!(
+                "Allowing changes only from {} except {}",
+                list(&self.allow),
+                list(&self.deny)
+            ),
+        }
+    }
+
+    /// Whether a change may come from the address, without it only when
+    /// nothing is allowed explicitly.
+    pub fn allows(&self, address: Option<IpAddr>) -> bool {
+        let address = match address {
+            Some(address) => canonical(address),
+            None => return self.allow.is_empty(),
+        };
+        !self.deny.iter().any(|network| network.contains(address))
+            && (self.allow.is_empty() || self.allow.iter().any(|network| network.contains(address)))
+    }
+}
+
+impl Network {
+    fn parse(network: &str) -> Result<Network, String> {
+        let (address, prefix) = match network.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (network, None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|err| format!("{} is not an address, {}", address, err))?;
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("{} is not a prefix length of {}", prefix, address))?,
+            None => bits,
+        };
+        Ok(Network {
+            address: canonical(address),
+            prefix,
+        })
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => prefix_matches(
+                u32::from(network).into(),
+                u32::from(address).into(),
+                32,
+                self.prefix,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                prefix_matches(network.into(), address.into(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn networks(variable: &str) -> Vec<Network> {
+    env_or_default(variable, "")
+        .split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(|network| {
+            Network::parse(network).unwrap_or_else(|err| panic!("{}: {}", variable, err))
+        })
+        .collect()
+}
+
+fn prefix_matches(network: u128, address: u128, bits: u8, prefix: u8) -> bool {
+    let shift = u32::from(bits - prefix);
+    shift == u32::from(bits) || network >> shift == address >> shift
+}
+
+/// IPv4 clients of a dual stack listener arrive as `::ffff:a.b.c.d`.
+fn canonical(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+        IpAddr::V4(_) => address,
+    }
+}
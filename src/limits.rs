@@ -0,0 +1,148 @@
+use crate::history::History;
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// What to do with a write that would make a series exceed its limit.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitPolicy {
+    /// Accept the write and drop the oldest day of data.
+    Rotate,
+    /// Refuse the write.
+    Reject,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct SizeLimit {
+    pub max_points: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub policy: LimitPolicy,
+}
+
+impl SizeLimit {
+    /// The limit applied to series without a limit of their own, configured
+    /// with `STS_RS_MAX_POINTS`, `STS_RS_MAX_BYTES` and `STS_RS_LIMIT_POLICY`.
+    pub fn from_env() -> Option<SizeLimit> {
+        let max_points = std::env::var("STS_RS_MAX_POINTS").ok().map(|value| {
+            value
+                .parse::<usize>()
+                .expect("STS_RS_MAX_POINTS should be a number of values")
+        });
+        let max_bytes = std::env::var("STS_RS_MAX_BYTES").ok().map(|value| {
+            value
+                .parse::<u64>()
+                .expect("STS_RS_MAX_BYTES should be a number of bytes")
+        });
+        if max_points.is_none() && max_bytes.is_none() {
+            return None;
+        }
+        let policy = match crate::env_or_default("STS_RS_LIMIT_POLICY", "rotate")
+            .to_lowercase()
+            .as_str()
+        {
+            "reject" => LimitPolicy::Reject,
+            "rotate" => LimitPolicy::Rotate,
+            other => panic!(
+                "STS_RS_LIMIT_POLICY should be rotate or reject, not {}",
+                other
+            ),
+        };
+        Some(SizeLimit {
+            max_points,
+            max_bytes,
+            policy,
+        })
+    }
+
+    pub fn exceeded_by(&self, points: usize, bytes: u64) -> bool {
+        self.max_points.is_some_and(|max| points > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+
+    /// Determines the time stamp before which data has to be rotated out to
+    /// get back within the limit. Whole days are rotated, unless the oldest
+    /// day alone is all there is.
+    pub fn rotation_cutoff(&self, data: &History, bytes: u64) -> Option<i64> {
+        if data.is_empty() || !self.exceeded_by(data.len(), bytes) {
+            return None;
+        }
+        let bytes_per_point = bytes / data.len() as u64;
+        let mut time_stamps = data.iter().map(|datum| datum.timeStamp).collect::<Vec<_>>();
+        time_stamps.sort_unstable();
+        let last_day = day_start(*time_stamps.last().unwrap());
+        let mut removed = 0;
+        while removed < time_stamps.len() {
+            let cutoff = day_start(time_stamps[removed]) + SECONDS_PER_DAY;
+            if cutoff > last_day {
+                break;
+            }
+            removed += time_stamps[removed..]
+                .iter()
+                .take_while(|time_stamp| **time_stamp < cutoff)
+                .count();
+            let remaining = time_stamps.len() - removed;
+            if !self.exceeded_by(remaining, remaining as u64 * bytes_per_point) {
+                return Some(cutoff);
+            }
+        }
+        let mut keep = time_stamps.len();
+        while keep > 0 && self.exceeded_by(keep, keep as u64 * bytes_per_point) {
+            keep -= 1;
+        }
+        Some(time_stamps[time_stamps.len() - keep.max(1)])
+    }
+}
+
+fn day_start(time_stamp: i64) -> i64 {
+    time_stamp - time_stamp.rem_euclid(SECONDS_PER_DAY)
+}
+
+/// Bounds on what a single request may send, configured with
+/// `STS_RS_MAX_BODY_BYTES`, `STS_RS_MAX_BATCH` and `STS_RS_MAX_NAME_LENGTH`.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLimits {
+    pub max_body_bytes: usize,
+    /// Thresholds or annotations replaced at once.
+    pub max_batch: usize,
+    /// Bytes in the name of a new series.
+    pub max_name_length: usize,
+}
+
+impl RequestLimits {
+    pub fn from_env() -> RequestLimits {
+        let number = |variable: &str, default: usize| {
+            crate::env_or_default(variable, &default.to_string())
+                .parse::<usize>()
+                .ok()
+                .filter(|number| *number > 0)
+                .unwrap_or_else(|| panic!("{} should be a positive number", variable))
+        };
+        RequestLimits {
+            max_body_bytes: number("STS_RS_MAX_BODY_BYTES", 256 * 1024),
+            max_batch: number("STS_RS_MAX_BATCH", 1000),
+            max_name_length: number("STS_RS_MAX_NAME_LENGTH", 128),
+        }
+    }
+
+    pub fn check_batch(&self, length: usize) -> Result<(), String> {
+        if length > self.max_batch {
+            return Err(format!(
+                "{} items are more than the {} allowed at once",
+                length, self.max_batch
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_name(&self, name: &str) -> Result<(), String> {
+        if name.len() > self.max_name_length {
+            return Err(format!(
+                "Series names may be {} bytes long, this one is {}",
+                self.max_name_length,
+                name.len()
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::auth::Caller;
+use crate::namespaces;
+use crate::Datum;
+use actix::prelude::*;
+use bytes::Bytes;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Comment lines sent to idle streams, which also notice closed ones.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Streams accepted values as server-sent events to the pages showing them.
+#[derive(Default)]
+pub struct LiveUpdates {
+    clients: Vec<Client>,
+}
+
+struct Client {
+    namespace: Option<String>,
+    /// The series of the namespace the client follows, all of them when
+    /// empty.
+    series: Vec<String>,
+    caller: Caller,
+    sender: UnboundedSender<Bytes>,
+}
+
+/// Starts a stream of the values of `series`, or of every series when it is
+/// empty, in a namespace. Events name series within the namespace, those
+/// of private series only reach callers who may read them.
+pub struct Subscribe {
+    pub namespace: Option<String>,
+    pub series: Vec<String>,
+    pub caller: Caller,
+}
+
+impl Message for Subscribe {
+    type Result = UnboundedReceiver<Bytes>;
+}
+
+pub struct Publish {
+    pub series_name: String,
+    pub datum: Datum,
+    /// The readers of a private series.
+    pub readers: Option<Vec<String>>,
+}
+
+impl Message for Publish {
+    type Result = ();
+}
+
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct Event<'a> {
+    series: &'a str,
+    timeStamp: i64,
+    value: f64,
+}
+
+impl LiveUpdates {
+    /// Sends `event` to the clients `follows` accepts, dropping the ones that
+    /// went away.
+    fn send(&mut self, event: Bytes, follows: impl Fn(&Client) -> bool) {
+        self.clients.retain(|client| {
+            !follows(client) || client.sender.unbounded_send(event.clone()).is_ok()
+        });
+    }
+}
+
+impl Actor for LiveUpdates {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |this, _| {
+            this.send(Bytes::from_static(b": heartbeat\n\n"), |_| true);
+        });
+    }
+}
+
+impl Handler<Subscribe> for LiveUpdates {
+    type Result = MessageResult<Subscribe>;
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Self::Context) -> Self::Result {
+        let (sender, receiver) = unbounded();
+        // Lets the browser know the stream is open before the first value.
+        let _ = sender.unbounded_send(Bytes::from_static(b": connected\n\n"));
+        self.clients.push(Client {
+            namespace: msg.namespace,
+            series: msg.series,
+            caller: msg.caller,
+            sender,
+        });
+        MessageResult(receiver)
+    }
+}
+
+impl Handler<Publish> for LiveUpdates {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Self::Context) {
+        if self.clients.is_empty() {
+            return;
+        }
+        let (namespace, series_name) = namespaces::split(&msg.series_name);
+        let data = serde_json::to_string(&Event {
+            series: series_name,
+            timeStamp: msg.datum.timeStamp,
+            value: msg.datum.value,
+        })
+        .unwrap();
+        self.send(
+            Bytes::from(format!("event: value\ndata: {}\n\n", data)),
+            |client| {
+                client.namespace.as_deref() == namespace
+                    && (client.series.is_empty()
+                        || client.series.iter().any(|name| name == series_name))
+                    && msg
+                        .readers
+                        .as_ref()
+                        .is_none_or(|readers| client.caller.may_read_private(readers))
+            },
+        );
+    }
+}
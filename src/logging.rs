@@ -0,0 +1,69 @@
+use crate::otlp::OtlpLayer;
+use actix_web::dev::ServiceRequest;
+use std::io::IsTerminal;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Logs the lines of the server and of the libraries it uses, as text or,
+/// with `json`, as one JSON object per line carrying the fields of the
+/// request it belongs to. The level is like `RUST_LOG`, which is used
+/// when none is given, and defaults to errors only.
+pub fn init(level: Option<&str>, format: &str) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level),
+        None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("error")),
+    }
+    .map_err(|err| format!("The log level is not valid, {}", err))?;
+    // Traces cover the spans of the server whatever is logged.
+    let otlp = OtlpLayer::from_env()
+        .map(|otlp| otlp.with_filter(Targets::new().with_target("sts_rs", Level::INFO)));
+    let subscriber = tracing_subscriber::registry().with(otlp);
+    let lines = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    match format {
+        "text" => subscriber.with(lines.with_filter(filter)).init(),
+        "json" => subscriber
+            .with(
+                lines
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false)
+                    .with_filter(filter),
+            )
+            .init(),
+        other => {
+            return Err(format!(
+                "STS_RS_LOG_FORMAT should be text or json, not {}",
+                other
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// The id of a request in the logs, the `X-Request-Id` a proxy in front
+/// sent along or a new one.
+pub fn request_id(req: &ServiceRequest) -> String {
+    let given = req
+        .headers()
+        .get("X-Request-Id")
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 64
+                && id
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(&byte))
+        });
+    match given {
+        Some(id) => id.to_owned(),
+        None => {
+            let mut bytes = [0; 8];
+            getrandom::getrandom(&mut bytes).unwrap();
+            hex::encode(bytes)
+        }
+    }
+}
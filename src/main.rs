@@ -8,233 +8,4124 @@ extern crate serde;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "tls")]
+mod acme;
+mod alerts;
+mod annotations;
+mod archive;
+mod assets;
+mod audit;
+mod auth;
+mod bench;
+mod chat;
+mod cli;
+mod client_certs;
+mod cold_storage;
+mod columns;
+mod config;
+mod csrf;
+mod dashboards;
+mod disk;
+mod email;
+mod error;
+mod export;
+mod history;
+mod import;
+mod integrity;
+mod ip_rules;
+mod limits;
+mod live;
+mod logging;
+mod metadata;
+mod metrics;
+mod namespaces;
+mod oidc;
+mod open_files;
+mod otlp;
+mod plot;
+mod pushgateway;
+mod rate_limit;
+mod reload;
+mod replication;
+mod replot;
+mod reports;
+mod response_cache;
+mod s3;
+mod scripts;
+mod secrets;
+mod series_names;
+mod shutdown;
+mod signatures;
+mod smtp;
+mod stats;
+mod systemd;
+#[cfg(feature = "tls")]
+mod tls;
+mod trash;
+mod units;
+mod users;
+mod watcher;
+mod webhooks;
+
 use actix::prelude::*;
 use actix_files as fs;
-use actix_web::{middleware, web, App, HttpResponse, HttpServer, Result};
+use actix_session::{CookieSession, Session, UserSession};
+use actix_web::dev::{BodyEncoding, Service};
+use actix_web::http::{self, ContentEncoding, StatusCode};
+use actix_web::{middleware, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Result};
+use annotations::Annotation;
 use askama::Template;
+use auth::Role;
+use bytes::Bytes;
 use chrono::{DateTime, TimeZone, Utc};
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use chrono_tz::Tz;
+use cold_storage::{ColdPartition, ColdStorage};
+use columns::Columns;
+use dashboards::Dashboards;
+use error::{Context as _, Error, Unpoisoned};
+use futures::future::{self, Either};
+use futures::{FutureExt, StreamExt};
+use history::History;
+use limits::{LimitPolicy, RequestLimits, SizeLimit};
+use live::{LiveUpdates, Publish, Subscribe};
+use metadata::{CounterStorage, SeriesMeta, Threshold};
+use namespaces::{Namespace, Namespaces, SeriesName};
+use plot::{
+    PlotCache, PlotFormat, PlotKey, PlotKind, PlotSettings, Plotter, Regenerate, Theme, Throttle,
+    Window,
+};
+use replication::{Replicate, Replicator};
+use response_cache::ResponseCache;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::clone::Clone;
-use std::collections::HashMap;
-use std::fs::OpenOptions;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
-use std::str;
-use std::sync::Mutex;
-
-const VERSION: &'static str = env!("VERGEN_SEMVER");
-const PACKAGE_NAME: &'static str = env!("CARGO_PKG_NAME");
-const SHORT_SHA: &'static str = env!("VERGEN_SHA_SHORT");
-const BUILD_TIMESTAMP: &'static str = env!("VERGEN_BUILD_TIMESTAMP");
-const GNUPLOT_COMMANDS: &'static str = r#"set timefmt "%s";
-set format x "%Y/%m/%d %H:%M:%S";
-set xdata time;
-set xtics rotate font ", 8";
-set ytics font ", 8";
-set terminal svg;
-set xlabel 'Time' offset 0,5;
-set key off;
-set datafile separator ",";
-set autoscale;
-set offsets 0.0, 0.0, 0.01, 0.01;
-set grid;
-set output"#;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::Instrument;
+use trash::{Trash, TrashEntry};
+use units::Conversion;
+
+const VERSION: &str = env!("VERGEN_SEMVER");
+const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
+const SHORT_SHA: &str = env!("VERGEN_SHA_SHORT");
+const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
+/// Builds without the `tls` feature listen for plain HTTP only.
+#[cfg(feature = "tls")]
+const DEFAULT_HTTPS_ADDRESS: &str = "127.0.0.1:8443";
+#[cfg(not(feature = "tls"))]
+const DEFAULT_HTTPS_ADDRESS: &str = "";
+const COLD_STORAGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const READINESS_TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds between reloads of the dashboard.
+const DASHBOARD_REFRESH: u32 = 60;
+/// Number of values in the table of a series page.
+const RECENT_VALUES: usize = 20;
+/// Number of series on a page of the index.
+const INDEX_PAGE_SIZE: usize = 50;
 
 struct SeriesInfo<'a> {
     name: &'a str,
     last_modified: String,
+    /// The time of the first and the last value, empty without values.
+    period: String,
     number_of_observations: usize,
+    tags: &'a [String],
+    group: &'a str,
+    modified: DateTime<Utc>,
 }
 
 #[derive(Template)]
 #[template(path = "index.html")]
 struct AvailableSeries<'a> {
-    series: Vec<SeriesInfo<'a>>,
+    /// The color scheme of the page and its plots.
+    theme: &'static str,
+    /// Leads the links to the pages outside a namespace back to the root.
+    root: &'static str,
+    /// The signed in user, empty without users.
+    user: String,
+    /// The series of the page by group, series without one come last in a
+    /// group without a name.
+    groups: Vec<(String, Vec<SeriesInfo<'a>>)>,
+    /// The series whose plot is shown when the page opens.
+    first: &'a str,
+    archived: Vec<SeriesInfo<'a>>,
+    search: &'a str,
+    /// The label, link and direction marker of every sort order.
+    sort_links: Vec<(&'static str, String, &'static str)>,
+    page: usize,
+    pages: usize,
+    previous: Option<String>,
+    next: Option<String>,
+    /// The space used and free, empty when it is unknown.
+    disk_usage: String,
+    disk_warning: String,
+}
+
+struct DashboardSeries<'a> {
+    name: &'a str,
+    unit: &'a str,
+    latest_value: String,
+    latest_time: String,
+    /// The alert rules of the series that are firing.
+    alerts: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct Dashboard<'a> {
+    /// The color scheme of the page and its plots.
+    theme: &'static str,
+    title: String,
+    /// Empty for the dashboard of all series.
+    slug: &'a str,
+    /// The series by group, the ones without a group in one without a name.
+    groups: Vec<(String, Vec<DashboardSeries<'a>>)>,
+    /// Plots per row, 0 fits as many as there is room for.
+    columns: u32,
+    timezone: &'static str,
+    /// Follow new values, unless the range ends in the past.
+    live: bool,
+    range: RangePicker,
+    /// The window and time zone passed on to the plots.
+    query: String,
+    /// Seconds after which the page reloads when the live updates stop, 0
+    /// does not reload.
+    refresh: u32,
+}
+
+/// The quick ranges and custom range form of `range.html`.
+struct RangePicker {
+    /// The label, link and class, `current` for the current range.
+    quick: Vec<(&'static str, String, &'static str)>,
+    /// The custom range, as `datetime-local` input values.
+    from: String,
+    to: String,
+    timezone: &'static str,
+}
+
+/// The window or custom range a page shows.
+struct PageRange {
+    window: Window,
+    from: Option<i64>,
+    to: Option<i64>,
+    /// Passes the range and time zone on to the plots.
+    query: String,
+    picker: RangePicker,
+}
+
+const QUICK_RANGES: [&str; 5] = ["1h", "24h", "7d", "30d", "all"];
+
+impl PageRange {
+    /// A custom range given with `from` and `to` overrides the window. They are
+    /// Unix time stamps or local times like `2020-09-13T12:26` in `timezone`.
+    fn new(
+        window: Window,
+        from: Option<&str>,
+        to: Option<&str>,
+        timezone: Tz,
+    ) -> Result<PageRange, String> {
+        let parse = |time: Option<&str>| match time.filter(|time| !time.is_empty()) {
+            Some(time) => parse_page_time(time, timezone).map(Some),
+            None => Ok(None),
+        };
+        let (from, to) = (parse(from)?, parse(to)?);
+        if let (Some(from), Some(to)) = (from, to) {
+            if from >= to {
+                return Err("from should be before to".to_owned());
+            }
+        }
+        let mut query = match (from, to) {
+            (None, None) => format!("window={}", window),
+            _ => [("from", from), ("to", to)]
+                .iter()
+                .filter_map(|(name, time)| time.map(|time| format!("{}={}", name, time)))
+                .collect::<Vec<_>>()
+                .join("&"),
+        };
+        query.push_str(&format!("&tz={}", query_component(timezone.name())));
+        let custom = from.is_some() || to.is_some();
+        let local = |time: Option<i64>| {
+            time.and_then(|time| timezone.timestamp_opt(time, 0).single())
+                .map(|time| time.format("%Y-%m-%dT%H:%M").to_string())
+                .unwrap_or_default()
+        };
+        let picker = RangePicker {
+            quick: QUICK_RANGES
+                .iter()
+                .map(|quick| {
+                    (
+                        *quick,
+                        format!("?window={}&tz={}", quick, query_component(timezone.name())),
+                        if !custom && quick.parse::<Window>() == Ok(window) {
+                            "current"
+                        } else {
+                            ""
+                        },
+                    )
+                })
+                .collect(),
+            from: local(from),
+            to: local(to),
+            timezone: timezone.name(),
+        };
+        Ok(PageRange {
+            window,
+            from,
+            to,
+            query,
+            picker,
+        })
+    }
+}
+
+fn parse_page_time(time: &str, timezone: Tz) -> Result<i64, String> {
+    if let Ok(time_stamp) = time.parse::<i64>() {
+        return Ok(time_stamp);
+    }
+    chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .and_then(|time| timezone.from_local_datetime(&time).earliest())
+        .map(|time| time.timestamp())
+        .ok_or_else(|| format!("{} is not a time like 2020-09-13T12:26", time))
+}
+
+#[derive(Template)]
+#[template(path = "dashboards.html")]
+struct DashboardList<'a> {
+    /// The color scheme of the page and its plots.
+    theme: &'static str,
+    csrf_token: String,
+    dashboards: Vec<(String, dashboards::Dashboard)>,
+    series: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "series.html")]
+struct SeriesPage<'a> {
+    /// The color scheme of the page and its plots.
+    theme: &'static str,
+    name: &'a str,
+    /// The path the routes of the namespace of the series start with.
+    base: String,
+    /// The unit the values are shown in.
+    unit: &'a str,
+    /// The unit new values are added in.
+    series_unit: &'a str,
+    /// The window and time zone passed on to the plots.
+    query: String,
+    /// The values the statistics are of, like `the last 7d`.
+    period: String,
+    /// Follow new values, unless the range ends in the past or the values
+    /// are converted to another unit.
+    live: bool,
+    range: RangePicker,
+    timezone: &'static str,
+    statistics: Vec<(&'static str, String)>,
+    latest_value: String,
+    latest_time: String,
+    /// The most recent values, newest first.
+    recent: Vec<(String, String)>,
+    recent_limit: usize,
+    number_of_observations: usize,
+    archived: bool,
+    csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "plot.html")]
+struct PlotPage {
+    title: String,
+    series: String,
+    secondary: String,
+    /// The path the routes of the namespace of the series start with.
+    base: String,
+    from: String,
+    to: String,
+    /// Offer a form to change the plot settings of the single series.
+    settings: bool,
+    theme: &'static str,
+    /// Overrides the scale of the plot settings when not empty.
+    log: String,
+    /// Overrides the kind of the plot settings when not empty.
+    kind: &'static str,
+    timezone: &'static str,
+    width: u32,
+    height: u32,
+    csrf_token: String,
+}
+
+#[derive(Deserialize, Serialize, Copy, Debug, Clone)]
+#[allow(non_snake_case)]
+struct Datum {
+    timeStamp: i64,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    purge: bool,
+}
+
+#[derive(Deserialize)]
+struct IndexQuery {
+    tz: Option<Tz>,
+    /// Words that the names should contain, or `tag:<tag>` for tags.
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    sort: IndexSort,
+    #[serde(default)]
+    order: SortOrder,
+    #[serde(default = "first_page")]
+    page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum IndexSort {
+    #[default]
+    Name,
+    Modified,
+    Observations,
+}
+
+impl IndexSort {
+    fn name(&self) -> &'static str {
+        match self {
+            IndexSort::Name => "name",
+            IndexSort::Modified => "modified",
+            IndexSort::Observations => "observations",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn name(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DashboardQuery {
+    window: Option<Window>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<Tz>,
+    refresh: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct AlertsQuery {
+    /// Only the alerts in this state.
+    state: Option<alerts::AlertState>,
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    series: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    #[serde(default)]
+    format: DataFormat,
+    unit: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum DataFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct SeriesPageQuery {
+    window: Option<Window>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<Tz>,
+    /// Shows the values converted to this unit.
+    unit: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EmbedQuery {
+    window: Option<Window>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<Tz>,
+    width: Option<u32>,
+    height: Option<u32>,
+    theme: Option<Theme>,
+}
+
+#[derive(Deserialize)]
+struct PlotQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    window: Option<Window>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<PlotFormat>,
+    theme: Option<Theme>,
+    log: Option<bool>,
+    kind: Option<PlotKind>,
+    tz: Option<Tz>,
+    unit: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SparklineQuery {
+    window: Option<Window>,
+    width: Option<u32>,
+    height: Option<u32>,
+    theme: Option<Theme>,
+}
+
+#[derive(Deserialize)]
+struct ComparisonQuery {
+    series: String,
+    secondary: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    window: Option<Window>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<PlotFormat>,
+    theme: Option<Theme>,
+    log: Option<bool>,
+    kind: Option<PlotKind>,
+    tz: Option<Tz>,
+    unit: Option<String>,
+}
+
+struct Series {
+    data: History,
+    /// When values were last added or removed, or when the data file was
+    /// last written for a series loaded from it.
+    last_modification_time: DateTime<Utc>,
+    cold_partitions: Vec<ColdPartition>,
+    meta: SeriesMeta,
+    annotations: Vec<Annotation>,
+    stored_bytes: u64,
+    stored_modified: Option<SystemTime>,
+    /// Values accepted in memory and not yet appended to the data file.
+    pending_writes: usize,
+    /// The latest reading of a counter that stores its increase, also in
+    /// `<series>.counter.json`.
+    counter_reading: Option<f64>,
+}
+
+impl Series {
+    /// A series that is created by its first value.
+    fn new(first: Datum, now: DateTime<Utc>) -> Series {
+        Series {
+            data: std::iter::once(first).collect(),
+            last_modification_time: now,
+            cold_partitions: Vec::new(),
+            meta: SeriesMeta::default(),
+            annotations: Vec::new(),
+            stored_bytes: 0,
+            stored_modified: None,
+            pending_writes: 1,
+            counter_reading: None,
+        }
+    }
+
+    /// Adds a value that is still to be appended to the data file.
+    fn append(&mut self, datum: Datum, now: DateTime<Utc>) {
+        self.data.push(datum);
+        self.pending_writes += 1;
+        self.last_modification_time = now;
+    }
+
+    /// Drops the values before `cutoff` from memory, after they were
+    /// rotated out of the data file.
+    fn retain_from(&mut self, cutoff: i64) {
+        self.data.retain(|datum| datum.timeStamp >= cutoff);
+        self.last_modification_time = Utc::now();
+    }
+
+    /// The time stamps of the first and the last value, those in cold
+    /// storage included.
+    fn time_range(&self) -> Option<(i64, i64)> {
+        let cold = self
+            .cold_partitions
+            .iter()
+            .map(|partition| (partition.first, partition.last));
+        self.data.time_range().into_iter().chain(cold).reduce(
+            |(first, last), (other_first, other_last)| {
+                (first.min(other_first), last.max(other_last))
+            },
+        )
+    }
+
+    /// The values in `window` of the plots rendered up front, decompressed.
+    fn recent(&self, window: Window) -> Columns {
+        let from = window.start(self.data.latest().map(|datum| datum.timeStamp));
+        self.data.between(from.unwrap_or(i64::MIN), i64::MAX)
+    }
+
+    /// Remembers size and modification time of the data file after the
+    /// server changed it, so changes made by others can be told apart.
+    fn record_file_state(&mut self, file_name: &Path) {
+        let metadata = std::fs::metadata(file_name).ok();
+        self.stored_bytes = metadata.as_ref().map_or(0, |metadata| metadata.len());
+        self.stored_modified = metadata.and_then(|metadata| metadata.modified().ok());
+    }
+
+    fn changed_externally(&self, file_name: &Path) -> bool {
+        let metadata = std::fs::metadata(file_name).ok();
+        metadata.as_ref().map(|metadata| metadata.len()) != Some(self.stored_bytes)
+            || metadata.and_then(|metadata| metadata.modified().ok()) != self.stored_modified
+    }
+
+    fn size_limit(&self, default_limit: Option<SizeLimit>) -> Option<SizeLimit> {
+        self.meta.limit.or(default_limit)
+    }
+
+    fn lock(serie: &Mutex<Series>) -> MutexGuard<'_, Series> {
+        serie.lock().unpoisoned()
+    }
+}
+
+/// The series in memory. The map is only locked for writing to add or
+/// remove series, every series has a lock of its own, so values for
+/// different series are added and read in parallel.
+type SeriesMap = Arc<RwLock<HashMap<String, Mutex<Series>>>>;
+
+struct AppState {
+    background_actor: Addr<BackgroundActor>,
+    series: SeriesMap,
+    cold_storage: Option<Arc<ColdStorage>>,
+    replicator: Option<Addr<Replicator>>,
+    default_limit: Arc<reload::Reloadable<Option<SizeLimit>>>,
+    request_limits: RequestLimits,
+    scripts: Arc<reload::Reloadable<scripts::Scripts>>,
+    trash: Arc<Trash>,
+    plot_cache: PlotCache,
+    /// Rendered index pages.
+    index_cache: ResponseCache<Bytes>,
+    /// What series pages show of the values in their range.
+    range_cache: ResponseCache<RangeSummary>,
+    default_theme: Theme,
+    timezone: Tz,
+    /// The window of the pre-rendered plots, also used for sparklines.
+    window: Window,
+    /// Why plotting is disabled, if it is.
+    plotting_disabled: Option<String>,
+    dashboards: Dashboards,
+    namespaces: Namespaces,
+    disk: disk::Disk,
+    device_secrets: Arc<reload::Reloadable<signatures::DeviceSecrets>>,
+    audit_log: Option<Arc<audit::AuditLog>>,
+    metrics: Arc<metrics::Metrics>,
+    live_updates: Addr<LiveUpdates>,
+    alerts: Arc<alerts::Alerts>,
+    webhooks: Option<Addr<webhooks::Webhooks>>,
+    reports: reports::Reports,
+    reporter: Option<Addr<reports::Reporter>>,
+    /// How the grouping labels of pushed metrics are kept.
+    pushgateway_labels: pushgateway::LabelMapping,
+    users: Arc<reload::Reloadable<users::Users>>,
+    oidc: Option<Arc<oidc::Oidc>>,
+    reloader: Arc<reload::Reloader>,
+    started: DateTime<Utc>,
+    /// The `frame-ancestors` allowed to show `/embed` pages, configured with
+    /// `STS_RS_FRAME_ANCESTORS`.
+    frame_ancestors: String,
+    #[cfg(feature = "tls")]
+    acme: Option<Arc<acme::Acme>>,
+    data_path: PathBuf,
+    image_path: PathBuf,
+}
+
+struct BackgroundActor {
+    data_storage_path: PathBuf,
+    image_output_path: PathBuf,
+    series: SeriesMap,
+    cold_storage: Option<Arc<ColdStorage>>,
+    default_limit: Arc<reload::Reloadable<Option<SizeLimit>>>,
+    trash: Arc<Trash>,
+    /// Shared with the blocking threads plots are rendered on.
+    plotter: Arc<Plotter>,
+    throttle: Throttle,
+    /// The series whose plot is being rendered, and whether it has to be
+    /// rendered again after that.
+    rendering: HashMap<String, bool>,
+    /// Files written since the last `Flush`.
+    unsynced: HashSet<PathBuf>,
+    open_files: open_files::OpenFiles,
+    /// Values of every series waiting to be appended together.
+    batches: HashMap<String, Vec<Datum>>,
+    batch_window: Duration,
+}
+
+impl BackgroundActor {
+    pub fn new(
+        data_storage_path: PathBuf,
+        image_output_path: PathBuf,
+        series: SeriesMap,
+        cold_storage: Option<Arc<ColdStorage>>,
+        default_limit: Arc<reload::Reloadable<Option<SizeLimit>>>,
+        trash: Arc<Trash>,
+        plotter: Plotter,
+    ) -> BackgroundActor {
+        BackgroundActor {
+            data_storage_path,
+            image_output_path,
+            series,
+            cold_storage,
+            default_limit,
+            trash,
+            plotter: Arc::new(plotter),
+            throttle: Throttle::from_env(),
+            rendering: HashMap::new(),
+            unsynced: HashSet::new(),
+            open_files: open_files::OpenFiles::from_env(),
+            batches: HashMap::new(),
+            batch_window: Duration::from_millis(
+                env_or_default("STS_RS_WRITE_BATCH", "100")
+                    .parse::<u64>()
+                    .expect("STS_RS_WRITE_BATCH should be a whole number of milliseconds"),
+            ),
+        }
+    }
+
+    /// Appends the values of a series that came in during the batch window
+    /// to its data file in one write, and regenerates its plot once for
+    /// them as often as the throttle allows.
+    fn write_batch(&mut self, series_name: &str, ctx: &mut Context<Self>) {
+        let batch = match self.batches.remove(series_name) {
+            Some(batch) => batch,
+            None => return,
+        };
+        let file_name = self.data_file(series_name);
+        if let Err(err) = self.open_files.append(&file_name, &batch) {
+            // The values stay in memory, a disk that filled up gets another
+            // chance after the next window.
+            warn!("Writing values of series {} failed, {}", series_name, err);
+            self.retry_batch(series_name, batch, ctx);
+            return;
+        }
+        self.unsynced.insert(file_name.clone());
+        if let Some(mut serie) = self
+            .series
+            .read()
+            .unpoisoned()
+            .get(series_name)
+            .map(Series::lock)
+        {
+            serie.pending_writes = serie.pending_writes.saturating_sub(batch.len());
+            serie.record_file_state(&file_name);
+        }
+        info!("Appended {} values to series {}.", batch.len(), series_name);
+        self.rotate_if_needed(series_name, ctx);
+        let regenerate = self
+            .throttle
+            .record(series_name, batch.len(), Instant::now());
+        self.plot_throttled(regenerate, series_name, ctx);
+    }
+
+    /// Puts the values of a batch that could not be written before the values
+    /// that came in since and writes them again later.
+    fn retry_batch(&mut self, series_name: &str, mut batch: Vec<Datum>, ctx: &mut Context<Self>) {
+        let queued = self.batches.entry(series_name.to_owned()).or_default();
+        let scheduled = !queued.is_empty();
+        batch.append(queued);
+        *queued = batch;
+        if !scheduled {
+            let series_name = series_name.to_owned();
+            ctx.run_later(
+                self.batch_window.max(Duration::from_secs(1)),
+                move |actor, ctx| actor.write_batch(&series_name, ctx),
+            );
+        }
+    }
+
+    /// Writes the batches of all series, before files are closed for
+    /// something else.
+    fn write_batches(&mut self, ctx: &mut Context<Self>) {
+        let series_names = self.batches.keys().cloned().collect::<Vec<_>>();
+        for series_name in series_names {
+            self.write_batch(&series_name, ctx);
+        }
+    }
+
+    /// Regenerates the plot of a series after a new value, as often as the
+    /// throttle allows.
+    fn plot_throttled(
+        &mut self,
+        regenerate: Regenerate,
+        series_name: &str,
+        ctx: &mut Context<Self>,
+    ) {
+        match regenerate {
+            Regenerate::Now => self.plot(series_name, ctx),
+            Regenerate::After(delay) => {
+                let series_name = series_name.to_owned();
+                ctx.run_later(delay, move |actor, ctx| {
+                    let regenerate = actor.throttle.due(&series_name, Instant::now());
+                    actor.plot_throttled(regenerate, &series_name, ctx);
+                });
+            }
+            Regenerate::Wait => {}
+        }
+    }
+
+    /// Renders the plot of a series on a blocking thread, so the actor
+    /// goes on with the next messages meanwhile. A series is rendered once
+    /// at a time, a plot asked for during that follows it. Private series
+    /// are not pre-rendered, `/images` is served to every reader.
+    fn plot(&mut self, series_name: &str, ctx: &mut Context<Self>) {
+        self.throttle.plotted(series_name, Instant::now());
+        if let Some(again) = self.rendering.get_mut(series_name) {
+            *again = true;
+            return;
+        }
+        let (data, meta, annotations) = match self
+            .series
+            .read()
+            .unpoisoned()
+            .get(series_name)
+            .map(Series::lock)
+        {
+            Some(serie) if serie.meta.private => {
+                let _ = std::fs::remove_file(self.image_file(series_name));
+                return;
+            }
+            Some(serie) => (
+                serie.recent(self.plotter.window),
+                serie.meta.clone(),
+                serie.annotations.clone(),
+            ),
+            None => return,
+        };
+        self.rendering.insert(series_name.to_owned(), false);
+        let plotter = self.plotter.clone();
+        let series_name = series_name.to_owned();
+        let (data_file, image_file) = (self.data_file(&series_name), self.image_file(&series_name));
+        let rendered = {
+            let series_name = series_name.clone();
+            web::block(move || {
+                plotter.plot(
+                    plot::Line {
+                        series_name: namespaces::split(&series_name).1,
+                        data: &data,
+                        settings: &meta.plot,
+                        thresholds: &meta.thresholds,
+                        annotations: &annotations,
+                        secondary: false,
+                        unit: meta.unit.as_deref(),
+                    },
+                    &data_file,
+                    &image_file,
+                )
+            })
+        };
+        ctx.spawn(actix::fut::wrap_future(rendered).map(
+            move |plotted, actor: &mut BackgroundActor, ctx| {
+                if let Err(err) = plotted {
+                    warn!("Plotting series {} failed, {}", series_name, err);
+                }
+                // The series may have been renamed or trashed meanwhile.
+                if !actor.series.read().unpoisoned().contains_key(&series_name) {
+                    let _ = std::fs::remove_file(actor.image_file(&series_name));
+                }
+                if actor.rendering.remove(&series_name) == Some(true) {
+                    actor.plot(&series_name, ctx);
+                }
+            },
+        ));
+    }
+
+    fn image_file(&self, series_name: &str) -> PathBuf {
+        series_names::image_file(&self.image_output_path, series_name)
+    }
+
+    fn data_file(&self, series_name: &str) -> PathBuf {
+        series_names::data_file(&self.data_storage_path, series_name)
+    }
+
+    /// Rotates the oldest data out of a series that grew beyond its limit.
+    /// With cold storage enabled the rotated data is offloaded instead of
+    /// discarded.
+    fn rotate_if_needed(&mut self, series_name: &str, ctx: &mut Context<Self>) {
+        let (cutoff, partitions) = match self
+            .series
+            .read()
+            .unpoisoned()
+            .get(series_name)
+            .map(Series::lock)
+        {
+            Some(serie) if serie.meta.archived => return,
+            Some(serie) => match serie.size_limit(*self.default_limit.get()) {
+                Some(limit) if limit.policy == LimitPolicy::Rotate => {
+                    match limit.rotation_cutoff(&serie.data, serie.stored_bytes) {
+                        Some(cutoff) => (cutoff, serie.cold_partitions.clone()),
+                        None => return,
+                    }
+                }
+                _ => return,
+            },
+            None => return,
+        };
+        let file_name = self.data_file(series_name);
+        self.open_files.close(&file_name);
+        let series = self.series.clone();
+        let series_name = series_name.to_owned();
+        match &self.cold_storage {
+            Some(cold_storage) => {
+                let cold_storage = cold_storage.clone();
+                ctx.wait(actix::fut::wrap_future(async move {
+                    match cold_storage
+                        .offload(&series_name, &file_name, partitions, cutoff)
+                        .await
+                    {
+                        Ok((partitions, _)) => {
+                            finish_rotation(&series, &series_name, &file_name, cutoff, partitions)
+                        }
+                        Err(err) => warn!(
+                            "Rotating series {} to cold storage failed, {}",
+                            series_name, err
+                        ),
+                    }
+                }));
+            }
+            None => {
+                let rotated = read_csv_data(&file_name).and_then(|(data, _)| {
+                    let kept = data
+                        .into_iter()
+                        .filter(|datum| datum.timeStamp >= cutoff)
+                        .collect::<Vec<_>>();
+                    rewrite_data_file(&file_name, &kept)
+                });
+                match rotated {
+                    Ok(()) => {
+                        finish_rotation(&series, &series_name, &file_name, cutoff, partitions)
+                    }
+                    Err(err) => warn!("Rotating series {} failed, {}", series_name, err),
+                }
+            }
+        }
+    }
+}
+
+fn finish_rotation(
+    series: &SeriesMap,
+    series_name: &str,
+    file_name: &Path,
+    cutoff: i64,
+    cold_partitions: Vec<ColdPartition>,
+) {
+    if let Some(mut serie) = series
+        .read()
+        .unpoisoned()
+        .get(series_name)
+        .map(Series::lock)
+    {
+        serie.retain_from(cutoff);
+        serie.record_file_state(file_name);
+        serie.cold_partitions = cold_partitions;
+        info!(
+            "Rotated values before {} out of series {}, {} values remain.",
+            Utc.timestamp(cutoff, 0).format("%+"),
+            series_name,
+            serie.data.len()
+        );
+    }
+}
+
+/// Appends a new value to the data file of a series, the values before it
+/// are in the file already.
+struct WriteCsv {
+    series_name: String,
+    datum: Datum,
+}
+
+impl Message for WriteCsv {
+    type Result = ();
+}
+
+struct WriteMeta {
+    series_name: String,
+    meta: SeriesMeta,
+}
+
+impl Message for WriteMeta {
+    type Result = ();
+}
+
+struct WriteAnnotations {
+    series_name: String,
+    annotations: Vec<Annotation>,
+}
+
+impl Message for WriteAnnotations {
+    type Result = ();
+}
+
+/// Stores the latest reading of a counter that stores its increase.
+struct WriteCounter {
+    series_name: String,
+    reading: f64,
+}
+
+impl Message for WriteCounter {
+    type Result = ();
+}
+
+/// Sent when a data file was changed by something other than the server.
+struct ReloadSeries {
+    file_name: PathBuf,
+}
+
+impl Message for ReloadSeries {
+    type Result = ();
+}
+
+struct TrashSeries {
+    series_name: String,
+}
+
+impl Message for TrashSeries {
+    type Result = std::io::Result<TrashEntry>;
+}
+
+struct RenameSeries {
+    series_name: String,
+    new_name: String,
+}
+
+impl Message for RenameSeries {
+    type Result = std::io::Result<()>;
+}
+
+struct RestoreSeries {
+    id: String,
+}
+
+impl Message for RestoreSeries {
+    type Result = std::io::Result<String>;
+}
+
+struct PurgeTrash {
+    id: Option<String>,
+}
+
+impl Message for PurgeTrash {
+    type Result = ();
+}
+
+struct OffloadColdData;
+
+impl Message for OffloadColdData {
+    type Result = ();
+}
+
+/// Sent by the systemd watchdog to see whether the actor is handling
+/// messages.
+struct Ping;
+
+impl Message for Ping {
+    type Result = ();
+}
+
+/// Sent for `/readyz`, the actor answers when it is not stuck.
+struct CheckReadiness;
+
+impl Message for CheckReadiness {
+    type Result = Vec<(&'static str, Result<(), String>)>;
+}
+
+/// Sent when the server stopped, it is handled after the writes queued
+/// before it.
+struct Flush;
+
+impl Message for Flush {
+    type Result = ();
+}
+
+impl Actor for BackgroundActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.notify(PurgeTrash { id: None });
+        ctx.run_interval(TRASH_PURGE_INTERVAL, |_, ctx| {
+            ctx.notify(PurgeTrash { id: None })
+        });
+        ctx.run_interval(
+            self.open_files.idle.max(Duration::from_secs(1)),
+            |actor, _| actor.open_files.close_idle(Instant::now()),
+        );
+        if self.cold_storage.is_some() {
+            ctx.notify(OffloadColdData);
+            ctx.run_interval(COLD_STORAGE_INTERVAL, |_, ctx| ctx.notify(OffloadColdData));
+        }
+    }
+}
+
+fn rewrite_data_file(data_file: &Path, data: &[Datum]) -> Result<(), Error> {
+    let temporary = data_file.with_extension("csv.tmp");
+    to_csv(data.iter().copied())
+        .and_then(|csv| std::fs::write(&temporary, csv))
+        .context(|| format!("writing {:?} failed", temporary))?;
+    std::fs::rename(&temporary, data_file).context(|| format!("replacing {:?} failed", data_file))
+}
+
+fn to_csv(data: impl IntoIterator<Item = Datum>) -> std::io::Result<Vec<u8>> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    for datum in data {
+        wtr.serialize(datum)?;
+    }
+    wtr.into_inner()
+        .map_err(|err| std::io::Error::new(err.error().kind(), err.to_string()))
+}
+
+impl Handler<WriteCsv> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: WriteCsv, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("WriteCsv", series = %msg.series_name).entered();
+        info!(
+            "BackgroundActor received a value of series {}.",
+            msg.series_name
+        );
+        let batch = self.batches.entry(msg.series_name.clone()).or_default();
+        batch.push(msg.datum);
+        if self.batch_window == Duration::from_secs(0) {
+            self.write_batch(&msg.series_name, ctx);
+        } else if batch.len() == 1 {
+            ctx.run_later(self.batch_window, move |actor, ctx| {
+                actor.write_batch(&msg.series_name, ctx)
+            });
+        }
+    }
+}
+
+impl Handler<WriteMeta> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: WriteMeta, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("WriteMeta", series = %msg.series_name).entered();
+        let file_name = self.data_file(&msg.series_name);
+        match metadata::write_meta(&metadata::meta_file(&file_name), &msg.meta) {
+            Ok(()) => {
+                self.unsynced.insert(metadata::meta_file(&file_name));
+            }
+            Err(err) => warn!(
+                "Writing the settings of series {} failed, {}",
+                msg.series_name, err
+            ),
+        }
+        let archived_file = archive::archived_file(&file_name);
+        self.write_batch(&msg.series_name, ctx);
+        if msg.meta.archived && file_name.exists() {
+            self.open_files.close(&file_name);
+            match archive::compress(&file_name) {
+                Ok(()) => info!("Archived series {}.", msg.series_name),
+                Err(err) => warn!("Archiving series {} failed, {}", msg.series_name, err),
+            }
+        } else if !msg.meta.archived && archived_file.exists() {
+            match archive::decompress(&file_name) {
+                Ok(()) => {
+                    if let Some(mut serie) = self
+                        .series
+                        .read()
+                        .unpoisoned()
+                        .get(&msg.series_name)
+                        .map(Series::lock)
+                    {
+                        serie.record_file_state(&file_name);
+                    }
+                    info!("Unarchived series {}.", msg.series_name)
+                }
+                Err(err) => warn!("Unarchiving series {} failed, {}", msg.series_name, err),
+            }
+        }
+        self.rotate_if_needed(&msg.series_name, ctx);
+        self.plot(&msg.series_name, ctx);
+    }
+}
+
+impl Handler<WriteAnnotations> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: WriteAnnotations, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("WriteAnnotations", series = %msg.series_name).entered();
+        let file_name = self.data_file(&msg.series_name);
+        match annotations::write_annotations(
+            &annotations::annotations_file(&file_name),
+            &msg.annotations,
+        ) {
+            Ok(()) => {
+                self.unsynced
+                    .insert(annotations::annotations_file(&file_name));
+            }
+            Err(err) => warn!(
+                "Writing the annotations of series {} failed, {}",
+                msg.series_name, err
+            ),
+        }
+        self.plot(&msg.series_name, ctx);
+    }
+}
+
+impl Handler<WriteCounter> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: WriteCounter, _ctx: &mut Context<Self>) -> Self::Result {
+        let counter_file = metadata::counter_file(&self.data_file(&msg.series_name));
+        match metadata::write_counter(&counter_file, msg.reading) {
+            Ok(()) => {
+                self.unsynced.insert(counter_file);
+            }
+            Err(err) => warn!(
+                "Writing the counter reading of series {} failed, {}",
+                msg.series_name, err
+            ),
+        }
+    }
+}
+
+impl Handler<Ping> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) -> Self::Result {}
+}
+
+impl Handler<CheckReadiness> for BackgroundActor {
+    type Result = MessageResult<CheckReadiness>;
+    fn handle(&mut self, _msg: CheckReadiness, _ctx: &mut Context<Self>) -> Self::Result {
+        let probe = self.data_storage_path.join(".readyz.tmp");
+        let storage = std::fs::write(&probe, b"")
+            .and_then(|()| std::fs::remove_file(&probe))
+            .map_err(|err| format!("{:?} is not writable, {}", self.data_storage_path, err));
+        MessageResult(vec![("storage", storage)])
+    }
+}
+
+impl Handler<Flush> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, _msg: Flush, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("Flush").entered();
+        self.write_batches(ctx);
+        self.open_files.close_all();
+        let files = self.unsynced.drain().collect::<Vec<_>>();
+        // Windows can not sync directories, their entries are in its journal.
+        let directories = if cfg!(windows) {
+            None
+        } else {
+            Some(&self.data_storage_path)
+        };
+        for file in files.iter().chain(directories) {
+            // Files that were renamed or trashed since have nothing to sync.
+            match sync_to_disk(file) {
+                Ok(()) => (),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => warn!("Could not sync {:?} to disk, {}", file, err),
+            }
+        }
+        info!("Synced {} changed files to disk.", files.len());
+    }
+}
+
+impl Handler<ReloadSeries> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: ReloadSeries, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("ReloadSeries", file = ?msg.file_name).entered();
+        let series_name = match series_names::from_file(&self.data_storage_path, &msg.file_name) {
+            Some(series_name) => series_name,
+            None => return,
+        };
+        // The file may have been replaced, the next value opens it again.
+        self.write_batch(&series_name, ctx);
+        self.open_files.close(&msg.file_name);
+        let mut series = self.series.write().unpoisoned();
+        if !msg.file_name.exists() && !archive::archived_file(&msg.file_name).exists() {
+            if series.remove(&series_name).is_some() {
+                info!(
+                    "Data file of series {} was removed, dropped it.",
+                    series_name
+                );
+            }
+            return;
+        }
+        if let Some(serie) = series.get(&series_name).map(Series::lock) {
+            if !serie.changed_externally(&msg.file_name) {
+                return;
+            }
+        }
+        let mut reloaded = match load_series(&msg.file_name) {
+            Some(reloaded) => reloaded,
+            None => return,
+        };
+        // Values still waiting in the mailbox are not in the file yet, keep them.
+        if let Some(serie) = series.get(&series_name).map(Series::lock) {
+            let pending = serie.pending_writes.min(serie.data.len());
+            reloaded
+                .data
+                .extend(serie.data.iter().skip(serie.data.len() - pending));
+            reloaded.pending_writes = pending;
+            reloaded.last_modification_time = Utc::now();
+        }
+        info!(
+            "Reloaded series {} with {} values after an external change.",
+            series_name,
+            reloaded.data.len()
+        );
+        series.insert(series_name.clone(), Mutex::new(reloaded));
+        drop(series);
+        self.plot(&series_name, ctx);
+    }
+}
+
+impl Handler<TrashSeries> for BackgroundActor {
+    type Result = std::io::Result<TrashEntry>;
+    fn handle(&mut self, msg: TrashSeries, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("TrashSeries", series = %msg.series_name).entered();
+        let data_file = self.data_file(&msg.series_name);
+        self.write_batch(&msg.series_name, ctx);
+        self.open_files.close(&data_file);
+        let entry = self
+            .trash
+            .move_to_trash(&self.data_storage_path, &msg.series_name)?;
+        self.throttle.forget(&msg.series_name);
+        let image_file = self.image_file(&msg.series_name);
+        if image_file.exists() {
+            std::fs::remove_file(image_file)?;
+        }
+        info!(
+            "Moved series {} to the trash as {}.",
+            msg.series_name, entry.id
+        );
+        Ok(entry)
+    }
+}
+
+impl Handler<RenameSeries> for BackgroundActor {
+    type Result = std::io::Result<()>;
+    fn handle(&mut self, msg: RenameSeries, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("RenameSeries", series = %msg.series_name, new_name = %msg.new_name).entered();
+        self.write_batch(&msg.series_name, ctx);
+        let mut series = self.series.write().unpoisoned();
+        if !series.contains_key(&msg.series_name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no series {}", msg.series_name),
+            ));
+        }
+        let new_file = self.data_file(&msg.new_name);
+        if series.contains_key(&msg.new_name)
+            || new_file.exists()
+            || archive::archived_file(&new_file).exists()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("series {} already exists", msg.new_name),
+            ));
+        }
+        // The partitions in cold storage are stored under the series name.
+        let index_file = cold_storage::index_file(&self.data_file(&msg.series_name));
+        if !cold_storage::read_index(&index_file).is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "series {} has data in cold storage and can not be renamed",
+                    msg.series_name
+                ),
+            ));
+        }
+        let data_file = self.data_file(&msg.series_name);
+        self.open_files.close(&data_file);
+        trash::rename_series_files(&self.data_storage_path, &msg.series_name, &msg.new_name)?;
+        let serie = series.remove(&msg.series_name).unwrap();
+        series.insert(msg.new_name.clone(), serie);
+        drop(series);
+        self.throttle.forget(&msg.series_name);
+        let image_file = self.image_file(&msg.series_name);
+        if image_file.exists() {
+            std::fs::remove_file(image_file)?;
+        }
+        self.plot(&msg.new_name, ctx);
+        info!("Renamed series {} to {}.", msg.series_name, msg.new_name);
+        Ok(())
+    }
+}
+
+impl Handler<RestoreSeries> for BackgroundActor {
+    type Result = std::io::Result<String>;
+    fn handle(&mut self, msg: RestoreSeries, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("RestoreSeries", id = %msg.id).entered();
+        let mut series = self.series.write().unpoisoned();
+        if let Some(entry) = self
+            .trash
+            .list()
+            .into_iter()
+            .find(|entry| entry.id == msg.id)
+        {
+            if series.contains_key(&entry.series_name) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("series {} already exists", entry.series_name),
+                ));
+            }
+        }
+        let series_name = self.trash.restore(&msg.id, &self.data_storage_path)?;
+        let file_name = self.data_file(&series_name);
+        if let Some(serie) = load_series(&file_name) {
+            series.insert(series_name.clone(), Mutex::new(serie));
+        }
+        drop(series);
+        self.plot(&series_name, ctx);
+        info!("Restored series {} from the trash.", series_name);
+        Ok(series_name)
+    }
+}
+
+impl Handler<PurgeTrash> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, msg: PurgeTrash, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("PurgeTrash").entered();
+        let entries = match msg.id {
+            Some(id) => self
+                .trash
+                .list()
+                .into_iter()
+                .filter(|entry| entry.id == id)
+                .collect(),
+            None => self.trash.expired(Utc::now()),
+        };
+        for entry in entries {
+            let entry_directory = self.trash.entry_directory(&entry.id);
+            let cold_partitions = cold_storage::read_index(&entry_directory.join(format!(
+                "{}.cold.json",
+                series_names::file_stem(namespaces::split(&entry.series_name).1)
+            )));
+            if let (Some(cold_storage), false) = (&self.cold_storage, cold_partitions.is_empty()) {
+                let cold_storage = cold_storage.clone();
+                let series_name = entry.series_name.clone();
+                ctx.spawn(actix::fut::wrap_future(async move {
+                    if let Err(err) = cold_storage.delete(&series_name, &cold_partitions).await {
+                        warn!(
+                            "Removing cold data of series {} failed, {}",
+                            series_name, err
+                        );
+                    }
+                }));
+            }
+            match self.trash.purge(&entry.id) {
+                Ok(()) => info!("Purged {} from the trash.", entry.id),
+                Err(err) => warn!("Purging {} from the trash failed, {}", entry.id, err),
+            }
+        }
+    }
+}
+
+impl Handler<OffloadColdData> for BackgroundActor {
+    type Result = ();
+    fn handle(&mut self, _msg: OffloadColdData, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::info_span!("OffloadColdData").entered();
+        let cold_storage = match &self.cold_storage {
+            Some(cold_storage) => cold_storage.clone(),
+            None => return,
+        };
+        let cutoff = cold_storage.cutoff(Utc::now());
+        let series = self.series.clone();
+        let data_storage_path = self.data_storage_path.clone();
+        self.write_batches(ctx);
+        self.open_files.close_all();
+        let candidates = series
+            .read()
+            .unpoisoned()
+            .iter()
+            .map(|(name, serie)| (name, Series::lock(serie)))
+            .filter(|(_, serie)| !serie.meta.archived)
+            .map(|(name, serie)| (name.clone(), serie.cold_partitions.clone()))
+            .collect::<Vec<_>>();
+        // Waiting blocks the mailbox, so no appends interleave with the rewrite of a data file.
+        ctx.wait(actix::fut::wrap_future(async move {
+            for (series_name, partitions) in candidates {
+                let file_name = series_names::data_file(&data_storage_path, &series_name);
+                match cold_storage
+                    .offload(&series_name, &file_name, partitions, cutoff)
+                    .await
+                {
+                    Ok((partitions, moved)) if moved > 0 => {
+                        if let Some(mut serie) = series
+                            .read()
+                            .unpoisoned()
+                            .get(&series_name)
+                            .map(Series::lock)
+                        {
+                            serie.retain_from(cutoff);
+                            serie.cold_partitions = partitions;
+                            serie.record_file_state(&file_name);
+                        }
+                        info!(
+                            "Moved {} values of series {} to cold storage.",
+                            moved, series_name
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!(
+                        "Moving series {} to cold storage failed, {}",
+                        series_name, err
+                    ),
+                }
+            }
+        }));
+    }
+}
+
+async fn index(
+    req: HttpRequest,
+    namespace: Namespace,
+    session: Session,
+    query: web::Query<IndexQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let timezone = query.tz.unwrap_or(state.timezone);
+    let (disk_usage, disk_warning) = match state.disk.summary() {
+        Ok(summary) => (
+            format!(
+                "Data takes {}, {} of {} is free",
+                format_bytes(summary.data_bytes),
+                format_bytes(summary.free_bytes),
+                format_bytes(summary.total_bytes)
+            ),
+            summary.warning.unwrap_or_default(),
+        ),
+        Err(err) => {
+            warn!(
+                "The disk usage of {:?} is unknown, {}",
+                state.data_path, err
+            );
+            (String::new(), String::new())
+        }
+    };
+    let series = state.series.read().unpoisoned();
+    let (words, tags): (Vec<_>, Vec<_>) = query
+        .q
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .partition(|word| !word.starts_with("tag:"));
+    let matches = |name: &str, meta: &SeriesMeta| {
+        let name = name.to_lowercase();
+        words.iter().all(|word| name.contains(word.as_str()))
+            && tags.iter().all(|tag| {
+                meta.tags
+                    .iter()
+                    .any(|series_tag| series_tag.to_lowercase() == tag["tag:".len()..])
+            })
+    };
+    // The listed series are locked until the page is rendered.
+    let caller = auth::Caller::of_request(&req);
+    let series = series
+        .iter()
+        .filter(|(name, _)| namespace.contains(name))
+        .map(|(name, serie)| (namespaces::split(name).1, Series::lock(serie)))
+        .filter(|(_, serie)| caller.may_read(&serie.meta))
+        .collect::<Vec<_>>();
+    let theme = page_theme(&req);
+    let user = session
+        .get::<String>("user")
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    // Any value or setting shown that changes makes another page.
+    let shown = series
+        .iter()
+        .map(|(name, serie)| {
+            response_cache::key((
+                name,
+                serie.data.len(),
+                serie.last_modification_time,
+                &serie.meta.tags,
+                &serie.meta.group,
+                serie.meta.archived,
+            ))
+        })
+        .fold(0, u64::wrapping_add);
+    let key = response_cache::key((
+        shown,
+        namespace.name(),
+        &query.q,
+        query.sort.name(),
+        query.order.name(),
+        query.page,
+        timezone.name(),
+        theme.name(),
+        &user,
+        &disk_usage,
+        &disk_warning,
+    ));
+    if let Some(page) = state.index_cache.get(key) {
+        return Ok(HttpResponse::Ok().content_type("text/html").body(page));
+    }
+    let (mut archived, mut infos): (Vec<_>, Vec<_>) = series
+        .iter()
+        .filter(|(key, val)| matches(key, &val.meta))
+        .map(|(key, val)| {
+            (
+                val.meta.archived,
+                SeriesInfo {
+                    name: key,
+                    number_of_observations: val.data.len(),
+                    last_modified: format!(
+                        "{}",
+                        val.last_modification_time
+                            .with_timezone(&timezone)
+                            .format("%+")
+                    ),
+                    period: val
+                        .time_range()
+                        .map(|(first, last)| {
+                            let time = |time_stamp: i64| match timezone
+                                .timestamp_opt(time_stamp, 0)
+                                .single()
+                            {
+                                Some(time) => time.format("%+").to_string(),
+                                None => time_stamp.to_string(),
+                            };
+                            format!("{} to {}", time(first), time(last))
+                        })
+                        .unwrap_or_default(),
+                    tags: &val.meta.tags,
+                    group: val.meta.group.as_deref().unwrap_or_default(),
+                    modified: val.last_modification_time,
+                },
+            )
+        })
+        .partition(|(archived, _)| *archived);
+    // Series without a group sort last.
+    let group_order = |lhs: &SeriesInfo, rhs: &SeriesInfo| {
+        (lhs.group.is_empty(), lhs.group).cmp(&(rhs.group.is_empty(), rhs.group))
+    };
+    let order = |lhs: &SeriesInfo, rhs: &SeriesInfo| {
+        let ordering = match query.sort {
+            IndexSort::Name => lhs.name.cmp(rhs.name),
+            IndexSort::Modified => lhs.modified.cmp(&rhs.modified),
+            IndexSort::Observations => lhs.number_of_observations.cmp(&rhs.number_of_observations),
+        }
+        .then_with(|| lhs.name.cmp(rhs.name));
+        match query.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    };
+    infos.sort_by(|(_, lhs), (_, rhs)| group_order(lhs, rhs).then_with(|| order(lhs, rhs)));
+    archived.sort_by(|(_, lhs), (_, rhs)| order(lhs, rhs));
+    let pages = infos.len().div_ceil(INDEX_PAGE_SIZE).max(1);
+    let page = query.page.clamp(1, pages);
+    let link = |sort: IndexSort, order: SortOrder, page: usize| {
+        let mut link = format!("?sort={}&order={}", sort.name(), order.name());
+        if !query.q.is_empty() {
+            link.push_str(&format!("&q={}", query_component(&query.q)));
+        }
+        if let Some(tz) = query.tz {
+            link.push_str(&format!("&tz={}", query_component(tz.name())));
+        }
+        if page > 1 {
+            link.push_str(&format!("&page={}", page));
+        }
+        link
+    };
+    let sort_links = [
+        ("Name", IndexSort::Name),
+        ("Last modified", IndexSort::Modified),
+        ("Observations", IndexSort::Observations),
+    ]
+    .iter()
+    .map(|(label, sort)| match (*sort == query.sort, query.order) {
+        (true, SortOrder::Asc) => (*label, link(*sort, SortOrder::Desc, 1), " ▲"),
+        (true, SortOrder::Desc) => (*label, link(*sort, SortOrder::Asc, 1), " ▼"),
+        (false, _) => (*label, link(*sort, SortOrder::Asc, 1), ""),
+    })
+    .collect();
+    let mut groups: Vec<(String, Vec<SeriesInfo>)> = Vec::new();
+    for (_, info) in infos
+        .into_iter()
+        .skip((page - 1) * INDEX_PAGE_SIZE)
+        .take(INDEX_PAGE_SIZE)
+    {
+        match groups.last_mut() {
+            Some((group, series)) if *group == group_label(info.group) => series.push(info),
+            _ => groups.push((group_label(info.group), vec![info])),
+        }
+    }
+    let rendered = AvailableSeries {
+        theme: theme.name(),
+        root: match namespace.name() {
+            Some(_) => "../../",
+            None => "",
+        },
+        user,
+        first: groups
+            .first()
+            .and_then(|(_, series)| series.first())
+            .map_or("", |info| info.name),
+        groups,
+        archived: archived.into_iter().map(|(_, info)| info).collect(),
+        search: &query.q,
+        sort_links,
+        page,
+        pages,
+        previous: Some(page - 1)
+            .filter(|page| *page >= 1)
+            .map(|page| link(query.sort, query.order, page)),
+        next: Some(page + 1)
+            .filter(|page| *page <= pages)
+            .map(|page| link(query.sort, query.order, page)),
+        disk_usage,
+        disk_warning,
+    }
+    .render()
+    .map_err(Error::from)?;
+    let rendered = Bytes::from(rendered);
+    state.index_cache.insert(key, rendered.clone());
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+async fn dashboard(
+    req: HttpRequest,
+    query: web::Query<DashboardQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut names = state
+        .series
+        .read()
+        .unpoisoned()
+        .iter()
+        .filter(|(name, _)| namespaces::split(name).0.is_none())
+        .map(|(name, serie)| (name, Series::lock(serie)))
+        .filter(|(_, serie)| !serie.meta.archived)
+        .map(|(name, serie)| (serie.meta.group.clone(), name.clone()))
+        .collect::<Vec<_>>();
+    names.sort_by(|(lhs_group, lhs), (rhs_group, rhs)| {
+        (lhs_group.is_none(), lhs_group, lhs).cmp(&(rhs_group.is_none(), rhs_group, rhs))
+    });
+    let names = names.into_iter().map(|(_, name)| name).collect();
+    dashboard_page(
+        &state,
+        &dashboards::Dashboard {
+            title: "Dashboard".to_owned(),
+            series: names,
+            columns: None,
+            window: query.window,
+            refresh: query.refresh,
+        },
+        "",
+        &query,
+        &req,
+    )
+}
+
+async fn user_dashboard(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DashboardQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    match state.dashboards.get(&path) {
+        Some(mut dashboard) => {
+            dashboard.window = query.window.or(dashboard.window);
+            dashboard.refresh = query.refresh.or(dashboard.refresh);
+            dashboard_page(&state, &dashboard, &path, &query, &req)
+        }
+        None => Ok(Error::NotFound(format!("no dashboard {}", path)).into()),
+    }
+}
+
+/// Renders the plots of the series of `dashboard` in its order, leaving out
+/// series that no longer exist and those the caller may not read.
+fn dashboard_page(
+    state: &AppState,
+    dashboard: &dashboards::Dashboard,
+    slug: &str,
+    query: &DashboardQuery,
+    req: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let theme = page_theme(req);
+    let caller = auth::Caller::of_request(req);
+    let timezone = query.tz.unwrap_or(state.timezone);
+    let range = match PageRange::new(
+        dashboard.window.unwrap_or(state.window),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        timezone,
+    ) {
+        Ok(range) => range,
+        Err(err) => return Err(Error::invalid(err)),
+    };
+    let series = state.series.read().unpoisoned();
+    // Groups are shown in the order of their first series.
+    let mut groups: Vec<(String, Vec<DashboardSeries>)> = Vec::new();
+    let series = dashboard
+        .series
+        .iter()
+        .filter_map(|name| series.get(name).map(|serie| (name, Series::lock(serie))))
+        .filter(|(_, serie)| caller.may_read(&serie.meta))
+        .collect::<Vec<_>>();
+    for (name, serie) in &series {
+        let group = group_label(serie.meta.group.as_deref().unwrap_or_default());
+        let tile = {
+            let latest = serie.data.latest();
+            DashboardSeries {
+                name,
+                unit: serie.meta.unit.as_deref().unwrap_or_default(),
+                latest_value: latest.map_or("-".to_owned(), |datum| {
+                    show_value(datum.value, serie.meta.precision)
+                }),
+                latest_time: latest
+                    .and_then(|datum| timezone.timestamp_opt(datum.timeStamp, 0).single())
+                    .map(|time| time.format("%Y/%m/%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+                alerts: state.alerts.firing(name),
+            }
+        };
+        match groups.iter_mut().find(|(existing, _)| *existing == group) {
+            Some((_, tiles)) => tiles.push(tile),
+            None => groups.push((group, vec![tile])),
+        }
+    }
+    let rendered = Dashboard {
+        theme: theme.name(),
+        title: dashboard.title.clone(),
+        slug,
+        groups,
+        columns: dashboard.columns.unwrap_or(0),
+        timezone: timezone.name(),
+        live: range.to.is_none(),
+        query: format!("{}&theme={}", range.query, theme.name()),
+        range: range.picker,
+        refresh: dashboard.refresh.unwrap_or(DASHBOARD_REFRESH),
+    }
+    .render()?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+async fn dashboard_list(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = auth::Caller::of_request(&req);
+    let series = state.series.read().unpoisoned();
+    let mut names = series
+        .iter()
+        .filter(|(_, serie)| caller.may_read(&Series::lock(serie).meta))
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    let rendered = DashboardList {
+        theme: page_theme(&req).name(),
+        csrf_token: csrf::token(&req.get_session()),
+        dashboards: state.dashboards.list(),
+        series: names,
+    }
+    .render()?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+/// Streams new values as server-sent `value` events, of the series listed
+/// in `series` or of all series.
+async fn stream(
+    req: HttpRequest,
+    namespace: Namespace,
+    query: web::Query<StreamQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series = query
+        .series
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect();
+    let subscribe = Subscribe {
+        namespace: namespace.name().map(str::to_owned),
+        series,
+        caller: auth::Caller::of_request(&req),
+    };
+    match state.live_updates.send(subscribe).await {
+        Ok(events) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .header("Cache-Control", "no-cache")
+            // Compressing would hold events back until a block is full.
+            .encoding(ContentEncoding::Identity)
+            .streaming(events.map(Ok::<_, actix_web::Error>)),
+        Err(err) => Error::Internal(format!("subscribing to live updates failed, {}", err)).into(),
+    }
+}
+
+async fn list_dashboards(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(
+        state
+            .dashboards
+            .list()
+            .into_iter()
+            .map(|(slug, _)| slug)
+            .collect::<Vec<_>>(),
+    )
+}
+
+async fn get_dashboard(path: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    match state.dashboards.get(&path) {
+        Some(dashboard) => HttpResponse::Ok().json(dashboard),
+        None => Error::NotFound(format!("no dashboard {}", path)).into(),
+    }
+}
+
+async fn put_dashboard(
+    path: web::Path<String>,
+    dashboard: web::Json<dashboards::Dashboard>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(err) = dashboards::validate_slug(&path) {
+        return Error::invalid(err).for_field("name").into();
+    }
+    if let Err(err) = dashboard.validate() {
+        return Error::invalid(err).into();
+    }
+    match state.dashboards.save(&path, &dashboard) {
+        Ok(()) => HttpResponse::Ok().json(dashboard.0),
+        Err(source) => Error::Storage {
+            context: format!("saving dashboard {} failed", path),
+            source,
+        }
+        .into(),
+    }
+}
+
+async fn delete_dashboard(path: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    if dashboards::validate_slug(&path).is_err() {
+        return Error::NotFound(format!("no dashboard {}", path)).into();
+    }
+    match state.dashboards.delete(&path) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Error::NotFound(format!("no dashboard {}", path)).into()
+        }
+        Err(source) => Error::Storage {
+            context: format!("deleting dashboard {} failed", path),
+            source,
+        }
+        .into(),
+    }
+}
+
+async fn get_series(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series_name = path.to_string();
+    let caller = auth::Caller::of_request(&req);
+    if let Some(serie) = state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        HttpResponse::Ok().content_type("text/plain").body(format!(
+            "Series {} has {} values.",
+            series_name,
+            serie.data.len()
+        ))
+    } else {
+        Error::NotFound(format!("no series {}", series_name)).into()
+    }
+}
+
+async fn get_series_data(
+    req: HttpRequest,
+    path: SeriesName,
+    query: web::Query<RangeQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    export::response(
+        state,
+        auth::Caller::of_request(&req),
+        path.into_inner(),
+        (query.from, query.to),
+        query.format,
+        query.unit,
+    )
+    .await
+}
+
+/// Whether there is a series the caller of the request may read. Private
+/// series others may not read are answered like missing ones.
+fn readable(req: &HttpRequest, state: &AppState, series_name: &str) -> bool {
+    let caller = auth::Caller::of_request(req);
+    state
+        .series
+        .read()
+        .unpoisoned()
+        .get(series_name)
+        .is_some_and(|serie| caller.may_read(&Series::lock(serie).meta))
+}
+
+/// Also served under the routes of namespaces, for the relative links of
+/// their index pages.
+async fn static_file(req: HttpRequest) -> HttpResponse {
+    asset_response(&req, req.match_info().get("file").unwrap_or_default())
+}
+
+async fn favicon(req: HttpRequest) -> HttpResponse {
+    asset_response(&req, "favicon.ico")
+}
+
+/// Serves a static file, browsers check whether theirs is still current as
+/// the files change with the executable.
+fn asset_response(req: &HttpRequest, name: &str) -> HttpResponse {
+    match assets::get(name) {
+        Some((_, etag))
+            if req
+                .headers()
+                .get(http::header::IF_NONE_MATCH)
+                .is_some_and(|tag| tag.as_bytes() == etag.as_bytes()) =>
+        {
+            HttpResponse::NotModified().finish()
+        }
+        Some((contents, etag)) => HttpResponse::Ok()
+            .content_type(assets::content_type(name))
+            .header(http::header::ETAG, etag)
+            .header(http::header::CACHE_CONTROL, "no-cache")
+            .body(contents),
+        None => Error::NotFound(format!("no file {}", name)).into(),
+    }
+}
+
+/// Without users or single sign-on everybody may read, without any API
+/// keys, users, single sign-on or client certificates everybody may change
+/// series. Otherwise the role of the key, the signed in user or the client
+/// certificate of the request should cover the route, a verified client
+/// certificate without a key may read.
+fn authorize(
+    api_keys: &auth::ApiKeys,
+    users: &users::Users,
+    sso: bool,
+    client_certs: bool,
+    required: Role,
+    req: &actix_web::dev::ServiceRequest,
+) -> Result<(), StatusCode> {
+    let reading_is_open = users.is_empty() && !sso;
+    if required == Role::Reader && reading_is_open
+        || reading_is_open && api_keys.is_empty() && !client_certs
+    {
+        return Ok(());
+    }
+    match role(api_keys, users, req)? {
+        Some(role) if role >= required => Ok(()),
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// The role of the key, the signed in user or the client certificate of the
+/// request.
+fn role(
+    api_keys: &auth::ApiKeys,
+    users: &users::Users,
+    req: &actix_web::dev::ServiceRequest,
+) -> Result<Option<Role>, StatusCode> {
+    let session = req.get_session();
+    let role = api_keys
+        .role(req)?
+        .or_else(|| {
+            let user = session.get::<String>("user").ok().flatten()?;
+            // Users of single sign-on are not in the users file, their role
+            // holds until they have to sign in again.
+            match session.get::<i64>("sso_until").ok().flatten() {
+                Some(until) if Utc::now().timestamp() < until => {
+                    session.get::<Role>("role").ok().flatten()
+                }
+                Some(_) => None,
+                None => users.role(&user),
+            }
+        })
+        .or_else(|| users.basic_auth(req).and_then(|user| users.role(&user)))
+        .or_else(|| client_certs::ClientIdentity::of_request(req).map(|_| Role::Reader));
+    Ok(role)
+}
+
+/// Who makes a request, for the audit log. Keys are named by a digest, so
+/// the log does not hold them.
+fn principal(users: &users::Users, req: &actix_web::dev::ServiceRequest) -> String {
+    let headers = req.headers();
+    let key = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get("X-Api-Key")
+                .and_then(|value| value.to_str().ok())
+        });
+    if let Some(key) = key {
+        let digest = hex::encode(Sha256::digest(key.trim().as_bytes()));
+        return format!("key:{}", &digest[..12]);
+    }
+    let session = req.get_session();
+    // Names of single sign-on may be those of users in the users file.
+    if let Some(subject) = session.get::<String>("subject").ok().flatten() {
+        return format!("sso:{}", subject);
+    }
+    if let Some(user) = session.get::<String>("user").ok().flatten() {
+        return format!("user:{}", user);
+    }
+    if let Some(user) = users.basic_auth(req) {
+        return format!("user:{}", user);
+    }
+    match client_certs::ClientIdentity::of_request(req) {
+        Some(identity) => format!("cert:{}", identity),
+        None => "anonymous".to_owned(),
+    }
+}
+
+/// Browsers are sent to the sign in page, other clients are asked for HTTP
+/// Basic authentication.
+fn login_required(req: &actix_web::dev::ServiceRequest) -> HttpResponse {
+    let accepts_html = req
+        .headers()
+        .get(http::header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if accepts_html {
+        HttpResponse::SeeOther()
+            .header(
+                http::header::LOCATION,
+                format!(
+                    "/login?next={}",
+                    query_component(req.uri().path_and_query().map_or("/", |path| path.as_str()))
+                ),
+            )
+            .finish()
+    } else {
+        let mut response: HttpResponse =
+            Error::Unauthorized("signing in is required".to_owned()).into();
+        response.headers_mut().insert(
+            http::header::WWW_AUTHENTICATE,
+            http::HeaderValue::from_static("Basic realm=\"sts-rs\""),
+        );
+        response
+    }
+}
+
+/// The key the session cookies are encrypted with, from
+/// `STS_RS_SESSION_KEY`. Without it sessions end when the server restarts.
+fn session_key() -> Vec<u8> {
+    match secrets::secret("STS_RS_SESSION_KEY") {
+        Some(key) if key.len() >= 32 => key.into_bytes(),
+        Some(_) => panic!("STS_RS_SESSION_KEY should be at least 32 characters"),
+        None => {
+            let mut key = vec![0; 32];
+            getrandom::getrandom(&mut key).unwrap();
+            key
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct Login<'a> {
+    theme: &'static str,
+    next: &'a str,
+    next_query: String,
+    error: &'a str,
+    csrf_token: String,
+    /// Whether there are users with a password.
+    passwords: bool,
+    sso: bool,
+}
+
+impl Login<'_> {
+    fn render_for(
+        req: &HttpRequest,
+        state: &AppState,
+        next: &str,
+        error: &str,
+    ) -> Result<String, Error> {
+        Login {
+            theme: page_theme(req).name(),
+            next,
+            next_query: query_component(next),
+            error,
+            csrf_token: csrf::token(&req.get_session()),
+            passwords: !state.users.get().is_empty(),
+            sso: state.oidc.is_some(),
+        }
+        .render()
+        .map_err(Error::from)
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    #[serde(default)]
+    next: String,
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    user: String,
+    password: String,
+    #[serde(default)]
+    next: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+async fn login_page(
+    req: HttpRequest,
+    query: web::Query<LoginQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(Login::render_for(&req, &state, &query.next, "")?))
+}
+
+async fn login(
+    req: HttpRequest,
+    form: web::Form<LoginForm>,
+    session: Session,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if !csrf::token_matches(&session, &form.csrf_token) {
+        return Ok(HttpResponse::Forbidden()
+            .content_type("text/html")
+            .body(Login::render_for(
+                &req,
+                &state,
+                &form.next,
+                "The sign in form expired, try again",
+            )?));
+    }
+    if !state.users.get().verify(&form.user, &form.password) {
+        warn!("Failed sign in of {}", form.user);
+        return Ok(HttpResponse::Unauthorized()
+            .content_type("text/html")
+            .body(Login::render_for(
+                &req,
+                &state,
+                &form.next,
+                "Unknown user or wrong password",
+            )?));
+    }
+    session.renew();
+    for key in &["subject", "role", "sso_until"] {
+        session.remove(key);
+    }
+    session.set("user", &form.user)?;
+    Ok(redirect_after_login(&form.next))
+}
+
+/// Only goes on to pages of this server.
+fn redirect_after_login(next: &str) -> HttpResponse {
+    // `//host` and `/\host` lead browsers to another site, as does either
+    // with a tab or newline in between, which browsers leave out.
+    let next = match next.strip_prefix('/') {
+        Some(rest) if !rest.starts_with(['/', '\\']) && !rest.contains(char::is_control) => next,
+        _ => "/",
+    };
+    HttpResponse::SeeOther()
+        .header(http::header::LOCATION, next)
+        .finish()
+}
+
+/// Sends the browser to the OpenID Connect provider to sign in.
+async fn oidc_login(
+    query: web::Query<LoginQuery>,
+    session: Session,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let oidc = match &state.oidc {
+        Some(oidc) => oidc,
+        None => return Ok(Error::NotFound("single sign-on is not configured".to_owned()).into()),
+    };
+    let (login_state, nonce) = (oidc::random_token(), oidc::random_token());
+    match oidc.authorization_url(&login_state, &nonce).await {
+        Ok(url) => {
+            session.set("oidc_state", &login_state)?;
+            session.set("oidc_nonce", &nonce)?;
+            session.set("oidc_next", &query.next)?;
+            Ok(HttpResponse::SeeOther()
+                .header(http::header::LOCATION, url)
+                .finish())
+        }
+        Err(err) => {
+            warn!("Signing in with {} failed, {}", oidc.issuer(), err);
+            Ok(Error::Upstream("the sign in provider is unavailable".to_owned()).into())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OidcCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+async fn oidc_callback(
+    req: HttpRequest,
+    query: web::Query<OidcCallback>,
+    session: Session,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let oidc = match &state.oidc {
+        Some(oidc) => oidc,
+        None => return Ok(Error::NotFound("single sign-on is not configured".to_owned()).into()),
+    };
+    let expected_state = session.get::<String>("oidc_state")?;
+    let nonce = session.get::<String>("oidc_nonce")?.unwrap_or_default();
+    let next = session.get::<String>("oidc_next")?.unwrap_or_default();
+    for key in &["oidc_state", "oidc_nonce", "oidc_next"] {
+        session.remove(key);
+    }
+    let failed = |error: &str| -> Result<HttpResponse, Error> {
+        Ok(HttpResponse::Unauthorized()
+            .content_type("text/html")
+            .body(Login::render_for(&req, &state, &next, error)?))
+    };
+    let code = match (&query.code, &query.state, &query.error) {
+        (_, _, Some(error)) => {
+            warn!("Signing in with {} failed, {}", oidc.issuer(), error);
+            return Ok(failed("Signing in was refused")?);
+        }
+        (Some(code), Some(login_state), None) if Some(login_state) == expected_state.as_ref() => {
+            code
+        }
+        _ => return Ok(failed("Signing in took too long or was started elsewhere")?),
+    };
+    match oidc.identify(code, &nonce).await {
+        Ok(identity) if oidc.admits(&identity) => {
+            session.renew();
+            session.set("subject", &identity.subject)?;
+            session.set("user", &identity.user)?;
+            session.set("role", oidc.role(&identity))?;
+            session.set("sso_until", oidc.signed_in_until())?;
+            info!(
+                "Signed in {} ({}) as {} with {}",
+                identity.user,
+                identity.subject,
+                oidc.role(&identity),
+                oidc.issuer()
+            );
+            Ok(redirect_after_login(&next))
+        }
+        Ok(identity) => {
+            warn!("Refused {}, not in the allowed groups", identity.user);
+            Ok(failed("You are not in a group that may sign in")?)
+        }
+        Err(err) => {
+            warn!("Signing in with {} failed, {}", oidc.issuer(), err);
+            Ok(failed("Signing in failed")?)
+        }
+    }
+}
+
+/// Answers the HTTP-01 challenges of the ACME provider.
+#[cfg(feature = "tls")]
+async fn acme_challenge(token: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    match state
+        .acme
+        .as_ref()
+        .and_then(|acme| acme.key_authorization(&token))
+    {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization),
+        None => Error::NotFound(format!("no challenge {}", token)).into(),
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+async fn acme_challenge() -> HttpResponse {
+    Error::NotFound("ACME is not configured".to_owned()).into()
+}
+
+async fn logout(session: Session) -> HttpResponse {
+    session.purge();
+    HttpResponse::SeeOther()
+        .header(http::header::LOCATION, "/login")
+        .finish()
+}
+
+/// What is running and how much it holds, for `/about` and
+/// `/api/v1/version`.
+#[derive(Serialize)]
+struct Status {
+    name: &'static str,
+    version: &'static str,
+    git_sha: &'static str,
+    dirty: bool,
+    built_at: &'static str,
+    started_at: String,
+    uptime_seconds: i64,
+    series: usize,
+    points: usize,
+    /// Bytes in the data directory, including the trash and plot cache.
+    data_bytes: u64,
+    image_bytes: u64,
+    /// `ok`, or why plotting is disabled.
+    plotting: String,
+}
+
+impl Status {
+    fn of(state: &AppState) -> Status {
+        let (series, points) = {
+            let series = state.series.read().unpoisoned();
+            (
+                series.len(),
+                series
+                    .values()
+                    .map(|serie| Series::lock(serie).data.len())
+                    .sum(),
+            )
+        };
+        Status {
+            name: PACKAGE_NAME,
+            version: VERSION,
+            git_sha: SHORT_SHA,
+            dirty: workspace_is_dirty(),
+            built_at: BUILD_TIMESTAMP,
+            started_at: state.started.format("%+").to_string(),
+            uptime_seconds: (Utc::now() - state.started).num_seconds(),
+            series,
+            points,
+            data_bytes: directory_size(&state.data_path),
+            image_bytes: directory_size(&state.image_path),
+            plotting: state
+                .plotting_disabled
+                .clone()
+                .unwrap_or_else(|| "ok".to_owned()),
+        }
+    }
+}
+
+/// The answer of `/healthz` and `/readyz`, every check is `ok` or what is
+/// wrong.
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, String>,
+}
+
+#[derive(Template)]
+#[template(path = "embed.html")]
+struct Embed<'a> {
+    name: &'a str,
+    /// The path the routes of the namespace of the series start with.
+    base: String,
+    /// Empty when the plot keeps the theme of its settings.
+    theme: &'static str,
+    query: String,
+}
+
+/// A plot without anything around it, to show in an iframe on other pages.
+async fn embed(
+    req: HttpRequest,
+    path: SeriesName,
+    query: web::Query<EmbedQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if !readable(&req, &state, &path) {
+        return Ok(Error::NotFound(format!("no series {}", path)).into());
+    }
+    let range = match PageRange::new(
+        query.window.unwrap_or(state.window),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.tz.unwrap_or(state.timezone),
+    ) {
+        Ok(range) => range,
+        Err(err) => return Err(Error::invalid(err)),
+    };
+    let mut plot_query = range.query;
+    for (name, size) in [("width", query.width), ("height", query.height)] {
+        match size {
+            Some(size) if !plot::SIZES.contains(&size) => {
+                let message = format!(
+                    "{} should be between {} and {}",
+                    name,
+                    plot::SIZES.start(),
+                    plot::SIZES.end()
+                );
+                return Err(Error::invalid(message).for_field(name));
+            }
+            Some(size) => plot_query.push_str(&format!("&{}={}", name, size)),
+            None => {}
+        }
+    }
+    if let Some(theme) = query.theme {
+        plot_query.push_str(&format!("&theme={}", theme.name()));
+    }
+    let rendered = Embed {
+        name: path.local(),
+        base: path.namespace().base(),
+        theme: query.theme.map_or("", |theme| theme.name()),
+        query: plot_query,
+    }
+    .render()?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .header(
+            http::header::CONTENT_SECURITY_POLICY,
+            content_security_policy(&state.frame_ancestors),
+        )
+        .body(rendered))
+}
+
+/// Scripts and styles of the pages come from `/static` and the plots are
+/// images of this server. Only the pages in `frame_ancestors` may show them
+/// in a frame.
+fn content_security_policy(frame_ancestors: &str) -> String {
+    format!(
+        "default-src 'self'; img-src 'self' data:; style-src 'self' 'unsafe-inline'; \
+         object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors {}",
+        frame_ancestors
+    )
+}
+
+#[derive(Template)]
+#[template(path = "about.html")]
+struct About {
+    /// The color scheme of the page and its plots.
+    theme: &'static str,
+    status: Status,
+    uptime: String,
+    data_size: String,
+    image_size: String,
+}
+
+async fn version(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(Status::of(&state))
+}
+
+async fn internal_metrics(state: web::Data<AppState>) -> HttpResponse {
+    let status = Status::of(&state);
+    let queued = {
+        let series = state.series.read().unpoisoned();
+        series
+            .values()
+            .map(|serie| Series::lock(serie).pending_writes)
+            .sum::<usize>()
+    };
+    let mut gauges = vec![
+        metrics::Gauge {
+            name: "sts_rs_background_queue_depth",
+            help: "Values accepted but not yet written by the background actor.",
+            value: queued as f64,
+        },
+        metrics::Gauge {
+            name: "sts_rs_series",
+            help: "Series held in memory.",
+            value: status.series as f64,
+        },
+        metrics::Gauge {
+            name: "sts_rs_points",
+            help: "Values held in memory.",
+            value: status.points as f64,
+        },
+        metrics::Gauge {
+            name: "sts_rs_data_bytes",
+            help: "Bytes in the data directory.",
+            value: status.data_bytes as f64,
+        },
+        metrics::Gauge {
+            name: "sts_rs_image_bytes",
+            help: "Bytes in the image directory.",
+            value: status.image_bytes as f64,
+        },
+        metrics::Gauge {
+            name: "sts_rs_uptime_seconds",
+            help: "Seconds since the server started.",
+            value: status.uptime_seconds as f64,
+        },
+    ];
+    if let Ok((free_bytes, _)) = state.disk.space() {
+        gauges.push(metrics::Gauge {
+            name: "sts_rs_disk_free_bytes",
+            help: "Bytes free on the volume of the data directory.",
+            value: free_bytes as f64,
+        });
+    }
+    if let Some(bytes) = metrics::resident_memory_bytes() {
+        gauges.push(metrics::Gauge {
+            name: "process_resident_memory_bytes",
+            help: "Resident memory of the server.",
+            value: bytes as f64,
+        });
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render(&gauges))
+}
+
+/// Reads the configuration again like `kill -HUP` does, the current one
+/// is kept when the new one is wrong.
+async fn admin_reload(state: web::Data<AppState>) -> HttpResponse {
+    match state.reloader.reload() {
+        Ok(()) => HttpResponse::Ok().body("Reloaded the configuration"),
+        Err(err) => {
+            warn!("Keeping the current configuration, {}", err);
+            Error::unprocessable(err).into()
+        }
+    }
+}
+
+/// The space taken by every series and the data directory, and what is
+/// left of the volume.
+async fn admin_disk(state: web::Data<AppState>) -> HttpResponse {
+    let names: Vec<String> = state.series.read().unpoisoned().keys().cloned().collect();
+    match state.disk.usage(names.iter().map(String::as_str)) {
+        Ok(usage) => HttpResponse::Ok().json(usage),
+        Err(source) => Error::Storage {
+            context: format!("the disk usage of {:?} is unknown", state.data_path),
+            source,
+        }
+        .into(),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "reports.html")]
+struct ReportList {
+    /// The color scheme of the page.
+    theme: &'static str,
+    reports: Vec<String>,
+}
+
+async fn list_reports(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let rendered = ReportList {
+        theme: page_theme(&req).name(),
+        reports: state.reports.list(),
+    }
+    .render()?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+async fn get_report(file: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
+    match state.reports.get(&file) {
+        Some(report) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(report),
+        None => Error::NotFound(format!("no report {}", file)).into(),
+    }
+}
+
+/// Renders a report right away, next to its scheduled runs.
+async fn admin_report(
+    name: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let found = match &state.reporter {
+        Some(reporter) => {
+            reporter
+                .send(reports::RunNow {
+                    name: name.into_inner(),
+                })
+                .await?
+        }
+        None => false,
+    };
+    if found {
+        Ok(HttpResponse::Accepted().finish())
+    } else {
+        Err(Error::NotFound("no report of that name".to_owned()))
+    }
+}
+
+/// The process is up, for liveness probes.
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(Health {
+        status: "ok",
+        checks: BTreeMap::new(),
+    })
+}
+
+/// Whether values can be written and plotted, for load balancers and
+/// readiness probes. The series are loaded before the server starts, so
+/// only the actor writing them has to answer.
+async fn readyz(state: web::Data<AppState>) -> HttpResponse {
+    let mut checks = match state
+        .background_actor
+        .send(CheckReadiness)
+        .timeout(READINESS_TIMEOUT)
+        .await
+    {
+        Ok(mut checks) => {
+            checks.push(("data", Ok(())));
+            checks
+        }
+        Err(err) => vec![(
+            "data",
+            Err(format!("the data files are not handled, {}", err)),
+        )],
+    };
+    let ready = checks.iter().all(|(_, check)| check.is_ok());
+    // Little free space and disabled plotting are warnings, values are
+    // still written.
+    let disk = state.disk.check();
+    let plotter = state.plotting_disabled.clone().map_or(Ok(()), Err);
+    let status = match (ready, &disk, &plotter) {
+        (false, _, _) => "unavailable",
+        (true, Err(_), _) | (true, _, Err(_)) => "warning",
+        (true, Ok(()), Ok(())) => "ok",
+    };
+    checks.push(("disk", disk));
+    checks.push(("plotter", plotter));
+    let health = Health {
+        status,
+        checks: checks
+            .into_iter()
+            .map(|(name, check)| (name, check.err().unwrap_or_else(|| "ok".to_owned())))
+            .collect(),
+    };
+    if ready {
+        HttpResponse::Ok().json(health)
+    } else {
+        HttpResponse::ServiceUnavailable().json(health)
+    }
+}
+
+async fn about(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let status = Status::of(&state);
+    let uptime = status.uptime_seconds;
+    let rendered = About {
+        theme: page_theme(&req).name(),
+        uptime: format!(
+            "{}d {}h {}m",
+            uptime / 86400,
+            uptime % 86400 / 3600,
+            uptime % 3600 / 60
+        ),
+        data_size: format_bytes(status.data_bytes),
+        image_size: format_bytes(status.image_bytes),
+        status,
+    }
+    .render()?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} TiB", size)
+}
+
+/// What the page of a series shows of the values in its range.
+#[derive(Clone)]
+struct RangeSummary {
+    summary: Option<stats::Summary>,
+    first: Option<Datum>,
+    last: Option<Datum>,
+    /// The most recent values, the latest first.
+    recent: Vec<Datum>,
+}
+
+impl RangeSummary {
+    fn of(data: &Columns) -> RangeSummary {
+        RangeSummary {
+            summary: stats::summarize(data),
+            first: data.first(),
+            last: data.last(),
+            recent: data.iter().rev().take(RECENT_VALUES).collect(),
+        }
+    }
+}
+
+async fn series_page(
+    req: HttpRequest,
+    path: SeriesName,
+    query: web::Query<SeriesPageQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let series_name = path.to_string();
+    let timezone = query.tz.unwrap_or(state.timezone);
+    let range = match PageRange::new(
+        query.window.unwrap_or(state.window),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        timezone,
+    ) {
+        Ok(range) => range,
+        Err(err) => return Err(Error::invalid(err)),
+    };
+    let (latest, series_unit, precision, number_of_observations, archived, modified) = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+        .filter(|serie| auth::Caller::of_request(&req).may_read(&serie.meta))
+    {
+        Some(serie) => (
+            serie.data.latest(),
+            serie.meta.unit.clone(),
+            serie.meta.precision,
+            serie.data.len(),
+            serie.meta.archived,
+            serie.last_modification_time,
+        ),
+        None => return Ok(Error::NotFound(format!("no series {}", series_name)).into()),
+    };
+    let conversion = match &query.unit {
+        Some(unit) => Some(
+            Conversion::new(series_unit.as_deref(), unit)
+                .map_err(|err| Error::invalid(err).for_field("unit"))?,
+        ),
+        None => None,
+    };
+    let unit = query
+        .unit
+        .clone()
+        .or_else(|| series_unit.clone())
+        .unwrap_or_default();
+    let convert = |value| conversion.map_or(value, |conversion| conversion.apply(value));
+    let with_unit = |value: String| match unit.as_str() {
+        "" => value,
+        unit => format!("{} {}", value, unit),
+    };
+    let from = range
+        .from
+        .or_else(|| range.window.start(latest.map(|datum| datum.timeStamp)));
+    let key = response_cache::key((&series_name, from, range.to, modified));
+    let summary = match state.range_cache.get(key) {
+        Some(summary) => summary,
+        None => match series_data(&state, &series_name, from, range.to).await {
+            Ok(data) => {
+                let summary = RangeSummary::of(&data);
+                state.range_cache.insert(key, summary.clone());
+                summary
+            }
+            Err(response) => return Ok(response),
+        },
+    };
+    let time = |time_stamp: i64| match timezone.timestamp_opt(time_stamp, 0).single() {
+        Some(time) => time.format("%Y/%m/%d %H:%M:%S").to_string(),
+        None => time_stamp.to_string(),
+    };
+    let statistics = match (summary.summary, summary.first, summary.last) {
+        (Some(summary), Some(first), Some(last)) => vec![
+            ("Values", summary.count.to_string()),
+            ("First", time(first.timeStamp)),
+            ("Last", time(last.timeStamp)),
+            (
+                "Minimum",
+                with_unit(format_value(convert(summary.min), precision)),
+            ),
+            (
+                "Maximum",
+                with_unit(format_value(convert(summary.max), precision)),
+            ),
+            (
+                "Mean",
+                with_unit(format_value(convert(summary.mean), precision)),
+            ),
+            (
+                "Median",
+                with_unit(format_value(convert(summary.median), precision)),
+            ),
+            (
+                "Standard deviation",
+                with_unit(format_value(
+                    conversion.map_or(summary.std_dev, |conversion| {
+                        conversion.apply_to_difference(summary.std_dev)
+                    }),
+                    precision,
+                )),
+            ),
+        ],
+        _ => Vec::new(),
+    };
+    let recent = summary
+        .recent
+        .iter()
+        .map(|datum| {
+            (
+                time(datum.timeStamp),
+                show_value(convert(datum.value), precision),
+            )
+        })
+        .collect();
+    let theme = page_theme(&req);
+    let mut plot_query = format!("{}&theme={}", range.query, theme.name());
+    if let Some(unit) = &query.unit {
+        plot_query.push_str(&format!("&unit={}", query_component(unit)));
+    }
+    let page = SeriesPage {
+        theme: theme.name(),
+        name: path.local(),
+        base: path.namespace().base(),
+        unit: &unit,
+        series_unit: series_unit.as_deref().unwrap_or_default(),
+        period: match (range.from, range.to, range.window.to_string().as_str()) {
+            (None, None, "all") => "all values".to_owned(),
+            (None, None, window) => format!("the last {}", window),
+            (from, to, _) => format!(
+                "{} to {}",
+                from.map_or("the first value".to_owned(), time),
+                to.map_or("the latest value".to_owned(), time)
+            ),
+        },
+        live: range.to.is_none() && conversion.is_none(),
+        query: plot_query,
+        range: range.picker,
+        timezone: timezone.name(),
+        statistics,
+        latest_value: latest.map_or("-".to_owned(), |datum| {
+            show_value(convert(datum.value), precision)
+        }),
+        latest_time: latest
+            .map(|datum| time(datum.timeStamp))
+            .unwrap_or_default(),
+        recent,
+        recent_limit: RECENT_VALUES,
+        number_of_observations,
+        archived,
+        csrf_token: csrf::token(&req.get_session()),
+    }
+    .render()?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(page))
+}
+
+/// The theme picked with the toggle on the pages, kept in the `theme` cookie.
+/// Without one the pages and their plots follow the `prefers-color-scheme`
+/// of the viewer.
+fn page_theme(req: &HttpRequest) -> Theme {
+    match req.cookie("theme").as_ref().map(|cookie| cookie.value()) {
+        Some("light") => Theme::Light,
+        Some("dark") => Theme::Dark,
+        _ => Theme::Auto,
+    }
+}
+
+/// The heading of a group, like `greenhouse / north`.
+fn group_label(group: &str) -> String {
+    group
+        .split('/')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+/// Percent-encodes `value` for use in a query string.
+fn query_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// A statistic with the precision of its series, or else with at most 3
+/// decimal places.
+fn format_value(value: f64, precision: Option<u32>) -> String {
+    if let Some(precision) = precision {
+        return format!("{:.*}", precision as usize, value);
+    }
+    let rounded = format!("{:.3}", value);
+    if rounded.contains('.') {
+        rounded
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_owned()
+    } else {
+        rounded
+    }
+}
+
+/// A value with the precision of its series, or else as it is.
+fn show_value(value: f64, precision: Option<u32>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision as usize, value),
+        None => value.to_string(),
+    }
+}
+
+/// A value with the precision and the unit of its series, like `21.5 °C`.
+fn show_value_with_unit(value: f64, meta: &SeriesMeta) -> String {
+    match &meta.unit {
+        Some(unit) => format!("{} {}", show_value(value, meta.precision), unit),
+        None => show_value(value, meta.precision),
+    }
+}
+
+/// Collects the values of a series between `from` and `to`, including the
+/// ones that were moved to cold storage.
+async fn series_data(
+    state: &AppState,
+    series_name: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Columns, HttpResponse> {
+    let from = from.unwrap_or(i64::MIN);
+    let to = to.unwrap_or(i64::MAX);
+    let in_range = |datum: &Datum| datum.timeStamp >= from && datum.timeStamp <= to;
+    let (hot, cold_partitions) = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(series_name)
+        .map(Series::lock)
+    {
+        Some(serie) => (
+            serie.data.between(from, to),
+            serie
+                .cold_partitions
+                .iter()
+                .filter(|partition| partition.overlaps(from, to))
+                .cloned()
+                .collect::<Vec<_>>(),
+        ),
+        None => return Err(Error::NotFound(format!("no series {}", series_name)).into()),
+    };
+    if let (Some(cold_storage), false) = (&state.cold_storage, cold_partitions.is_empty()) {
+        match cold_storage.fetch(series_name, &cold_partitions).await {
+            Ok(cold_data) => {
+                // Cold values are older than the ones in memory, unless
+                // those were added out of order.
+                let mut data = cold_data.into_iter().filter(in_range).collect::<Columns>();
+                data.extend(hot.iter());
+                data.sort();
+                return Ok(data);
+            }
+            Err(err) => {
+                warn!(
+                    "Fetching cold data for series {} failed, {}",
+                    series_name, err
+                );
+                return Err(Error::Upstream("cold storage is unavailable".to_owned()).into());
+            }
+        }
+    }
+    Ok(hot)
+}
+
+async fn get_plot(
+    req: HttpRequest,
+    path: SeriesName,
+    query: web::Query<PlotQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    plot_response(
+        &req,
+        &state,
+        PlotKey {
+            series: vec![path.to_string()],
+            secondary: Vec::new(),
+            from: query.from,
+            to: query.to,
+            window: query.window,
+            width: query.width,
+            height: query.height,
+            format: query.format.unwrap_or(PlotFormat::Svg),
+            theme: query.theme,
+            log_scale: query.log,
+            kind: query.kind,
+            timezone: query.tz,
+            unit: query.unit.clone(),
+        },
+    )
+    .await
+}
+
+async fn get_sparkline(
+    req: HttpRequest,
+    path: SeriesName,
+    query: web::Query<SparklineQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    plot_response(
+        &req,
+        &state,
+        PlotKey {
+            series: vec![path.to_string()],
+            secondary: Vec::new(),
+            from: None,
+            to: None,
+            window: Some(query.window.unwrap_or(state.window)),
+            width: query.width,
+            height: query.height,
+            format: PlotFormat::Sparkline,
+            theme: query.theme,
+            log_scale: None,
+            kind: None,
+            timezone: None,
+            unit: None,
+        },
+    )
+    .await
+}
+
+async fn get_comparison_plot(
+    req: HttpRequest,
+    namespace: Namespace,
+    query: web::Query<ComparisonQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let names = |list: &str| {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| namespace.qualify(name))
+            .collect::<Vec<_>>()
+    };
+    let series = names(&query.series);
+    if series.is_empty() {
+        return Error::invalid("series should name one or more series")
+            .for_field("series")
+            .into();
+    }
+    if let Some(name) = series.iter().find(|name| !readable(&req, &state, name)) {
+        return Error::NotFound(format!("no series {}", name)).into();
+    }
+    let secondary = match &query.secondary {
+        Some(secondary) => names(secondary),
+        None => match secondary_by_unit(&state, &series) {
+            Ok(secondary) => secondary,
+            Err(response) => return response,
+        },
+    };
+    if let Some(name) = secondary.iter().find(|name| !series.contains(name)) {
+        let message = format!("secondary series {} is not one of the plotted series", name);
+        return Error::invalid(message).for_field("secondary").into();
+    }
+    plot_response(
+        &req,
+        &state,
+        PlotKey {
+            series,
+            secondary,
+            from: query.from,
+            to: query.to,
+            window: query.window,
+            width: query.width,
+            height: query.height,
+            format: query.format.unwrap_or(PlotFormat::Svg),
+            theme: query.theme,
+            log_scale: query.log,
+            kind: query.kind,
+            timezone: query.tz,
+            unit: query.unit.clone(),
+        },
+    )
+    .await
+}
+
+/// Without an explicit secondary axis, series of two different units are
+/// plotted against an axis per unit, the unit of the first series on the
+/// left. Series without a unit stay on the left.
+fn secondary_by_unit(state: &AppState, series: &[String]) -> Result<Vec<String>, HttpResponse> {
+    let units = {
+        let all_series = state.series.read().unpoisoned();
+        series
+            .iter()
+            .map(|name| match all_series.get(name) {
+                Some(serie) => Ok(Series::lock(serie).meta.unit.clone()),
+                None => Err(Error::NotFound(format!("no series {}", name))),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let mut distinct = units.iter().flatten().collect::<Vec<_>>();
+    distinct.sort();
+    distinct.dedup();
+    if distinct.len() > 2 {
+        let message = format!(
+            "a plot has two y-axes, the series have the units {}",
+            distinct
+                .iter()
+                .map(|unit| unit.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        return Err(Error::invalid(message).for_field("series").into());
+    }
+    let primary = units.iter().flatten().next();
+    Ok(series
+        .iter()
+        .zip(&units)
+        .filter(|(_, unit)| unit.is_some() && unit.as_ref() != primary)
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Serves the plot described by `key` from the cache, rendering it first
+/// when any of its series changed.
+async fn plot_response(req: &HttpRequest, state: &AppState, key: PlotKey) -> HttpResponse {
+    let caller = auth::Caller::of_request(req);
+    let mut modified = None;
+    let mut latest = None;
+    let mut settings = Vec::new();
+    let mut thresholds = Vec::new();
+    let mut annotations = Vec::new();
+    let mut units = Vec::new();
+    let mut conversions = Vec::new();
+    for series_name in &key.series {
+        match state
+            .series
+            .read()
+            .unpoisoned()
+            .get(series_name)
+            .map(Series::lock)
+            .filter(|serie| caller.may_read(&serie.meta))
+        {
+            Some(serie) => {
+                modified = modified.max(Some(serie.last_modification_time));
+                latest = latest.max(serie.data.latest().map(|datum| datum.timeStamp));
+                let mut series_settings = serie.meta.plot.clone();
+                series_settings.log_scale = key.log_scale.unwrap_or(series_settings.log_scale);
+                series_settings.kind = key.kind.unwrap_or(series_settings.kind);
+                settings.push(series_settings);
+                thresholds.push(serie.meta.thresholds.clone());
+                annotations.push(serie.annotations.clone());
+                units.push(serie.meta.unit.clone());
+                if let Some(unit) = &key.unit {
+                    match Conversion::new(serie.meta.unit.as_deref(), unit) {
+                        Ok(conversion) => conversions.push(conversion),
+                        Err(err) => return Error::invalid(err).for_field("unit").into(),
+                    }
+                }
+            }
+            None => return Error::NotFound(format!("no series {}", series_name)).into(),
+        }
+    }
+    let ((default_width, default_height), sizes) = match key.format {
+        PlotFormat::Sparkline => (plot::SPARKLINE_SIZE, plot::SPARKLINE_SIZES),
+        _ => (settings[0].size(), plot::SIZES),
+    };
+    let (width, height) = (
+        key.width.unwrap_or(default_width),
+        key.height.unwrap_or(default_height),
+    );
+    for (name, size) in [("width", width), ("height", height)] {
+        if !sizes.contains(&size) {
+            let message = format!(
+                "width and height should be between {} and {}",
+                sizes.start(),
+                sizes.end()
+            );
+            return Error::invalid(message).for_field(name).into();
+        }
+    }
+    let from = key
+        .from
+        .or_else(|| key.window.and_then(|window| window.start(latest)));
+    let theme = key
+        .theme
+        .or(settings[0].theme)
+        .unwrap_or(state.default_theme);
+    let timezone = key.timezone.unwrap_or(state.timezone);
+    if key.format == PlotFormat::Heatmap && key.series.len() > 1 {
+        return Error::invalid("a heatmap shows a single series")
+            .for_field("series")
+            .into();
+    }
+    if key.format == PlotFormat::Sparkline && key.series.len() > 1 {
+        return Error::invalid("a sparkline shows a single series")
+            .for_field("series")
+            .into();
+    }
+    if key.format == PlotFormat::Html {
+        let local = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| namespaces::split(name).1.to_owned())
+                .collect::<Vec<_>>()
+        };
+        let page = PlotPage {
+            title: local(&key.series).join(", "),
+            series: local(&key.series).join(","),
+            secondary: local(&key.secondary).join(","),
+            base: Namespace::of_series(&key.series[0]).base(),
+            from: from.map(|from| from.to_string()).unwrap_or_default(),
+            to: key.to.map(|to| to.to_string()).unwrap_or_default(),
+            settings: key.series.len() == 1,
+            theme: theme.name(),
+            log: key.log_scale.map(|log| log.to_string()).unwrap_or_default(),
+            kind: key.kind.map(|kind| kind.name()).unwrap_or_default(),
+            timezone: timezone.name(),
+            width,
+            height,
+            csrf_token: csrf::token(&req.get_session()),
+        }
+        .render();
+        return match page {
+            Ok(page) => HttpResponse::Ok().content_type("text/html").body(page),
+            Err(err) => HttpResponse::from_error(Error::from(err).into()),
+        };
+    }
+    let modified = modified.unwrap_or_else(Utc::now);
+    if let Some(image) = state.plot_cache.get(&key, modified) {
+        return HttpResponse::Ok()
+            .content_type(key.format.content_type())
+            .body(image);
+    }
+    let mut data = Vec::new();
+    for series_name in &key.series {
+        match series_data(state, series_name, from, key.to).await {
+            Ok(series_data) => data.push(series_data),
+            Err(response) => return response,
+        }
+    }
+    for ((data, thresholds), conversion) in data.iter_mut().zip(&mut thresholds).zip(&conversions) {
+        data.map_values(|value| conversion.apply(value));
+        for threshold in thresholds.iter_mut() {
+            threshold.value = conversion.apply(threshold.value);
+        }
+    }
+    if key.unit.is_some() {
+        units.iter_mut().for_each(|unit| *unit = key.unit.clone());
+    }
+    let lines = key
+        .series
+        .iter()
+        .zip(&data)
+        .zip(&settings)
+        .zip(thresholds.iter().zip(&annotations))
+        .zip(&units)
+        .map(
+            |((((series_name, data), settings), (thresholds, annotations)), unit)| plot::Line {
+                series_name: namespaces::split(series_name).1,
+                data,
+                settings,
+                thresholds,
+                annotations,
+                secondary: key.secondary.contains(series_name),
+                unit: unit.as_deref(),
+            },
+        )
+        .collect::<Vec<_>>();
+    let window = (from, key.to);
+    let size = (width, height);
+    let started = Instant::now();
+    let span = tracing::info_span!("plot", series = %key.series.join(","), format = ?key.format);
+    let rendered = span.in_scope(|| match key.format {
+        PlotFormat::Png => plot::render_png(&lines, window, size, theme, timezone).map(Bytes::from),
+        PlotFormat::Heatmap => {
+            plot::render_heatmap(&lines[0], size, theme, timezone).map(Bytes::from)
+        }
+        PlotFormat::Sparkline => {
+            plot::render_sparkline(&lines[0], window, size, theme).map(Bytes::from)
+        }
+        _ => plot::render_svg(&lines, window, size, theme, timezone).map(Bytes::from),
+    });
+    state.metrics.plot_durations().observe(started.elapsed());
+    match rendered {
+        Ok(image) => {
+            let content_type = key.format.content_type();
+            state.plot_cache.insert(key, modified, image.clone());
+            HttpResponse::Ok().content_type(content_type).body(image)
+        }
+        Err(err) => Error::Internal(format!(
+            "plotting series {} failed, {}",
+            key.series.join(", "),
+            err
+        ))
+        .into(),
+    }
+}
+
+async fn get_series_meta(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let caller = auth::Caller::of_request(&req);
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => HttpResponse::Ok().json(&serie.meta),
+        None => Error::NotFound(format!("no series {}", path)).into(),
+    }
+}
+
+async fn put_series_meta(
+    path: SeriesName,
+    meta: web::Json<SeriesMeta>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series_name = path.to_string();
+    if let Err(err) = meta.plot.validate() {
+        return Error::invalid(err).for_field("plot").into();
+    }
+    if let Err(err) = state.request_limits.check_batch(meta.thresholds.len()) {
+        return Error::unprocessable(err).for_field("thresholds").into();
+    }
+    if let Err(err) = meta.thresholds.iter().try_for_each(Threshold::validate) {
+        return Error::invalid(err).for_field("thresholds").into();
+    }
+    if let Err(err) = state.request_limits.check_batch(meta.alerts.len()) {
+        return Error::unprocessable(err).for_field("alerts").into();
+    }
+    if let Err(err) = alerts::validate_rules(&meta.alerts) {
+        return Error::invalid(err).for_field("alerts").into();
+    }
+    if let Err(err) = metadata::validate_tags(&meta.tags) {
+        return Error::invalid(err).for_field("tags").into();
+    }
+    if let Err(err) = metadata::validate_group(&meta.group) {
+        return Error::invalid(err).for_field("group").into();
+    }
+    if let Err(err) = metadata::validate_precision(meta.precision) {
+        return Error::invalid(err).for_field("precision").into();
+    }
+    if let Err(err) = metadata::validate_readers(&meta.readers) {
+        return Error::invalid(err).for_field("readers").into();
+    }
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+    {
+        Some(mut serie) => serie.meta = meta.0.clone(),
+        None => return Error::NotFound(format!("no series {}", path)).into(),
+    }
+    state.plot_cache.invalidate(&series_name);
+    state.background_actor.do_send(WriteMeta {
+        series_name,
+        meta: meta.0.clone(),
+    });
+    HttpResponse::Ok().json(meta.0)
+}
+
+async fn get_plot_settings(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let caller = auth::Caller::of_request(&req);
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => HttpResponse::Ok().json(&serie.meta.plot),
+        None => Error::NotFound(format!("no series {}", path)).into(),
+    }
+}
+
+async fn put_plot_settings(
+    path: SeriesName,
+    settings: web::Json<PlotSettings>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series_name = path.to_string();
+    if let Err(err) = settings.validate() {
+        return Error::invalid(err).into();
+    }
+    let meta = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+    {
+        Some(mut serie) => {
+            serie.meta.plot = settings.0.clone();
+            serie.meta.clone()
+        }
+        None => return Error::NotFound(format!("no series {}", path)).into(),
+    };
+    state.plot_cache.invalidate(&series_name);
+    state
+        .background_actor
+        .do_send(WriteMeta { series_name, meta });
+    HttpResponse::Ok().json(settings.0)
+}
+
+async fn get_thresholds(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let caller = auth::Caller::of_request(&req);
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => HttpResponse::Ok().json(&serie.meta.thresholds),
+        None => Error::NotFound(format!("no series {}", path)).into(),
+    }
+}
+
+async fn put_thresholds(
+    path: SeriesName,
+    thresholds: web::Json<Vec<Threshold>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series_name = path.to_string();
+    if let Err(err) = state.request_limits.check_batch(thresholds.len()) {
+        return Error::unprocessable(err).into();
+    }
+    if let Err(err) = thresholds.iter().try_for_each(Threshold::validate) {
+        return Error::invalid(err).into();
+    }
+    let meta = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+    {
+        Some(mut serie) => {
+            serie.meta.thresholds = thresholds.0.clone();
+            serie.meta.clone()
+        }
+        None => return Error::NotFound(format!("no series {}", path)).into(),
+    };
+    state.plot_cache.invalidate(&series_name);
+    state
+        .background_actor
+        .do_send(WriteMeta { series_name, meta });
+    HttpResponse::Ok().json(thresholds.0)
+}
+
+async fn get_alert_rules(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let caller = auth::Caller::of_request(&req);
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => HttpResponse::Ok().json(&serie.meta.alerts),
+        None => Error::NotFound(format!("no series {}", path)).into(),
+    }
+}
+
+async fn put_alert_rules(
+    path: SeriesName,
+    rules: web::Json<Vec<alerts::AlertRule>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let series_name = path.to_string();
+    if let Err(err) = state.request_limits.check_batch(rules.len()) {
+        return Error::unprocessable(err).into();
+    }
+    if let Err(err) = alerts::validate_rules(&rules) {
+        return Error::invalid(err).into();
+    }
+    let meta = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+    {
+        Some(mut serie) => {
+            serie.meta.alerts = rules.0.clone();
+            serie.meta.clone()
+        }
+        None => return Error::NotFound(format!("no series {}", path)).into(),
+    };
+    state
+        .background_actor
+        .do_send(WriteMeta { series_name, meta });
+    HttpResponse::Ok().json(rules.0)
+}
+
+/// The alerts of the series of the namespace the caller may read, as they
+/// were last evaluated.
+async fn list_alerts(
+    req: HttpRequest,
+    namespace: Namespace,
+    query: web::Query<AlertsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let listed = state
+        .alerts
+        .list()
+        .into_iter()
+        .filter(|alert| namespace.contains(&alert.series))
+        .filter(|alert| query.state.is_none_or(|state| alert.state == state))
+        .filter(|alert| readable(&req, &state, &alert.series))
+        .map(|alert| alerts::Alert {
+            series: namespaces::split(&alert.series).1.to_owned(),
+            ..alert
+        })
+        .collect::<Vec<_>>();
+    HttpResponse::Ok().json(listed)
+}
+
+async fn get_annotations(
+    req: HttpRequest,
+    path: SeriesName,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let caller = auth::Caller::of_request(&req);
+    match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+        .filter(|serie| caller.may_read(&serie.meta))
+    {
+        Some(serie) => HttpResponse::Ok().json(&serie.annotations),
+        None => Error::NotFound(format!("no series {}", path)).into(),
+    }
+}
+
+async fn post_annotation(
+    path: SeriesName,
+    annotation: web::Json<Annotation>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(err) = annotation.validate() {
+        return Error::invalid(err).into();
+    }
+    let mut annotations = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(path.as_str())
+        .map(Series::lock)
+    {
+        Some(serie) => serie.annotations.clone(),
+        None => return Error::NotFound(format!("no series {}", path)).into(),
+    };
+    annotations.push(annotation.0.clone());
+    replace_annotations(&state, path.to_string(), annotations);
+    HttpResponse::Ok().json(annotation.0)
+}
+
+async fn put_annotations(
+    path: SeriesName,
+    annotations: web::Json<Vec<Annotation>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(err) = state.request_limits.check_batch(annotations.len()) {
+        return Error::unprocessable(err).into();
+    }
+    if let Err(err) = annotations.iter().try_for_each(Annotation::validate) {
+        return Error::invalid(err).into();
+    }
+    if !state.series.read().unpoisoned().contains_key(path.as_str()) {
+        return Error::NotFound(format!("no series {}", path)).into();
+    }
+    replace_annotations(&state, path.to_string(), annotations.0.clone());
+    HttpResponse::Ok().json(annotations.0)
+}
+
+/// Keeps the annotations of a series in time order and stores them.
+fn replace_annotations(state: &AppState, series_name: String, mut annotations: Vec<Annotation>) {
+    annotations.sort_by_key(|annotation| annotation.time_stamp);
+    if let Some(mut serie) = state
+        .series
+        .read()
+        .unpoisoned()
+        .get(&series_name)
+        .map(Series::lock)
+    {
+        serie.annotations = annotations.clone();
+    }
+    state.plot_cache.invalidate(&series_name);
+    state.background_actor.do_send(WriteAnnotations {
+        series_name,
+        annotations,
+    });
+}
+
+async fn delete_series(
+    path: SeriesName,
+    query: web::Query<DeleteQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if state
+        .series
+        .write()
+        .unpoisoned()
+        .remove(path.as_str())
+        .is_none()
+    {
+        return Err(Error::NotFound(format!("no series {}", path)));
+    }
+    let entry = state
+        .background_actor
+        .send(TrashSeries {
+            series_name: path.to_string(),
+        })
+        .await?
+        .map_err(|err| Error::io(format!("moving series {} to the trash failed", path), err))?;
+    if query.purge {
+        state
+            .background_actor
+            .do_send(PurgeTrash { id: Some(entry.id) });
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::Ok().json(entry))
+    }
 }
 
-#[derive(Deserialize, Serialize, Copy, Debug, Clone)]
-#[allow(non_snake_case)]
-struct Datum {
-    timeStamp: i64,
-    value: f64,
+#[derive(Deserialize)]
+struct RenameRequest {
+    name: String,
 }
 
-struct Series {
-    data: Vec<Datum>,
-    last_modification_time: DateTime<Utc>,
+/// Says what was wrong with a JSON body, an oversized one gets a 413.
+fn json_error(err: actix_web::error::JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match err {
+        actix_web::error::JsonPayloadError::Overflow => {
+            Error::TooLarge("the body is larger than STS_RS_MAX_BODY_BYTES allows".to_owned())
+        }
+        actix_web::error::JsonPayloadError::Deserialize(err) => Error::deserialize(err),
+        err => Error::invalid(err.to_string()),
+    }
+    .into()
 }
 
-struct AppState {
-    background_actor: Addr<BackgroundActor>,
-    series: Mutex<HashMap<String, Series>>,
+/// Says which query parameter is wrong, when serde tells.
+fn query_error(err: actix_web::error::QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match err {
+        actix_web::error::QueryPayloadError::Deserialize(err) => Error::deserialize(err),
+    }
+    .into()
 }
 
-struct BackgroundActor {
-    data_storage_path: PathBuf,
-    image_output_path: PathBuf,
+async fn rename_series(
+    path: SeriesName,
+    request: web::Json<RenameRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Err(err) = series_names::validate(&request.name) {
+        return Err(Error::invalid(err).for_field("name"));
+    }
+    if let Err(err) = state.request_limits.check_name(&request.name) {
+        return Err(Error::unprocessable(err).for_field("name"));
+    }
+    let new_name = path.namespace().qualify(&request.name);
+    state
+        .background_actor
+        .send(RenameSeries {
+            series_name: path.to_string(),
+            new_name: new_name.clone(),
+        })
+        .await?
+        .map_err(|err| Error::io(format!("renaming series {} failed", path), err))?;
+    state.plot_cache.invalidate(&path);
+    if let Err(err) = state.dashboards.rename_series(&path, &new_name) {
+        warn!("Updating the dashboards showing {} failed, {}", path, err);
+    }
+    Ok(HttpResponse::Ok().body(format!(
+        "Renamed series {} to {}",
+        path.local(),
+        request.name
+    )))
 }
 
-impl BackgroundActor {
-    pub fn new(data_storage_path: PathBuf, image_output_path: PathBuf) -> BackgroundActor {
-        BackgroundActor {
-            data_storage_path,
-            image_output_path,
-        }
+async fn get_audit_log(
+    query: web::Query<audit::AuditQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    match &state.audit_log {
+        Some(audit_log) => match audit_log.query(&query) {
+            Ok(entries) => HttpResponse::Ok().json(entries),
+            Err(source) => Error::Storage {
+                context: format!("reading the audit log {:?} failed", audit_log.path()),
+                source,
+            }
+            .into(),
+        },
+        None => Error::NotFound("there is no audit log".to_owned()).into(),
     }
 }
 
-struct WriteCsv {
-    series_name: String,
-    data: Vec<Datum>,
+async fn list_trash(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.trash.list())
 }
 
-impl Message for WriteCsv {
-    type Result = ();
+async fn restore_series(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let series_name = state
+        .background_actor
+        .send(RestoreSeries {
+            id: path.to_string(),
+        })
+        .await?
+        .map_err(|err| Error::io(format!("restoring {} from the trash failed", path), err))?;
+    Ok(HttpResponse::Ok().body(format!("Restored series {}", series_name)))
 }
 
-impl Actor for BackgroundActor {
-    type Context = Context<Self>;
+async fn archive_series(path: SeriesName, state: web::Data<AppState>) -> HttpResponse {
+    set_archived(&path, true, &state)
 }
 
-fn append_last_datum(file_name: &PathBuf, data: &Vec<Datum>) {
-    let mut options = OpenOptions::new();
-    let file = options
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(file_name)
-        .unwrap();
-    let mut wtr = csv::WriterBuilder::new()
-        .has_headers(false)
-        .from_writer(file);
-    if let Some(datum) = data.last() {
-        wtr.serialize(datum).unwrap();
-    }
-    wtr.flush().unwrap();
-}
-
-fn generate_plot(series_name: &str, data_file_name: &PathBuf, images_directory: &PathBuf) {
-    let full_command = format!(
-        r#"{} '{}';
-set title '{} over time';
-set ylabel '{}';
-plot '{}' using 1:2 with lines notitle;"#,
-        GNUPLOT_COMMANDS,
-        images_directory
-            .join(format!("{}.svg", series_name))
-            .display(),
-        series_name,
-        series_name,
-        data_file_name.display()
-    );
-    let output = Command::new("gnuplot")
-        .args(&["-e", &full_command])
-        .output()
-        .expect("failed to execute process");
-    log_command_failure(&output);
+async fn unarchive_series(path: SeriesName, state: web::Data<AppState>) -> HttpResponse {
+    set_archived(&path, false, &state)
 }
 
-fn log_command_failure(output: &Output) {
-    if !output.status.success() {
-        warn!("Gnuplot command failed with status code: {}", output.status);
-    }
-    if output.stdout.len() > 0 {
-        info!(
-            "Gnuplot command ouput: {}\n",
-            str::from_utf8(&output.stdout).unwrap()
-        );
+fn set_archived(series_name: &str, archived: bool, state: &AppState) -> HttpResponse {
+    let meta = match state
+        .series
+        .read()
+        .unpoisoned()
+        .get(series_name)
+        .map(Series::lock)
+    {
+        Some(mut serie) => {
+            serie.meta.archived = archived;
+            serie.meta.clone()
+        }
+        None => return Error::NotFound(format!("no series {}", series_name)).into(),
+    };
+    state.background_actor.do_send(WriteMeta {
+        series_name: series_name.to_owned(),
+        meta: meta.clone(),
+    });
+    HttpResponse::Ok().json(meta)
+}
+
+async fn add_datum(
+    req: HttpRequest,
+    path: SeriesName,
+    body: Bytes,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    // The signature covers the body as sent, so it is read before parsing.
+    if let Err(err) = state
+        .device_secrets
+        .get()
+        .verify(&path, req.headers(), &body)
+    {
+        return Error::Unauthorized(err).into();
     }
-    if output.stderr.len() > 0 {
-        warn!(
-            "Gnuplot command stderr:\n{}",
-            str::from_utf8(&output.stderr).unwrap()
-        );
+    let info = match serde_json::from_slice::<Datum>(&body) {
+        Ok(datum) => datum,
+        Err(err) => return Error::deserialize(err).into(),
+    };
+    let (created, name, info) = match ingest(&state, path.namespace(), path.local(), info) {
+        Ok(Some(appended)) => appended,
+        Ok(None) => return HttpResponse::NoContent().finish(),
+        Err(response) => return response,
+    };
+    if created {
+        HttpResponse::Created()
+            .header(
+                http::header::LOCATION,
+                format!(
+                    "{}/api/v1/series/{}",
+                    path.namespace().base(),
+                    query_component(&name)
+                ),
+            )
+            .json(info)
+    } else {
+        HttpResponse::Ok().json(info)
     }
 }
 
-impl Handler<WriteCsv> for BackgroundActor {
-    type Result = ();
-    fn handle(&mut self, msg: WriteCsv, _ctx: &mut Context<Self>) -> Self::Result {
-        info!(
-            "BackgroundActor received series {} with {} values.",
-            msg.series_name,
-            msg.data.len()
-        );
-        let file_name = self
-            .data_storage_path
-            .join(format!("{}.csv", msg.series_name));
-        append_last_datum(&file_name, &msg.data);
-        generate_plot(&msg.series_name, &file_name, &self.image_output_path);
+/// Adds a value that came in after running the script of its series on
+/// it, with the values the script derived from it. Whether its series was
+/// created, its name and the value as it was stored, none when the script
+/// dropped it.
+fn ingest(
+    state: &AppState,
+    namespace: &Namespace,
+    local: &str,
+    datum: Datum,
+) -> Result<Option<(bool, String, Datum)>, HttpResponse> {
+    let outcome = match state.scripts.get().run(namespace, local, datum) {
+        Ok(outcome) => outcome,
+        Err(err) => return Err(Error::unprocessable(err).into()),
+    };
+    let now = Utc::now();
+    for (name, datum) in outcome.derived {
+        append_datum(state, namespace, &name, datum, now)?;
+    }
+    match outcome.datum {
+        Some((name, datum)) => Ok(append_datum(state, namespace, &name, datum, now)?
+            .map(|(created, datum)| (created, name, datum))),
+        None => Ok(None),
     }
 }
 
-async fn index(state: web::Data<AppState>) -> Result<HttpResponse> {
-    let series = state.series.lock().unwrap();
-    let mut infos = series
-        .iter()
-        .map(|(key, val)| SeriesInfo {
-            name: key,
-            number_of_observations: val.data.len(),
-            last_modified: format!("{}", val.last_modification_time.format("%+")),
-        })
-        .into_iter()
-        .collect::<Vec<_>>();
-    infos.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
-    let rendered = AvailableSeries { series: infos }.render().unwrap();
-    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+/// Adds a value to the series `local` of `namespace`, creating it for its
+/// first value. Whether the series was created and the value as it was
+/// stored, rounded to the precision of the series. None when the series is
+/// a counter that stores its increase and this is its first reading.
+fn append_datum(
+    state: &AppState,
+    namespace: &Namespace,
+    local: &str,
+    info: Datum,
+    now: DateTime<Utc>,
+) -> Result<Option<(bool, Datum)>, HttpResponse> {
+    let series_name = namespace.qualify(local);
+    loop {
+        let all_series = state.series.read().unpoisoned();
+        let new_series = !all_series.contains_key(&series_name);
+        if let Err(err) = namespace.check_quota(state, &all_series, new_series) {
+            return Err(err.into());
+        }
+        if let Some(serie) = all_series.get(&series_name) {
+            let mut series = Series::lock(serie);
+            let mut info = Datum {
+                value: series.meta.rounded(info.value),
+                ..info
+            };
+            if series.meta.archived {
+                return Err(Error::Conflict(format!(
+                    "series {} is archived and read-only, value rejected",
+                    series_name
+                ))
+                .into());
+            }
+            if let Some(limit) = series.size_limit(*state.default_limit.get()) {
+                let row_size = series.stored_bytes / series.data.len().max(1) as u64;
+                if limit.policy == LimitPolicy::Reject
+                    && limit.exceeded_by(series.data.len() + 1, series.stored_bytes + row_size)
+                {
+                    return Err(Error::Full(format!(
+                        "series {} has reached its size limit, value rejected",
+                        series_name
+                    ))
+                    .into());
+                }
+            }
+            // A reading below the previous one is counted from zero, the
+            // device restarted.
+            let mut increase = None;
+            match series.meta.counter {
+                Some(CounterStorage::Increase) => {
+                    let previous = series.counter_reading.replace(info.value);
+                    state.background_actor.do_send(WriteCounter {
+                        series_name: series_name.clone(),
+                        reading: info.value,
+                    });
+                    match previous {
+                        Some(previous) => {
+                            info.value = series
+                                .meta
+                                .rounded(metadata::increase(previous, info.value))
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                Some(CounterStorage::Both) => {
+                    increase = series.data.latest().map(|previous| Datum {
+                        value: metadata::increase(previous.value, info.value),
+                        ..info
+                    })
+                }
+                None => (),
+            }
+            series.append(info, now);
+            queue_datum(state, &series_name, info, &series.meta);
+            drop(series);
+            drop(all_series);
+            if let Some(increase) = increase {
+                append_datum(
+                    state,
+                    namespace,
+                    &format!("{}.increase", local),
+                    increase,
+                    now,
+                )?;
+            }
+            return Ok(Some((false, info)));
+        }
+        drop(all_series);
+        if let Err(err) = series_names::validate(local) {
+            return Err(Error::invalid(err).into());
+        }
+        if let Err(err) = state.request_limits.check_name(local) {
+            return Err(Error::unprocessable(err).into());
+        }
+        // Another request may have added the series in the meantime, the
+        // value is then added to that one.
+        if let Entry::Vacant(entry) = state.series.write().unpoisoned().entry(series_name.clone()) {
+            entry.insert(Mutex::new(Series::new(info, now)));
+            queue_datum(state, &series_name, info, &SeriesMeta::default());
+            if let Some(webhooks) = &state.webhooks {
+                webhooks.do_send(webhooks::Event::SeriesCreated {
+                    series: series_name.clone(),
+                    time: now.timestamp(),
+                });
+            }
+            return Ok(Some((true, info)));
+        }
+    }
 }
 
-async fn get_series(path: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
-    let series_name = path.to_string();
-    let series = state.series.lock().unwrap();
-    if let Some(serie) = series.get(&series_name) {
-        HttpResponse::Ok().content_type("text/plain").body(format!(
-            "Series {} has {} values.",
-            series_name,
-            serie.data.len()
-        ))
-    } else {
-        HttpResponse::NotFound().body("")
+/// Adds the samples pushed by a batch job like to a Prometheus
+/// Pushgateway. Each is checked before any is added, so a push with an
+/// invalid sample adds nothing.
+async fn push_metrics(
+    req: HttpRequest,
+    namespace: Namespace,
+    body: Bytes,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let is_protobuf = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/vnd.google.protobuf"));
+    if is_protobuf {
+        return Error::UnsupportedMediaType(
+            "push metrics in the text exposition format".to_owned(),
+        )
+        .into();
+    }
+    let grouping = match pushgateway::grouping(req.match_info().get("grouping").unwrap_or_default())
+    {
+        Ok(grouping) => grouping,
+        Err(err) => return Error::invalid(err).into(),
+    };
+    let samples = match std::str::from_utf8(&body)
+        .map_err(|err| err.to_string())
+        .and_then(pushgateway::parse)
+    {
+        Ok(samples) => samples,
+        Err(err) => return Error::invalid(err).into(),
+    };
+    if let Err(err) = state.request_limits.check_batch(samples.len()) {
+        return Error::unprocessable(err).into();
     }
+    let push = pushgateway::Push::of(&grouping, samples, state.pushgateway_labels);
+    for (name, _, _) in &push.values {
+        if let Err(err) = series_names::validate(name) {
+            return Error::invalid(err).into();
+        }
+        if let Err(err) = state.request_limits.check_name(name) {
+            return Error::unprocessable(err).into();
+        }
+        // Devices sign the body, which covers every series of the push.
+        if let Err(err) =
+            state
+                .device_secrets
+                .get()
+                .verify(&namespace.qualify(name), req.headers(), &body)
+        {
+            return Error::Unauthorized(err).into();
+        }
+    }
+    let now = Utc::now().timestamp();
+    for (name, value, time_stamp) in push.values {
+        let datum = Datum {
+            timeStamp: time_stamp.unwrap_or(now),
+            value,
+        };
+        match ingest(&state, &namespace, &name, datum) {
+            Ok(Some((_, name, _))) if !push.tags.is_empty() => {
+                tag_series(&state, &namespace.qualify(&name), &push.tags)
+            }
+            Ok(_) => (),
+            Err(response) => return response,
+        }
+    }
+    HttpResponse::Ok().finish()
 }
 
-async fn add_datum(
-    path: web::Path<String>,
-    info: web::Json<Datum>,
-    state: web::Data<AppState>,
-) -> Result<String> {
-    let dt = Utc.timestamp(info.timeStamp, 0);
-    let series_name = path.to_string();
-    let mut w = state.series.lock().unwrap();
-    let now = Utc::now();
-    let current_values = if let Some(series) = w.get_mut(&series_name) {
-        series.data.push(info.0);
-        series.last_modification_time = now;
-        series.data.to_vec()
-    } else {
-        let values = vec![info.0];
-        w.insert(
-            series_name.clone(),
-            Series {
-                data: values.to_vec(),
-                last_modification_time: now,
-            },
-        );
-        values
+/// Adds the tags a series does not have yet.
+fn tag_series(state: &AppState, series_name: &str, tags: &[String]) {
+    let meta = {
+        let all_series = state.series.read().unpoisoned();
+        let mut serie = match all_series.get(series_name).map(Series::lock) {
+            Some(serie) => serie,
+            None => return,
+        };
+        let missing = tags
+            .iter()
+            .filter(|tag| !serie.meta.tags.contains(tag))
+            .cloned()
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return;
+        }
+        serie.meta.tags.extend(missing);
+        serie.meta.clone()
     };
-    state.background_actor.do_send(WriteCsv {
-        series_name,
-        data: current_values,
+    state.background_actor.do_send(WriteMeta {
+        series_name: series_name.to_owned(),
+        meta,
     });
+}
 
-    Ok(format!(
-        "Administered value {}, for parameter {}, for time {}",
-        info.value,
-        path,
-        dt.format("%Y-%m-%d %H:%M:%S %z")
-    ))
+/// Passes an added value on. It is queued while its series is locked, so
+/// values are written in the order they were added.
+fn queue_datum(state: &AppState, series_name: &str, datum: Datum, meta: &SeriesMeta) {
+    if let Some(replicator) = &state.replicator {
+        replicator.do_send(Replicate {
+            series_name: series_name.to_owned(),
+            datum,
+        });
+    }
+    state.live_updates.do_send(Publish {
+        series_name: series_name.to_owned(),
+        datum,
+        readers: Some(meta.readers.clone()).filter(|_| meta.private),
+    });
+    state.metrics.record_value();
+    state.background_actor.do_send(WriteCsv {
+        series_name: series_name.to_owned(),
+        datum,
+    });
 }
 
 fn env_or_default(key: &str, default: &str) -> String {
@@ -244,6 +4135,57 @@ fn env_or_default(key: &str, default: &str) -> String {
     }
 }
 
+/// A client for other servers, `insecure` accepts any certificate of
+/// theirs. Builds without the `tls` feature only reach plain HTTP.
+#[cfg(feature = "tls")]
+fn client(insecure: bool) -> actix_web::client::Client {
+    use actix_web::client::{Client, Connector};
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+    let mut ssl = SslConnector::builder(SslMethod::tls()).unwrap();
+    if insecure {
+        ssl.set_verify(SslVerifyMode::NONE);
+    }
+    Client::build()
+        .connector(Connector::new().ssl(ssl.build()).finish())
+        .finish()
+}
+
+#[cfg(not(feature = "tls"))]
+fn client(_insecure: bool) -> actix_web::client::Client {
+    actix_web::client::Client::default()
+}
+
+fn workspace_is_dirty() -> bool {
+    match option_env!("BUILD_GIT_WORKSPACE_IS_DIRTY") {
+        Some(v) => v.to_lowercase() != "false",
+        None => true,
+    }
+}
+
+/// Windows only flushes files that are opened for writing.
+fn sync_to_disk(file: &Path) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(cfg!(windows))
+        .open(file)?
+        .sync_all()
+}
+
+/// The bytes taken by the files in `directory` and below.
+fn directory_size(directory: &Path) -> u64 {
+    match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => directory_size(&entry.path()),
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            })
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
 fn data_dir_or_empty() -> PathBuf {
     match dirs::data_dir() {
         Some(path) => path,
@@ -251,79 +4193,312 @@ fn data_dir_or_empty() -> PathBuf {
     }
 }
 
-fn ensure_dir(directory: &PathBuf) {
+fn ensure_dir(directory: &Path) {
     if !directory.exists() {
-        std::fs::create_dir_all(directory.as_path()).unwrap();
-    }
-}
-
-fn read_series(data_output_path: &PathBuf) -> HashMap<String, Series> {
-    let mut result: HashMap<String, Series> = HashMap::new();
-    for file in data_output_path.read_dir().expect("read_dir call failed") {
-        if let Ok(entry) = file {
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_file() {
-                    info!("Reading data from {:?}", entry.path());
-                    let file_path = entry.path();
-                    let series_name = file_path.file_stem().unwrap();
-                    let (data, last_modified) = read_csv_data(&file_path);
-                    let dt = Utc.timestamp(last_modified, 0);
-                    let number_of_data_items = data.len();
-                    result.insert(
-                        series_name.to_os_string().into_string().unwrap(),
-                        Series {
-                            data,
-                            last_modification_time: dt,
-                        },
+        std::fs::create_dir_all(directory).unwrap();
+    }
+}
+
+fn read_series(data_output_path: &Path, namespaces: &Namespaces) -> HashMap<String, Series> {
+    let mut files = Vec::new();
+    // Listed up front, the files of series with names from before the
+    // encoding are renamed along the way.
+    let mut entries = data_output_path
+        .read_dir()
+        .expect("read_dir call failed")
+        .flatten()
+        .map(|entry| (None, entry))
+        .collect::<Vec<_>>();
+    for namespace in namespaces.names() {
+        if let Ok(directory) = data_output_path.join(namespace).read_dir() {
+            entries.extend(directory.flatten().map(|entry| (Some(namespace), entry)));
+        }
+    }
+    for (namespace, entry) in entries {
+        if let Ok(file_type) = entry.file_type() {
+            let mut file_path = entry.path();
+            if file_path.to_string_lossy().ends_with(".csv.gz") {
+                file_path.set_extension("");
+            }
+            if file_type.is_file() && file_path.extension() == Some("csv".as_ref()) {
+                let stem = match file_path.file_stem().and_then(|stem| stem.to_str()) {
+                    Some(stem) => stem.to_owned(),
+                    None => {
+                        warn!("Skipping {:?}, its name is not UTF-8", entry.path());
+                        continue;
+                    }
+                };
+                let series_name = series_names::from_file_stem(&stem);
+                if let Err(err) = series_names::validate(&series_name) {
+                    warn!("Skipping {:?}, {}", entry.path(), err);
+                    continue;
+                }
+                let (stem, series_name) = match namespace {
+                    Some(namespace) => (
+                        format!("{}/{}", namespace, stem),
+                        format!("{}/{}", namespace, series_name),
+                    ),
+                    None => (stem, series_name),
+                };
+                if series_names::file_stem(&series_name) != stem {
+                    info!(
+                        "Renaming the files of series {} to encode its name",
+                        series_name
                     );
+                    if let Err(err) = trash::rename_files(
+                        data_output_path,
+                        &stem,
+                        &series_names::file_stem(&series_name),
+                    ) {
+                        error!("Renaming the files of {} failed, {}", series_name, err);
+                        continue;
+                    }
+                    file_path = series_names::data_file(data_output_path, &series_name);
+                }
+                files.push((series_name, file_path));
+            }
+        }
+    }
+    // The largest files first, so no thread is left with one at the end.
+    files.sort_by_cached_key(|(_, file_path)| {
+        std::cmp::Reverse(std::fs::metadata(file_path).map_or(0, |metadata| metadata.len()))
+    });
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(files.len().max(1));
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let result = Mutex::new(HashMap::with_capacity(files.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let (series_name, file_path) = match files.get(index) {
+                    Some(file) => file,
+                    None => break,
+                };
+                info!("Reading data from {:?}", file_path);
+                if let Some(serie) = load_series(file_path) {
                     info!(
                         "Finished reading {} values from {:?}",
-                        number_of_data_items,
-                        entry.path()
+                        serie.data.len(),
+                        file_path
                     );
+                    result
+                        .lock()
+                        .unpoisoned()
+                        .insert(series_name.clone(), serie);
                 }
+            });
+        }
+    });
+    result.into_inner().unwrap()
+}
+
+fn load_series(file_path: &Path) -> Option<Series> {
+    let meta = metadata::read_meta(&metadata::meta_file(file_path));
+    if !file_path.exists() && archive::archived_file(file_path).exists() {
+        return match archive::read_archived(file_path) {
+            Ok(data) => Some(series_from(file_path, data, meta)),
+            Err(err) => {
+                error!("Skipping unreadable archive of {:?}, {}", file_path, err);
+                None
             }
+        };
+    }
+    let (data, report) = match integrity::check_data_file(file_path) {
+        Ok(checked) => checked,
+        Err(err) => {
+            error!("Skipping unreadable data file {:?}, {}", file_path, err);
+            return None;
         }
+    };
+    if report.quarantined() > 0 {
+        warn!(
+            "Moved {} of {} rows of {:?} to {:?}: {}",
+            report.quarantined(),
+            report.rows,
+            file_path,
+            report.quarantine_file,
+            report.summary()
+        );
+    }
+    if report.out_of_order {
+        warn!(
+            "Rows of {:?} are not in time stamp order, sorted them",
+            file_path
+        );
     }
-    result
+    Some(series_from(file_path, data, meta))
+}
+
+fn series_from(file_path: &Path, data: Vec<Datum>, meta: SeriesMeta) -> Series {
+    let mut serie = Series {
+        data: data.into(),
+        last_modification_time: Utc.timestamp(0, 0),
+        cold_partitions: cold_storage::read_index(&cold_storage::index_file(file_path)),
+        meta,
+        annotations: annotations::read_annotations(&annotations::annotations_file(file_path)),
+        stored_bytes: 0,
+        stored_modified: None,
+        pending_writes: 0,
+        counter_reading: metadata::read_counter(&metadata::counter_file(file_path)),
+    };
+    serie.record_file_state(file_path);
+    // Without the time the file was written, its last value tells.
+    serie.last_modification_time = match (serie.stored_modified, serie.time_range()) {
+        (Some(modified), _) => modified.into(),
+        (None, Some((_, last))) => Utc.timestamp(last.max(0), 0),
+        (None, None) => Utc.timestamp(0, 0),
+    };
+    serie
 }
 
-fn read_csv_data(file_path: &Path) -> (Vec<Datum>, i64) {
-    let mut last_modified = std::i64::MIN;
+fn read_csv_data(file_path: &Path) -> Result<(Vec<Datum>, i64), Error> {
+    let mut last_modified = i64::MIN;
+    let file =
+        std::fs::File::open(file_path).context(|| format!("opening {:?} failed", file_path))?;
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
-        .from_path(&file_path)
-        .unwrap();
-    let data: Vec<Datum> = rdr
-        .records()
-        .map(|result| {
-            let record = result.unwrap();
-            let time_stamp = record.get(0).unwrap().parse::<i64>().unwrap();
-            let value = record.get(1).unwrap().parse::<f64>().unwrap();
-            if last_modified < time_stamp {
-                last_modified = time_stamp;
-            }
-            Datum {
-                timeStamp: time_stamp,
-                value,
-            }
-        })
-        .collect();
-    (data, last_modified)
+        .from_reader(file);
+    let mut data = Vec::with_capacity(estimated_rows(file_path));
+    let mut record = csv::ByteRecord::new();
+    let mut row = 0;
+    loop {
+        row += 1;
+        let malformed = |reason: String| Error::MalformedRow {
+            file: file_path.to_path_buf(),
+            row,
+            reason,
+        };
+        match rdr.read_byte_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => return Err(malformed(err.to_string())),
+        }
+        let field = |index: usize| {
+            record
+                .get(index)
+                .and_then(|field| std::str::from_utf8(field).ok())
+                .ok_or_else(|| malformed(format!("field {} is missing", index + 1)))
+        };
+        let time_stamp = field(0)?
+            .parse::<i64>()
+            .map_err(|err| malformed(err.to_string()))?;
+        let value = field(1)?
+            .parse::<f64>()
+            .map_err(|err| malformed(err.to_string()))?;
+        if last_modified < time_stamp {
+            last_modified = time_stamp;
+        }
+        data.push(Datum {
+            timeStamp: time_stamp,
+            value,
+        });
+    }
+    Ok((data, last_modified))
+}
+
+/// About the number of rows of a data file, rows like
+/// `1700000000,21.5` take some 16 bytes.
+fn estimated_rows(file_path: &Path) -> usize {
+    std::fs::metadata(file_path).map_or(0, |metadata| metadata.len() as usize / 16)
+}
+
+/// The routes of the series, served for the series outside namespaces and
+/// under `/ns/{namespace}` for those of a namespace.
+fn series_routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/", web::get().to(index))
+        .route("/static/{file:.*}", web::get().to(static_file))
+        .route("/series/{name}", web::get().to(series_page))
+        .route("/embed/{name}", web::get().to(embed))
+        .route("/plot", web::get().to(get_comparison_plot))
+        .route("/plot/{name}.svg", web::get().to(get_plot))
+        .route("/plot/{name}", web::get().to(get_plot))
+        .route("/spark/{name}.svg", web::get().to(get_sparkline))
+        .route("/api/v1/series/{name}/data", web::get().to(get_series_data))
+        .route("/api/v1/series/{name}/meta", web::get().to(get_series_meta))
+        .route("/api/v1/series/{name}/meta", web::put().to(put_series_meta))
+        .route(
+            "/api/v1/series/{name}/plot-settings",
+            web::get().to(get_plot_settings),
+        )
+        .route(
+            "/api/v1/series/{name}/plot-settings",
+            web::put().to(put_plot_settings),
+        )
+        .route(
+            "/api/v1/series/{name}/thresholds",
+            web::get().to(get_thresholds),
+        )
+        .route(
+            "/api/v1/series/{name}/thresholds",
+            web::put().to(put_thresholds),
+        )
+        .route(
+            "/api/v1/series/{name}/alerts",
+            web::get().to(get_alert_rules),
+        )
+        .route(
+            "/api/v1/series/{name}/alerts",
+            web::put().to(put_alert_rules),
+        )
+        .route("/api/v1/alerts", web::get().to(list_alerts))
+        .route(
+            "/api/v1/series/{name}/annotations",
+            web::get().to(get_annotations),
+        )
+        .route(
+            "/api/v1/series/{name}/annotations",
+            web::post().to(post_annotation),
+        )
+        .route(
+            "/api/v1/series/{name}/annotations",
+            web::put().to(put_annotations),
+        )
+        .route("/api/v1/series/{name}", web::delete().to(delete_series))
+        .route(
+            "/api/v1/series/{name}/rename",
+            web::post().to(rename_series),
+        )
+        .route(
+            "/api/v1/series/{name}/archive",
+            web::post().to(archive_series),
+        )
+        .route(
+            "/api/v1/series/{name}/unarchive",
+            web::post().to(unarchive_series),
+        )
+        .route("/api/v1/stream", web::get().to(stream))
+        .route(
+            "/metrics/{grouping:job(@base64)?/.+}",
+            web::put().to(push_metrics),
+        )
+        .route(
+            "/metrics/{grouping:job(@base64)?/.+}",
+            web::post().to(push_metrics),
+        )
+        .route("/{name}", web::get().to(get_series))
+        .route("/{name}", web::post().to(add_datum));
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
-    let is_dirty: Option<&'static str> = option_env!("BUILD_GIT_WORKSPACE_IS_DIRTY");
-    let is_dirty_token = match is_dirty {
-        Some(v) if v.to_lowercase() == "false" => "",
-        _ => "*",
-    };
+    let matches = cli::app().get_matches();
+    let config = cli::apply(&matches).unwrap_or_else(|err| panic!("{}", err));
+    cli::init_logging(&matches).unwrap_or_else(|err| panic!("{}", err));
+    if matches.subcommand_name() == Some("hash-password") {
+        return users::run_hash_password().map_err(std::io::Error::other);
+    }
+    let is_dirty_token = if workspace_is_dirty() { "*" } else { "" };
     info!(
         "Starting {} v{}, git commit sha: {}{}, built on {}",
         PACKAGE_NAME, VERSION, SHORT_SHA, is_dirty_token, BUILD_TIMESTAMP
     );
+    match &config {
+        Some(config) => info!("Read the configuration from {:?}", config.file),
+        None => info!("No configuration file, using the environment only"),
+    }
     let config_dir = data_dir_or_empty().join(".sts-rs");
     let data_output_path = PathBuf::from(env_or_default(
         "STS_RS_DATA_PATH",
@@ -337,44 +4512,635 @@ async fn main() -> std::io::Result<()> {
     ensure_dir(&image_output_path);
     info!("Using data directory {}", data_output_path.display());
     info!("Using image directory {}", image_output_path.display());
-    let series = read_series(&data_output_path);
-    let bt_actor = BackgroundActor::new(
-        data_output_path.to_path_buf(),
-        image_output_path.to_path_buf(),
-    )
-    .start();
+    let namespaces = Namespaces::from_env();
+    match matches.subcommand() {
+        ("import", Some(import)) => {
+            let format = import::Format::parse(import.value_of("format").unwrap()).unwrap();
+            let files = import.values_of("files").unwrap().collect::<Vec<_>>();
+            return import::run(format, &files, &data_output_path).map_err(std::io::Error::other);
+        }
+        ("check", _) => {
+            return integrity::check(&data_output_path, &image_output_path, &namespaces)
+                .map_err(std::io::Error::other);
+        }
+        ("compact", _) => {
+            return integrity::compact(&data_output_path, &namespaces)
+                .map_err(std::io::Error::other);
+        }
+        ("bench", Some(bench)) => {
+            let bench = cli::bench(bench).map_err(std::io::Error::other)?;
+            return bench.run().await.map_err(std::io::Error::other);
+        }
+        ("replot", Some(replot)) => {
+            let names = replot.values_of("series").into_iter().flatten();
+            let window = replot
+                .value_of("range")
+                .map(|range| {
+                    range
+                        .parse::<plot::Window>()
+                        .map_err(|err| format!("--range {}", err))
+                })
+                .transpose()
+                .map_err(std::io::Error::other)?;
+            return replot::run(
+                &data_output_path,
+                &image_output_path,
+                &names.collect::<Vec<_>>(),
+                window,
+            )
+            .map_err(std::io::Error::other);
+        }
+        _ => (),
+    }
+    for namespace in namespaces.names() {
+        ensure_dir(&data_output_path.join(namespace));
+        ensure_dir(&image_output_path.join(namespace));
+    }
+    if namespaces.len() > 0 {
+        info!("Serving {} namespaces.", namespaces.len());
+    }
+    let series = Arc::new(RwLock::new(
+        read_series(&data_output_path, &namespaces)
+            .into_iter()
+            .map(|(name, serie)| (name, Mutex::new(serie)))
+            .collect(),
+    ));
+    let cold_storage = ColdStorage::from_env().map(Arc::new);
+    if let Some(cold_storage) = &cold_storage {
+        info!("Using cold storage for {}", cold_storage.describe());
+    }
+    let default_limit = Arc::new(reload::Reloadable::new(SizeLimit::from_env()));
+    let scripts = Arc::new(reload::Reloadable::new(scripts::Scripts::from_env(
+        &data_output_path,
+    )));
+    if let Some(description) = scripts.get().describe() {
+        info!("{}.", description);
+    }
+    let request_limits = RequestLimits::from_env();
+    let mut plotter = Plotter::from_env();
+    plotter.disable_if_unavailable();
+    let plotting_disabled = plotter.disabled().map(str::to_owned);
+    let metrics = Arc::new(metrics::Metrics::new(plotter.render_durations.clone()));
+    let default_theme = plotter.default_theme;
+    let timezone = plotter.timezone;
+    let window = plotter.window;
+    let trash = Arc::new(Trash::from_env(&data_output_path));
+    // The files are written on a thread of their own, so a slow disk does
+    // not hold up the other actors.
+    let bt_actor = {
+        let (data_path, image_path) = (data_output_path.clone(), image_output_path.clone());
+        let (series, cold_storage) = (series.clone(), cold_storage.clone());
+        let (default_limit, trash) = (default_limit.clone(), trash.clone());
+        BackgroundActor::start_in_arbiter(&Arbiter::new(), move |_| {
+            BackgroundActor::new(
+                data_path,
+                image_path,
+                series,
+                cold_storage,
+                default_limit,
+                trash,
+                plotter,
+            )
+        })
+    };
+    if env_or_default("STS_RS_WATCH_DATA", "true").to_lowercase() != "false" {
+        watcher::watch(
+            data_output_path.clone(),
+            namespaces.names().map(str::to_owned).collect(),
+            bt_actor.clone(),
+        );
+    }
+    let replicator = Replicator::from_env().map(|replicator| {
+        info!("Replicating accepted values to {}", replicator.peer_url());
+        replicator.start()
+    });
+    let api_keys = Arc::new(reload::Reloadable::new(auth::ApiKeys::from_env()));
+    if !api_keys.get().is_empty() {
+        info!(
+            "Requiring one of {} API keys to change series.",
+            api_keys.get().len()
+        );
+    }
+    let users = Arc::new(reload::Reloadable::new(users::Users::from_env()));
+    if !users.get().is_empty() {
+        info!("Requiring one of {} users to sign in.", users.get().len());
+    }
+    let session_key = session_key();
+    let oidc = oidc::Oidc::from_env().map(Arc::new);
+    if let Some(oidc) = &oidc {
+        info!("Signing in with {}", oidc.issuer());
+    }
+    #[cfg(feature = "tls")]
+    let acme = acme::Acme::from_env(&config_dir).map(Arc::new);
+    let audit_log = audit::AuditLog::from_env(&data_output_path).map(Arc::new);
+    if let Some(audit_log) = &audit_log {
+        info!("Recording changes in {:?}", audit_log.path());
+    }
+    let device_secrets = Arc::new(reload::Reloadable::new(
+        signatures::DeviceSecrets::from_env(),
+    ));
+    if !device_secrets.get().is_empty() {
+        info!(
+            "Requiring signed values from {} devices.",
+            device_secrets.get().len()
+        );
+    }
+    let https_address = env_or_default("STS_RS_HTTPS_ADDRESS", DEFAULT_HTTPS_ADDRESS);
+    let http_address = env_or_default("STS_RS_HTTP_ADDRESS", "");
+    #[cfg(feature = "tls")]
+    if let Some(acme) = &acme {
+        info!("Obtaining the certificate of {} with ACME", acme.domain());
+        if http_address.is_empty() {
+            warn!("ACME checks the domain over HTTP on port 80, STS_RS_HTTP_ADDRESS is not set.");
+        }
+    }
+    let listeners = systemd::listeners();
+    for listener in &listeners {
+        match listener.listener.local_addr() {
+            Ok(address) => info!("Listening on {} passed by systemd.", address),
+            Err(err) => panic!("A socket passed by systemd is not usable, {}", err),
+        }
+    }
+    if https_address.is_empty() && http_address.is_empty() && listeners.is_empty() {
+        panic!("STS_RS_HTTPS_ADDRESS or STS_RS_HTTP_ADDRESS should be set");
+    }
+    if !http_address.is_empty() && listeners.is_empty() {
+        info!("Listening on http://{}.", http_address);
+    }
+    let client_certs = client_certs::ClientCerts::from_env();
+    if let Some(client_certs) = &client_certs {
+        info!("{}.", client_certs.describe());
+    }
+    let has_client_certs = client_certs.is_some();
+    if api_keys.get().is_empty() && users.get().is_empty() && oidc.is_none() && !has_client_certs {
+        warn!("No API keys, users, single sign-on or client certificates are configured, anybody may change series.");
+    }
+    #[cfg(not(feature = "tls"))]
+    if !https_address.is_empty()
+        || client_certs.is_some()
+        || std::env::var_os("STS_RS_ACME_DOMAIN").is_some()
+    {
+        panic!("STS_RS_HTTPS_ADDRESS, STS_RS_CLIENT_CA and STS_RS_ACME_DOMAIN need a build with the tls feature");
+    }
+    #[cfg(feature = "tls")]
+    let tls = if https_address.is_empty() {
+        if client_certs.is_some() || acme.is_some() {
+            panic!("STS_RS_CLIENT_CA and STS_RS_ACME_DOMAIN need STS_RS_HTTPS_ADDRESS");
+        }
+        None
+    } else {
+        if listeners.is_empty() {
+            info!("Listening on https://{}.", https_address);
+        }
+        let (key_file, cert_file, passphrase) = match &acme {
+            Some(acme) => {
+                acme.ensure_certificate()?;
+                (acme.key_file(), acme.cert_file(), None)
+            }
+            None => (
+                PathBuf::from(env_or_default("STS_RS_TLS_KEY", "key.pem")),
+                PathBuf::from(env_or_default("STS_RS_TLS_CERT", "cert.pem")),
+                secrets::secret("STS_RS_TLS_KEY_PASSPHRASE"),
+            ),
+        };
+        Some(Arc::new(tls::Tls::new(
+            key_file,
+            cert_file,
+            passphrase,
+            client_certs,
+        )))
+    };
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &tls {
+        watcher::watch_tls(tls.clone());
+    }
+    #[cfg(feature = "tls")]
+    if let (Some(acme), Some(tls)) = (&acme, &tls) {
+        acme::Renewer {
+            acme: acme.clone(),
+            tls: tls.clone(),
+            renewing: false,
+        }
+        .start();
+    }
+    let webhooks = webhooks::Webhooks::from_env(series.clone(), timezone).map(|webhooks| {
+        info!("{}.", webhooks.describe());
+        webhooks.start()
+    });
+    let alerts = Arc::new(alerts::Alerts::default());
+    let mailer = smtp::Smtp::from_env().map(|smtp| {
+        let mailer = email::Mailer::new(smtp, series.clone(), alerts.clone(), timezone);
+        info!("{}.", mailer.describe());
+        mailer.start()
+    });
+    alerts::Evaluator::from_env(series.clone(), alerts.clone(), webhooks.clone(), mailer).start();
+    let report_specs = reports::specs_from_env();
+    let reporter = if report_specs.is_empty() {
+        None
+    } else {
+        let reporter =
+            reports::Reporter::new(report_specs, &data_output_path, series.clone(), timezone);
+        info!("{}.", reporter.describe());
+        Some(reporter.start())
+    };
+    let reloader = Arc::new(reload::Reloader {
+        config,
+        #[cfg(feature = "tls")]
+        tls: tls.clone(),
+        trash: trash.clone(),
+        api_keys: api_keys.clone(),
+        users: users.clone(),
+        device_secrets: device_secrets.clone(),
+        default_limit: default_limit.clone(),
+        scripts: scripts.clone(),
+        data_path: data_output_path.clone(),
+    });
+    #[cfg(unix)]
+    reload::reload_on_hangup(reloader.clone());
     let state = web::Data::new(AppState {
         background_actor: bt_actor.clone(),
-        series: Mutex::new(series),
+        series,
+        cold_storage,
+        replicator,
+        default_limit: default_limit.clone(),
+        request_limits,
+        scripts,
+        trash,
+        plot_cache: PlotCache::from_env(&data_output_path),
+        index_cache: ResponseCache::from_env(),
+        range_cache: ResponseCache::from_env(),
+        default_theme,
+        timezone,
+        window,
+        plotting_disabled,
+        dashboards: Dashboards::new(&data_output_path),
+        namespaces,
+        disk: disk::Disk::from_env(&data_output_path),
+        device_secrets: device_secrets.clone(),
+        audit_log: audit_log.clone(),
+        metrics: metrics.clone(),
+        live_updates: LiveUpdates::default().start(),
+        alerts: alerts.clone(),
+        webhooks: webhooks.clone(),
+        reports: reports::Reports::new(&data_output_path),
+        reporter,
+        pushgateway_labels: pushgateway::LabelMapping::from_env(),
+        users: users.clone(),
+        oidc: oidc.clone(),
+        reloader: reloader.clone(),
+        started: Utc::now(),
+        frame_ancestors: env_or_default("STS_RS_FRAME_ANCESTORS", "'self'"),
+        #[cfg(feature = "tls")]
+        acme: acme.clone(),
+        data_path: data_output_path.clone(),
+        image_path: image_output_path.clone(),
     });
 
-    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-    builder
-        .set_private_key_file("key.pem", SslFiletype::PEM)
-        .unwrap();
-    builder.set_certificate_chain_file("cert.pem").unwrap();
-    let url = "127.0.0.1:8443";
-    info!("Listening on {}.", url);
-    HttpServer::new(move || {
+    let ip_rules = ip_rules::IpRules::from_env().map(Arc::new);
+    if let Some(ip_rules) = &ip_rules {
+        info!("{}.", ip_rules.describe());
+    }
+    let rate_limiter = rate_limit::RateLimiter::from_env().map(Arc::new);
+    if let Some(rate_limiter) = &rate_limiter {
+        info!("Allowing each client {}.", rate_limiter.describe());
+    }
+    // Browsers only send secure cookies over HTTPS, which a proxy in front
+    // of the plain HTTP listener may serve.
+    let secure_cookies = env_or_default(
+        "STS_RS_SECURE_COOKIES",
+        if https_address.is_empty() {
+            "false"
+        } else {
+            "true"
+        },
+    )
+    .to_lowercase()
+        == "true";
+    let app = move || {
+        let api_keys = api_keys.clone();
+        let users = users.clone();
+        let sso = oidc.is_some();
+        let rate_limiter = rate_limiter.clone();
+        let ip_rules = ip_rules.clone();
+        let audit_log = audit_log.clone();
+        let audit_users = users.clone();
+        let metrics = metrics.clone();
         App::new()
-            .wrap(middleware::Logger::default())
+            .wrap_fn(|req, srv| {
+                if csrf::is_cross_site(&req) {
+                    Either::Left(future::err(
+                        Error::Forbidden("cross-site requests are refused".to_owned()).into(),
+                    ))
+                } else if csrf::lacks_token(&req) {
+                    Either::Left(future::err(
+                        Error::Forbidden(
+                            "the CSRF token is missing or wrong, reload the page".to_owned(),
+                        )
+                        .into(),
+                    ))
+                } else {
+                    Either::Right(srv.call(req))
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                let required = match auth::required_role(&req) {
+                    Some(required) => required,
+                    None => return Either::Right(srv.call(req)),
+                };
+                let (api_keys, users) = (api_keys.get(), users.get());
+                let response =
+                    match authorize(&api_keys, &users, sso, has_client_certs, required, &req) {
+                        Ok(()) => {
+                            // A key limited to other series reads like nobody.
+                            let caller = auth::Caller {
+                                principal: principal(&users, &req),
+                                role: role(&api_keys, &users, &req).ok().flatten(),
+                            };
+                            req.extensions_mut().insert(caller);
+                            return Either::Right(srv.call(req));
+                        }
+                        Err(StatusCode::FORBIDDEN) => {
+                            Error::Forbidden(format!("this needs the {} role", required)).into()
+                        }
+                        Err(_) if required > Role::Reader => {
+                            let mut response: HttpResponse = Error::Unauthorized(
+                                "an API key is required to change series".to_owned(),
+                            )
+                            .into();
+                            response.headers_mut().insert(
+                                http::header::WWW_AUTHENTICATE,
+                                http::HeaderValue::from_static("Bearer"),
+                            );
+                            response
+                        }
+                        Err(_) => login_required(&req),
+                    };
+                Either::Left(future::ok(req.into_response(response)))
+            })
+            .wrap_fn(move |req, srv| {
+                let decision = rate_limiter
+                    .as_ref()
+                    .and_then(|rate_limiter| rate_limiter.check(&req));
+                match decision {
+                    Some(decision) if !decision.allowed => {
+                        let mut response: HttpResponse =
+                            Error::TooManyRequests("too many changes, try again later".to_owned())
+                                .into();
+                        decision.add_headers(response.headers_mut());
+                        Either::Left(future::ok(req.into_response(response)))
+                    }
+                    _ => Either::Right(srv.call(req).map(move |result| {
+                        result.map(|mut response| {
+                            if let Some(decision) = decision {
+                                decision.add_headers(response.headers_mut());
+                            }
+                            response
+                        })
+                    })),
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                let refused = ip_rules.as_ref().is_some_and(|ip_rules| {
+                    auth::required_role(&req).is_some_and(|required| required > Role::Reader)
+                        && !ip_rules.allows(req.peer_addr().map(|address| address.ip()))
+                });
+                if refused {
+                    let response: HttpResponse =
+                        Error::Forbidden("changes are not allowed from this address".to_owned())
+                            .into();
+                    Either::Left(future::ok(req.into_response(response)))
+                } else {
+                    Either::Right(srv.call(req))
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                let audit = match &audit_log {
+                    Some(audit_log)
+                        if ![http::Method::GET, http::Method::HEAD, http::Method::OPTIONS]
+                            .contains(req.method()) =>
+                    {
+                        Some((
+                            audit_log.clone(),
+                            audit::AuditEntry {
+                                time: Utc::now().timestamp(),
+                                who: principal(&audit_users.get(), &req),
+                                address: req
+                                    .peer_addr()
+                                    .map_or_else(String::new, |address| address.ip().to_string()),
+                                method: req.method().to_string(),
+                                path: req.path().to_owned(),
+                                status: 0,
+                            },
+                        ))
+                    }
+                    _ => None,
+                };
+                let method = req.method().to_string();
+                let metrics = metrics.clone();
+                let request_id = logging::request_id(&req);
+                let span = tracing::info_span!(
+                    "request",
+                    id = %request_id,
+                    method = %req.method(),
+                    path = %req.path(),
+                    address = %req
+                        .peer_addr()
+                        .map_or_else(String::new, |address| address.ip().to_string()),
+                    series = tracing::field::Empty,
+                    status = tracing::field::Empty,
+                );
+                let started = Instant::now();
+                srv.call(req)
+                    .map(move |mut result| {
+                        let (status, route) = match &result {
+                            Ok(response) => {
+                                let status = response.status().as_u16();
+                                (status, metrics::route(response.request(), status))
+                            }
+                            // Only refusals before routing end up here.
+                            Err(err) => (
+                                err.as_response_error().status_code().as_u16(),
+                                "refused".to_owned(),
+                            ),
+                        };
+                        metrics.record_request(&method, route, status);
+                        if let Some((audit_log, mut entry)) = audit {
+                            entry.status = status;
+                            audit_log.record(&entry);
+                        }
+                        if let Ok(response) = &mut result {
+                            if let Some(series) = response.request().match_info().get("name") {
+                                tracing::Span::current().record("series", &series);
+                            }
+                            if let Ok(id) = http::HeaderValue::from_str(&request_id) {
+                                response.headers_mut().insert(
+                                    http::header::HeaderName::from_static("x-request-id"),
+                                    id,
+                                );
+                            }
+                        }
+                        tracing::Span::current().record("status", &status);
+                        tracing::info!(
+                            status,
+                            latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+                            "finished"
+                        );
+                        result
+                    })
+                    .instrument(span)
+            })
+            .wrap(
+                CookieSession::private(&session_key)
+                    .name("sts-rs-session")
+                    .secure(secure_cookies)
+                    .same_site(actix_web::cookie::SameSite::Lax),
+            )
             .wrap(middleware::Compress::default())
             .wrap(
                 middleware::DefaultHeaders::new()
-                    .header("Server", format!("{}/{}", PACKAGE_NAME, VERSION)),
+                    .header("Server", format!("{}/{}", PACKAGE_NAME, VERSION))
+                    .header("X-Content-Type-Options", "nosniff")
+                    .header("Referrer-Policy", "same-origin")
+                    .header(
+                        http::header::CONTENT_SECURITY_POLICY,
+                        content_security_policy("'self'"),
+                    ),
             )
             .service(fs::Files::new(
                 "/images",
                 image_output_path.to_str().unwrap(),
             ))
-            .service(fs::Files::new("/static", "static"))
-            .service(fs::Files::new("/favicon.ico", "static/favicon.ico"))
             .app_data(state.clone())
-            .route("/", web::get().to(index))
-            .route("/{name}", web::get().to(get_series))
-            .route("/{name}", web::post().to(add_datum))
-    })
-    .bind_openssl(url, builder)?
-    .run()
-    .await
+            .app_data(web::PayloadConfig::new(request_limits.max_body_bytes))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(request_limits.max_body_bytes)
+                    .error_handler(json_error),
+            )
+            .app_data(web::QueryConfig::default().error_handler(query_error))
+            .route("/favicon.ico", web::get().to(favicon))
+            .route("/about", web::get().to(about))
+            .route("/login", web::get().to(login_page))
+            .route("/login", web::post().to(login))
+            .route("/login/oidc", web::get().to(oidc_login))
+            .route("/login/oidc/callback", web::get().to(oidc_callback))
+            .route("/logout", web::get().to(logout))
+            .route(
+                "/.well-known/acme-challenge/{token}",
+                web::get().to(acme_challenge),
+            )
+            .route("/dashboard", web::get().to(dashboard))
+            .route("/dashboards", web::get().to(dashboard_list))
+            .route("/dashboards/{slug}", web::get().to(user_dashboard))
+            .route("/api/v1/version", web::get().to(version))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/internal/metrics", web::get().to(internal_metrics))
+            .route("/admin/reload", web::post().to(admin_reload))
+            .route("/admin/disk", web::get().to(admin_disk))
+            .route("/admin/reports/{name}", web::post().to(admin_report))
+            .route("/reports", web::get().to(list_reports))
+            .route("/reports/", web::get().to(list_reports))
+            .route("/reports/{file}", web::get().to(get_report))
+            .route("/api/v1/dashboards", web::get().to(list_dashboards))
+            .route("/api/v1/dashboards/{slug}", web::get().to(get_dashboard))
+            .route("/api/v1/dashboards/{slug}", web::put().to(put_dashboard))
+            .route(
+                "/api/v1/dashboards/{slug}",
+                web::delete().to(delete_dashboard),
+            )
+            .route("/api/v1/audit", web::get().to(get_audit_log))
+            .route("/api/v1/trash", web::get().to(list_trash))
+            .route("/api/v1/trash/{id}/restore", web::post().to(restore_series))
+            .service(web::scope("/ns/{namespace}").configure(series_routes))
+            .configure(series_routes)
+    };
+    let server_has_no_sockets = listeners.is_empty();
+    #[cfg(not(feature = "tls"))]
+    let server = {
+        let mut server = HttpServer::new(app).disable_signals();
+        for systemd::Listener { listener, .. } in listeners {
+            server = server.listen(listener)?;
+        }
+        if server_has_no_sockets {
+            server = server.bind(&http_address)?;
+        }
+        server.run()
+    };
+    #[cfg(feature = "tls")]
+    let server = match tls {
+        Some(tls) if tls.client_certs().is_some() => {
+            // The server of actix-web does not tell the application about
+            // the connection, so the identity of the client certificate is
+            // passed along by building the HTTP service here.
+            let acceptor = tls.acceptor().build();
+            let http = {
+                let app = app.clone();
+                move || {
+                    actix_http::HttpService::build()
+                        .finish(actix_service::map_config(app(), |_| {
+                            actix_web::dev::AppConfig::default()
+                        }))
+                        .tcp()
+                }
+            };
+            let https = move || {
+                actix_http::HttpService::build()
+                    .on_connect(
+                        |io: &actix_tls::openssl::SslStream<actix_rt::net::TcpStream>| {
+                            client_certs::ClientIdentity::of_certificate(
+                                io.ssl().peer_certificate().as_deref(),
+                            )
+                        },
+                    )
+                    .finish(actix_service::map_config(app(), |_| {
+                        actix_web::dev::AppConfig::default()
+                    }))
+                    .openssl(acceptor.clone())
+            };
+            let mut server = actix_web::dev::Server::build().disable_signals();
+            for systemd::Listener { name, listener } in listeners {
+                server = match name.as_str() {
+                    "http" => server.listen("sts-rs-http", listener, http.clone())?,
+                    _ => server.listen("sts-rs", listener, https.clone())?,
+                };
+            }
+            if server_has_no_sockets {
+                if !http_address.is_empty() {
+                    server = server.bind("sts-rs-http", &http_address, http)?;
+                }
+                server = server.bind("sts-rs", &https_address, https)?;
+            }
+            server.run()
+        }
+        tls => {
+            let mut server = HttpServer::new(app.clone()).disable_signals();
+            for systemd::Listener { name, listener } in listeners {
+                server = match &tls {
+                    Some(tls) if name != "http" => {
+                        server.listen_openssl(listener, tls.acceptor())?
+                    }
+                    _ => server.listen(listener)?,
+                };
+            }
+            if server_has_no_sockets {
+                if let Some(tls) = tls {
+                    server = server.bind_openssl(&https_address, tls.acceptor())?;
+                }
+                if !http_address.is_empty() {
+                    server = server.bind(&http_address)?;
+                }
+            }
+            server.run()
+        }
+    };
+    shutdown::stop_on_signal(server.clone());
+    systemd::start_watchdog(bt_actor.clone());
+    systemd::notify("READY=1");
+    server.await?;
+    if let Err(err) = bt_actor.send(Flush).await {
+        warn!("Could not write the queued values, {}", err);
+    }
+    info!("Stopped.");
+    Ok(())
 }
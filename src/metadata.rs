@@ -0,0 +1,230 @@
+use crate::alerts::{self, AlertRule};
+use crate::error::{Context, Error};
+use crate::limits::SizeLimit;
+use crate::plot::{parse_color, PlotSettings};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MAX_PRECISION: u32 = 15;
+
+/// Per series settings, stored next to the data file as `<series>.meta.json`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SeriesMeta {
+    pub limit: Option<SizeLimit>,
+    /// Archived series are read-only and stored compressed.
+    pub archived: bool,
+    pub plot: PlotSettings,
+    pub thresholds: Vec<Threshold>,
+    pub alerts: Vec<AlertRule>,
+    /// Unit of the values, like `°C`, shown with the axis of the series.
+    pub unit: Option<String>,
+    /// Decimal places values are stored and shown with, all by default.
+    pub precision: Option<u32>,
+    /// Labels to find the series by on the index page.
+    pub tags: Vec<String>,
+    /// The group the series is listed under, nested groups are separated by
+    /// slashes like `greenhouse/north`.
+    pub group: Option<String>,
+    /// Private series are only read by admins and by their readers, named
+    /// like `user:alice` or `key:5e8b27d0c1ab`. Others are answered as if
+    /// there were no such series.
+    pub private: bool,
+    pub readers: Vec<String>,
+    /// Marks the values as the readings of a counter, to store how much it
+    /// went up. Readings are stored as they are without.
+    pub counter: Option<CounterStorage>,
+}
+
+impl SeriesMeta {
+    /// Whether the plot settings, thresholds, alert rules, tags, group and
+    /// readers are valid.
+    pub fn validate(&self) -> Result<(), String> {
+        self.plot.validate()?;
+        self.thresholds.iter().try_for_each(Threshold::validate)?;
+        alerts::validate_rules(&self.alerts)?;
+        validate_tags(&self.tags)?;
+        validate_group(&self.group)?;
+        validate_readers(&self.readers)?;
+        validate_precision(self.precision)
+    }
+
+    /// `value` with the decimal places of the precision of the series.
+    pub fn rounded(&self, value: f64) -> f64 {
+        round(value, self.precision)
+    }
+}
+
+/// `value` with `precision` decimal places, as it is without one.
+pub fn round(value: f64, precision: Option<u32>) -> f64 {
+    match precision {
+        Some(precision) => {
+            let scale = 10f64.powi(precision as i32);
+            let scaled = value * scale;
+            match scaled.is_finite() {
+                true => scaled.round() / scale,
+                false => value,
+            }
+        }
+        None => value,
+    }
+}
+
+/// What is stored of the readings of a counter that only goes up, like an
+/// energy meter, and starts from zero again when its device restarts.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CounterStorage {
+    /// The increase since the previous reading instead of the reading.
+    Increase,
+    /// The reading, and the increase in the series `<name>.increase`.
+    Both,
+}
+
+/// The increase from the reading `previous` to `reading`. A reading below
+/// the previous one was counted from zero after a reset.
+pub fn increase(previous: f64, reading: f64) -> f64 {
+    if reading >= previous {
+        reading - previous
+    } else {
+        reading
+    }
+}
+
+/// A reference level drawn on the plots of a series, e.g. a warning level.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Threshold {
+    pub name: String,
+    pub value: f64,
+    /// Color as `#rrggbb`, picked from a palette when not set.
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Shade the region beyond the threshold.
+    #[serde(default)]
+    pub shade: bool,
+    /// Values below the threshold breach it, rather than values above it.
+    #[serde(default)]
+    pub below: bool,
+}
+
+impl Threshold {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.value.is_finite() {
+            return Err(format!(
+                "threshold {} should have a finite value",
+                self.name
+            ));
+        }
+        match &self.color {
+            Some(color) if parse_color(color).is_none() => {
+                Err(format!("{} is not a #rrggbb color", color))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Tags are searched for as `tag:<tag>`, so they are kept to letters,
+/// digits, dashes, underscores and dots.
+pub fn validate_tags(tags: &[String]) -> Result<(), String> {
+    let valid = |tag: &&String| {
+        !tag.is_empty()
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+    };
+    match tags.iter().find(|tag| !valid(tag)) {
+        Some(tag) => Err(format!(
+            "tag {:?} should be letters, digits, dashes, underscores and dots",
+            tag
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn validate_group(group: &Option<String>) -> Result<(), String> {
+    match group {
+        Some(group) if group.split('/').any(|part| part.trim().is_empty()) => Err(format!(
+            "group {:?} should be names separated by slashes",
+            group
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Readers are named like the callers they match.
+pub fn validate_readers(readers: &[String]) -> Result<(), String> {
+    let valid = |reader: &&String| {
+        ["user:", "key:", "cert:"]
+            .iter()
+            .any(|prefix| reader.len() > prefix.len() && reader.starts_with(prefix))
+    };
+    match readers.iter().find(|reader| !valid(reader)) {
+        Some(reader) => Err(format!(
+            "reader {:?} should be user:<name>, key:<digest> or cert:<identity>",
+            reader
+        )),
+        None => Ok(()),
+    }
+}
+
+/// More decimal places than an `f64` holds change nothing.
+pub fn validate_precision(precision: Option<u32>) -> Result<(), String> {
+    match precision {
+        Some(precision) if precision > MAX_PRECISION => Err(format!(
+            "precision should be at most {} decimal places",
+            MAX_PRECISION
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn meta_file(data_file: &Path) -> PathBuf {
+    data_file.with_extension("meta.json")
+}
+
+pub fn read_meta(meta_file: &Path) -> SeriesMeta {
+    match File::open(meta_file) {
+        Ok(file) => serde_json::from_reader(file).unwrap_or_else(|err| {
+            warn!("Ignoring unreadable metadata {:?}, {}", meta_file, err);
+            SeriesMeta::default()
+        }),
+        Err(_) => SeriesMeta::default(),
+    }
+}
+
+pub fn write_meta(meta_file: &Path, meta: &SeriesMeta) -> Result<(), Error> {
+    let file = File::create(meta_file).context(|| format!("creating {:?} failed", meta_file))?;
+    serde_json::to_writer_pretty(file, meta)
+        .map_err(io::Error::from)
+        .context(|| format!("writing {:?} failed", meta_file))
+}
+
+/// The latest reading of a counter that stores its increase is stored next
+/// to the data file as `<series>.counter.json`, so the first reading after a
+/// restart is counted from it.
+pub fn counter_file(data_file: &Path) -> PathBuf {
+    data_file.with_extension("counter.json")
+}
+
+pub fn read_counter(counter_file: &Path) -> Option<f64> {
+    let file = File::open(counter_file).ok()?;
+    serde_json::from_reader(file)
+        .map_err(|err| {
+            warn!(
+                "Ignoring unreadable counter reading {:?}, {}",
+                counter_file, err
+            )
+        })
+        .ok()
+}
+
+pub fn write_counter(counter_file: &Path, reading: f64) -> Result<(), Error> {
+    let file =
+        File::create(counter_file).context(|| format!("creating {:?} failed", counter_file))?;
+    serde_json::to_writer(file, &reading)
+        .map_err(io::Error::from)
+        .context(|| format!("writing {:?} failed", counter_file))
+}
@@ -0,0 +1,181 @@
+use actix_web::HttpRequest;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the plot duration buckets, in seconds.
+const PLOT_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// What the server did since it started, served in the Prometheus text
+/// format on `/internal/metrics`.
+pub struct Metrics {
+    /// Responses by method, route and status.
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    values: AtomicU64,
+    plot_durations: Arc<Histogram>,
+}
+
+/// The durations of plot renderings, shared with the plotter of the
+/// background actor.
+#[derive(Default)]
+pub struct Histogram {
+    counts: Mutex<HistogramCounts>,
+}
+
+#[derive(Default)]
+struct HistogramCounts {
+    buckets: [u64; PLOT_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// A value that is only known when scraped, like the number of series.
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub value: f64,
+}
+
+impl Metrics {
+    pub fn new(plot_durations: Arc<Histogram>) -> Metrics {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            values: AtomicU64::new(0),
+            plot_durations,
+        }
+    }
+
+    pub fn record_request(&self, method: &str, route: String, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.to_owned(), route, status))
+            .or_insert(0) += 1;
+    }
+
+    pub fn record_value(&self) {
+        self.values.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn plot_durations(&self) -> &Histogram {
+        &self.plot_durations
+    }
+
+    pub fn render(&self, gauges: &[Gauge]) -> String {
+        let mut text = String::new();
+        header(
+            &mut text,
+            "sts_rs_http_requests_total",
+            "counter",
+            "Responses by method, route and status.",
+        );
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "sts_rs_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            );
+        }
+        header(
+            &mut text,
+            "sts_rs_values_received_total",
+            "counter",
+            "Values accepted for series.",
+        );
+        let _ = writeln!(
+            text,
+            "sts_rs_values_received_total {}",
+            self.values.load(Ordering::Relaxed)
+        );
+        self.plot_durations
+            .render(&mut text, "sts_rs_plot_render_seconds");
+        for gauge in gauges {
+            header(&mut text, gauge.name, "gauge", gauge.help);
+            let _ = writeln!(text, "{} {}", gauge.name, gauge.value);
+        }
+        text
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut counts = self.counts.lock().unwrap();
+        for (bucket, bound) in counts.buckets.iter_mut().zip(&PLOT_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        counts.count += 1;
+        counts.sum += seconds;
+    }
+
+    fn render(&self, text: &mut String, name: &str) {
+        let counts = self.counts.lock().unwrap();
+        header(text, name, "histogram", "Time taken to render plots.");
+        for (bucket, bound) in counts.buckets.iter().zip(&PLOT_BUCKETS) {
+            let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, bucket);
+        }
+        let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, counts.count);
+        let _ = writeln!(text, "{}_sum {}", name, counts.sum);
+        let _ = writeln!(text, "{}_count {}", name, counts.count);
+    }
+}
+
+/// The route a request matched, like `/api/v1/series/{name}/meta`, so
+/// series names do not end up in the labels. Requests that matched no
+/// route are counted together.
+pub fn route(req: &HttpRequest, status: u16) -> String {
+    let path = req.path();
+    let params = req.match_info();
+    if params.iter().next().is_none() && status == 404 {
+        return "unmatched".to_owned();
+    }
+    let mut route = String::with_capacity(path.len());
+    let mut rest = path;
+    for (name, value) in params.iter() {
+        let segment = format!("/{}", value);
+        match rest.find(&segment) {
+            Some(start) if !value.is_empty() => {
+                route.push_str(&rest[..start]);
+                route.push_str(&format!("/{{{}}}", name));
+                rest = &rest[start + segment.len()..];
+            }
+            _ => (),
+        }
+    }
+    route.push_str(rest);
+    route
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The resident memory of the process, where `/proc` tells.
+pub fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,289 @@
+//! Namespaces keep the series of several projects or households apart on
+//! one server. The routes under `/ns/{namespace}/` are those of the server
+//! itself, for the series of that namespace only. A series of a namespace
+//! is kept under its name qualified as `namespace/name`, which no series
+//! outside it can have, and its files are stored in a subdirectory named
+//! after the namespace.
+
+use crate::error::Error;
+use crate::{env_or_default, AppState, Series};
+use actix_web::{dev, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// What the series of a namespace may take together.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Quota {
+    pub max_series: Option<usize>,
+    /// Values in memory over all its series.
+    pub max_values: Option<usize>,
+}
+
+impl Quota {
+    /// Refuses a value that would take the namespace beyond its quota, a
+    /// value of a new series counts as a series as well.
+    pub fn check(
+        &self,
+        namespace: &str,
+        series: &HashMap<String, Mutex<Series>>,
+        new_series: bool,
+    ) -> Result<(), Error> {
+        let prefix = format!("{}/", namespace);
+        let mut count = 0;
+        let mut values = 0;
+        for (_, serie) in series.iter().filter(|(name, _)| name.starts_with(&prefix)) {
+            count += 1;
+            values += Series::lock(serie).data.len();
+        }
+        match (self.max_series, self.max_values) {
+            (Some(max), _) if new_series && count >= max => Err(Error::QuotaExceeded(format!(
+                "namespace {} may have {} series",
+                namespace, max
+            ))),
+            (_, Some(max)) if values >= max => Err(Error::QuotaExceeded(format!(
+                "namespace {} may have {} values",
+                namespace, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The namespaces configured with `STS_RS_NAMESPACES` as a comma separated
+/// list or with `STS_RS_NAMESPACES_FILE`, one per line. A line is the name
+/// followed by its quota, like `garden max_series:20 max_values:100000`.
+#[derive(Default)]
+pub struct Namespaces {
+    namespaces: BTreeMap<String, Quota>,
+}
+
+impl Namespaces {
+    pub fn from_env() -> Namespaces {
+        let mut lines = env_or_default("STS_RS_NAMESPACES", "")
+            .split(',')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        if let Ok(file) = std::env::var("STS_RS_NAMESPACES_FILE") {
+            let contents = std::fs::read_to_string(&file)
+                .unwrap_or_else(|err| panic!("Could not read {:?}, {}", file, err));
+            lines.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_owned),
+            );
+        }
+        Namespaces {
+            namespaces: lines
+                .iter()
+                .map(|line| parse(line).unwrap_or_else(|err| panic!("{}", err)))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, namespace: &str) -> Option<&Quota> {
+        self.namespaces.get(namespace)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.namespaces.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.namespaces.len()
+    }
+}
+
+fn parse(line: &str) -> Result<(String, Quota), String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    validate(name)?;
+    let mut quota = Quota::default();
+    for word in words {
+        let number = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} of namespace {} should be a number", word, name))
+        };
+        match word.split_once(':') {
+            Some(("max_series", value)) => quota.max_series = Some(number(value)?),
+            Some(("max_values", value)) => quota.max_values = Some(number(value)?),
+            _ => {
+                return Err(format!(
+                    "{} of namespace {} is not max_series:<number> or max_values:<number>",
+                    word, name
+                ))
+            }
+        }
+    }
+    Ok((name.to_owned(), quota))
+}
+
+/// Namespaces end up in paths and file names as they are.
+fn validate(name: &str) -> Result<(), String> {
+    let valid = name
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_');
+    if name.is_empty() || !valid {
+        return Err(format!(
+            "{:?} is not a valid namespace, use ASCII letters, digits, - and _",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// The namespace a name qualified with it is in, and the name within it.
+pub fn split(series_name: &str) -> (Option<&str>, &str) {
+    match series_name.split_once('/') {
+        Some((namespace, name)) => (Some(namespace), name),
+        None => (None, series_name),
+    }
+}
+
+/// The path of a request without the `/ns/{namespace}` it starts with, and
+/// that namespace.
+pub fn strip(path: &str) -> (Option<&str>, &str) {
+    let rest = match path.strip_prefix("/ns/") {
+        Some(rest) => rest,
+        None => return (None, path),
+    };
+    match rest.find('/') {
+        Some(end) => (Some(&rest[..end]), &rest[end..]),
+        None => (Some(rest), "/"),
+    }
+}
+
+/// The namespace of a request, from the `{namespace}` of its route. Names of
+/// namespaces that are not configured are answered with 404.
+#[derive(Clone, Debug, Default)]
+pub struct Namespace(Option<String>);
+
+impl Namespace {
+    /// The namespace of a series as it is kept.
+    pub fn of_series(series_name: &str) -> Namespace {
+        Namespace(split(series_name).0.map(str::to_owned))
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// A name within the namespace as the series is kept.
+    pub fn qualify(&self, name: &str) -> String {
+        match &self.0 {
+            Some(namespace) => format!("{}/{}", namespace, name),
+            None => name.to_owned(),
+        }
+    }
+
+    /// Whether a series as it is kept is in the namespace.
+    pub fn contains(&self, series_name: &str) -> bool {
+        split(series_name).0 == self.name()
+    }
+
+    /// The path routes of the namespace start with, empty outside them.
+    pub fn base(&self) -> String {
+        match &self.0 {
+            Some(namespace) => format!("/ns/{}", namespace),
+            None => String::new(),
+        }
+    }
+
+    /// Refuses values beyond the quota of the namespace.
+    pub fn check_quota(
+        &self,
+        state: &AppState,
+        series: &HashMap<String, Mutex<Series>>,
+        new_series: bool,
+    ) -> Result<(), Error> {
+        match self
+            .name()
+            .and_then(|name| Some((name, state.namespaces.get(name)?)))
+        {
+            Some((name, quota)) => quota.check(name, series, new_series),
+            None => Ok(()),
+        }
+    }
+
+    fn of_request(req: &HttpRequest) -> Result<Namespace, Error> {
+        let namespace = match req.match_info().get("namespace") {
+            Some(namespace) => namespace,
+            None => return Ok(Namespace(None)),
+        };
+        let configured = req
+            .app_data::<web::Data<AppState>>()
+            .is_some_and(|state| state.namespaces.get(namespace).is_some());
+        if configured {
+            Ok(Namespace(Some(namespace.to_owned())))
+        } else {
+            Err(Error::NotFound(format!("no namespace {}", namespace)))
+        }
+    }
+}
+
+impl FromRequest for Namespace {
+    type Error = Error;
+    type Future = Ready<Result<Namespace, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        ready(Namespace::of_request(req))
+    }
+}
+
+/// The `{name}` of the route of a request, qualified with its namespace.
+/// It derefs to the name the series is kept under.
+#[derive(Clone, Debug)]
+pub struct SeriesName {
+    namespace: Namespace,
+    name: String,
+}
+
+impl SeriesName {
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// The name within the namespace, as it is in the route.
+    pub fn local(&self) -> &str {
+        split(&self.name).1
+    }
+
+    pub fn into_inner(self) -> String {
+        self.name
+    }
+}
+
+impl Deref for SeriesName {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.name
+    }
+}
+
+impl fmt::Display for SeriesName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl FromRequest for SeriesName {
+    type Error = Error;
+    type Future = Ready<Result<SeriesName, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        ready(Namespace::of_request(req).map(|namespace| SeriesName {
+            name: namespace.qualify(req.match_info().get("name").unwrap_or_default()),
+            namespace,
+        }))
+    }
+}
@@ -0,0 +1,258 @@
+use crate::auth::Role;
+use crate::env_or_default;
+use crate::secrets;
+use actix_web::client::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Signing in with an OpenID Connect provider, using the authorization code
+/// flow. Configured with `STS_RS_OIDC_ISSUER`, `STS_RS_OIDC_CLIENT_ID`,
+/// `STS_RS_OIDC_CLIENT_SECRET` and `STS_RS_OIDC_REDIRECT_URL`.
+pub struct Oidc {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_url: String,
+    scopes: String,
+    groups_claim: String,
+    /// Groups whose members may sign in, everybody the provider knows may
+    /// when empty.
+    groups: Vec<String>,
+    /// The roles of the members of groups, others are readers.
+    roles: Vec<(String, Role)>,
+    /// How long a sign in holds, the groups and so the role are looked up
+    /// again when signing in after.
+    session_seconds: i64,
+    /// Read from the discovery document on the first sign in.
+    provider: Mutex<Option<Provider>>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Provider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: Option<String>,
+}
+
+/// Who signed in, `subject` is what the provider knows the user by for
+/// good, `user` the name to show.
+pub struct Identity {
+    pub subject: String,
+    pub user: String,
+    pub groups: Vec<String>,
+}
+
+impl Oidc {
+    pub fn from_env() -> Option<Oidc> {
+        let issuer = std::env::var("STS_RS_OIDC_ISSUER").ok()?;
+        let required = |key: &str| {
+            std::env::var(key)
+                .unwrap_or_else(|_| panic!("{} is required with STS_RS_OIDC_ISSUER", key))
+        };
+        Some(Oidc {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            client_id: required("STS_RS_OIDC_CLIENT_ID"),
+            client_secret: secrets::secret("STS_RS_OIDC_CLIENT_SECRET").unwrap_or_else(|| {
+                panic!("STS_RS_OIDC_CLIENT_SECRET is required with STS_RS_OIDC_ISSUER")
+            }),
+            redirect_url: required("STS_RS_OIDC_REDIRECT_URL"),
+            scopes: env_or_default("STS_RS_OIDC_SCOPES", "openid profile email"),
+            groups_claim: env_or_default("STS_RS_OIDC_GROUPS_CLAIM", "groups"),
+            groups: env_or_default("STS_RS_OIDC_GROUPS", "")
+                .split(',')
+                .map(str::trim)
+                .filter(|group| !group.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            roles: env_or_default("STS_RS_OIDC_ROLES", "")
+                .split(',')
+                .map(str::trim)
+                .filter(|mapping| !mapping.is_empty())
+                .map(|mapping| {
+                    mapping
+                        .split_once('=')
+                        .and_then(|(group, role)| Some((group.trim().to_owned(), Role::parse(role.trim())?)))
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "STS_RS_OIDC_ROLES should be <group>=<reader|writer|admin> separated by commas, not {}",
+                                mapping
+                            )
+                        })
+                })
+                .collect(),
+            session_seconds: env_or_default("STS_RS_OIDC_SESSION_HOURS", "8")
+                .parse::<i64>()
+                .expect("STS_RS_OIDC_SESSION_HOURS should be a whole number of hours")
+                * 3600,
+            provider: Mutex::new(None),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The Unix time a sign in made now stops holding.
+    pub fn signed_in_until(&self) -> i64 {
+        chrono::Utc::now().timestamp() + self.session_seconds
+    }
+
+    async fn provider(&self) -> Result<Provider, String> {
+        if let Some(provider) = self.provider.lock().unwrap().as_ref() {
+            return Ok(provider.clone());
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let provider: Provider = Client::default()
+            .get(&url)
+            .send()
+            .await
+            .map_err(|err| format!("fetching {} failed, {}", url, err))?
+            .json()
+            .await
+            .map_err(|err| format!("reading {} failed, {}", url, err))?;
+        *self.provider.lock().unwrap() = Some(provider.clone());
+        Ok(provider)
+    }
+
+    /// Where to send the browser to sign in, `state` and `nonce` come back
+    /// with the callback and in the ID token.
+    pub async fn authorization_url(&self, state: &str, nonce: &str) -> Result<String, String> {
+        let provider = self.provider().await?;
+        let separator = if provider.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}",
+            provider.authorization_endpoint,
+            separator,
+            crate::query_component(&self.client_id),
+            crate::query_component(&self.redirect_url),
+            crate::query_component(&self.scopes),
+            state,
+            nonce
+        ))
+    }
+
+    /// Trades the code of the callback for the identity of the user. The ID
+    /// token comes straight from the provider over TLS, so its claims are
+    /// checked rather than its signature.
+    pub async fn identify(&self, code: &str, nonce: &str) -> Result<Identity, String> {
+        let provider = self.provider().await?;
+        let tokens: TokenResponse = Client::default()
+            .post(&provider.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .send_form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_url),
+            ])
+            .await
+            .map_err(|err| format!("requesting a token failed, {}", err))?
+            .json()
+            .await
+            .map_err(|err| format!("reading the token failed, {}", err))?;
+        let claims = token_claims(&tokens.id_token)?;
+        if claims["iss"].as_str().map(|iss| iss.trim_end_matches('/')) != Some(&self.issuer) {
+            return Err("the ID token is of another issuer".to_owned());
+        }
+        let audience_matches = match &claims["aud"] {
+            Value::String(aud) => *aud == self.client_id,
+            Value::Array(aud) => aud.iter().any(|aud| *aud == *self.client_id),
+            _ => false,
+        };
+        if !audience_matches {
+            return Err("the ID token is meant for another client".to_owned());
+        }
+        if claims["exp"].as_i64().unwrap_or(0) < chrono::Utc::now().timestamp() {
+            return Err("the ID token expired".to_owned());
+        }
+        if claims["nonce"].as_str() != Some(nonce) {
+            return Err("the ID token is of another sign in".to_owned());
+        }
+        let mut groups = claims.get(&self.groups_claim).cloned();
+        if groups.is_none() {
+            if let (Some(userinfo), Some(access_token)) =
+                (&provider.userinfo_endpoint, &tokens.access_token)
+            {
+                let userinfo: Value = Client::default()
+                    .get(userinfo)
+                    .bearer_auth(access_token)
+                    .send()
+                    .await
+                    .map_err(|err| format!("requesting the user info failed, {}", err))?
+                    .json()
+                    .await
+                    .map_err(|err| format!("reading the user info failed, {}", err))?;
+                groups = userinfo.get(&self.groups_claim).cloned();
+            }
+        }
+        let groups = match groups {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(|group| group.as_str().map(str::to_owned))
+                .collect(),
+            Some(Value::String(group)) => vec![group],
+            _ => Vec::new(),
+        };
+        let subject = claims["sub"]
+            .as_str()
+            .ok_or("the ID token names no user")?
+            .to_owned();
+        let user = ["preferred_username", "email"]
+            .iter()
+            .find_map(|claim| claims[claim].as_str())
+            .unwrap_or(&subject)
+            .to_owned();
+        Ok(Identity {
+            subject,
+            user,
+            groups,
+        })
+    }
+
+    pub fn admits(&self, identity: &Identity) -> bool {
+        self.groups.is_empty()
+            || identity
+                .groups
+                .iter()
+                .any(|group| self.groups.contains(group))
+    }
+
+    /// The highest role of the groups of the user.
+    pub fn role(&self, identity: &Identity) -> Role {
+        self.roles
+            .iter()
+            .filter(|(group, _)| identity.groups.contains(group))
+            .map(|(_, role)| *role)
+            .max()
+            .unwrap_or(Role::Reader)
+    }
+}
+
+/// The payload of a JSON web token, without checking its signature.
+fn token_claims(token: &str) -> Result<Value, String> {
+    let payload = token.split('.').nth(1).ok_or("the ID token is malformed")?;
+    let mut payload = payload.replace('-', "+").replace('_', "/");
+    while payload.len() % 4 != 0 {
+        payload.push('=');
+    }
+    let payload =
+        base64::decode(&payload).map_err(|err| format!("the ID token is malformed, {}", err))?;
+    serde_json::from_slice(&payload).map_err(|err| format!("the ID token is malformed, {}", err))
+}
+
+/// Random hexadecimal for the state and nonce of a sign in.
+pub fn random_token() -> String {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes).unwrap();
+    hex::encode(bytes)
+}
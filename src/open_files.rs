@@ -0,0 +1,104 @@
+use crate::error::{Context, Error};
+use crate::{env_or_default, Datum};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The data files values are appended to, kept open between values so a
+/// busy series does not open its file for every value. At most
+/// `STS_RS_OPEN_FILES` files are kept open, the least recently used one is
+/// closed for another, and files without values for
+/// `STS_RS_OPEN_FILE_IDLE` seconds are closed. Files are closed before the
+/// actor rewrites, moves or archives them.
+pub struct OpenFiles {
+    max_open: usize,
+    pub idle: Duration,
+    open: HashMap<PathBuf, OpenFile>,
+}
+
+struct OpenFile {
+    writer: csv::Writer<File>,
+    used: Instant,
+}
+
+impl OpenFiles {
+    pub fn from_env() -> OpenFiles {
+        let max_open = env_or_default("STS_RS_OPEN_FILES", "128")
+            .parse::<usize>()
+            .expect("STS_RS_OPEN_FILES should be a number of files");
+        let idle = env_or_default("STS_RS_OPEN_FILE_IDLE", "60")
+            .parse::<u64>()
+            .expect("STS_RS_OPEN_FILE_IDLE should be a whole number of seconds");
+        OpenFiles {
+            max_open,
+            idle: Duration::from_secs(idle),
+            open: HashMap::new(),
+        }
+    }
+
+    /// Appends `data` to `file_name`, which is created when it does not
+    /// exist. The values are handed to the system in one write before this
+    /// returns. A file that could not be written to is closed, so the next
+    /// append opens it again.
+    pub fn append(&mut self, file_name: &Path, data: &[Datum]) -> Result<(), Error> {
+        let appended = self.write(file_name, data);
+        // Without files to keep open every value opens its file.
+        if appended.is_err() || self.max_open == 0 {
+            self.close(file_name);
+        }
+        appended
+    }
+
+    fn write(&mut self, file_name: &Path, data: &[Datum]) -> Result<(), Error> {
+        let now = Instant::now();
+        if !self.open.contains_key(file_name) {
+            if self.open.len() >= self.max_open {
+                let least_recent = self
+                    .open
+                    .iter()
+                    .min_by_key(|(_, open)| open.used)
+                    .map(|(file_name, _)| file_name.clone());
+                if let Some(least_recent) = least_recent {
+                    self.close(&least_recent);
+                }
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_name)
+                .context(|| format!("opening {:?} failed", file_name))?;
+            let writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(file);
+            self.open
+                .insert(file_name.to_path_buf(), OpenFile { writer, used: now });
+        }
+        let open = self.open.get_mut(file_name).unwrap();
+        open.used = now;
+        for datum in data {
+            open.writer
+                .serialize(datum)
+                .map_err(io::Error::from)
+                .context(|| format!("appending to {:?} failed", file_name))?;
+        }
+        open.writer
+            .flush()
+            .context(|| format!("appending to {:?} failed", file_name))
+    }
+
+    pub fn close(&mut self, file_name: &Path) {
+        self.open.remove(file_name);
+    }
+
+    pub fn close_idle(&mut self, now: Instant) {
+        let idle = self.idle;
+        self.open
+            .retain(|_, open| now.saturating_duration_since(open.used) < idle);
+    }
+
+    pub fn close_all(&mut self) {
+        self.open.clear();
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{env_or_default, PACKAGE_NAME, VERSION};
+use actix::prelude::*;
+use actix_web::client::Client;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// Spans kept while the collector is unreachable, newer ones are dropped.
+const MAX_QUEUED_SPANS: usize = 10_000;
+
+/// Collects the spans of the server, the requests, the messages of the
+/// background actor and the plots, for the `Exporter`.
+pub struct OtlpLayer {
+    exporter: Addr<Exporter>,
+}
+
+/// Sends the finished spans to an OpenTelemetry collector, like Jaeger or
+/// Tempo, with OTLP over HTTP in its JSON encoding.
+pub struct Exporter {
+    url: String,
+    service_name: String,
+    client: Client,
+    spans: Vec<Value>,
+    dropped: usize,
+    connected: bool,
+}
+
+struct SpanData {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start: SystemTime,
+    attributes: Vec<(&'static str, Value)>,
+}
+
+struct Finished {
+    span: SpanData,
+    end: SystemTime,
+}
+
+impl Message for Finished {
+    type Result = ();
+}
+
+impl OtlpLayer {
+    /// Tracing is enabled by setting `STS_RS_OTLP_ENDPOINT`, the exporter
+    /// runs on the current arbiter.
+    pub fn from_env() -> Option<OtlpLayer> {
+        let endpoint = std::env::var("STS_RS_OTLP_ENDPOINT").ok()?;
+        let exporter = Exporter {
+            url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+            service_name: env_or_default("STS_RS_OTLP_SERVICE_NAME", PACKAGE_NAME),
+            client: Client::default(),
+            spans: Vec::new(),
+            dropped: 0,
+            connected: true,
+        };
+        Some(OtlpLayer {
+            exporter: exporter.start(),
+        })
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let parent = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|data| (data.trace_id, data.span_id))
+        });
+        let mut data = SpanData {
+            trace_id: parent.map_or_else(random_id, |(trace_id, _)| trace_id),
+            span_id: random_id(),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            name: attrs.metadata().name(),
+            start: SystemTime::now(),
+            attributes: Vec::new(),
+        };
+        attrs.record(&mut AttributeVisitor(&mut data.attributes));
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: LayerContext<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut AttributeVisitor(&mut data.attributes));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let data = ctx
+            .span(&id)
+            .and_then(|span| span.extensions_mut().remove::<SpanData>());
+        if let Some(span) = data {
+            self.exporter.do_send(Finished {
+                span,
+                end: SystemTime::now(),
+            });
+        }
+    }
+}
+
+impl Exporter {
+    fn export(&mut self, ctx: &mut Context<Self>) {
+        if self.dropped > 0 {
+            warn!(
+                "Dropped {} spans the collector at {} did not take in time.",
+                self.dropped, self.url
+            );
+            self.dropped = 0;
+        }
+        if self.spans.is_empty() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let count = spans.len();
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [attribute("service.name", json!(self.service_name))]},
+                "scopeSpans": [{
+                    "scope": {"name": PACKAGE_NAME, "version": VERSION},
+                    "spans": spans,
+                }],
+            }],
+        });
+        let request = self.client.post(&self.url).send_json(&body);
+        ctx.spawn(
+            actix::fut::wrap_future::<_, Self>(request).map(move |result, act, _| {
+                let failure = match result {
+                    Ok(response) if response.status().is_success() => None,
+                    Ok(response) => Some(format!("status code {}", response.status())),
+                    Err(err) => Some(err.to_string()),
+                };
+                match failure {
+                    Some(reason) if act.connected => {
+                        warn!(
+                            "Exporting {} spans to {} failed, {}.",
+                            count, act.url, reason
+                        );
+                        act.connected = false;
+                    }
+                    None if !act.connected => {
+                        info!("Exporting spans to {} again.", act.url);
+                        act.connected = true;
+                    }
+                    _ => (),
+                }
+            }),
+        );
+    }
+}
+
+impl Actor for Exporter {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Exporting traces to {}", self.url);
+        ctx.run_interval(EXPORT_INTERVAL, |act, ctx| act.export(ctx));
+    }
+}
+
+impl Handler<Finished> for Exporter {
+    type Result = ();
+    fn handle(&mut self, msg: Finished, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.spans.len() >= MAX_QUEUED_SPANS {
+            self.dropped += 1;
+            return;
+        }
+        let span = msg.span;
+        // Requests whose response is a server error are marked failed.
+        let failed = span
+            .attributes
+            .iter()
+            .any(|(key, value)| *key == "status" && value.as_u64().is_some_and(|code| code >= 500));
+        let mut otlp_span = json!({
+            "traceId": hex::encode(span.trace_id),
+            "spanId": hex::encode(span.span_id),
+            "name": span.name,
+            // Requests are handled for a client, the rest happens inside.
+            "kind": if span.name == "request" { 2 } else { 1 },
+            "startTimeUnixNano": unix_nanos(span.start),
+            "endTimeUnixNano": unix_nanos(msg.end),
+            "attributes": span
+                .attributes
+                .into_iter()
+                .map(|(key, value)| attribute(key, value))
+                .collect::<Vec<_>>(),
+            "status": {"code": if failed { 2 } else { 0 }},
+        });
+        if let Some(parent_span_id) = span.parent_span_id {
+            otlp_span["parentSpanId"] = json!(hex::encode(parent_span_id));
+        }
+        self.spans.push(otlp_span);
+    }
+}
+
+struct AttributeVisitor<'a>(&'a mut Vec<(&'static str, Value)>);
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: Value) {
+        self.0.retain(|(key, _)| *key != field.name());
+        self.0.push((field.name(), value));
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, json!(format!("{:?}", value)));
+    }
+}
+
+/// An attribute as OTLP encodes it in JSON, 64 bit integers as strings.
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::Bool(value) => json!({ "boolValue": value }),
+        Value::Number(number) if number.is_f64() => json!({ "doubleValue": number }),
+        Value::Number(number) => json!({ "intValue": number.to_string() }),
+        Value::String(value) => json!({ "stringValue": value }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({"key": key, "value": value})
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos())
+        .to_string()
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    let mut id = [0; N];
+    getrandom::getrandom(&mut id).unwrap();
+    id
+}
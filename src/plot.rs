@@ -0,0 +1,1859 @@
+use crate::annotations::Annotation;
+use crate::columns::Columns;
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use crate::metadata::{SeriesMeta, Threshold};
+use crate::metrics::Histogram;
+use crate::{namespaces, Series};
+use bytes::Bytes;
+#[cfg(feature = "gnuplot")]
+use chrono::Offset;
+use chrono::{DateTime, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "gnuplot")]
+use std::process::{Command, Output};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const WIDTH: u32 = 640;
+pub const HEIGHT: u32 = 480;
+/// Smallest and largest width or height of an on-demand plot.
+pub const SIZES: std::ops::RangeInclusive<u32> = 100..=4096;
+pub const SPARKLINE_SIZES: std::ops::RangeInclusive<u32> = 10..=1000;
+/// The default size of a sparkline, about a line of text.
+pub const SPARKLINE_SIZE: (u32, u32) = (120, 24);
+#[cfg(feature = "gnuplot")]
+const GNUPLOT_COMMANDS: &str = r#"set timefmt "%s";
+set format x "%Y/%m/%d %H:%M:%S";
+set xdata time;
+set xtics rotate font ", 8";
+set ytics font ", 8";
+set terminal svg;
+set xlabel 'Time' offset 0,5;
+set key off;
+set datafile separator ",";
+set autoscale;
+set offsets 0.0, 0.0, 0.01, 0.01;
+set grid;
+set output"#;
+
+/// How plots are rendered, configured with `STS_RS_PLOTTER`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Renderer {
+    /// In-process rendering, the image format follows from the file extension.
+    Native,
+    /// Rendering by an external gnuplot process, SVG only.
+    #[cfg(feature = "gnuplot")]
+    Gnuplot,
+}
+
+/// Renders the plots kept in the image directory.
+pub struct Plotter {
+    renderer: Renderer,
+    /// The theme of series without a theme of their own.
+    pub default_theme: Theme,
+    /// The time zone of the time axis, configured with `STS_RS_TIMEZONE`.
+    pub timezone: Tz,
+    /// The recent values drawn in the pre-rendered plots.
+    pub window: Window,
+    /// Configured with `STS_RS_GNUPLOT`.
+    #[cfg(feature = "gnuplot")]
+    gnuplot: PathBuf,
+    /// The script used instead of the built-in one, configured with
+    /// `STS_RS_GNUPLOT_TEMPLATE`.
+    #[cfg(feature = "gnuplot")]
+    gnuplot_template: Option<PathBuf>,
+    /// Shared with the metrics of the server.
+    pub render_durations: Arc<Histogram>,
+    /// Why plots can not be rendered, found once when the server starts.
+    disabled: Option<String>,
+}
+
+impl Plotter {
+    pub fn from_env() -> Plotter {
+        let renderer = match env_or_default("STS_RS_PLOTTER", "native")
+            .to_lowercase()
+            .as_str()
+        {
+            "native" => Renderer::Native,
+            #[cfg(feature = "gnuplot")]
+            "gnuplot" => Renderer::Gnuplot,
+            #[cfg(not(feature = "gnuplot"))]
+            "gnuplot" => panic!("STS_RS_PLOTTER=gnuplot needs a build with the gnuplot feature"),
+            other => panic!("STS_RS_PLOTTER should be native or gnuplot, not {}", other),
+        };
+        Plotter {
+            renderer,
+            default_theme: Theme::from_env(),
+            timezone: env_or_default("STS_RS_TIMEZONE", "UTC")
+                .parse()
+                .unwrap_or_else(|err| panic!("STS_RS_TIMEZONE {}", err)),
+            window: Window::from_env(),
+            #[cfg(feature = "gnuplot")]
+            gnuplot: std::env::var_os("STS_RS_GNUPLOT")
+                .map(PathBuf::from)
+                .unwrap_or_else(find_gnuplot),
+            #[cfg(feature = "gnuplot")]
+            gnuplot_template: std::env::var("STS_RS_GNUPLOT_TEMPLATE")
+                .ok()
+                .map(PathBuf::from),
+            render_durations: Arc::new(Histogram::default()),
+            disabled: None,
+        }
+    }
+
+    /// Checks once that plots can be rendered, rather than failing for
+    /// every value. Without gnuplot the plots are a placeholder telling so
+    /// until the server is restarted with it.
+    pub fn disable_if_unavailable(&mut self) {
+        if let Err(err) = self.check() {
+            warn!(
+                "Plotting is disabled, {}. Plots show a placeholder until the server is restarted with a plotter that runs.",
+                err
+            );
+            self.disabled = Some(err);
+        }
+    }
+
+    /// Why plotting is disabled, if it is.
+    pub fn disabled(&self) -> Option<&str> {
+        self.disabled.as_deref()
+    }
+
+    /// Whether plots can be rendered, gnuplot has to be installed when it
+    /// is used.
+    pub fn check(&self) -> Result<(), String> {
+        match self.renderer {
+            Renderer::Native => Ok(()),
+            #[cfg(feature = "gnuplot")]
+            Renderer::Gnuplot => match Command::new(&self.gnuplot).arg("--version").output() {
+                Ok(output) if output.status.success() => Ok(()),
+                Ok(output) => Err(format!("{:?} failed, {}", self.gnuplot, output.status)),
+                Err(err) => Err(format!("could not run {:?}, {}", self.gnuplot, err)),
+            },
+        }
+    }
+
+    /// Plots `line`. Gnuplot reads the values from `data_file` instead,
+    /// ignores smoothing and draws the automatic theme light.
+    #[cfg_attr(not(feature = "gnuplot"), allow(unused_variables))]
+    pub fn plot(&self, line: Line, data_file: &Path, image_file: &Path) -> Result<(), String> {
+        let _span = tracing::info_span!("plot", series = %line.series_name).entered();
+        if self.disabled.is_some() {
+            return std::fs::write(image_file, placeholder())
+                .map_err(|err| format!("writing {:?} failed, {}", image_file, err));
+        }
+        let started = Instant::now();
+        let theme = line.settings.theme.unwrap_or(self.default_theme);
+        let latest = line.data.latest().map(|datum| datum.timeStamp);
+        let from = self.window.start(latest);
+        let plotted = match self.renderer {
+            Renderer::Native => {
+                let recent = line.data.between(from.unwrap_or(i64::MIN), i64::MAX);
+                let line = Line {
+                    data: &recent,
+                    ..line
+                };
+                render(line, theme, self.timezone, image_file)
+            }
+            #[cfg(feature = "gnuplot")]
+            Renderer::Gnuplot => {
+                let time_axis = TimeAxis {
+                    from,
+                    to: latest,
+                    utc_offset: utc_offset(self.timezone, latest.unwrap_or(0)),
+                    description: time_description("Time", self.timezone),
+                };
+                let commands = match self.gnuplot_template(data_file) {
+                    Some(template) => {
+                        fill_template(&template, line, data_file, &time_axis, image_file)
+                    }
+                    None => gnuplot_commands(line, data_file, theme, &time_axis, image_file),
+                };
+                let output = Command::new(&self.gnuplot)
+                    .args(["-e", &commands])
+                    .output()
+                    .map_err(|err| format!("could not run {:?}, {}", self.gnuplot, err))?;
+                log_command_output(&output);
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    Err(format!("gnuplot failed with {}", output.status))
+                }
+            }
+        };
+        self.render_durations.observe(started.elapsed());
+        plotted
+    }
+
+    /// The script of a series, `<series>.gnuplot` next to its data file, or
+    /// else the one configured for all series.
+    #[cfg(feature = "gnuplot")]
+    fn gnuplot_template(&self, data_file: &Path) -> Option<String> {
+        let series_template = data_file.with_extension("gnuplot");
+        let template = if series_template.exists() {
+            series_template
+        } else {
+            self.gnuplot_template.clone()?
+        };
+        std::fs::read_to_string(&template)
+            .map_err(|err| warn!("Ignoring unreadable gnuplot script {:?}, {}", template, err))
+            .ok()
+    }
+}
+
+/// What to do with the plot of a series after a new value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Regenerate {
+    Now,
+    /// Regenerate once the delay has passed, coalescing the values until then.
+    After(Duration),
+    /// Nothing to do, a regeneration is already scheduled or nothing changed.
+    Wait,
+}
+
+/// Limits how often the plot of a series is regenerated while values come
+/// in: at most once per interval, unless enough new values piled up.
+pub struct Throttle {
+    interval: Duration,
+    points: usize,
+    series: HashMap<String, ThrottleState>,
+}
+
+#[derive(Default)]
+struct ThrottleState {
+    plotted: Option<Instant>,
+    points: usize,
+    scheduled: bool,
+}
+
+impl Throttle {
+    /// Configured with `STS_RS_PLOT_INTERVAL` in seconds and
+    /// `STS_RS_PLOT_POINTS`, an interval of 0 plots every value.
+    pub fn from_env() -> Throttle {
+        let interval = env_or_default("STS_RS_PLOT_INTERVAL", "10")
+            .parse::<u64>()
+            .expect("STS_RS_PLOT_INTERVAL should be a whole number of seconds");
+        let points = env_or_default("STS_RS_PLOT_POINTS", "100")
+            .parse::<usize>()
+            .expect("STS_RS_PLOT_POINTS should be a number of values");
+        Throttle {
+            interval: Duration::from_secs(interval),
+            points: points.max(1),
+            series: HashMap::new(),
+        }
+    }
+
+    /// Records new values of a series.
+    pub fn record(&mut self, series_name: &str, points: usize, now: Instant) -> Regenerate {
+        let state = self.series.entry(series_name.to_owned()).or_default();
+        state.points += points;
+        if state.scheduled {
+            if state.points >= self.points {
+                Regenerate::Now
+            } else {
+                Regenerate::Wait
+            }
+        } else {
+            self.decide(series_name, now)
+        }
+    }
+
+    /// Handles a scheduled regeneration of a series coming due.
+    pub fn due(&mut self, series_name: &str, now: Instant) -> Regenerate {
+        match self.series.get_mut(series_name) {
+            Some(state) if state.points > 0 => {
+                state.scheduled = false;
+                self.decide(series_name, now)
+            }
+            Some(state) => {
+                state.scheduled = false;
+                Regenerate::Wait
+            }
+            None => Regenerate::Wait,
+        }
+    }
+
+    /// Records that the plot of a series was regenerated.
+    pub fn plotted(&mut self, series_name: &str, now: Instant) {
+        let state = self.series.entry(series_name.to_owned()).or_default();
+        state.plotted = Some(now);
+        state.points = 0;
+    }
+
+    pub fn forget(&mut self, series_name: &str) {
+        self.series.remove(series_name);
+    }
+
+    fn decide(&mut self, series_name: &str, now: Instant) -> Regenerate {
+        let state = self.series.get_mut(series_name).unwrap();
+        let elapsed = state
+            .plotted
+            .map_or(self.interval, |plotted| now.duration_since(plotted));
+        if elapsed >= self.interval || state.points >= self.points {
+            Regenerate::Now
+        } else {
+            state.scheduled = true;
+            Regenerate::After(self.interval - elapsed)
+        }
+    }
+}
+
+/// Colors of thresholds without a color of their own, in order.
+const THRESHOLD_COLORS: [RGBColor; 3] = [
+    RGBColor(255, 127, 14),
+    RGBColor(214, 39, 40),
+    RGBColor(148, 103, 189),
+];
+
+/// The formats on-demand plots are served in.
+#[derive(Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotFormat {
+    Svg,
+    Png,
+    /// A page with an interactive chart of the data API.
+    Html,
+    /// An SVG grid of the average value per hour of the day and date.
+    Heatmap,
+    /// A tiny SVG line without axes, for tables and status pages.
+    Sparkline,
+}
+
+impl PlotFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PlotFormat::Svg | PlotFormat::Heatmap | PlotFormat::Sparkline => "image/svg+xml",
+            PlotFormat::Png => "image/png",
+            PlotFormat::Html => "text/html",
+        }
+    }
+}
+
+/// The series, window, size and format of an on-demand plot.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PlotKey {
+    pub series: Vec<String>,
+    /// The series plotted against the secondary y-axis.
+    pub secondary: Vec<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// The recent values to plot when `from` is not given.
+    pub window: Option<Window>,
+    /// The size defaults to the plot settings of the first series.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: PlotFormat,
+    pub theme: Option<Theme>,
+    /// Overrides the scale of the plot settings.
+    pub log_scale: Option<bool>,
+    /// Overrides the kind of the plot settings.
+    pub kind: Option<PlotKind>,
+    pub timezone: Option<Tz>,
+    /// Converts the values from the unit of the series to this one.
+    pub unit: Option<String>,
+}
+
+struct CachedPlot {
+    series: Vec<String>,
+    size: u64,
+    used: Instant,
+}
+
+/// Recently rendered on-demand plots, stored in a directory as a file named
+/// after their key and the last modification of their series. A plot is
+/// served until its series changes, after which the stale file is evicted
+/// like any other when the directory grows beyond its size.
+pub struct PlotCache {
+    directory: PathBuf,
+    max_bytes: u64,
+    plots: Mutex<CachedPlots>,
+}
+
+#[derive(Default)]
+struct CachedPlots {
+    files: HashMap<String, CachedPlot>,
+    bytes: u64,
+}
+
+impl PlotCache {
+    /// The cache directory is `STS_RS_PLOT_CACHE_PATH`, or `.plot-cache` in
+    /// the data directory, and is emptied on start.
+    pub fn from_env(data_path: &Path) -> PlotCache {
+        let directory = std::env::var("STS_RS_PLOT_CACHE_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| data_path.join(".plot-cache"));
+        let max_bytes = env_or_default("STS_RS_PLOT_CACHE_BYTES", "67108864")
+            .parse::<u64>()
+            .expect("STS_RS_PLOT_CACHE_BYTES should be a number of bytes");
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        PlotCache {
+            directory,
+            max_bytes,
+            plots: Mutex::new(CachedPlots::default()),
+        }
+    }
+
+    fn file_name(key: &PlotKey, modified: DateTime<Utc>) -> String {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        modified.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    pub fn get(&self, key: &PlotKey, modified: DateTime<Utc>) -> Option<Bytes> {
+        let file_name = PlotCache::file_name(key, modified);
+        let mut plots = self.plots.lock().unpoisoned();
+        if !plots.files.contains_key(&file_name) {
+            return None;
+        }
+        match std::fs::read(self.directory.join(&file_name)) {
+            Ok(image) => {
+                if let Some(plot) = plots.files.get_mut(&file_name) {
+                    plot.used = Instant::now();
+                }
+                Some(Bytes::from(image))
+            }
+            Err(err) => {
+                warn!("Dropping unreadable cached plot {}, {}", file_name, err);
+                self.remove(&mut plots, &file_name);
+                None
+            }
+        }
+    }
+
+    /// Drops the plots of a series, e.g. after its plot settings changed.
+    pub fn invalidate(&self, series_name: &str) {
+        let mut plots = self.plots.lock().unpoisoned();
+        let stale = plots
+            .files
+            .iter()
+            .filter(|(_, plot)| plot.series.iter().any(|name| name == series_name))
+            .map(|(file_name, _)| file_name.clone())
+            .collect::<Vec<_>>();
+        for file_name in stale {
+            self.remove(&mut plots, &file_name);
+        }
+    }
+
+    /// Stores a plot, evicting the least recently used ones to stay within
+    /// the size of the cache.
+    pub fn insert(&self, key: PlotKey, modified: DateTime<Utc>, image: Bytes) {
+        let size = image.len() as u64;
+        if size > self.max_bytes {
+            return;
+        }
+        let file_name = PlotCache::file_name(&key, modified);
+        let mut plots = self.plots.lock().unpoisoned();
+        self.remove(&mut plots, &file_name);
+        while plots.bytes + size > self.max_bytes {
+            match plots
+                .files
+                .iter()
+                .min_by_key(|(_, plot)| plot.used)
+                .map(|(file_name, _)| file_name.clone())
+            {
+                Some(oldest) => self.remove(&mut plots, &oldest),
+                None => break,
+            }
+        }
+        if let Err(err) = std::fs::write(self.directory.join(&file_name), &image) {
+            warn!("Caching plot {} failed, {}", file_name, err);
+            return;
+        }
+        plots.bytes += size;
+        plots.files.insert(
+            file_name,
+            CachedPlot {
+                series: key.series,
+                size,
+                used: Instant::now(),
+            },
+        );
+    }
+
+    fn remove(&self, plots: &mut CachedPlots, file_name: &str) {
+        if let Some(plot) = plots.files.remove(file_name) {
+            if let Err(err) = std::fs::remove_file(self.directory.join(file_name)) {
+                warn!("Removing cached plot {} failed, {}", file_name, err);
+            }
+            plots.bytes -= plot.size;
+        }
+    }
+}
+
+/// The image of a plot while plotting is disabled.
+fn placeholder() -> String {
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#,
+            r##"<rect width="100%" height="100%" fill="#eeeeee"/>"##,
+            r##"<text x="50%" y="50%" text-anchor="middle" font-family="sans-serif" font-size="16" fill="#555555">"##,
+            "Plotting is disabled, see the about page</text></svg>"
+        ),
+        width = WIDTH,
+        height = HEIGHT
+    )
+}
+
+/// `gnuplot` from the `PATH`. On Windows its installer does not add it to
+/// the `PATH` by default, so the program files directories are searched
+/// as well.
+#[cfg(feature = "gnuplot")]
+fn find_gnuplot() -> PathBuf {
+    if cfg!(windows) {
+        let on_path = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let installed = ["ProgramFiles", "ProgramW6432", "ProgramFiles(x86)"]
+            .iter()
+            .filter_map(std::env::var_os)
+            .map(|directory| PathBuf::from(directory).join("gnuplot").join("bin"));
+        let found = on_path
+            .into_iter()
+            .chain(installed)
+            .map(|directory| directory.join("gnuplot.exe"))
+            .find(|program| program.is_file());
+        if let Some(program) = found {
+            return program;
+        }
+    }
+    PathBuf::from("gnuplot")
+}
+
+/// How far back from the latest value a plot goes, written like `24h`,
+/// `7d` or `all`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Window {
+    /// `None` plots the full history.
+    seconds: Option<i64>,
+}
+
+impl Window {
+    /// The window of the pre-rendered plots, configured with
+    /// `STS_RS_PLOT_WINDOW`.
+    pub fn from_env() -> Window {
+        env_or_default("STS_RS_PLOT_WINDOW", "7d")
+            .parse()
+            .unwrap_or_else(|err| panic!("STS_RS_PLOT_WINDOW {}", err))
+    }
+
+    /// The time stamp the window starts at for series ending at `latest`.
+    pub fn start(&self, latest: Option<i64>) -> Option<i64> {
+        Some(latest? - self.seconds?)
+    }
+}
+
+impl std::str::FromStr for Window {
+    type Err = String;
+
+    fn from_str(window: &str) -> Result<Window, String> {
+        if window == "all" {
+            return Ok(Window { seconds: None });
+        }
+        let error = || {
+            format!(
+                "should be a number of hours or days like 24h or 7d, not {}",
+                window
+            )
+        };
+        let (count, unit) = window.split_at(window.len().saturating_sub(1));
+        let count = count.parse::<i64>().map_err(|_| error())?;
+        let seconds = match unit {
+            "h" => count * 60 * 60,
+            "d" => count * 24 * 60 * 60,
+            _ => return Err(error()),
+        };
+        if seconds <= 0 {
+            return Err(error());
+        }
+        Ok(Window {
+            seconds: Some(seconds),
+        })
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.seconds {
+            None => write!(f, "all"),
+            Some(seconds) if seconds % (24 * 60 * 60) == 0 => {
+                write!(f, "{}d", seconds / (24 * 60 * 60))
+            }
+            Some(seconds) => write!(f, "{}h", seconds / (60 * 60)),
+        }
+    }
+}
+
+impl From<Window> for String {
+    fn from(window: Window) -> String {
+        window.to_string()
+    }
+}
+
+impl std::convert::TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(window: String) -> Result<Window, String> {
+        window.parse()
+    }
+}
+
+/// The color scheme of a plot, configured with `STS_RS_PLOT_THEME`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Follows the `prefers-color-scheme` of the viewer, SVG only.
+    Auto,
+}
+
+struct Colors {
+    background: RGBColor,
+    foreground: RGBColor,
+}
+
+const LIGHT: Colors = Colors {
+    background: RGBColor(255, 255, 255),
+    foreground: RGBColor(0, 0, 0),
+};
+const DARK: Colors = Colors {
+    background: RGBColor(32, 33, 36),
+    foreground: RGBColor(232, 234, 237),
+};
+
+impl Theme {
+    pub fn from_env() -> Theme {
+        match env_or_default("STS_RS_PLOT_THEME", "light")
+            .to_lowercase()
+            .as_str()
+        {
+            "light" => Theme::Light,
+            "dark" => Theme::Dark,
+            "auto" => Theme::Auto,
+            other => panic!(
+                "STS_RS_PLOT_THEME should be light, dark or auto, not {}",
+                other
+            ),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+            Theme::Auto => "auto",
+        }
+    }
+
+    /// The colors drawn with, an automatic theme is drawn light.
+    fn colors(&self) -> &'static Colors {
+        match self {
+            Theme::Dark => &DARK,
+            _ => &LIGHT,
+        }
+    }
+}
+
+fn hex(color: &RGBColor) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.0, color.1, color.2)
+}
+
+/// How a series is drawn, part of the series metadata.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct PlotSettings {
+    pub kind: PlotKind,
+    /// Line color as `#rrggbb`, picked from a palette when not set.
+    pub color: Option<String>,
+    pub points: PointStyle,
+    pub y_min: Option<f64>,
+    pub y_max: Option<f64>,
+    pub log_scale: bool,
+    /// Draw the moving average of this many values instead of the values.
+    pub smoothing: Option<usize>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Overrides the theme configured for all series.
+    pub theme: Option<Theme>,
+}
+
+/// The type of chart a series is drawn in, the kind of the first series
+/// decides whether a plot is a histogram.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Hash, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotKind {
+    #[default]
+    Line,
+    /// Markers without connecting lines, for irregularly sampled values.
+    Scatter,
+    /// The distribution of the values over equally wide bins.
+    Histogram,
+}
+
+impl PlotKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlotKind::Line => "line",
+            PlotKind::Scatter => "scatter",
+            PlotKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// The marker drawn at every value.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PointStyle {
+    #[default]
+    None,
+    Circle,
+    Cross,
+    Triangle,
+}
+
+impl PlotSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(color) = &self.color {
+            parse_color(color).ok_or_else(|| format!("{} is not a #rrggbb color", color))?;
+        }
+        if let (Some(y_min), Some(y_max)) = (self.y_min, self.y_max) {
+            if y_min >= y_max {
+                return Err("y_min should be less than y_max".to_owned());
+            }
+        }
+        if self.log_scale && self.y_min.is_some_and(|y_min| y_min <= 0.0) {
+            return Err("y_min should be positive on a logarithmic scale".to_owned());
+        }
+        if self.smoothing == Some(0) {
+            return Err("smoothing should be at least 1".to_owned());
+        }
+        for size in self.width.iter().chain(self.height.iter()) {
+            if !SIZES.contains(size) {
+                return Err(format!(
+                    "width and height should be between {} and {}",
+                    SIZES.start(),
+                    SIZES.end()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width.unwrap_or(WIDTH), self.height.unwrap_or(HEIGHT))
+    }
+}
+
+pub fn parse_color(color: &str) -> Option<RGBColor> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let component = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+    Some(RGBColor(component(0)?, component(2)?, component(4)?))
+}
+
+/// One series in a plot.
+#[derive(Clone, Copy)]
+pub struct Line<'a> {
+    pub series_name: &'a str,
+    pub data: &'a Columns,
+    pub settings: &'a PlotSettings,
+    pub thresholds: &'a [Threshold],
+    /// Events marked with a vertical line where they fall in the plot.
+    pub annotations: &'a [Annotation],
+    /// Plot against the secondary y-axis on the right.
+    pub secondary: bool,
+    pub unit: Option<&'a str>,
+}
+
+impl Line<'_> {
+    /// The position of `value` on the axis of the line, if it has one.
+    fn scaled(&self, value: f64) -> Option<f64> {
+        if !self.settings.log_scale {
+            Some(value)
+        } else if value > 0.0 {
+            Some(value.log10())
+        } else {
+            None
+        }
+    }
+
+    /// The points to draw, smoothed and, on a logarithmic scale, as the
+    /// logarithm of the positive values.
+    fn points(&self) -> Vec<(i64, f64)> {
+        let window = self.settings.smoothing.unwrap_or(1).max(1);
+        let mut sum = 0.0;
+        let values = self.data.values();
+        let mut points = Vec::with_capacity(values.len());
+        for (index, (time_stamp, value)) in self.data.time_stamps().iter().zip(values).enumerate() {
+            sum += value;
+            if index >= window {
+                sum -= values[index - window];
+            }
+            let value = sum / (index + 1).min(window) as f64;
+            if let Some(value) = self.scaled(value) {
+                points.push((*time_stamp, value));
+            }
+        }
+        points
+    }
+}
+
+/// Consecutive points that fall on the same pixel column, drawn as their
+/// mean within a band from their minimum to their maximum.
+struct Aggregate {
+    time_stamp: i64,
+    min: f64,
+    mean: f64,
+    max: f64,
+}
+
+/// Downsamples `points` to at most `buckets` aggregates of equal duration,
+/// or returns `None` when there are few enough points to draw them all.
+fn aggregate(
+    points: &[(i64, f64)],
+    (first, last): (i64, i64),
+    buckets: usize,
+) -> Option<Vec<Aggregate>> {
+    if points.len() <= buckets || buckets == 0 {
+        return None;
+    }
+    let duration = (last - first) as i128 + 1;
+    let bucket = |time_stamp: i64| (time_stamp - first) as i128 * buckets as i128 / duration;
+    let mut aggregates = Vec::with_capacity(buckets);
+    let mut start = 0;
+    while start < points.len() {
+        let current = bucket(points[start].0);
+        let end = start
+            + points[start..]
+                .iter()
+                .take_while(|(time_stamp, _)| bucket(*time_stamp) == current)
+                .count();
+        let values = &points[start..end];
+        let (min, max, sum) = values
+            .iter()
+            .fold((f64::MAX, f64::MIN, 0.0), |(min, max, sum), (_, value)| {
+                (min.min(*value), max.max(*value), sum + value)
+            });
+        aggregates.push(Aggregate {
+            time_stamp: values[0].0 + (values[values.len() - 1].0 - values[0].0) / 2,
+            min,
+            mean: sum / values.len() as f64,
+            max,
+        });
+        start = end;
+    }
+    Some(aggregates)
+}
+
+/// Renders an SVG plot of `lines` for the window `from` to `to`, the window
+/// defaults to the extent of the data.
+pub fn render_svg(
+    lines: &[Line],
+    window: (Option<i64>, Option<i64>),
+    size: (u32, u32),
+    theme: Theme,
+    timezone: Tz,
+) -> Result<String, String> {
+    let mut svg = String::new();
+    draw(
+        SVGBackend::with_string(&mut svg, size).into_drawing_area(),
+        lines,
+        window,
+        theme,
+        timezone,
+    )?;
+    if theme == Theme::Auto {
+        // Plotters writes the colors as attributes, which style sheets override.
+        let style = format!(
+            "<style>@media (prefers-color-scheme: dark) {{ \
+             rect[fill=\"#FFFFFF\"] {{ fill: {background}; }} \
+             text {{ fill: {foreground}; }} \
+             [stroke=\"#000000\"] {{ stroke: {foreground}; }} }}</style>",
+            background = hex(&DARK.background),
+            foreground = hex(&DARK.foreground)
+        );
+        if let Some(end) = svg.find('>') {
+            svg.insert_str(end + 1, &style);
+        }
+    }
+    Ok(svg)
+}
+
+/// Renders a PNG plot of `lines`, like `render_svg`. PNG plots cannot follow
+/// the color scheme, the automatic theme is light.
+pub fn render_png(
+    lines: &[Line],
+    window: (Option<i64>, Option<i64>),
+    (width, height): (u32, u32),
+    theme: Theme,
+    timezone: Tz,
+) -> Result<Vec<u8>, String> {
+    let mut pixels = vec![0; width as usize * height as usize * 3];
+    draw(
+        BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area(),
+        lines,
+        window,
+        theme,
+        timezone,
+    )?;
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&pixels, width, height, ColorType::Rgb8)
+        .map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+/// What a plot of a series shows, taken from it so the plot is rendered
+/// without holding its lock, for mails, chats and reports.
+pub struct Snapshot {
+    pub name: String,
+    data: Columns,
+    meta: SeriesMeta,
+    annotations: Vec<Annotation>,
+    window: (i64, i64),
+}
+
+impl Snapshot {
+    /// The values of `serie` in `window`, none without values.
+    pub fn of(name: &str, serie: &Series, window: (i64, i64)) -> Option<Snapshot> {
+        let data = serie.data.between(window.0, window.1);
+        if data.is_empty() {
+            return None;
+        }
+        Some(Snapshot {
+            name: name.to_owned(),
+            data,
+            meta: serie.meta.clone(),
+            annotations: serie.annotations.clone(),
+            window,
+        })
+    }
+
+    /// A light PNG of the size of the plot settings of the series, `i64::MAX`
+    /// as the end of the window leaves it open.
+    pub fn render_png(&self, timezone: Tz) -> Result<Vec<u8>, String> {
+        let (line, window) = self.line();
+        render_png(
+            &[line],
+            window,
+            self.meta.plot.size(),
+            Theme::Light,
+            timezone,
+        )
+    }
+
+    /// A light SVG, like `render_png`.
+    pub fn render_svg(&self, timezone: Tz) -> Result<String, String> {
+        let (line, window) = self.line();
+        render_svg(
+            &[line],
+            window,
+            self.meta.plot.size(),
+            Theme::Light,
+            timezone,
+        )
+    }
+
+    fn line(&self) -> (Line<'_>, (Option<i64>, Option<i64>)) {
+        let line = Line {
+            series_name: namespaces::split(&self.name).1,
+            data: &self.data,
+            settings: &self.meta.plot,
+            thresholds: &self.meta.thresholds,
+            annotations: &self.annotations,
+            secondary: false,
+            unit: self.meta.unit.as_deref(),
+        };
+        let (from, to) = self.window;
+        (line, (Some(from), Some(to).filter(|to| *to != i64::MAX)))
+    }
+}
+
+/// Renders an SVG heatmap of `line`, a cell per hour of the day in
+/// `timezone` and per date colored by the average value.
+pub fn render_heatmap(
+    line: &Line,
+    size: (u32, u32),
+    theme: Theme,
+    timezone: Tz,
+) -> Result<String, String> {
+    let mut cells = HashMap::<(NaiveDate, u32), (f64, usize)>::new();
+    for (time_stamp, value) in line.points() {
+        if let Some(time) = timezone.timestamp_opt(time_stamp, 0).single() {
+            let cell = cells
+                .entry((time.naive_local().date(), time.hour()))
+                .or_default();
+            *cell = (cell.0 + value, cell.1 + 1);
+        }
+    }
+    let cells = cells
+        .into_iter()
+        .map(|(cell, (sum, count))| (cell, sum / count as f64))
+        .collect::<Vec<_>>();
+    let (first, last) = match (
+        cells.iter().map(|((date, _), _)| *date).min(),
+        cells.iter().map(|((date, _), _)| *date).max(),
+    ) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Err("there are no values".to_owned()),
+    };
+    let (min, max) = cells
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (_, mean)| {
+            (min.min(*mean), max.max(*mean))
+        });
+    let max = if max > min { max } else { min + 1.0 };
+    let days = (last - first).num_days() as i32 + 1;
+    let colors = theme.colors();
+    let text = |size: u32| ("sans-serif", size).into_font().color(&colors.foreground);
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        let error = |err: DrawingAreaErrorKind<std::io::Error>| err.to_string();
+        root.fill(&colors.background).map_err(error)?;
+        let (map_area, scale_area) = root.split_horizontally(size.0.saturating_sub(80));
+        let mut chart = ChartBuilder::on(&map_area)
+            .caption(format!("{} by time of day", line.series_name), text(16))
+            .margin(10)
+            .x_label_area_size(60)
+            .y_label_area_size(40)
+            .build_cartesian_2d(0..days, 0..24)
+            .map_err(error)?;
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_desc("Date")
+            .y_desc(time_description("Hour", timezone))
+            .x_labels(5)
+            .x_label_formatter(&|day| {
+                (first + chrono::Duration::days(*day as i64))
+                    .format("%Y/%m/%d")
+                    .to_string()
+            })
+            .label_style(text(11))
+            .axis_desc_style(text(11))
+            .axis_style(colors.foreground)
+            .draw()
+            .map_err(error)?;
+        chart
+            .draw_series(cells.iter().map(|((date, hour), mean)| {
+                let day = (*date - first).num_days() as i32;
+                let hour = *hour as i32;
+                Rectangle::new(
+                    [(day, hour), (day + 1, hour + 1)],
+                    ViridisRGB::get_color_normalized(*mean, min, max).filled(),
+                )
+            }))
+            .map_err(error)?;
+        // A color scale of the averages next to the grid.
+        let mut scale = ChartBuilder::on(&scale_area)
+            .margin_top(40)
+            .margin_bottom(70)
+            .margin_right(10)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0..1, min..max)
+            .map_err(error)?;
+        scale
+            .configure_mesh()
+            .disable_mesh()
+            .disable_x_axis()
+            .y_label_formatter(&|value| axis_label(*value, line.settings.log_scale))
+            .label_style(text(11))
+            .axis_style(colors.foreground)
+            .draw()
+            .map_err(error)?;
+        let steps = 100;
+        scale
+            .draw_series((0..steps).map(|step| {
+                let low = min + (max - min) * step as f64 / steps as f64;
+                let high = min + (max - min) * (step + 1) as f64 / steps as f64;
+                Rectangle::new(
+                    [(0, low), (1, high)],
+                    ViridisRGB::get_color_normalized(low, min, max).filled(),
+                )
+            }))
+            .map_err(error)?;
+        root.present().map_err(error)?;
+    }
+    Ok(svg)
+}
+
+/// Renders an SVG sparkline of `line`, just the values on a transparent
+/// background with a dot at the latest one.
+pub fn render_sparkline(
+    line: &Line,
+    (from, to): (Option<i64>, Option<i64>),
+    size: (u32, u32),
+    theme: Theme,
+) -> Result<String, String> {
+    let points = line.points();
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (from.unwrap_or(first.0), to.unwrap_or(last.0)),
+        _ => return Err("there are no values".to_owned()),
+    };
+    let last = if last > first { last } else { first + 1 };
+    let color = match line.settings.color.as_deref().and_then(parse_color) {
+        Some(color) => color.to_rgba(),
+        None => theme.colors().foreground.to_rgba(),
+    };
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        let error = |err: DrawingAreaErrorKind<std::io::Error>| err.to_string();
+        let mut chart = ChartBuilder::on(&root)
+            .margin(2)
+            .build_cartesian_2d(first..last, value_range(points.iter(), line.settings))
+            .map_err(error)?;
+        let drawn = aggregate(&points, (first, last), size.0 as usize)
+            .map(|aggregates| {
+                aggregates
+                    .into_iter()
+                    .map(|aggregate| (aggregate.time_stamp, aggregate.mean))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|| points.clone());
+        chart
+            .draw_series(LineSeries::new(drawn, color.stroke_width(1)))
+            .map_err(error)?;
+        chart
+            .draw_series(
+                points
+                    .last()
+                    .map(|point| Circle::new(*point, 2, color.filled())),
+            )
+            .map_err(error)?;
+        root.present().map_err(error)?;
+    }
+    if theme == Theme::Auto && line.settings.color.is_none() {
+        let style = format!(
+            "<style>@media (prefers-color-scheme: dark) {{ \
+             [stroke=\"{light}\"] {{ stroke: {dark}; }} \
+             [fill=\"{light}\"] {{ fill: {dark}; }} }}</style>",
+            light = hex(&LIGHT.foreground),
+            dark = hex(&DARK.foreground)
+        );
+        if let Some(end) = svg.find('>') {
+            svg.insert_str(end + 1, &style);
+        }
+    }
+    Ok(svg)
+}
+
+/// Renders a plot of `line` to `image_file`, as PNG when the file has
+/// a `png` extension and as SVG otherwise.
+pub fn render(line: Line, theme: Theme, timezone: Tz, image_file: &Path) -> Result<(), String> {
+    let settings = line.settings;
+    let lines = [line];
+    let image = if image_file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        == Some("png")
+    {
+        render_png(&lines, (None, None), settings.size(), theme, timezone)?
+    } else {
+        render_svg(&lines, (None, None), settings.size(), theme, timezone)?.into_bytes()
+    };
+    std::fs::write(image_file, image).map_err(|err| err.to_string())
+}
+
+/// Draws `lines`, the settings of the first line on an axis determine the
+/// range and scale of that axis.
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    lines: &[Line],
+    (from, to): (Option<i64>, Option<i64>),
+    theme: Theme,
+    timezone: Tz,
+) -> Result<(), String> {
+    if lines
+        .first()
+        .is_some_and(|line| line.settings.kind == PlotKind::Histogram)
+    {
+        return draw_histogram(root, lines, theme);
+    }
+    let colors = theme.colors();
+    let text = |size: u32| ("sans-serif", size).into_font().color(&colors.foreground);
+    let first = from.or_else(|| {
+        lines
+            .iter()
+            .filter_map(|line| line.data.first())
+            .map(|datum| datum.timeStamp)
+            .min()
+    });
+    let last = to.or_else(|| {
+        lines
+            .iter()
+            .filter_map(|line| line.data.last())
+            .map(|datum| datum.timeStamp)
+            .max()
+    });
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Err("there are no values".to_owned()),
+    };
+    let names =
+        |secondary: bool| axis_description(lines.iter().filter(|line| line.secondary == secondary));
+    let default_settings = PlotSettings::default();
+    let axis_settings = |secondary: bool| {
+        lines
+            .iter()
+            .find(|line| line.secondary == secondary)
+            .map_or(&default_settings, |line| line.settings)
+    };
+    let points = lines.iter().map(Line::points).collect::<Vec<_>>();
+    let levels = lines
+        .iter()
+        .map(|line| {
+            line.thresholds
+                .iter()
+                .enumerate()
+                .filter_map(|(index, threshold)| {
+                    line.scaled(threshold.value)
+                        .map(|level| (first, level, index, threshold))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // Thresholds are kept in view, to show how close the values get to them.
+    let axis_range = |secondary: bool| {
+        let level_points = lines
+            .iter()
+            .zip(&levels)
+            .filter(|(line, _)| line.secondary == secondary)
+            .flat_map(|(_, levels)| levels.iter().map(|(time, level, _, _)| (*time, *level)))
+            .collect::<Vec<_>>();
+        value_range(
+            lines
+                .iter()
+                .zip(&points)
+                .filter(|(line, _)| line.secondary == secondary)
+                .flat_map(|(_, points)| points.iter())
+                .chain(level_points.iter()),
+            axis_settings(secondary),
+        )
+    };
+    let (primary_range, secondary_range) = (axis_range(false), axis_range(true));
+    let has_secondary = lines.iter().any(|line| line.secondary);
+    let all_names = lines
+        .iter()
+        .map(|line| line.series_name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let error = |err: DrawingAreaErrorKind<DB::ErrorType>| err.to_string();
+    root.fill(&colors.background).map_err(error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{} over time", all_names), text(16))
+        .margin(10)
+        // Room for the last time stamp label.
+        .margin_right(if has_secondary { 10 } else { 50 })
+        .x_label_area_size(60)
+        .y_label_area_size(60)
+        .right_y_label_area_size(if has_secondary { 60 } else { 0 })
+        .build_cartesian_2d(first..last.max(first + 1), primary_range.clone())
+        .map_err(error)?
+        .set_secondary_coord(first..last.max(first + 1), secondary_range.clone());
+    let primary_log = axis_settings(false).log_scale;
+    let secondary_log = axis_settings(true).log_scale;
+    chart
+        .configure_mesh()
+        .x_desc(time_description("Time", timezone))
+        .y_desc(names(false))
+        .x_labels(5)
+        .x_label_formatter(
+            &|time_stamp| match timezone.timestamp_opt(*time_stamp, 0).single() {
+                Some(time) => time.format("%Y/%m/%d %H:%M:%S").to_string(),
+                None => time_stamp.to_string(),
+            },
+        )
+        .y_label_formatter(&|value| axis_label(*value, primary_log))
+        .label_style(text(11))
+        .axis_desc_style(text(11))
+        .axis_style(colors.foreground)
+        .bold_line_style(colors.foreground.mix(0.2))
+        .light_line_style(colors.foreground.mix(0.05))
+        .draw()
+        .map_err(error)?;
+    if has_secondary {
+        chart
+            .configure_secondary_axes()
+            .y_desc(names(true))
+            .y_label_formatter(&|value| axis_label(*value, secondary_log))
+            .label_style(text(11))
+            .axis_desc_style(text(11))
+            .axis_style(colors.foreground)
+            .draw()
+            .map_err(error)?;
+    }
+    let last = last.max(first + 1);
+    let buckets = chart.plotting_area().dim_in_pixel().0 as usize;
+    for (line, levels) in lines.iter().zip(&levels) {
+        let range = if line.secondary {
+            &secondary_range
+        } else {
+            &primary_range
+        };
+        for (_, level, index, threshold) in levels {
+            let color = threshold
+                .color
+                .as_deref()
+                .and_then(parse_color)
+                .unwrap_or(THRESHOLD_COLORS[index % THRESHOLD_COLORS.len()]);
+            let series = DashedLineSeries::new(
+                vec![(first, *level), (last, *level)],
+                6,
+                4,
+                color.stroke_width(1),
+            );
+            let shade = threshold.shade.then(|| {
+                let edge = if threshold.below {
+                    range.start
+                } else {
+                    range.end
+                };
+                Rectangle::new([(first, *level), (last, edge)], color.mix(0.1).filled())
+            });
+            let label = format!("{} {}", threshold.name, threshold.value);
+            let anno = if line.secondary {
+                chart.draw_secondary_series(shade).map_err(error)?;
+                chart.draw_secondary_series(series)
+            } else {
+                chart.draw_series(shade).map_err(error)?;
+                chart.draw_series(series)
+            }
+            .map_err(error)?;
+            anno.label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+    }
+    let annotations = lines
+        .iter()
+        .flat_map(|line| line.annotations)
+        .filter(|annotation| (first..=last).contains(&annotation.time_stamp));
+    for annotation in annotations {
+        let time = annotation.time_stamp;
+        chart
+            .draw_series(DashedLineSeries::new(
+                vec![(time, primary_range.start), (time, primary_range.end)],
+                3,
+                3,
+                colors.foreground.mix(0.6).stroke_width(1),
+            ))
+            .map_err(error)?;
+        chart
+            .draw_series(std::iter::once(
+                EmptyElement::at((time, primary_range.end))
+                    + Text::new(annotation.label.clone(), (3, 3), text(10)),
+            ))
+            .map_err(error)?;
+    }
+    for (index, (line, points)) in lines.iter().zip(points).enumerate() {
+        let color = line_color(line, index);
+        let scatter = line.settings.kind == PlotKind::Scatter;
+        // Averaging hides spikes, so downsampled lines show the extremes too.
+        let aggregates = if scatter {
+            None
+        } else {
+            aggregate(&points, (first, last), buckets)
+        };
+        let points = match aggregates {
+            Some(aggregates) => {
+                let band = Polygon::new(
+                    aggregates
+                        .iter()
+                        .map(|aggregate| (aggregate.time_stamp, aggregate.max))
+                        .chain(
+                            aggregates
+                                .iter()
+                                .rev()
+                                .map(|aggregate| (aggregate.time_stamp, aggregate.min)),
+                        )
+                        .collect::<Vec<_>>(),
+                    color.mix(0.2).filled(),
+                );
+                if line.secondary {
+                    chart.draw_secondary_series(std::iter::once(band))
+                } else {
+                    chart.draw_series(std::iter::once(band))
+                }
+                .map_err(error)?;
+                aggregates
+                    .iter()
+                    .map(|aggregate| (aggregate.time_stamp, aggregate.mean))
+                    .collect()
+            }
+            None => points,
+        };
+        let markers = points.clone();
+        if !scatter {
+            let series = if line.secondary {
+                chart.draw_secondary_series(LineSeries::new(points, color))
+            } else {
+                chart.draw_series(LineSeries::new(points, color))
+            }
+            .map_err(error)?;
+            series
+                .label(line.series_name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        // Scatter plots are all markers, so they need one.
+        let point_style = match line.settings.points {
+            PointStyle::None if scatter => PointStyle::Circle,
+            style => style,
+        };
+        macro_rules! draw_markers {
+            ($marker:expr) => {{
+                let series = if line.secondary {
+                    chart.draw_secondary_series(markers.into_iter().map($marker))
+                } else {
+                    chart.draw_series(markers.into_iter().map($marker))
+                }
+                .map_err(error)?;
+                if scatter {
+                    series
+                        .label(line.series_name)
+                        .legend(move |(x, y)| Circle::new((x + 10, y), 3, color.filled()));
+                }
+            }};
+        }
+        match point_style {
+            PointStyle::None => {}
+            PointStyle::Circle => draw_markers!(|point| Circle::new(point, 3, color)),
+            PointStyle::Cross => draw_markers!(|point| Cross::new(point, 3, color)),
+            PointStyle::Triangle => draw_markers!(|point| TriangleMarker::new(point, 4, color)),
+        }
+    }
+    if lines.len() > 1 || levels.iter().any(|levels| !levels.is_empty()) {
+        chart
+            .configure_series_labels()
+            .label_font(text(11))
+            .background_style(colors.background.mix(0.8))
+            .border_style(colors.foreground)
+            .draw()
+            .map_err(error)?;
+    }
+    root.present().map_err(error)
+}
+
+/// Draws the distribution of the values of `lines`, as translucent bars on
+/// top of each other. The number of bins follows Sturges' rule.
+fn draw_histogram<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    lines: &[Line],
+    theme: Theme,
+) -> Result<(), String> {
+    let colors = theme.colors();
+    let text = |size: u32| ("sans-serif", size).into_font().color(&colors.foreground);
+    let values = lines
+        .iter()
+        .map(|line| {
+            line.points()
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let (min, max) = values
+        .iter()
+        .flatten()
+        .fold((f64::MAX, f64::MIN), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        });
+    if min > max {
+        return Err("there are no values".to_owned());
+    }
+    let most = values.iter().map(Vec::len).max().unwrap_or(0);
+    let bins = ((most as f64).log2().ceil() as usize + 1).clamp(1, 100);
+    let bin_width = if max > min {
+        (max - min) / bins as f64
+    } else {
+        1.0
+    };
+    let counts = values
+        .iter()
+        .map(|values| {
+            let mut counts = vec![0; bins];
+            for value in values {
+                counts[(((value - min) / bin_width) as usize).min(bins - 1)] += 1;
+            }
+            counts
+        })
+        .collect::<Vec<_>>();
+    let highest = counts.iter().flatten().copied().max().unwrap_or(0);
+    let all_names = lines
+        .iter()
+        .map(|line| line.series_name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let log = lines[0].settings.log_scale;
+    let error = |err: DrawingAreaErrorKind<DB::ErrorType>| err.to_string();
+    root.fill(&colors.background).map_err(error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Distribution of {}", all_names), text(16))
+        .margin(10)
+        .margin_right(30)
+        .x_label_area_size(60)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min..min + bin_width * bins as f64, 0..highest + 1)
+        .map_err(error)?;
+    chart
+        .configure_mesh()
+        .x_desc(axis_description(lines.iter()))
+        .y_desc("Count")
+        .x_label_formatter(&|value| axis_label(*value, log))
+        .label_style(text(11))
+        .axis_desc_style(text(11))
+        .axis_style(colors.foreground)
+        .bold_line_style(colors.foreground.mix(0.2))
+        .light_line_style(colors.foreground.mix(0.05))
+        .draw()
+        .map_err(error)?;
+    let opacity = if lines.len() > 1 { 0.5 } else { 0.8 };
+    for (index, (line, counts)) in lines.iter().zip(counts).enumerate() {
+        let color = line_color(line, index);
+        chart
+            .draw_series(counts.into_iter().enumerate().map(|(bin, count)| {
+                let start = min + bin as f64 * bin_width;
+                Rectangle::new(
+                    [(start, 0), (start + bin_width, count)],
+                    color.mix(opacity).filled(),
+                )
+            }))
+            .map_err(error)?
+            .label(line.series_name)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 20, y + 4)], color.filled()));
+    }
+    if lines.len() > 1 {
+        chart
+            .configure_series_labels()
+            .label_font(text(11))
+            .background_style(colors.background.mix(0.8))
+            .border_style(colors.foreground)
+            .draw()
+            .map_err(error)?;
+    }
+    root.present().map_err(error)
+}
+
+/// The color of a line, from its settings or else from the palette.
+fn line_color(line: &Line, index: usize) -> RGBAColor {
+    line.settings
+        .color
+        .as_deref()
+        .and_then(parse_color)
+        .map_or_else(|| Palette99::pick(index).to_rgba(), |color| color.to_rgba())
+}
+
+/// The names of the series drawn against an axis, followed by their unit
+/// when they have the same one.
+fn axis_description<'a>(lines: impl Iterator<Item = &'a Line<'a>> + Clone) -> String {
+    let names = lines
+        .clone()
+        .map(|line| line.series_name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut units = lines.map(|line| line.unit);
+    match units.next() {
+        Some(Some(unit)) if units.all(|other| other == Some(unit)) => {
+            format!("{} ({})", names, unit)
+        }
+        _ => names,
+    }
+}
+
+/// The label of a time axis, naming the time zone unless it is UTC.
+fn time_description(label: &str, timezone: Tz) -> String {
+    match timezone {
+        Tz::UTC => label.to_owned(),
+        other => format!("{} ({})", label, other.name()),
+    }
+}
+
+/// The offset of `timezone` from UTC in seconds at `time_stamp`.
+#[cfg(feature = "gnuplot")]
+fn utc_offset(timezone: Tz, time_stamp: i64) -> i64 {
+    match Utc.timestamp_opt(time_stamp, 0).single() {
+        Some(time) => time
+            .with_timezone(&timezone)
+            .offset()
+            .fix()
+            .local_minus_utc() as i64,
+        None => 0,
+    }
+}
+
+/// The range of `points`, widened by the same margins as the gnuplot offsets
+/// and by some room for constant series, unless the settings fix the range.
+fn value_range<'a>(
+    points: impl Iterator<Item = &'a (i64, f64)>,
+    settings: &PlotSettings,
+) -> std::ops::Range<f64> {
+    let scale = |value: f64| {
+        if settings.log_scale {
+            value.log10()
+        } else {
+            value
+        }
+    };
+    let (min, max) = points.fold((f64::MAX, f64::MIN), |(min, max), (_, value)| {
+        (min.min(*value), max.max(*value))
+    });
+    let (min, max) = if min > max {
+        (-1.0, 1.0)
+    } else {
+        let offset = if max > min { (max - min) * 0.01 } else { 1.0 };
+        (min - offset, max + offset)
+    };
+    // A logarithmic scale requested for a series with a non-positive bound.
+    let usable = |bound: &f64| !settings.log_scale || *bound > 0.0;
+    let min = settings.y_min.filter(usable).map_or(min, scale);
+    let max = settings.y_max.filter(usable).map_or(max, scale);
+    if min < max {
+        min..max
+    } else {
+        min..min + 1.0
+    }
+}
+
+/// Labels the position `value` on an axis. On a logarithmic axis the labels
+/// are rounded to three significant digits.
+fn axis_label(value: f64, log_scale: bool) -> String {
+    let mut value = if log_scale { 10f64.powf(value) } else { value };
+    if value != 0.0 && (value.abs() >= 1e5 || value.abs() < 1e-3) {
+        return format!("{:.1e}", value);
+    }
+    let mut decimals = 3;
+    if log_scale {
+        let exponent = value.abs().log10().floor() as i32;
+        let magnitude = 10f64.powi(exponent - 2);
+        value = (value / magnitude).round() * magnitude;
+        decimals = (2 - exponent).clamp(0, 5) as usize;
+    }
+    let label = format!("{:.*}", decimals, value);
+    if label.contains('.') {
+        label.trim_end_matches('0').trim_end_matches('.').to_owned()
+    } else {
+        label
+    }
+}
+
+/// The time axis of a gnuplot plot. Gnuplot only knows UTC, so the times
+/// are shifted by the offset of the time zone at the latest value.
+#[cfg(feature = "gnuplot")]
+struct TimeAxis {
+    from: Option<i64>,
+    /// The latest value.
+    to: Option<i64>,
+    utc_offset: i64,
+    description: String,
+}
+
+/// The gnuplot script of the built-in plot of `line`.
+#[cfg(feature = "gnuplot")]
+fn gnuplot_commands(
+    line: Line,
+    data_file_name: &Path,
+    theme: Theme,
+    time_axis: &TimeAxis,
+    image_file: &Path,
+) -> String {
+    let (series_name, settings) = (line.series_name, line.settings);
+    let (width, height) = settings.size();
+    let foreground = hex(&theme.colors().foreground);
+    let mut annotation_commands = String::new();
+    for annotation in line.annotations {
+        if time_axis
+            .from
+            .is_some_and(|from| annotation.time_stamp < from)
+        {
+            continue;
+        }
+        // Gnuplot reads time coordinates like the data, as quoted seconds.
+        let time = annotation.time_stamp + time_axis.utc_offset;
+        annotation_commands += &format!(
+            "set arrow from first '{time}', graph 0 to first '{time}', graph 1 nohead lc rgb '{}' dt 3;\n\
+             set label '{}' at first '{time}', graph 1 offset 0.5,-1 textcolor rgb '{}' font ',8';\n",
+            foreground,
+            annotation.label.replace('\'', "''"),
+            foreground,
+            time = time
+        );
+    }
+    let mut threshold_commands = String::new();
+    for (index, threshold) in line.thresholds.iter().enumerate() {
+        let color = threshold
+            .color
+            .clone()
+            .unwrap_or_else(|| hex(&THRESHOLD_COLORS[index % THRESHOLD_COLORS.len()]));
+        threshold_commands += &format!(
+            "set arrow from graph 0, first {value} to graph 1, first {value} nohead lc rgb '{}' dt 2;\n",
+            color,
+            value = threshold.value
+        );
+        if threshold.shade {
+            threshold_commands += &format!(
+                "set object rect from graph 0, first {} to graph 1, graph {} fc rgb '{}' fs transparent solid 0.1 noborder behind;\n",
+                threshold.value,
+                if threshold.below { 0 } else { 1 },
+                color
+            );
+        }
+    }
+    let background = hex(&theme.colors().background);
+    let bound = |bound: Option<f64>| bound.map_or("*".to_owned(), |bound| bound.to_string());
+    let marker = match settings.points {
+        PointStyle::None => None,
+        PointStyle::Circle => Some(7),
+        PointStyle::Cross => Some(2),
+        PointStyle::Triangle => Some(9),
+    };
+    let style = match (settings.kind, marker) {
+        (PlotKind::Scatter, marker) => format!("points pt {}", marker.unwrap_or(7)),
+        (_, None) => "lines".to_owned(),
+        (_, Some(marker)) => format!("linespoints pt {}", marker),
+    };
+    let color = settings
+        .color
+        .as_ref()
+        .map_or(String::new(), |color| format!(" lc rgb '{}'", color));
+    if settings.kind == PlotKind::Histogram {
+        // Values before the window are left out as undefined.
+        let value = time_axis.from.map_or("($2)".to_owned(), |from| {
+            format!("($1 >= {} ? $2 : NaN)", from)
+        });
+        return format!(
+            r#"{} '{}';
+set terminal svg size {},{} background '{}';
+set border lc rgb '{fg}';
+set xtics norotate textcolor rgb '{fg}';
+set ytics textcolor rgb '{fg}';
+unset xdata;
+set format x '%g';
+set xlabel '{}' offset 0,0 textcolor rgb '{fg}';
+set ylabel 'Count' textcolor rgb '{fg}';
+set title 'Distribution of {}' textcolor rgb '{fg}';
+stats '{}' using {value} nooutput;
+bins = ceil(log(STATS_records) / log(2)) + 1;
+bin_width = STATS_max > STATS_min ? (STATS_max - STATS_min) / bins : 1;
+bin(v) = floor((v - STATS_min) / bin_width) < bins ? floor((v - STATS_min) / bin_width) : bins - 1;
+set boxwidth bin_width;
+set style fill solid 0.8;
+set yrange [0:*];
+plot '{}' using (STATS_min + bin_width * (bin({value}) + 0.5)):(1) smooth frequency with boxes{} notitle;"#,
+            GNUPLOT_COMMANDS,
+            image_file.display(),
+            width,
+            height,
+            background,
+            axis_description(std::iter::once(&line)).replace('\'', "''"),
+            series_name,
+            data_file_name.display(),
+            data_file_name.display(),
+            color,
+            value = value,
+            fg = foreground
+        );
+    }
+    format!(
+        r#"{} '{}';
+set terminal svg size {},{} background '{}';
+set border lc rgb '{fg}';
+set xtics textcolor rgb '{fg}';
+set ytics textcolor rgb '{fg}';
+set xlabel '{}' offset 0,5 textcolor rgb '{fg}';
+set yrange [{}:{}];
+{}{}{}{}set title '{} over time' textcolor rgb '{fg}';
+set ylabel '{}' textcolor rgb '{fg}';
+plot '{}' using ($1+{}):2 with {}{} notitle;"#,
+        GNUPLOT_COMMANDS,
+        image_file.display(),
+        width,
+        height,
+        background,
+        time_axis.description,
+        bound(settings.y_min),
+        bound(settings.y_max),
+        if settings.log_scale {
+            "set logscale y;\n"
+        } else {
+            ""
+        },
+        threshold_commands,
+        annotation_commands,
+        time_axis
+            .from
+            .map(|from| format!("set xrange ['{}':];\n", from + time_axis.utc_offset))
+            .unwrap_or_default(),
+        series_name,
+        axis_description(std::iter::once(&line)).replace('\'', "''"),
+        data_file_name.display(),
+        time_axis.utc_offset,
+        style,
+        color,
+        fg = foreground
+    )
+}
+
+/// Fills in the placeholders of a user supplied gnuplot script, like
+/// `{{data_file}}`. Times are quoted seconds shifted to the time zone,
+/// `*` when there is no bound.
+#[cfg(feature = "gnuplot")]
+fn fill_template(
+    template: &str,
+    line: Line,
+    data_file_name: &Path,
+    time_axis: &TimeAxis,
+    image_file: &Path,
+) -> String {
+    let (width, height) = line.settings.size();
+    let time = |time: Option<i64>| {
+        time.map_or("*".to_owned(), |time| {
+            format!("'{}'", time + time_axis.utc_offset)
+        })
+    };
+    [
+        ("data_file", data_file_name.display().to_string()),
+        ("image_file", image_file.display().to_string()),
+        ("series", line.series_name.to_owned()),
+        ("title", format!("{} over time", line.series_name)),
+        ("from", time(time_axis.from)),
+        ("to", time(time_axis.to)),
+        ("utc_offset", time_axis.utc_offset.to_string()),
+        ("width", width.to_string()),
+        ("height", height.to_string()),
+    ]
+    .iter()
+    .fold(template.to_owned(), |script, (name, value)| {
+        script.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+#[cfg(feature = "gnuplot")]
+fn log_command_output(output: &Output) {
+    if !output.stdout.is_empty() {
+        info!(
+            "Gnuplot command ouput: {}\n",
+            str::from_utf8(&output.stdout).unwrap()
+        );
+    }
+    if !output.stderr.is_empty() {
+        warn!(
+            "Gnuplot command stderr:\n{}",
+            str::from_utf8(&output.stderr).unwrap()
+        );
+    }
+}
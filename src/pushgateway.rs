@@ -0,0 +1,256 @@
+//! Takes metrics pushed like to a Prometheus Pushgateway, with `PUT` or
+//! `POST /metrics/job/<job>{/<label>/<value>}` and a body in the text
+//! exposition format, so batch jobs push to the server without changes.
+//! Every sample becomes a value of a series named after its metric. The
+//! grouping labels of the path, like the job and the instance, are put in
+//! front of the name or, with `STS_RS_PUSHGATEWAY_LABELS=tags`, added as
+//! tags of the series.
+
+use crate::env_or_default;
+
+/// How the grouping labels of a push end up with its series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelMapping {
+    /// `backup.db1.duration_seconds` for the job `backup` and the instance
+    /// `db1`.
+    Names,
+    /// `duration_seconds` tagged `job.backup` and `instance.db1`.
+    Tags,
+}
+
+impl LabelMapping {
+    pub fn from_env() -> LabelMapping {
+        match env_or_default("STS_RS_PUSHGATEWAY_LABELS", "names").as_str() {
+            "names" => LabelMapping::Names,
+            "tags" => LabelMapping::Tags,
+            other => panic!(
+                "STS_RS_PUSHGATEWAY_LABELS should be names or tags, not {}",
+                other
+            ),
+        }
+    }
+}
+
+/// A line of the text exposition format, `name{label="value"} 1.5`
+/// optionally followed by a time stamp in milliseconds.
+#[derive(Debug)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    pub time_stamp: Option<i64>,
+}
+
+/// The series a push adds values to and the tags they get.
+pub struct Push {
+    /// The name of the series within the namespace of the route with its
+    /// value and time stamp in seconds, if the sample had one.
+    pub values: Vec<(String, f64, Option<i64>)>,
+    pub tags: Vec<String>,
+}
+
+impl Push {
+    /// The values of the finite samples, those that are not a number or
+    /// infinite have no place in a series. Labels with an empty value count
+    /// as missing, like in Prometheus.
+    pub fn of(grouping: &[(String, String)], samples: Vec<Sample>, mapping: LabelMapping) -> Push {
+        let grouping = grouping.iter().filter(|(_, value)| !value.is_empty());
+        let prefix = match mapping {
+            LabelMapping::Names => grouping
+                .clone()
+                .map(|(_, value)| format!("{}.", name_part(value)))
+                .collect(),
+            LabelMapping::Tags => String::new(),
+        };
+        let values = samples
+            .into_iter()
+            .filter(|sample| sample.value.is_finite())
+            .map(|sample| {
+                let mut name = format!("{}{}", prefix, name_part(&sample.name));
+                // Samples of one metric are told apart by their labels.
+                let mut labels = sample.labels;
+                labels.sort();
+                for (_, value) in labels.iter().filter(|(_, value)| !value.is_empty()) {
+                    name.push('.');
+                    name.push_str(&name_part(value));
+                }
+                let time_stamp = sample.time_stamp.map(|millis| millis.div_euclid(1000));
+                (name, sample.value, time_stamp)
+            })
+            .collect();
+        let tags = match mapping {
+            LabelMapping::Names => Vec::new(),
+            LabelMapping::Tags => grouping
+                .map(|(label, value)| format!("{}.{}", tag_part(label), tag_part(value)))
+                .collect(),
+        };
+        Push { values, tags }
+    }
+}
+
+/// The grouping labels of the path after `/metrics/`, like
+/// `job/backup/instance/db1`, the job first. Labels named with `@base64`
+/// appended have a base64url encoded value, to allow slashes in it.
+pub fn grouping(path: &str) -> Result<Vec<(String, String)>, String> {
+    let mut parts = path.trim_end_matches('/').split('/');
+    let mut labels = Vec::new();
+    while let Some(label) = parts.next() {
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("label {} should have a value", label))?;
+        let (label, value) = match label.strip_suffix("@base64") {
+            Some(label) => (label, decode_base64(value)?),
+            None => (label, value.to_owned()),
+        };
+        if !valid_label_name(label) {
+            return Err(format!("{:?} is not a valid label name", label));
+        }
+        labels.push((label.to_owned(), value));
+    }
+    match labels.first() {
+        Some((label, job)) if label == "job" && !job.is_empty() => Ok(labels),
+        _ => Err("the path should start with a job that is not empty".to_owned()),
+    }
+}
+
+/// The samples of a body in the text exposition format. Comments, like
+/// the `# TYPE` and `# HELP` lines, are skipped.
+pub fn parse(text: &str) -> Result<Vec<Sample>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            parse_sample(line).map_err(|err| format!("line {}: {}", index + 1, err))
+        })
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .ok_or("a sample should have a value")?;
+    let name = &line[..name_end];
+    if !valid_metric_name(name) {
+        return Err(format!("{:?} is not a valid metric name", name));
+    }
+    let mut rest = &line[name_end..];
+    let mut labels = Vec::new();
+    if let Some(after) = rest.strip_prefix('{') {
+        rest = after;
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (label, after) = rest
+                .split_once('=')
+                .ok_or("labels should be like name=\"value\"")?;
+            let label = label.trim();
+            if !valid_label_name(label) {
+                return Err(format!("{:?} is not a valid label name", label));
+            }
+            let (value, after) = quoted(after.trim_start())?;
+            labels.push((label.to_owned(), value));
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+    let mut fields = rest.split_whitespace();
+    let value = fields.next().ok_or("a sample should have a value")?;
+    let value = parse_value(value).ok_or_else(|| format!("{:?} is not a value", value))?;
+    let time_stamp = match fields.next() {
+        Some(time_stamp) => Some(
+            time_stamp
+                .parse::<i64>()
+                .map_err(|_| format!("{:?} is not a time stamp", time_stamp))?,
+        ),
+        None => None,
+    };
+    if fields.next().is_some() {
+        return Err("a sample should end after its time stamp".to_owned());
+    }
+    Ok(Sample {
+        name: name.to_owned(),
+        labels,
+        value,
+        time_stamp,
+    })
+}
+
+/// A label value in double quotes with `\\`, `\"` and `\n` escaped, and
+/// what follows it.
+fn quoted(text: &str) -> Result<(String, &str), String> {
+    let text = text
+        .strip_prefix('"')
+        .ok_or("label values should be in double quotes")?;
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &text[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, escaped)) => value.push(escaped),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err("a label value is not closed with a double quote".to_owned())
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        value => value.parse().ok(),
+    }
+}
+
+fn valid_metric_name(name: &str) -> bool {
+    name.chars().enumerate().all(|(index, c)| {
+        c.is_ascii_alphabetic() || c == '_' || c == ':' || index > 0 && c.is_ascii_digit()
+    }) && !name.is_empty()
+}
+
+fn valid_label_name(name: &str) -> bool {
+    name.chars()
+        .enumerate()
+        .all(|(index, c)| c.is_ascii_alphabetic() || c == '_' || index > 0 && c.is_ascii_digit())
+        && !name.is_empty()
+}
+
+fn decode_base64(value: &str) -> Result<String, String> {
+    // Pushgateway takes `=` for an empty value.
+    let value = value.trim_end_matches('=');
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| format!("{:?} is not base64url encoded text", value))
+}
+
+/// Slashes are not allowed in series names.
+fn name_part(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Tags are letters, digits, dashes, underscores and dots.
+fn tag_part(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect()
+}
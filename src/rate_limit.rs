@@ -0,0 +1,147 @@
+use crate::env_or_default;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::Method;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Buckets beyond this many are pruned of the clients that are back to a
+/// full bucket.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Limits how often each client may change series, with a bucket of
+/// tokens per API key, or per address for requests without a key.
+/// Configured with `STS_RS_RATE_LIMIT` in changes per minute and
+/// `STS_RS_RATE_BURST`, the number of changes that may be made at once.
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The state of the bucket of a client after a change, sent along as the
+/// `RateLimit-*` headers.
+#[derive(Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    limit: u64,
+    remaining: u64,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next change is allowed.
+    retry_after: u64,
+}
+
+impl RateLimiter {
+    pub fn from_env() -> Option<RateLimiter> {
+        let per_minute = std::env::var("STS_RS_RATE_LIMIT").ok()?;
+        let per_minute = per_minute
+            .parse::<u32>()
+            .ok()
+            .filter(|per_minute| *per_minute > 0)
+            .expect("STS_RS_RATE_LIMIT should be a number of changes per minute");
+        let burst = env_or_default("STS_RS_RATE_BURST", &per_minute.to_string())
+            .parse::<u32>()
+            .ok()
+            .filter(|burst| *burst > 0)
+            .expect("STS_RS_RATE_BURST should be a number of changes");
+        Some(RateLimiter {
+            per_second: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} changes per minute in bursts of up to {}",
+            self.per_second * 60.0,
+            self.burst
+        )
+    }
+
+    /// Takes a token for a change, reading is not limited.
+    pub fn check(&self, req: &ServiceRequest) -> Option<Decision> {
+        if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+            return None;
+        }
+        Some(self.take(client(req), Instant::now()))
+    }
+
+    fn take(&self, client: String, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_CLIENTS {
+            let (per_second, burst) = (self.per_second, self.burst);
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second
+                    < burst
+            });
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * self.per_second)
+            .min(self.burst);
+        bucket.updated = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        Decision {
+            allowed,
+            limit: self.burst as u64,
+            remaining: bucket.tokens.floor() as u64,
+            reset: ((self.burst - bucket.tokens) / self.per_second).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / self.per_second).ceil() as u64,
+        }
+    }
+}
+
+impl Decision {
+    pub fn add_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(
+            header::HeaderName::from_static("ratelimit-limit"),
+            HeaderValue::from(self.limit),
+        );
+        headers.insert(
+            header::HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(self.remaining),
+        );
+        headers.insert(
+            header::HeaderName::from_static("ratelimit-reset"),
+            HeaderValue::from(self.reset),
+        );
+        if !self.allowed {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(self.retry_after));
+        }
+    }
+}
+
+/// The API key of the request, by its digest so the keys are not kept, or
+/// else the address it comes from.
+fn client(req: &ServiceRequest) -> String {
+    let headers = req.headers();
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get("X-Api-Key")
+                .and_then(|value| value.to_str().ok())
+        });
+    match (key, req.peer_addr()) {
+        (Some(key), _) => format!("key {}", hex::encode(Sha256::digest(key.trim().as_bytes()))),
+        (None, Some(address)) => format!("address {}", address.ip()),
+        (None, None) => "unknown".to_owned(),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::auth::ApiKeys;
+use crate::config::Config;
+use crate::limits::SizeLimit;
+use crate::scripts::Scripts;
+use crate::signatures::DeviceSecrets;
+#[cfg(feature = "tls")]
+use crate::tls::Tls;
+use crate::trash::Trash;
+use crate::users::Users;
+use std::panic::{catch_unwind, UnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// A setting that is read again on reload, handlers take the current one
+/// for every request.
+pub struct Reloadable<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Reloadable<T> {
+        Reloadable {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn set(&self, value: T) {
+        *self.current.write().unwrap() = Arc::new(value);
+    }
+}
+
+/// Reads the configuration file and the settings that can change without
+/// a restart again, on `SIGHUP` or `POST /admin/reload`: the API keys,
+/// users, device secrets, the default size limit of series, the ingest
+/// scripts, the trash retention and the TLS certificate. The series in
+/// memory are kept.
+pub struct Reloader {
+    pub config: Option<Config>,
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<Tls>>,
+    pub trash: Arc<Trash>,
+    pub api_keys: Arc<Reloadable<ApiKeys>>,
+    pub users: Arc<Reloadable<Users>>,
+    pub device_secrets: Arc<Reloadable<DeviceSecrets>>,
+    pub default_limit: Arc<Reloadable<Option<SizeLimit>>>,
+    pub scripts: Arc<Reloadable<Scripts>>,
+    pub data_path: PathBuf,
+}
+
+impl Reloader {
+    /// Either every setting is replaced or, when one of them is wrong,
+    /// none is.
+    pub fn reload(&self) -> Result<(), String> {
+        if let Some(config) = &self.config {
+            config.reload()?;
+        }
+        let api_keys = read(ApiKeys::from_env)?;
+        let users = read(Users::from_env)?;
+        let device_secrets = read(DeviceSecrets::from_env)?;
+        let default_limit = read(SizeLimit::from_env)?;
+        let scripts = read(|| Scripts::from_env(&self.data_path))?;
+        self.trash.reload()?;
+        self.api_keys.set(api_keys);
+        self.users.set(users);
+        self.device_secrets.set(device_secrets);
+        self.default_limit.set(default_limit);
+        self.scripts.set(scripts);
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            crate::watcher::reload_tls(tls);
+        }
+        info!("Reloaded the configuration.");
+        Ok(())
+    }
+}
+
+/// Settings are read like at startup, where a wrong one stops the server.
+fn read<T>(from_env: impl FnOnce() -> T + UnwindSafe) -> Result<T, String> {
+    catch_unwind(from_env).map_err(|panic| {
+        panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| {
+                panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+            })
+            .unwrap_or_else(|| "a setting is wrong".to_owned())
+    })
+}
+
+/// `kill -HUP` reloads the configuration.
+#[cfg(unix)]
+pub fn reload_on_hangup(reloader: Arc<Reloader>) {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            warn!("Unable to handle SIGHUP, {}", err);
+            return;
+        }
+    };
+    actix_rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(err) = reloader.reload() {
+                warn!("Keeping the current configuration, {}", err);
+            }
+        }
+    });
+}
@@ -0,0 +1,170 @@
+use crate::namespaces::{self, Namespace};
+use crate::{env_or_default, secrets, series_names, Datum};
+use actix::prelude::*;
+use actix_web::client::Client;
+use actix_web::http::{header, StatusCode};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_BACKLOG: &str = "100000";
+
+/// Forwards every accepted datum to a standby instance. Data that cannot be
+/// delivered is kept in a bounded backlog which is replayed, in order, once
+/// the peer is reachable again.
+pub struct Replicator {
+    peer_url: String,
+    /// Sent to a standby that requires an API key.
+    api_key: Option<String>,
+    client: Client,
+    backlog: VecDeque<Replicate>,
+    max_backlog: usize,
+    in_flight: bool,
+    connected: bool,
+}
+
+#[derive(Clone)]
+pub struct Replicate {
+    pub series_name: String,
+    pub datum: Datum,
+}
+
+impl Message for Replicate {
+    type Result = ();
+}
+
+impl Replicator {
+    /// Replication is enabled by setting `STS_RS_REPLICA_URL`.
+    pub fn from_env() -> Option<Replicator> {
+        let peer_url = std::env::var("STS_RS_REPLICA_URL").ok()?;
+        let max_backlog = env_or_default("STS_RS_REPLICA_BACKLOG", DEFAULT_MAX_BACKLOG)
+            .parse::<usize>()
+            .expect("STS_RS_REPLICA_BACKLOG should be a number of values");
+        let insecure = env_or_default("STS_RS_REPLICA_INSECURE", "false").to_lowercase() == "true";
+        let client = crate::client(insecure);
+        Some(Replicator {
+            peer_url: peer_url.trim_end_matches('/').to_owned(),
+            api_key: secrets::secret("STS_RS_REPLICA_API_KEY"),
+            client,
+            backlog: VecDeque::new(),
+            max_backlog,
+            in_flight: false,
+            connected: true,
+        })
+    }
+
+    pub fn peer_url(&self) -> &str {
+        &self.peer_url
+    }
+
+    fn send_next(&mut self, ctx: &mut Context<Self>) {
+        if self.in_flight {
+            return;
+        }
+        let item = match self.backlog.front() {
+            Some(item) => item.clone(),
+            None => return,
+        };
+        self.in_flight = true;
+        let mut request = self.client.post(format!(
+            "{}{}/{}",
+            self.peer_url,
+            Namespace::of_series(&item.series_name).base(),
+            series_names::file_stem(namespaces::split(&item.series_name).1)
+        ));
+        if let Some(api_key) = &self.api_key {
+            request = request.header("X-Api-Key", api_key.as_str());
+        }
+        let request = request.send_json(&item.datum);
+        ctx.spawn(
+            actix::fut::wrap_future::<_, Self>(request).map(move |result, act, ctx| {
+                act.in_flight = false;
+                match result {
+                    // A replica that is busy or timed out is asked again.
+                    Ok(response)
+                        if response.status().is_server_error()
+                            || response.status() == StatusCode::REQUEST_TIMEOUT
+                            || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+                    {
+                        let delay = retry_after(response.headers()).unwrap_or(RETRY_INTERVAL);
+                        act.retry_later(ctx, format!("status code {}", response.status()), delay)
+                    }
+                    Ok(response) => {
+                        if !response.status().is_success() {
+                            warn!(
+                                "Replica rejected value for series {} with status code {}, dropping it.",
+                                item.series_name,
+                                response.status()
+                            );
+                        }
+                        if !act.connected {
+                            info!(
+                                "Replica {} is reachable again, catching up {} values.",
+                                act.peer_url,
+                                act.backlog.len()
+                            );
+                            act.connected = true;
+                        }
+                        act.backlog.pop_front();
+                        act.send_next(ctx);
+                    }
+                    Err(err) => act.retry_later(ctx, err.to_string(), RETRY_INTERVAL),
+                }
+            }),
+        );
+    }
+
+    fn retry_later(&mut self, ctx: &mut Context<Self>, reason: String, delay: Duration) {
+        if self.connected {
+            warn!(
+                "Replicating to {} failed, {}; retrying in {:?}.",
+                self.peer_url, reason, delay
+            );
+            self.connected = false;
+        }
+        ctx.run_later(delay, |act, ctx| act.send_next(ctx));
+    }
+}
+
+/// How long the replica asks to wait, in seconds or until a date.
+fn retry_after(headers: &header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = SystemTime::from(value.parse::<header::HttpDate>().ok()?);
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+impl Actor for Replicator {
+    type Context = Context<Self>;
+}
+
+impl Handler<Replicate> for Replicator {
+    type Result = ();
+    fn handle(&mut self, msg: Replicate, ctx: &mut Context<Self>) -> Self::Result {
+        if self.backlog.len() >= self.max_backlog {
+            // The front is being delivered while a request is in flight.
+            match self.backlog.remove(usize::from(self.in_flight)) {
+                Some(dropped) => warn!(
+                    "Replication backlog is full, dropping the oldest value of series {}; the replica needs a resync.",
+                    dropped.series_name
+                ),
+                None => {
+                    warn!(
+                        "Replication backlog is full, dropping a value of series {}; the replica needs a resync.",
+                        msg.series_name
+                    );
+                    return;
+                }
+            }
+        }
+        self.backlog.push_back(msg);
+        // While disconnected the retry timer drives delivery.
+        if self.connected {
+            self.send_next(ctx);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::namespaces::{self, Namespaces};
+use crate::plot::{self, Plotter, Window};
+use crate::series_names;
+use std::path::Path;
+
+/// Runs `sts-rs replot`, rendering the pre-rendered plots of `names`, or
+/// of every series, from their data files again, like after changing the
+/// plot settings or restoring a backup. `window` replaces the one of
+/// `STS_RS_PLOT_WINDOW` for these plots only.
+pub fn run(
+    data_path: &Path,
+    image_path: &Path,
+    names: &[&str],
+    window: Option<Window>,
+) -> Result<(), String> {
+    let series = crate::read_series(data_path, &Namespaces::from_env());
+    if let Some(missing) = names.iter().find(|name| !series.contains_key(**name)) {
+        return Err(format!("There is no series {}", missing));
+    }
+    let mut plotter = Plotter::from_env();
+    if let Some(window) = window {
+        plotter.window = window;
+    }
+    plotter.check()?;
+    let mut selected = series
+        .iter()
+        .filter(|(name, _)| names.is_empty() || names.contains(&name.as_str()))
+        .filter(|(_, serie)| !serie.meta.private)
+        .collect::<Vec<_>>();
+    selected.sort_by_key(|(name, _)| *name);
+    let mut failures = 0;
+    for (name, serie) in selected {
+        let plotted = plotter.plot(
+            plot::Line {
+                series_name: namespaces::split(name).1,
+                data: &serie.recent(plotter.window),
+                settings: &serie.meta.plot,
+                thresholds: &serie.meta.thresholds,
+                annotations: &serie.annotations,
+                secondary: false,
+                unit: serie.meta.unit.as_deref(),
+            },
+            &series_names::data_file(data_path, name),
+            &series_names::image_file(image_path, name),
+        );
+        match plotted {
+            Ok(()) => println!("{}: plotted {} values", name, serie.data.len()),
+            Err(err) => {
+                println!("{}: {}", name, err);
+                failures += 1;
+            }
+        }
+    }
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!("Plotting {} series failed", failures)),
+    }
+}
@@ -0,0 +1,516 @@
+//! Reports of a selection of series over a period, with a plot and the
+//! statistics of each, rendered on a schedule into a single HTML document
+//! and kept in `<data>/.reports/` to be served under `/reports/`.
+
+use crate::auth::series_matches;
+use crate::dashboards::validate_slug;
+use crate::error::Unpoisoned;
+use crate::metadata::SeriesMeta;
+use crate::plot::{Snapshot, Window};
+use crate::{env_or_default, show_value_with_unit, stats, Series, SeriesMap};
+use actix::prelude::*;
+use actix_web::web;
+use askama::Template;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Reports show this many series at most.
+const MAX_REPORT_SERIES: usize = 100;
+/// Runs are looked for this many days ahead, enough for February 29.
+const MAX_SCHEDULE_DAYS: i64 = 8 * 366;
+
+/// When a report runs, the five fields of a crontab line: minute, hour, day
+/// of the month, month and day of the week. Fields are `*`, numbers, ranges
+/// like `1-5` and lists of those, optionally stepped like `*/15`. Days of
+/// the week are 0 or 7 for Sunday to 6, or `mon` to `sun`. When both days
+/// are restricted either one may match, like in cron.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub fn parse(fields: &[&str]) -> Result<Schedule, String> {
+        if fields.len() != 5 {
+            return Err(format!(
+                "{:?} should be five fields, minute, hour, day, month and day of the week",
+                fields.join(" ")
+            ));
+        }
+        let weekdays = field(fields[4], 0, 7, "day of the week")?
+            .into_iter()
+            .map(|weekday| weekday % 7)
+            .collect();
+        Ok(Schedule {
+            minutes: field(fields[0], 0, 59, "minute")?,
+            hours: field(fields[1], 0, 23, "hour")?,
+            days: field(fields[2], 1, 31, "day")?,
+            months: field(fields[3], 1, 12, "month")?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days.contains(&date.day());
+        let weekday = self
+            .weekdays
+            .contains(&date.weekday().num_days_from_sunday());
+        self.months.contains(&date.month())
+            && match (self.any_day, self.any_weekday) {
+                (false, false) => day || weekday,
+                _ => day && weekday,
+            }
+    }
+
+    /// The first run after `now` in `timezone`, none for a schedule that
+    /// never runs, like on February 30.
+    pub fn next_after(&self, now: DateTime<Utc>, timezone: Tz) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&timezone).naive_local().date();
+        (0..MAX_SCHEDULE_DAYS)
+            .map(|days| today + Duration::days(days))
+            .filter(|date| self.matches_day(*date))
+            .flat_map(|date| {
+                self.hours.iter().flat_map(move |hour| {
+                    self.minutes
+                        .iter()
+                        .map(move |minute| date.and_hms(*hour, *minute, 0))
+                })
+            })
+            // Times skipped by daylight saving time do not run.
+            .filter_map(|time| timezone.from_local_datetime(&time).earliest())
+            .map(|time| time.with_timezone(&Utc))
+            .find(|time| *time > now)
+    }
+}
+
+fn field(text: &str, min: u32, max: u32, name: &str) -> Result<Vec<u32>, String> {
+    let error = || format!("{:?} is not a valid {}", text, name);
+    let number = |text: &str| {
+        weekday_number(text)
+            .filter(|_| name == "day of the week")
+            .or_else(|| text.parse::<u32>().ok())
+            .filter(|number| (min..=max).contains(number))
+            .ok_or_else(error)
+    };
+    let mut values = Vec::new();
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(error)?,
+            ),
+            None => (item, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if from > to {
+            return Err(error());
+        }
+        values.extend((from..=to).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn weekday_number(text: &str) -> Option<u32> {
+    ["sun", "mon", "tue", "wed", "thu", "fri", "sat"]
+        .iter()
+        .position(|day| day.eq_ignore_ascii_case(text))
+        .map(|day| day as u32)
+}
+
+/// A report configured in `STS_RS_REPORTS_FILE`, a line like
+/// `capacity 0 6 * * mon 7d disk-* memory-*`: the name, the schedule, the
+/// period before the run it covers and the series it shows, a trailing `*`
+/// matches every series starting with what comes before it.
+#[derive(Clone, Debug)]
+pub struct ReportSpec {
+    pub name: String,
+    schedule: Schedule,
+    period: Window,
+    series: Vec<String>,
+}
+
+impl ReportSpec {
+    fn parse(line: &str) -> Result<ReportSpec, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if words.len() < 8 {
+            return Err(format!(
+                "report {:?} should be a name, five schedule fields, a period and series",
+                line
+            ));
+        }
+        let name = words[0].to_owned();
+        validate_slug(&name).map_err(|err| format!("report {}", err))?;
+        let schedule = Schedule::parse(&words[1..6])
+            .map_err(|err| format!("the schedule of report {}, {}", name, err))?;
+        let period = words[6]
+            .parse::<Window>()
+            .ok()
+            .filter(|period| period.start(Some(0)).is_some())
+            .ok_or_else(|| {
+                format!(
+                    "the period of report {} should be hours or days like 24h or 7d, not {}",
+                    name, words[6]
+                )
+            })?;
+        Ok(ReportSpec {
+            name,
+            schedule,
+            period,
+            series: words[7..]
+                .iter()
+                .map(|pattern| (*pattern).to_owned())
+                .collect(),
+        })
+    }
+}
+
+/// The reports of `STS_RS_REPORTS_FILE`, one per line, lines starting with
+/// `#` are ignored.
+pub fn specs_from_env() -> Vec<ReportSpec> {
+    let file = match std::env::var("STS_RS_REPORTS_FILE") {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    let contents = std::fs::read_to_string(&file)
+        .unwrap_or_else(|err| panic!("Could not read {:?}, {}", file, err));
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| ReportSpec::parse(line).unwrap_or_else(|err| panic!("{}", err)))
+        .collect()
+}
+
+/// The rendered reports, stored as `<data>/.reports/<name>-<time>.html`
+/// with the time of the run like `2024-03-04-0600`.
+pub struct Reports {
+    directory: PathBuf,
+}
+
+impl Reports {
+    pub fn new(data_path: &Path) -> Reports {
+        Reports {
+            directory: data_path.join(".reports"),
+        }
+    }
+
+    /// The file names of the reports, the latest first.
+    pub fn list(&self) -> Vec<String> {
+        let mut files = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|file| file.ends_with(".html"))
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        files.sort_by(|lhs, rhs| time_of(rhs).cmp(time_of(lhs)).then(lhs.cmp(rhs)));
+        files
+    }
+
+    /// A report by its file name, none for names that are not of a report.
+    pub fn get(&self, file: &str) -> Option<Vec<u8>> {
+        if validate_slug(file.strip_suffix(".html")?).is_err() {
+            return None;
+        }
+        std::fs::read(self.directory.join(file)).ok()
+    }
+
+    fn save(&self, name: &str, time: &str, html: &str) -> io::Result<String> {
+        std::fs::create_dir_all(&self.directory)?;
+        let file = format!("{}-{}.html", name, time);
+        std::fs::write(self.directory.join(&file), html)?;
+        Ok(file)
+    }
+
+    /// Removes all but the `keep` latest reports of `name`.
+    fn prune(&self, name: &str, keep: usize) -> io::Result<()> {
+        let prefix = format!("{}-", name);
+        let of_report = self.list().into_iter().filter(|file| {
+            file.strip_prefix(&prefix)
+                .is_some_and(|rest| rest.len() == "2024-03-04-0600.html".len())
+        });
+        for file in of_report.skip(keep) {
+            std::fs::remove_file(self.directory.join(file))?;
+        }
+        Ok(())
+    }
+}
+
+/// The time in a file name sorts like the time itself.
+fn time_of(file: &str) -> &str {
+    let stem = file.strip_suffix(".html").unwrap_or(file);
+    &stem[stem.len().saturating_sub("2024-03-04-0600".len())..]
+}
+
+#[derive(Template)]
+#[template(path = "report.html")]
+struct ReportPage {
+    title: String,
+    from: String,
+    to: String,
+    rows: Vec<Row>,
+}
+
+/// A series in a report, its plot is an SVG document.
+struct Row {
+    name: String,
+    latest: String,
+    min: String,
+    max: String,
+    mean: String,
+    median: String,
+    change: String,
+    count: usize,
+    plot: Option<String>,
+}
+
+/// A series as it was at the run, taken so the report renders without
+/// holding its lock.
+struct Taken {
+    name: String,
+    summary: Option<(stats::Summary, f64, f64)>,
+    meta: SeriesMeta,
+    snapshot: Option<Snapshot>,
+}
+
+/// Renders the reports at their scheduled times on a blocking thread and
+/// keeps the `STS_RS_REPORTS_KEEP` latest of each.
+pub struct Reporter {
+    specs: Vec<ReportSpec>,
+    reports: Arc<Reports>,
+    series: SeriesMap,
+    keep: usize,
+    timezone: Tz,
+}
+
+impl Reporter {
+    pub fn new(
+        specs: Vec<ReportSpec>,
+        data_path: &Path,
+        series: SeriesMap,
+        timezone: Tz,
+    ) -> Reporter {
+        let keep = env_or_default("STS_RS_REPORTS_KEEP", "52")
+            .parse::<usize>()
+            .ok()
+            .filter(|keep| *keep > 0)
+            .expect("STS_RS_REPORTS_KEEP should be a positive number");
+        Reporter {
+            specs,
+            reports: Arc::new(Reports::new(data_path)),
+            series,
+            keep,
+            timezone,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let names = self
+            .specs
+            .iter()
+            .map(|spec| spec.name.as_str())
+            .collect::<Vec<_>>();
+        format!("Rendering reports {}", names.join(", "))
+    }
+
+    fn schedule(&self, index: usize, ctx: &mut Context<Self>) {
+        let spec = &self.specs[index];
+        let now = Utc::now();
+        let next = match spec.schedule.next_after(now, self.timezone) {
+            Some(next) => next,
+            None => return warn!("Report {} is never due, it is not rendered.", spec.name),
+        };
+        let delay = (next - now).to_std().unwrap_or_default();
+        ctx.run_later(delay, move |act, ctx| {
+            act.run(index, next, ctx);
+            act.schedule(index, ctx);
+        });
+    }
+
+    fn run(&self, index: usize, end: DateTime<Utc>, ctx: &mut Context<Self>) {
+        let spec = self.specs[index].clone();
+        let end = end.timestamp();
+        let start = spec.period.start(Some(end)).unwrap_or(end);
+        let taken = self.take(&spec, (start, end));
+        let (reports, keep, timezone) = (self.reports.clone(), self.keep, self.timezone);
+        let name = spec.name.clone();
+        let rendered = web::block(move || {
+            let time = |time_stamp| {
+                timezone
+                    .timestamp_opt(time_stamp, 0)
+                    .single()
+                    .map_or(time_stamp.to_string(), |time| {
+                        time.format("%Y-%m-%d %H:%M %Z").to_string()
+                    })
+            };
+            let page = ReportPage {
+                title: format!("Report {}", spec.name),
+                from: time(start),
+                to: time(end),
+                rows: taken
+                    .into_iter()
+                    .map(|taken| row(taken, timezone))
+                    .collect(),
+            };
+            let html = page.render().map_err(|err| err.to_string())?;
+            let stamp = timezone
+                .timestamp_opt(end, 0)
+                .single()
+                .map_or(end.to_string(), |time| {
+                    time.format("%Y-%m-%d-%H%M").to_string()
+                });
+            let file = reports
+                .save(&spec.name, &stamp, &html)
+                .map_err(|err| err.to_string())?;
+            reports
+                .prune(&spec.name, keep)
+                .map_err(|err| err.to_string())?;
+            Ok::<_, String>(file)
+        });
+        ctx.spawn(
+            actix::fut::wrap_future(rendered).map(move |rendered, _: &mut Reporter, _| {
+                match rendered {
+                    Ok(file) => info!("Rendered report {} to {}.", name, file),
+                    Err(err) => warn!("Rendering report {} failed, {}", name, err),
+                }
+            }),
+        );
+    }
+
+    /// The series of the report in `window`, by name. Private series are
+    /// left out, as every reader may read the reports.
+    fn take(&self, spec: &ReportSpec, window: (i64, i64)) -> Vec<Taken> {
+        let series = self.series.read().unpoisoned();
+        let mut names = series
+            .keys()
+            .filter(|name| {
+                spec.series
+                    .iter()
+                    .any(|pattern| series_matches(pattern, name))
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let serie = Series::lock(&series[name]);
+                if serie.meta.private {
+                    return None;
+                }
+                let data = serie.data.between(window.0, window.1);
+                let summary = stats::summarize(&data).map(|summary| {
+                    let (first, last) = (data.first().unwrap(), data.last().unwrap());
+                    (summary, last.value, last.value - first.value)
+                });
+                Some(Taken {
+                    name: name.clone(),
+                    summary,
+                    meta: serie.meta.clone(),
+                    snapshot: Snapshot::of(name, &serie, window),
+                })
+            })
+            .take(MAX_REPORT_SERIES)
+            .collect()
+    }
+}
+
+fn row(taken: Taken, timezone: Tz) -> Row {
+    let Taken {
+        name,
+        summary,
+        meta,
+        snapshot,
+    } = taken;
+    let show = |value: f64| show_value_with_unit(value, &meta);
+    let plot = snapshot.and_then(|snapshot| {
+        snapshot
+            .render_svg(timezone)
+            .map_err(|err| {
+                warn!(
+                    "Plotting series {} for a report failed, {}",
+                    snapshot.name, err
+                )
+            })
+            .ok()
+    });
+    match summary {
+        Some((summary, latest, change)) => Row {
+            name,
+            latest: show(latest),
+            min: show(summary.min),
+            max: show(summary.max),
+            mean: show(summary.mean),
+            median: show(summary.median),
+            change: format!("{}{}", if change > 0.0 { "+" } else { "" }, show(change)),
+            count: summary.count,
+            plot,
+        },
+        None => Row {
+            name,
+            latest: String::new(),
+            min: String::new(),
+            max: String::new(),
+            mean: String::new(),
+            median: String::new(),
+            change: String::new(),
+            count: 0,
+            plot,
+        },
+    }
+}
+
+impl Actor for Reporter {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for index in 0..self.specs.len() {
+            self.schedule(index, ctx);
+        }
+    }
+}
+
+/// Renders a report right away, for `POST /admin/reports/{name}`. Whether
+/// there is a report of that name.
+pub struct RunNow {
+    pub name: String,
+}
+
+impl Message for RunNow {
+    type Result = bool;
+}
+
+impl Handler<RunNow> for Reporter {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RunNow, ctx: &mut Context<Self>) -> bool {
+        match self.specs.iter().position(|spec| spec.name == msg.name) {
+            Some(index) => {
+                self.run(index, Utc::now().with_nanosecond(0).unwrap(), ctx);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Rendered pages and computed results, kept under a key of the request
+/// and of the state of the series they were made of. A write changes the
+/// key, so the stale result is no longer served and is evicted like any
+/// other beyond `STS_RS_RESPONSE_CACHE_ENTRIES`.
+pub struct ResponseCache<T> {
+    max_entries: usize,
+    entries: Mutex<HashMap<u64, Cached<T>>>,
+}
+
+struct Cached<T> {
+    value: T,
+    used: Instant,
+}
+
+impl<T: Clone> ResponseCache<T> {
+    /// `0` entries caches nothing.
+    pub fn from_env() -> ResponseCache<T> {
+        let max_entries = env_or_default("STS_RS_RESPONSE_CACHE_ENTRIES", "256")
+            .parse::<usize>()
+            .expect("STS_RS_RESPONSE_CACHE_ENTRIES should be a number of entries");
+        ResponseCache {
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<T> {
+        let mut entries = self.entries.lock().unpoisoned();
+        let cached = entries.get_mut(&key)?;
+        cached.used = Instant::now();
+        Some(cached.value.clone())
+    }
+
+    /// Stores a result, evicting the least recently used one when full.
+    pub fn insert(&self, key: u64, value: T) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unpoisoned();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let least_recent = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(key, _)| *key);
+            if let Some(least_recent) = least_recent {
+                entries.remove(&least_recent);
+            }
+        }
+        entries.insert(
+            key,
+            Cached {
+                value,
+                used: Instant::now(),
+            },
+        );
+    }
+}
+
+pub fn key(parts: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
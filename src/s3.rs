@@ -0,0 +1,200 @@
+use actix_web::client::Client;
+use actix_web::http::StatusCode;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const MAX_OBJECT_SIZE: usize = 256 * 1024 * 1024;
+
+/// Minimal client for S3-compatible object storage (AWS S3, MinIO, Ceph, ...)
+/// using path-style addressing and AWS signature version 4.
+pub struct ObjectStore {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+#[derive(Debug)]
+pub enum ObjectStoreError {
+    Request(String),
+    Status(StatusCode),
+}
+
+impl fmt::Display for ObjectStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectStoreError::Request(message) => write!(f, "request failed: {}", message),
+            ObjectStoreError::Status(status) => write!(f, "unexpected status code: {}", status),
+        }
+    }
+}
+
+impl ObjectStore {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> ObjectStore {
+        let endpoint = endpoint.trim_end_matches('/').to_owned();
+        let host = endpoint
+            .splitn(2, "://")
+            .last()
+            .unwrap_or_default()
+            .to_owned();
+        ObjectStore {
+            endpoint,
+            host,
+            bucket: bucket.to_owned(),
+            region: region.to_owned(),
+            access_key: access_key.to_owned(),
+            secret_key: secret_key.to_owned(),
+        }
+    }
+
+    pub async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<(), ObjectStoreError> {
+        let path = self.object_path(key);
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let now = Utc::now();
+        let response = Client::default()
+            .put(format!("{}{}", self.endpoint, path))
+            .header("x-amz-date", amz_date(&now))
+            .header("x-amz-content-sha256", payload_hash.as_str())
+            .header(
+                "Authorization",
+                self.authorization("PUT", &path, &payload_hash, &now),
+            )
+            .send_body(body)
+            .await
+            .map_err(|err| ObjectStoreError::Request(err.to_string()))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ObjectStoreError::Status(response.status()))
+        }
+    }
+
+    /// Fetches the object stored under `key`, `Ok(None)` means it does not exist.
+    pub async fn get_object(&self, key: &str) -> Result<Option<Bytes>, ObjectStoreError> {
+        let path = self.object_path(key);
+        let payload_hash = hex::encode(Sha256::digest(b""));
+        let now = Utc::now();
+        let mut response = Client::default()
+            .get(format!("{}{}", self.endpoint, path))
+            .header("x-amz-date", amz_date(&now))
+            .header("x-amz-content-sha256", payload_hash.as_str())
+            .header(
+                "Authorization",
+                self.authorization("GET", &path, &payload_hash, &now),
+            )
+            .send()
+            .await
+            .map_err(|err| ObjectStoreError::Request(err.to_string()))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => response
+                .body()
+                .limit(MAX_OBJECT_SIZE)
+                .await
+                .map(Some)
+                .map_err(|err| ObjectStoreError::Request(err.to_string())),
+            status => Err(ObjectStoreError::Status(status)),
+        }
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), ObjectStoreError> {
+        let path = self.object_path(key);
+        let payload_hash = hex::encode(Sha256::digest(b""));
+        let now = Utc::now();
+        let response = Client::default()
+            .delete(format!("{}{}", self.endpoint, path))
+            .header("x-amz-date", amz_date(&now))
+            .header("x-amz-content-sha256", payload_hash.as_str())
+            .header(
+                "Authorization",
+                self.authorization("DELETE", &path, &payload_hash, &now),
+            )
+            .send()
+            .await
+            .map_err(|err| ObjectStoreError::Request(err.to_string()))?;
+        if response.status().is_success() || response.status() == StatusCode::NOT_FOUND {
+            Ok(())
+        } else {
+            Err(ObjectStoreError::Status(response.status()))
+        }
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("/{}/{}", uri_encode(&self.bucket), uri_encode(key))
+    }
+
+    fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        payload_hash: &str,
+        now: &DateTime<Utc>,
+    ) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            path,
+            self.host,
+            payload_hash,
+            amz_date(now),
+            signed_headers,
+            payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date(now),
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac_sha256(
+                format!("AWS4{}", self.secret_key).as_bytes(),
+                date.as_bytes(),
+            ),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()))
+        )
+    }
+}
+
+fn amz_date(now: &DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+//! Scripts in the Rhai language that run on every value as it comes in,
+//! before it is added. A series runs its own script, `<series>.rhai` next
+//! to its data file, or else the script of `STS_RS_INGEST_SCRIPT`. The
+//! script sees the value in the variables `series`, `value` and `time` and
+//! may change them to rename or scale it, set `keep` to `false` to drop it
+//! and add values of other series, at the same time, to the map `derived`.
+
+use crate::namespaces::Namespace;
+#[cfg(feature = "scripts")]
+use crate::series_names;
+use crate::Datum;
+#[cfg(feature = "scripts")]
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(feature = "scripts")]
+use std::path::PathBuf;
+
+/// Scripts may take this many operations, so a loop that never ends does
+/// not hold up values.
+#[cfg(feature = "scripts")]
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// What became of a value: the value itself, unless the script dropped it,
+/// and the values of the series derived from it. Names are within the
+/// namespace of the value.
+pub struct Outcome {
+    pub datum: Option<(String, Datum)>,
+    pub derived: Vec<(String, Datum)>,
+}
+
+/// The scripts, read at startup and on reload.
+#[derive(Default)]
+pub struct Scripts {
+    #[cfg(feature = "scripts")]
+    engine: rhai::Engine,
+    #[cfg(feature = "scripts")]
+    global: Option<rhai::AST>,
+    /// The scripts of series by the name the series is kept under.
+    #[cfg(feature = "scripts")]
+    series: HashMap<String, rhai::AST>,
+}
+
+impl Scripts {
+    #[cfg(feature = "scripts")]
+    pub fn from_env(data_path: &Path) -> Scripts {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let compile = |file: &Path| {
+            let source = std::fs::read_to_string(file)
+                .unwrap_or_else(|err| panic!("Could not read {:?}, {}", file, err));
+            engine
+                .compile(source)
+                .unwrap_or_else(|err| panic!("The script {:?} is not valid, {}", file, err))
+        };
+        let global = std::env::var_os("STS_RS_INGEST_SCRIPT").map(|file| compile(Path::new(&file)));
+        let series = script_files(data_path)
+            .into_iter()
+            .filter_map(|file| Some((series_names::from_file(data_path, &file)?, compile(&file))))
+            .collect();
+        Scripts {
+            engine,
+            global,
+            series,
+        }
+    }
+
+    #[cfg(not(feature = "scripts"))]
+    pub fn from_env(_data_path: &Path) -> Scripts {
+        if std::env::var_os("STS_RS_INGEST_SCRIPT").is_some() {
+            panic!("STS_RS_INGEST_SCRIPT needs a build with the scripts feature");
+        }
+        Scripts::default()
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        #[cfg(feature = "scripts")]
+        match (&self.global, self.series.len()) {
+            (None, 0) => (),
+            (None, count) => return Some(format!("Running the scripts of {} series", count)),
+            (Some(_), count) => {
+                return Some(format!(
+                    "Running STS_RS_INGEST_SCRIPT and the scripts of {} series",
+                    count
+                ))
+            }
+        }
+        None
+    }
+
+    /// Runs the script of the series `local` of `namespace` on a value, the
+    /// value is kept as it is without one.
+    #[cfg(feature = "scripts")]
+    pub fn run(&self, namespace: &Namespace, local: &str, datum: Datum) -> Result<Outcome, String> {
+        let script = match self
+            .series
+            .get(&namespace.qualify(local))
+            .or(self.global.as_ref())
+        {
+            Some(script) => script,
+            None => return Ok(Outcome::unchanged(local, datum)),
+        };
+        let mut scope = rhai::Scope::new();
+        scope
+            .push("series", local.to_owned())
+            .push("value", datum.value)
+            .push("time", datum.timeStamp)
+            .push("keep", true)
+            .push("derived", rhai::Map::new());
+        let failed = |err: String| format!("the script of series {} {}", local, err);
+        self.engine
+            .run_ast_with_scope(&mut scope, script)
+            .map_err(|err| failed(format!("failed, {}", err)))?;
+        let variable = |name: &str| {
+            scope
+                .get_value::<rhai::Dynamic>(name)
+                .ok_or_else(|| failed(format!("removed {}", name)))
+        };
+        // Numbers without a fraction are integers in Rhai.
+        let number = |name: &str, value: rhai::Dynamic| {
+            value
+                .as_float()
+                .or_else(|_| value.as_int().map(|value| value as f64))
+                .map_err(|_| failed(format!("set {} to a {}", name, value.type_name())))
+        };
+        let time = variable("time")?
+            .as_int()
+            .map_err(|_| failed("set time to something else than an integer".to_owned()))?;
+        let keep = variable("keep")?
+            .as_bool()
+            .map_err(|_| failed("set keep to something else than true or false".to_owned()))?;
+        let datum = if keep {
+            let series = variable("series")?
+                .into_string()
+                .map_err(|_| failed("set series to something else than text".to_owned()))?;
+            let value = number("value", variable("value")?)?;
+            Some((
+                series,
+                Datum {
+                    timeStamp: time,
+                    value,
+                },
+            ))
+        } else {
+            None
+        };
+        let derived = variable("derived")?
+            .try_cast::<rhai::Map>()
+            .ok_or_else(|| failed("set derived to something else than a map".to_owned()))?
+            .into_iter()
+            .map(|(series, value)| {
+                let value = number(&format!("derived[{:?}]", series.as_str()), value)?;
+                Ok((
+                    series.to_string(),
+                    Datum {
+                        timeStamp: time,
+                        value,
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Outcome { datum, derived })
+    }
+
+    #[cfg(not(feature = "scripts"))]
+    pub fn run(
+        &self,
+        _namespace: &Namespace,
+        local: &str,
+        datum: Datum,
+    ) -> Result<Outcome, String> {
+        Ok(Outcome::unchanged(local, datum))
+    }
+}
+
+impl Outcome {
+    fn unchanged(local: &str, datum: Datum) -> Outcome {
+        Outcome {
+            datum: Some((local.to_owned(), datum)),
+            derived: Vec::new(),
+        }
+    }
+}
+
+/// The `.rhai` files of the data directory and of its subdirectories, those
+/// of the namespaces.
+#[cfg(feature = "scripts")]
+fn script_files(data_path: &Path) -> Vec<PathBuf> {
+    let scripts_in = |directory: &Path| -> Vec<PathBuf> {
+        match std::fs::read_dir(directory) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|file| {
+                    file.extension()
+                        .is_some_and(|extension| extension == "rhai")
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    };
+    let mut files = scripts_in(data_path);
+    if let Ok(entries) = std::fs::read_dir(data_path) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let hidden = name.to_str().is_none_or(|name| name.starts_with('.'));
+            if !hidden && entry.path().is_dir() {
+                files.extend(scripts_in(&entry.path()));
+            }
+        }
+    }
+    files
+}
@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+/// A secret setting, from the variable itself, from the file named by the
+/// variable with `_FILE` appended, or from the systemd credential of the
+/// same name, so it needs not be in the environment of the process.
+pub fn secret(name: &str) -> Option<String> {
+    if let Ok(value) = std::env::var(name) {
+        return Some(value);
+    }
+    let file = std::env::var(format!("{}_FILE", name))
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| credential(name))?;
+    let contents = std::fs::read_to_string(&file)
+        .unwrap_or_else(|err| panic!("Could not read {} from {:?}, {}", name, file, err));
+    Some(contents.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// The file systemd put the credential in, configured with
+/// `LoadCredential=<name>:<path>` in the unit.
+pub fn credential(name: &str) -> Option<PathBuf> {
+    let file = PathBuf::from(std::env::var_os("CREDENTIALS_DIRECTORY")?).join(name);
+    if file.is_file() {
+        Some(file)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,98 @@
+//! Series names come from URLs and end up in file names. The files of a
+//! series are named after it with every character other than ASCII
+//! letters, digits, `-`, `_` and an inner `.` written as `%` followed by
+//! the hex of its UTF-8 bytes, so `température` is stored as
+//! `temp%C3%A9rature.csv` and no name can point outside the data directory.
+//! The files of a series in a namespace are in a subdirectory named after
+//! the namespace.
+
+use crate::namespaces;
+use std::path::{Path, PathBuf};
+
+/// Names are checked before a series is created or renamed.
+pub fn validate(name: &str) -> Result<(), String> {
+    if name.trim().is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\'])
+        || name.chars().any(char::is_control)
+    {
+        return Err(format!(
+            "{:?} is not a valid series name, it should not start with a dot or contain slashes",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Names Windows keeps for devices, whatever the extension.
+const RESERVED_ON_WINDOWS: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// The name of the files of a series, without their extension. Names
+/// Windows keeps for devices, like `con`, have their first letter encoded
+/// so data directories can be copied between systems.
+pub fn file_stem(name: &str) -> String {
+    if let (Some(namespace), name) = namespaces::split(name) {
+        return format!("{}/{}", namespace, file_stem(name));
+    }
+    let device = name.split('.').next().unwrap_or_default().to_lowercase();
+    let reserved = RESERVED_ON_WINDOWS.contains(&device.as_str());
+    let mut stem = String::with_capacity(name.len());
+    for (index, byte) in name.bytes().enumerate() {
+        match byte {
+            _ if index == 0 && reserved => stem.push_str(&format!("%{:02X}", byte)),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => stem.push(byte as char),
+            b'.' if index > 0 => stem.push('.'),
+            _ => stem.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    stem
+}
+
+/// The name of the series stored in files with this stem. Stems from before
+/// names were encoded are taken as they are, apart from what looks encoded.
+pub fn from_file_stem(stem: &str) -> String {
+    let bytes = stem.as_bytes();
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = match bytes.get(index + 1..index + 3) {
+            Some(hex) if bytes[index] == b'%' => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                name.push(byte);
+                index += 3;
+            }
+            None => {
+                name.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(name).unwrap_or_else(|_| stem.to_owned())
+}
+
+/// The series stored in a file of the data directory or of the
+/// subdirectory of a namespace in it.
+pub fn from_file(directory: &Path, file: &Path) -> Option<String> {
+    let name = from_file_stem(file.file_stem()?.to_str()?);
+    match file.parent()? {
+        parent if parent == directory => Some(name),
+        parent => Some(format!("{}/{}", parent.file_name()?.to_str()?, name)),
+    }
+}
+
+pub fn data_file(directory: &Path, name: &str) -> PathBuf {
+    directory.join(format!("{}.csv", file_stem(name)))
+}
+
+/// The pre-rendered plot of a series in the image directory.
+pub fn image_file(directory: &Path, name: &str) -> PathBuf {
+    directory.join(format!("{}.svg", file_stem(name)))
+}
@@ -0,0 +1,61 @@
+use actix_web::dev::Server;
+#[cfg(any(unix, windows))]
+use futures::future::{self, Either};
+
+/// Stops the server gracefully on SIGINT and SIGTERM, it stops accepting
+/// connections and waits for the requests being handled. The values those
+/// requests queued are written by `main` once the server has stopped.
+pub fn stop_on_signal(server: Server) {
+    actix_rt::spawn(async move {
+        let signal = signal().await;
+        info!("Received {}, stopping", signal);
+        crate::systemd::notify("STOPPING=1");
+        server.stop(true).await;
+    });
+}
+
+#[cfg(unix)]
+async fn signal() -> &'static str {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    let mut terminations = match signal(SignalKind::terminate()) {
+        Ok(terminations) => terminations,
+        Err(err) => {
+            warn!("Unable to handle SIGTERM, {}", err);
+            let _ = actix_rt::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    let interrupt = Box::pin(actix_rt::signal::ctrl_c());
+    let termination = Box::pin(terminations.recv());
+    let signal = match future::select(interrupt, termination).await {
+        Either::Left(_) => "SIGINT",
+        Either::Right(_) => "SIGTERM",
+    };
+    signal
+}
+
+/// Windows has no signals, consoles send Ctrl-C or Ctrl-Break instead.
+#[cfg(windows)]
+async fn signal() -> &'static str {
+    let mut breaks = match tokio::signal::windows::ctrl_break() {
+        Ok(breaks) => breaks,
+        Err(err) => {
+            warn!("Unable to handle Ctrl-Break, {}", err);
+            let _ = actix_rt::signal::ctrl_c().await;
+            return "Ctrl-C";
+        }
+    };
+    let interrupt = Box::pin(actix_rt::signal::ctrl_c());
+    let ctrl_break = Box::pin(breaks.recv());
+    let signal = match future::select(interrupt, ctrl_break).await {
+        Either::Left(_) => "Ctrl-C",
+        Either::Right(_) => "Ctrl-Break",
+    };
+    signal
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn signal() -> &'static str {
+    let _ = actix_rt::signal::ctrl_c().await;
+    "Ctrl-C"
+}
@@ -0,0 +1,115 @@
+use crate::auth::series_matches;
+use crate::secrets;
+use actix_web::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::PathBuf;
+
+/// The secrets devices sign the values they add with, configured with
+/// `STS_RS_DEVICE_SECRETS_FILE`, lines of `<device> <secret>` followed by
+/// the series it adds values to. Values for those series are only accepted
+/// with `X-Sts-Device` naming the device and `X-Sts-Signature:
+/// sha256=<hex>`, the HMAC-SHA256 of the body with the secret.
+pub struct DeviceSecrets {
+    devices: Vec<Device>,
+}
+
+struct Device {
+    name: String,
+    secret: Vec<u8>,
+    /// Patterns like those of the API keys, every series when empty.
+    series: Vec<String>,
+}
+
+impl Device {
+    fn covers(&self, series_name: &str) -> bool {
+        self.series.is_empty()
+            || self
+                .series
+                .iter()
+                .any(|pattern| series_matches(pattern, series_name))
+    }
+}
+
+impl DeviceSecrets {
+    pub fn from_env() -> DeviceSecrets {
+        let secrets_file = std::env::var("STS_RS_DEVICE_SECRETS_FILE")
+            .ok()
+            .map(PathBuf::from)
+            .or_else(|| secrets::credential("STS_RS_DEVICE_SECRETS_FILE"));
+        let secrets_file = match secrets_file {
+            Some(secrets_file) => secrets_file,
+            None => return DeviceSecrets { devices: vec![] },
+        };
+        let contents = std::fs::read_to_string(&secrets_file)
+            .unwrap_or_else(|err| panic!("Could not read {:?}, {}", secrets_file, err));
+        let devices = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some(name), Some(secret)) => Device {
+                        name: name.to_owned(),
+                        secret: secret.as_bytes().to_vec(),
+                        series: words.map(str::to_owned).collect(),
+                    },
+                    _ => panic!(
+                        "{:?} should have lines of <device> <secret> [series...]",
+                        secrets_file
+                    ),
+                }
+            })
+            .collect();
+        DeviceSecrets { devices }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Values for series no device covers need no signature, unless the
+    /// request names a device anyway.
+    pub fn verify(
+        &self,
+        series_name: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), String> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let device_name = match header("X-Sts-Device") {
+            Some(device_name) => device_name,
+            None if self.devices.iter().any(|device| device.covers(series_name)) => {
+                return Err(format!(
+                    "Series {} only accepts values signed by a device.",
+                    series_name
+                ))
+            }
+            None => return Ok(()),
+        };
+        let device = self
+            .devices
+            .iter()
+            .find(|device| device.name == device_name && device.covers(series_name))
+            .ok_or_else(|| {
+                format!(
+                    "Device {} does not add values to series {}.",
+                    device_name, series_name
+                )
+            })?;
+        let signature = header("X-Sts-Signature")
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .and_then(|signature| hex::decode(signature.trim()).ok())
+            .ok_or("X-Sts-Signature should be sha256=<hex>.")?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&device.secret).expect("HMAC accepts keys of any size");
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| format!("The signature of device {} does not match.", device_name))
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{env_or_default, secrets};
+use chrono::Utc;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Security {
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// Plain text upgraded with `STARTTLS`, usually on port 587.
+    StartTls,
+    None,
+}
+
+/// The mail server mails are sent through, configured with
+/// `STS_RS_SMTP_HOST` and the other `STS_RS_SMTP_` variables.
+#[derive(Clone)]
+pub struct Smtp {
+    host: String,
+    port: u16,
+    security: Security,
+    /// Accept a certificate that does not verify.
+    #[cfg(feature = "tls")]
+    insecure: bool,
+    credentials: Option<(String, String)>,
+    pub from: String,
+}
+
+/// A mail of plain text with files attached.
+pub struct Mail {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+}
+
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+trait Stream: Read + Write + fmt::Debug {}
+
+impl<T: Read + Write + fmt::Debug> Stream for T {}
+
+impl Smtp {
+    pub fn from_env() -> Option<Smtp> {
+        let host = std::env::var("STS_RS_SMTP_HOST").ok()?;
+        let security = match env_or_default("STS_RS_SMTP_SECURITY", "starttls").as_str() {
+            "tls" => Security::Tls,
+            "starttls" => Security::StartTls,
+            "none" => Security::None,
+            other => panic!(
+                "STS_RS_SMTP_SECURITY should be tls, starttls or none, not {}",
+                other
+            ),
+        };
+        #[cfg(not(feature = "tls"))]
+        if security != Security::None {
+            panic!("STS_RS_SMTP_SECURITY needs a build with the tls feature, or none");
+        }
+        let default_port = match security {
+            Security::Tls => "465",
+            Security::StartTls => "587",
+            Security::None => "25",
+        };
+        let port = env_or_default("STS_RS_SMTP_PORT", default_port)
+            .parse::<u16>()
+            .expect("STS_RS_SMTP_PORT should be a port number");
+        let credentials = std::env::var("STS_RS_SMTP_USER").ok().map(|user| {
+            let password = secrets::secret("STS_RS_SMTP_PASSWORD")
+                .expect("STS_RS_SMTP_USER needs STS_RS_SMTP_PASSWORD");
+            (user, password)
+        });
+        let from =
+            std::env::var("STS_RS_SMTP_FROM").unwrap_or_else(|_| format!("sts-rs@{}", host_name()));
+        if let Err(err) = validate_address(&from) {
+            panic!("STS_RS_SMTP_FROM is not usable, {}", err);
+        }
+        Some(Smtp {
+            host,
+            port,
+            security,
+            #[cfg(feature = "tls")]
+            insecure: env_or_default("STS_RS_SMTP_INSECURE", "false").to_lowercase() == "true",
+            credentials,
+            from,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Sends `mail` and waits for the server to accept it, so this should
+    /// run on a blocking thread.
+    pub fn send(&self, mail: &Mail) -> Result<(), String> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|err| format!("connecting to {} failed, {}", self.describe(), err))?;
+        tcp.set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| tcp.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|err| err.to_string())?;
+        let stream: Box<dyn Stream> = match self.security {
+            Security::Tls => self.tls(Box::new(tcp))?,
+            _ => Box::new(tcp),
+        };
+        let mut session = Session {
+            stream: BufReader::new(stream),
+        };
+        session.expect(220)?;
+        session.command(&format!("EHLO {}", host_name()), 250)?;
+        if self.security == Security::StartTls {
+            session.command("STARTTLS", 220)?;
+            let tcp = session.stream.into_inner();
+            session = Session {
+                stream: BufReader::new(self.tls(tcp)?),
+            };
+            session.command(&format!("EHLO {}", host_name()), 250)?;
+        }
+        if let Some((user, password)) = &self.credentials {
+            let plain = base64::encode(format!("\0{}\0{}", user, password));
+            session.command(&format!("AUTH PLAIN {}", plain), 235)?;
+        }
+        session.command(&format!("MAIL FROM:<{}>", self.from), 250)?;
+        for to in &mail.to {
+            session.command(&format!("RCPT TO:<{}>", to), 250)?;
+        }
+        session.command("DATA", 354)?;
+        let mut message = String::new();
+        for line in self.message(mail).split("\r\n") {
+            // A line of a single dot would end the message.
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message.push('.');
+        session.command(&message, 250)?;
+        session.command("QUIT", 221)
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls(&self, stream: Box<dyn Stream>) -> Result<Box<dyn Stream>, String> {
+        use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+        let mut connector =
+            SslConnector::builder(SslMethod::tls()).map_err(|err| err.to_string())?;
+        if self.insecure {
+            connector.set_verify(SslVerifyMode::NONE);
+        }
+        let stream = connector
+            .build()
+            .connect(&self.host, stream)
+            .map_err(|err| format!("TLS with {} failed, {}", self.describe(), err))?;
+        Ok(Box::new(stream))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn tls(&self, _: Box<dyn Stream>) -> Result<Box<dyn Stream>, String> {
+        Err("TLS needs a build with the tls feature".to_owned())
+    }
+
+    /// The mail as MIME, with the text and the attachments in base64 so
+    /// no line is too long.
+    fn message(&self, mail: &Mail) -> String {
+        let boundary = format!("sts-rs-{}", hex::encode(random_bytes()));
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\nMIME-Version: 1.0\r\n",
+            self.from,
+            mail.to.join(", "),
+            encode_header(&mail.subject),
+            Utc::now().to_rfc2822(),
+            hex::encode(random_bytes()),
+            host_name()
+        );
+        let text = format!(
+            "Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+            wrapped_base64(mail.text.replace('\n', "\r\n").as_bytes())
+        );
+        if mail.attachments.is_empty() {
+            message.push_str(&text);
+            return message;
+        }
+        message.push_str(&format!(
+            "Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n--{}\r\n{}",
+            boundary, boundary, text
+        ));
+        for attachment in &mail.attachments {
+            message.push_str(&format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: base64\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n{}",
+                boundary,
+                attachment.content_type,
+                attachment.file_name.replace(['"', '\\', '\r', '\n'], "_"),
+                wrapped_base64(&attachment.data)
+            ));
+        }
+        message.push_str(&format!("--{}--", boundary));
+        message
+    }
+}
+
+struct Session {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Session {
+    fn command(&mut self, command: &str, expected: u16) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(command.as_bytes())
+            .and_then(|()| stream.write_all(b"\r\n"))
+            .and_then(|()| stream.flush())
+            .map_err(|err| err.to_string())?;
+        self.expect(expected).map_err(|err| {
+            // The credentials are not logged.
+            let verb = match command.contains('\n') {
+                true => "the message",
+                false => command.split_whitespace().next().unwrap_or_default(),
+            };
+            format!("{} was answered with {}", verb, err)
+        })
+    }
+
+    /// Reads a reply, the lines of a reply of several lines have a `-`
+    /// after the code but the last.
+    fn expect(&mut self, expected: u16) -> Result<(), String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            match self.stream.read_line(&mut line) {
+                Ok(0) => return Err("the connection was closed".to_owned()),
+                Ok(_) => {}
+                Err(err) => return Err(err.to_string()),
+            }
+            reply.push_str(line.trim_end());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+            reply.push(' ');
+        }
+        match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+            // A recipient the server forwards is accepted as well.
+            Some(code) if code == expected || (expected == 250 && code == 251) => Ok(()),
+            _ => Err(reply),
+        }
+    }
+}
+
+/// Addresses are written into the commands as they are.
+pub fn validate_address(address: &str) -> Result<(), String> {
+    let valid = address.contains('@')
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not an email address", address))
+    }
+}
+
+/// Headers are ASCII, others are written as UTF-8 in base64.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() && !value.contains(['\r', '\n']) {
+        value.to_owned()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::encode(value))
+    }
+}
+
+fn wrapped_base64(data: &[u8]) -> String {
+    let encoded = base64::encode(data);
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / 38 + 2);
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.push_str(std::str::from_utf8(line).unwrap());
+        wrapped.push_str("\r\n");
+    }
+    wrapped
+}
+
+fn random_bytes() -> [u8; 12] {
+    let mut bytes = [0; 12];
+    getrandom::getrandom(&mut bytes).expect("no random numbers to name a mail with");
+    bytes
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_owned())
+}
@@ -0,0 +1,42 @@
+use crate::columns::Columns;
+use serde::Serialize;
+
+/// Summary statistics of the values of a series.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// The population standard deviation.
+    pub std_dev: f64,
+}
+
+pub fn summarize(data: &Columns) -> Option<Summary> {
+    if data.is_empty() {
+        return None;
+    }
+    let mut values = data.values().to_vec();
+    values.sort_by(f64::total_cmp);
+    let count = values.len();
+    let mean = values.iter().sum::<f64>() / count as f64;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean) * (value - mean))
+        .sum::<f64>()
+        / count as f64;
+    let median = if count.is_multiple_of(2) {
+        (values[count / 2 - 1] + values[count / 2]) / 2.0
+    } else {
+        values[count / 2]
+    };
+    Some(Summary {
+        count,
+        min: values[0],
+        max: values[count - 1],
+        mean,
+        median,
+        std_dev: variance.sqrt(),
+    })
+}
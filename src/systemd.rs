@@ -0,0 +1,113 @@
+use crate::{BackgroundActor, Ping};
+use actix::Addr;
+use std::net::TcpListener;
+use std::time::Duration;
+
+/// A socket passed by systemd, named with `FileDescriptorName=` in the
+/// socket unit. Sockets named `http` serve plain HTTP, the others HTTPS
+/// unless `STS_RS_HTTPS_ADDRESS` is empty or the build has no TLS.
+pub struct Listener {
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub name: String,
+    pub listener: TcpListener,
+}
+
+/// The sockets systemd passed for socket activation, the configured
+/// addresses are not bound when there are any.
+#[cfg(unix)]
+pub fn listeners() -> Vec<Listener> {
+    use std::os::unix::io::FromRawFd;
+    const FIRST_FD: i32 = 3;
+    let for_this_process = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .filter(|_| for_this_process)
+        .unwrap_or(0);
+    let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+    // Children like gnuplot should not think the sockets are theirs.
+    for variable in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(variable);
+    }
+    (FIRST_FD..FIRST_FD + count)
+        .map(|fd| Listener {
+            name: names.next().unwrap_or_default().to_owned(),
+            // systemd passes the descriptors to this process only.
+            listener: unsafe { TcpListener::from_raw_fd(fd) },
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listeners() -> Vec<Listener> {
+    Vec::new()
+}
+
+/// Tells systemd about the state of the service when it runs as a
+/// `Type=notify` unit, does nothing otherwise.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Err(err) = send(state) {
+        warn!("Could not notify systemd of {}, {}", state, err);
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn send(state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+    let socket = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => socket,
+        None => return Ok(()),
+    };
+    let datagram = UnixDatagram::unbound()?;
+    match socket.to_str().and_then(|socket| socket.strip_prefix('@')) {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            datagram.send_to(state.as_bytes(), &socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// How often systemd should hear the service is alive, half of
+/// `WatchdogSec=` in the unit.
+fn watchdog_interval() -> Option<Duration> {
+    let for_this_process = std::env::var("WATCHDOG_PID")
+        .ok()
+        .is_none_or(|pid| pid.parse::<u32>().ok() == Some(std::process::id()));
+    std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0 && for_this_process)
+        .map(|usec| Duration::from_micros(usec / 2))
+}
+
+/// Pings the watchdog as long as the background actor keeps handling
+/// messages, so systemd restarts the service when writes hang.
+pub fn start_watchdog(background_actor: Addr<BackgroundActor>) {
+    let interval = match watchdog_interval() {
+        Some(interval) => interval,
+        None => return,
+    };
+    info!("Notifying the systemd watchdog every {:?}", interval);
+    actix_rt::spawn(async move {
+        let mut ticks = actix_rt::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            if background_actor.send(Ping).await.is_ok() {
+                notify("WATCHDOG=1");
+            }
+        }
+    });
+}
@@ -0,0 +1,128 @@
+use crate::client_certs::ClientCerts;
+use openssl::pkey::PKey;
+use openssl::ssl::{
+    AlpnError, SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslFiletype, SslMethod,
+};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The certificate of the HTTPS listener. Every handshake switches to the
+/// latest context, so a new certificate is used without restarting.
+pub struct Tls {
+    key_file: PathBuf,
+    cert_file: PathBuf,
+    /// The passphrase of an encrypted key.
+    passphrase: Option<String>,
+    client_certs: Option<ClientCerts>,
+    current: Mutex<SslContext>,
+}
+
+impl Tls {
+    pub fn new(
+        key_file: PathBuf,
+        cert_file: PathBuf,
+        passphrase: Option<String>,
+        client_certs: Option<ClientCerts>,
+    ) -> Tls {
+        let current = context(
+            &key_file,
+            &cert_file,
+            passphrase.as_deref(),
+            client_certs.as_ref(),
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
+        .build()
+        .into_context();
+        Tls {
+            key_file,
+            cert_file,
+            passphrase,
+            client_certs,
+            current: Mutex::new(current),
+        }
+    }
+
+    pub fn client_certs(&self) -> Option<&ClientCerts> {
+        self.client_certs.as_ref()
+    }
+
+    pub fn key_file(&self) -> &Path {
+        &self.key_file
+    }
+
+    pub fn cert_file(&self) -> &Path {
+        &self.cert_file
+    }
+
+    fn context(&self) -> Result<SslAcceptorBuilder, String> {
+        context(
+            &self.key_file,
+            &self.cert_file,
+            self.passphrase.as_deref(),
+            self.client_certs.as_ref(),
+        )
+    }
+
+    pub fn acceptor(self: &Arc<Self>) -> SslAcceptorBuilder {
+        let mut builder = self.context().unwrap_or_else(|err| panic!("{}", err));
+        let tls = self.clone();
+        builder.set_servername_callback(move |ssl, _| {
+            ssl.set_ssl_context(&tls.current.lock().unwrap())
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+        builder
+    }
+
+    /// Reads the certificate and key again, new connections use them.
+    pub fn reload(&self) -> Result<(), String> {
+        let context = self.context()?.build().into_context();
+        *self.current.lock().unwrap() = context;
+        Ok(())
+    }
+}
+
+/// The settings of the listener with the certificate as it is on disk.
+fn context(
+    key_file: &Path,
+    cert_file: &Path,
+    passphrase: Option<&str>,
+    client_certs: Option<&ClientCerts>,
+) -> Result<SslAcceptorBuilder, String> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    match passphrase {
+        Some(passphrase) => {
+            let key = std::fs::read(key_file)
+                .map_err(|err| err.to_string())
+                .and_then(|pem| {
+                    PKey::private_key_from_pem_passphrase(&pem, passphrase.as_bytes())
+                        .map_err(|err| err.to_string())
+                })
+                .map_err(|err| format!("Could not read the key {:?}, {}", key_file, err))?;
+            builder.set_private_key(&key)
+        }
+        None => builder.set_private_key_file(key_file, SslFiletype::PEM),
+    }
+    .map_err(|err| format!("Could not read the key {:?}, {}", key_file, err))?;
+    builder
+        .set_certificate_chain_file(cert_file)
+        .map_err(|err| format!("Could not read the certificate {:?}, {}", cert_file, err))?;
+    builder
+        .check_private_key()
+        .map_err(|err| format!("The key does not match the certificate, {}", err))?;
+    if let Some(client_certs) = client_certs {
+        client_certs.configure(&mut builder)?;
+    }
+    builder.set_alpn_select_callback(|_, protos| {
+        if protos.windows(3).any(|window| window == b"\x02h2") {
+            Ok(b"h2")
+        } else if protos.windows(9).any(|window| window == b"\x08http/1.1") {
+            Ok(b"http/1.1")
+        } else {
+            Err(AlpnError::NOACK)
+        }
+    });
+    builder
+        .set_alpn_protos(b"\x08http/1.1\x02h2")
+        .map_err(|err| err.to_string())?;
+    Ok(builder)
+}
@@ -0,0 +1,178 @@
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use crate::series_names;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Extensions of the files that together make up a series.
+const SERIES_FILE_EXTENSIONS: [&str; 8] = [
+    "csv",
+    "csv.gz",
+    "meta.json",
+    "events.json",
+    "counter.json",
+    "gnuplot",
+    "cold.json",
+    "quarantine",
+];
+
+/// Deleted series are moved to `<data>/.trash/<deleted at>-<series>/` and
+/// kept there for the retention period, so they can be restored.
+pub struct Trash {
+    directory: PathBuf,
+    retention: Mutex<Duration>,
+}
+
+#[derive(Serialize)]
+pub struct TrashEntry {
+    pub id: String,
+    pub series_name: String,
+    pub deleted_at: String,
+    pub expires_at: String,
+    #[serde(skip)]
+    deleted: DateTime<Utc>,
+}
+
+impl Trash {
+    pub fn from_env(data_path: &Path) -> Trash {
+        Trash {
+            directory: data_path.join(".trash"),
+            retention: Mutex::new(retention().unwrap_or_else(|err| panic!("{}", err))),
+        }
+    }
+
+    /// Reads the retention period again, entries that are already in the
+    /// trash are kept for the new period.
+    pub fn reload(&self) -> Result<(), String> {
+        *self.retention.lock().unpoisoned() = retention()?;
+        Ok(())
+    }
+
+    pub fn move_to_trash(&self, data_path: &Path, series_name: &str) -> io::Result<TrashEntry> {
+        let now = Utc::now();
+        // The stem of a series in a namespace has a slash, which the id of
+        // the entry keeps encoded.
+        let id = format!(
+            "{}-{}",
+            now.timestamp(),
+            series_names::file_stem(series_name).replace('/', "%2F")
+        );
+        let entry_directory = self.directory.join(&id);
+        std::fs::create_dir_all(&entry_directory)?;
+        for file in series_files(data_path, series_name) {
+            std::fs::rename(&file, entry_directory.join(file.file_name().unwrap()))?;
+        }
+        Ok(self.entry(&id).unwrap())
+    }
+
+    /// Moves the files of a trashed series back, returning its name. Fails if
+    /// a series with that name was created in the meantime.
+    pub fn restore(&self, id: &str, data_path: &Path) -> io::Result<String> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such trash entry"))?;
+        let data_file = series_names::data_file(data_path, &entry.series_name);
+        if data_file.exists() || crate::archive::archived_file(&data_file).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("series {} already exists", entry.series_name),
+            ));
+        }
+        let entry_directory = self.directory.join(id);
+        let directory = data_file.parent().unwrap_or(data_path);
+        for file in std::fs::read_dir(&entry_directory)?.flatten() {
+            std::fs::rename(file.path(), directory.join(file.file_name()))?;
+        }
+        std::fs::remove_dir(&entry_directory)?;
+        Ok(entry.series_name)
+    }
+
+    pub fn list(&self) -> Vec<TrashEntry> {
+        let mut entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter_map(|id| self.entry(&id))
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        entries.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+        entries
+    }
+
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<TrashEntry> {
+        let retention = *self.retention.lock().unpoisoned();
+        self.list()
+            .into_iter()
+            .filter(|entry| entry.deleted + retention < now)
+            .collect()
+    }
+
+    /// Directory holding the files of a trash entry.
+    pub fn entry_directory(&self, id: &str) -> PathBuf {
+        self.directory.join(id)
+    }
+
+    pub fn purge(&self, id: &str) -> io::Result<()> {
+        std::fs::remove_dir_all(self.directory.join(id))
+    }
+
+    fn entry(&self, id: &str) -> Option<TrashEntry> {
+        let (deleted_at, series_name) = id.split_once('-')?;
+        let deleted = Utc.timestamp_opt(deleted_at.parse().ok()?, 0).single()?;
+        if !self.directory.join(id).is_dir() {
+            return None;
+        }
+        Some(TrashEntry {
+            id: id.to_owned(),
+            series_name: series_names::from_file_stem(series_name),
+            deleted_at: deleted.format("%+").to_string(),
+            expires_at: (deleted + *self.retention.lock().unpoisoned())
+                .format("%+")
+                .to_string(),
+            deleted,
+        })
+    }
+}
+
+/// Moves all files of a series to a new name, the caller makes sure no
+/// series goes by that name yet.
+pub fn rename_series_files(data_path: &Path, series_name: &str, new_name: &str) -> io::Result<()> {
+    rename_files(
+        data_path,
+        &series_names::file_stem(series_name),
+        &series_names::file_stem(new_name),
+    )
+}
+
+/// Moves the files of a series from one file stem to another.
+pub fn rename_files(data_path: &Path, stem: &str, new_stem: &str) -> io::Result<()> {
+    for extension in SERIES_FILE_EXTENSIONS.iter() {
+        let file = data_path.join(format!("{}.{}", stem, extension));
+        if file.exists() {
+            std::fs::rename(&file, data_path.join(format!("{}.{}", new_stem, extension)))?;
+        }
+    }
+    Ok(())
+}
+
+/// The files of a series that exist.
+pub fn series_files(data_path: &Path, series_name: &str) -> Vec<PathBuf> {
+    let stem = series_names::file_stem(series_name);
+    SERIES_FILE_EXTENSIONS
+        .iter()
+        .map(|extension| data_path.join(format!("{}.{}", stem, extension)))
+        .filter(|file| file.exists())
+        .collect()
+}
+
+/// The retention period, configured with `STS_RS_TRASH_RETENTION_DAYS`.
+fn retention() -> Result<Duration, String> {
+    env_or_default("STS_RS_TRASH_RETENTION_DAYS", "30")
+        .parse::<i64>()
+        .map(Duration::days)
+        .map_err(|_| "STS_RS_TRASH_RETENTION_DAYS should be a whole number of days".to_owned())
+}
@@ -0,0 +1,81 @@
+/// Units values can be converted between, as their quantity, the factor
+/// and the offset that give the value in the first unit of that quantity.
+const UNITS: &[(&str, &str, f64, f64)] = &[
+    ("K", "temperature", 1.0, 0.0),
+    ("°C", "temperature", 1.0, 273.15),
+    ("C", "temperature", 1.0, 273.15),
+    ("°F", "temperature", 5.0 / 9.0, 459.67 * 5.0 / 9.0),
+    ("F", "temperature", 5.0 / 9.0, 459.67 * 5.0 / 9.0),
+    ("m", "length", 1.0, 0.0),
+    ("km", "length", 1_000.0, 0.0),
+    ("cm", "length", 0.01, 0.0),
+    ("mm", "length", 0.001, 0.0),
+    ("mi", "length", 1_609.344, 0.0),
+    ("ft", "length", 0.3048, 0.0),
+    ("in", "length", 0.0254, 0.0),
+    ("kg", "mass", 1.0, 0.0),
+    ("g", "mass", 0.001, 0.0),
+    ("lb", "mass", 0.453_592_37, 0.0),
+    ("oz", "mass", 0.028_349_523_125, 0.0),
+    ("Pa", "pressure", 1.0, 0.0),
+    ("hPa", "pressure", 100.0, 0.0),
+    ("kPa", "pressure", 1_000.0, 0.0),
+    ("bar", "pressure", 100_000.0, 0.0),
+    ("mbar", "pressure", 100.0, 0.0),
+    ("psi", "pressure", 6_894.757_293, 0.0),
+    ("inHg", "pressure", 3_386.389, 0.0),
+    ("m/s", "speed", 1.0, 0.0),
+    ("km/h", "speed", 1.0 / 3.6, 0.0),
+    ("mph", "speed", 0.447_04, 0.0),
+    ("kn", "speed", 1_852.0 / 3_600.0, 0.0),
+    ("J", "energy", 1.0, 0.0),
+    ("Wh", "energy", 3_600.0, 0.0),
+    ("kWh", "energy", 3_600_000.0, 0.0),
+];
+
+/// Converts values from the unit of a series to another unit of the same
+/// quantity, for `?unit=`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+    factor: f64,
+    offset: f64,
+}
+
+impl Conversion {
+    pub fn new(from: Option<&str>, to: &str) -> Result<Conversion, String> {
+        let from = from.ok_or("the series has no unit to convert from")?;
+        if from == to {
+            return Ok(Conversion {
+                factor: 1.0,
+                offset: 0.0,
+            });
+        }
+        let unit = |name: &str| {
+            UNITS
+                .iter()
+                .find(|unit| unit.0 == name)
+                .ok_or_else(|| format!("unit {} is not one that can be converted", name))
+        };
+        let ((_, from_quantity, from_factor, from_offset), (_, to_quantity, to_factor, to_offset)) =
+            (unit(from)?, unit(to)?);
+        if from_quantity != to_quantity {
+            return Err(format!(
+                "{} is a {} and {} a {}",
+                from, from_quantity, to, to_quantity
+            ));
+        }
+        Ok(Conversion {
+            factor: from_factor / to_factor,
+            offset: (from_offset - to_offset) / to_factor,
+        })
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.factor + self.offset
+    }
+
+    /// Converts a difference between values, like a standard deviation.
+    pub fn apply_to_difference(&self, difference: f64) -> f64 {
+        difference * self.factor
+    }
+}
@@ -0,0 +1,181 @@
+use crate::auth::Role;
+use crate::env_or_default;
+use crate::error::Unpoisoned;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header;
+use hmac::Hmac;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+
+const ITERATIONS: usize = 100_000;
+const HASH_LENGTH: usize = 32;
+
+/// The people allowed to see the pages and read the series, configured
+/// with `STS_RS_USERS_FILE`, lines of `<user>:<password hash>` as printed by
+/// `sts-rs hash-password`, optionally followed by the role of the user.
+/// Users are readers by default. Without users everything is open to all.
+pub struct Users {
+    passwords: HashMap<String, (PasswordHash, Role)>,
+    /// Checking a password takes a while on purpose, so the `Authorization`
+    /// headers that passed are remembered by their digest, with their user.
+    accepted: Mutex<HashMap<Vec<u8>, String>>,
+}
+
+struct PasswordHash {
+    iterations: usize,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    /// Reads `pbkdf2-sha256:<iterations>:<salt>:<hash>` with the salt and hash
+    /// in hexadecimal.
+    fn parse(value: &str) -> Option<PasswordHash> {
+        let mut parts = value.split(':');
+        if parts.next()? != "pbkdf2-sha256" {
+            return None;
+        }
+        let password_hash = PasswordHash {
+            iterations: parts.next()?.parse().ok()?,
+            salt: hex::decode(parts.next()?).ok()?,
+            hash: hex::decode(parts.next()?).ok()?,
+        };
+        match parts.next() {
+            Some(_) => None,
+            None => Some(password_hash),
+        }
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        let hash = derive(password, &self.salt, self.iterations, self.hash.len());
+        hash.len() == self.hash.len() && bool::from(hash.ct_eq(&self.hash))
+    }
+}
+
+fn derive(password: &str, salt: &[u8], iterations: usize, length: usize) -> Vec<u8> {
+    let mut hash = vec![0; length];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations as u32, &mut hash);
+    hash
+}
+
+/// A line for the users file, without the user.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0; 16];
+    getrandom::getrandom(&mut salt).unwrap();
+    format!(
+        "pbkdf2-sha256:{}:{}:{}",
+        ITERATIONS,
+        hex::encode(salt),
+        hex::encode(derive(password, &salt, ITERATIONS, HASH_LENGTH))
+    )
+}
+
+/// `sts-rs hash-password` reads a password from standard input and prints
+/// its hash.
+pub fn run_hash_password() -> Result<(), String> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .map_err(|err| format!("could not read the password, {}", err))?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("usage: echo <password> | sts-rs hash-password".to_owned());
+    }
+    println!("{}", hash_password(password));
+    Ok(())
+}
+
+impl Users {
+    pub fn from_env() -> Users {
+        let mut passwords = HashMap::new();
+        let users_file = env_or_default("STS_RS_USERS_FILE", "");
+        if !users_file.is_empty() {
+            let contents = std::fs::read_to_string(&users_file)
+                .unwrap_or_else(|err| panic!("Could not read {}, {}", users_file, err));
+            for line in contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            {
+                let (user, password_hash, role) = line
+                    .split_once(':')
+                    .and_then(|(user, rest)| {
+                        let mut words = rest.split_whitespace();
+                        let password_hash = PasswordHash::parse(words.next()?)?;
+                        let role = match words.next() {
+                            Some(role) => Role::parse(role)?,
+                            None => Role::Reader,
+                        };
+                        Some((user, password_hash, role))
+                    })
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "{} should have lines of <user>:<hash from sts-rs hash-password> [reader|writer|admin]",
+                            users_file
+                        )
+                    });
+                passwords.insert(user.to_owned(), (password_hash, role));
+            }
+        }
+        Users {
+            passwords,
+            accepted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passwords.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.passwords.len()
+    }
+
+    /// The role of a user, `None` for users no longer in the file.
+    pub fn role(&self, user: &str) -> Option<Role> {
+        self.passwords.get(user).map(|(_, role)| *role)
+    }
+
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        match self.passwords.get(user) {
+            Some((password_hash, _)) => password_hash.matches(password),
+            None => false,
+        }
+    }
+
+    /// The user whose password the request carries with HTTP Basic
+    /// authentication.
+    pub fn basic_auth(&self, req: &ServiceRequest) -> Option<String> {
+        let credentials = match req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+        {
+            Some(credentials) => credentials.trim(),
+            None => return None,
+        };
+        let digest = Sha256::digest(credentials.as_bytes()).to_vec();
+        if let Some(user) = self.accepted.lock().unpoisoned().get(&digest) {
+            return Some(user.clone());
+        }
+        let user = base64::decode(credentials)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|decoded| {
+                let (user, password) = decoded.split_once(':')?;
+                if self.verify(user, password) {
+                    Some(user.to_owned())
+                } else {
+                    None
+                }
+            })?;
+        self.accepted
+            .lock()
+            .unpoisoned()
+            .insert(digest, user.clone());
+        Some(user)
+    }
+}
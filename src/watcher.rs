@@ -0,0 +1,125 @@
+#[cfg(feature = "tls")]
+use crate::tls::Tls;
+use crate::{BackgroundActor, ReloadSeries};
+use actix::Addr;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+#[cfg(feature = "tls")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEBOUNCE_DELAY: Duration = Duration::from_secs(2);
+
+/// Watches the data directory and the directories of `namespaces` in it on
+/// a separate thread, and asks the background actor to reload every data
+/// file that changes, is created or is removed.
+pub fn watch(data_path: PathBuf, namespaces: Vec<String>, background_actor: Addr<BackgroundActor>) {
+    std::thread::spawn(move || {
+        let (tx, rx) = channel();
+        let mut watcher = match watcher(tx, DEBOUNCE_DELAY) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                warn!("Unable to watch the data directory, {}", err);
+                return;
+            }
+        };
+        let directories = std::iter::once(data_path.clone())
+            .chain(namespaces.iter().map(|namespace| data_path.join(namespace)));
+        for directory in directories {
+            if let Err(err) = watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                warn!("Unable to watch {:?}, {}", directory, err);
+                return;
+            }
+        }
+        info!("Watching {:?} for external changes", data_path);
+        // Events name files by their absolute path, the actor knows them
+        // under the data directory as it was given.
+        let absolute = data_path
+            .canonicalize()
+            .unwrap_or_else(|_| data_path.clone());
+        for event in rx {
+            let file_name = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                DebouncedEvent::Error(err, path) => {
+                    warn!("Error watching {:?}, {}", path, err);
+                    continue;
+                }
+                _ => continue,
+            };
+            if file_name.extension() == Some("csv".as_ref()) {
+                let file_name = match file_name.strip_prefix(&absolute) {
+                    Ok(relative) => data_path.join(relative),
+                    Err(_) => file_name,
+                };
+                background_actor.do_send(ReloadSeries { file_name });
+            }
+        }
+    });
+}
+
+/// Watches the directories of the TLS key and certificate, and has the
+/// listener use them again when they change, like after a renewal.
+#[cfg(feature = "tls")]
+pub fn watch_tls(tls: Arc<Tls>) {
+    std::thread::spawn(move || {
+        let (tx, rx) = channel();
+        let mut watcher = match watcher(tx, DEBOUNCE_DELAY) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                warn!("Unable to watch the TLS certificate, {}", err);
+                return;
+            }
+        };
+        let files = [tls.key_file().to_path_buf(), tls.cert_file().to_path_buf()];
+        let mut directories = files
+            .iter()
+            .map(|file| match file.parent() {
+                Some(directory) if directory != Path::new("") => directory.to_path_buf(),
+                _ => PathBuf::from("."),
+            })
+            .collect::<Vec<_>>();
+        directories.dedup();
+        for directory in &directories {
+            if let Err(err) = watcher.watch(directory, RecursiveMode::NonRecursive) {
+                warn!("Unable to watch {:?}, {}", directory, err);
+                return;
+            }
+        }
+        let file_names = files
+            .iter()
+            .filter_map(|file| file.file_name())
+            .collect::<Vec<_>>();
+        for event in rx {
+            let changed = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                DebouncedEvent::Error(err, path) => {
+                    warn!("Error watching {:?}, {}", path, err);
+                    continue;
+                }
+                _ => continue,
+            };
+            if changed
+                .file_name()
+                .is_some_and(|name| file_names.contains(&name))
+            {
+                reload_tls(&tls);
+            }
+        }
+    });
+}
+
+#[cfg(feature = "tls")]
+pub fn reload_tls(tls: &Tls) {
+    match tls.reload() {
+        Ok(()) => info!("Reloaded the TLS certificate {:?}", tls.cert_file()),
+        Err(err) => warn!("Keeping the current TLS certificate, {}", err),
+    }
+}
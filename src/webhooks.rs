@@ -0,0 +1,299 @@
+use crate::alerts::Alert;
+use crate::chat::{Chat, Messages};
+use crate::error::Unpoisoned;
+use crate::{env_or_default, Series, SeriesMap};
+use actix::prelude::*;
+use actix_web::client::Client;
+use actix_web::{http::StatusCode, web};
+use bytes::Bytes;
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(5 * 60);
+/// Events kept per webhook while it is unreachable.
+const MAX_BACKLOG: usize = 1000;
+
+/// What is posted to the webhooks, as JSON with the kind of event in
+/// `event`, like `{"event": "alert_fired", "series": "greenhouse", ...}`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    AlertFired(Alert),
+    AlertResolved(Alert),
+    /// A value was added to a series that did not exist yet.
+    SeriesCreated {
+        series: String,
+        time: i64,
+    },
+}
+
+impl Message for Event {
+    type Result = ();
+}
+
+/// A request to a webhook or a chat.
+pub struct Post {
+    pub url: String,
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+impl Post {
+    pub fn json<T: Serialize>(url: String, value: &T) -> Post {
+        Post {
+            url,
+            content_type: "application/json".to_owned(),
+            body: Bytes::from(serde_json::to_vec(value).unwrap()),
+        }
+    }
+}
+
+/// Posts events to the urls of `STS_RS_WEBHOOK_URLS` and alerts to the
+/// chats, each in the order they happened. A webhook that fails is tried
+/// again after a second, waiting twice as long after every failure up to
+/// five minutes, and the event is dropped after `STS_RS_WEBHOOK_RETRIES`
+/// tries. Events a webhook refuses with a client error other than too many
+/// requests are not tried again.
+pub struct Webhooks {
+    client: Client,
+    hooks: Vec<Hook>,
+    max_tries: u32,
+    messages: Messages,
+    /// Set `STS_RS_CHAT_PLOTS` to `false` to post messages only.
+    chat_plots: bool,
+    series: SeriesMap,
+    timezone: Tz,
+}
+
+struct Hook {
+    /// The url of a webhook or the name of a chat, for the log.
+    name: String,
+    target: Target,
+    backlog: VecDeque<Post>,
+    in_flight: bool,
+    /// Failed tries of the first event of the backlog.
+    failures: u32,
+}
+
+enum Target {
+    /// Events are posted as JSON.
+    Url(String),
+    Chat(Chat),
+}
+
+impl Webhooks {
+    /// Webhooks are enabled by setting `STS_RS_WEBHOOK_URLS` or one of the
+    /// chats.
+    pub fn from_env(series: SeriesMap, timezone: Tz) -> Option<Webhooks> {
+        let urls = env_or_default("STS_RS_WEBHOOK_URLS", "")
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| (url.to_owned(), Target::Url(url.to_owned())))
+            .collect::<Vec<_>>();
+        let chats = Chat::from_env()
+            .into_iter()
+            .map(|chat| (chat.name().to_owned(), Target::Chat(chat)));
+        let hooks = urls
+            .into_iter()
+            .chain(chats)
+            .map(|(name, target)| Hook {
+                name,
+                target,
+                backlog: VecDeque::new(),
+                in_flight: false,
+                failures: 0,
+            })
+            .collect::<Vec<_>>();
+        if hooks.is_empty() {
+            return None;
+        }
+        let max_tries = env_or_default("STS_RS_WEBHOOK_RETRIES", "8")
+            .parse::<u32>()
+            .expect("STS_RS_WEBHOOK_RETRIES should be a number of tries");
+        let insecure = env_or_default("STS_RS_WEBHOOK_INSECURE", "false").to_lowercase() == "true";
+        Some(Webhooks {
+            client: crate::client(insecure),
+            hooks,
+            max_tries: max_tries.max(1),
+            messages: Messages::from_env(timezone),
+            chat_plots: env_or_default("STS_RS_CHAT_PLOTS", "true").to_lowercase() == "true",
+            series,
+            timezone,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let urls = self
+            .hooks
+            .iter()
+            .filter(|hook| matches!(hook.target, Target::Url(_)))
+            .count();
+        let chats = self
+            .hooks
+            .iter()
+            .filter_map(|hook| match &hook.target {
+                Target::Chat(chat) => Some(chat.name()),
+                Target::Url(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let mut posting = Vec::new();
+        if urls > 0 {
+            posting.push(format!("alerts and new series to {} webhooks", urls));
+        }
+        if !chats.is_empty() {
+            posting.push(format!("alerts to {}", chats.join(", ")));
+        }
+        format!("Posting {}", posting.join(" and "))
+    }
+
+    fn queue(&mut self, index: usize, post: Post, ctx: &mut Context<Self>) {
+        let hook = &mut self.hooks[index];
+        if hook.backlog.len() >= MAX_BACKLOG {
+            warn!(
+                "The backlog of {} is full, dropping its oldest event.",
+                hook.name
+            );
+            // The event being posted stays.
+            hook.backlog.remove(usize::from(hook.in_flight));
+        }
+        hook.backlog.push_back(post);
+        // While retrying the timer drives delivery.
+        if hook.failures == 0 {
+            self.send_next(index, ctx);
+        }
+    }
+
+    /// Posts a message to every chat, with the plot to those that take it.
+    fn post_to_chats(&mut self, text: &str, png: Option<&[u8]>, ctx: &mut Context<Self>) {
+        for index in 0..self.hooks.len() {
+            let post = match &self.hooks[index].target {
+                Target::Chat(chat) => chat.post(text, png.filter(|_| chat.takes_plots())),
+                Target::Url(_) => continue,
+            };
+            self.queue(index, post, ctx);
+        }
+    }
+
+    fn send_next(&mut self, index: usize, ctx: &mut Context<Self>) {
+        let hook = &mut self.hooks[index];
+        if hook.in_flight {
+            return;
+        }
+        let post = match hook.backlog.front() {
+            Some(post) => post,
+            None => return,
+        };
+        hook.in_flight = true;
+        let request = self
+            .client
+            .post(&post.url)
+            .content_type(post.content_type.as_str())
+            .send_body(post.body.clone());
+        ctx.spawn(
+            actix::fut::wrap_future::<_, Self>(request).map(move |result, act, ctx| {
+                let hook = &mut act.hooks[index];
+                hook.in_flight = false;
+                match result {
+                    Ok(response) if response.status().is_success() => act.delivered(index, ctx),
+                    Ok(response)
+                        if response.status().is_client_error()
+                            && response.status() != StatusCode::TOO_MANY_REQUESTS =>
+                    {
+                        warn!(
+                            "{} refused an event with status code {}, dropping it.",
+                            hook.name,
+                            response.status()
+                        );
+                        act.delivered(index, ctx)
+                    }
+                    Ok(response) => {
+                        act.retry_later(index, ctx, format!("status code {}", response.status()))
+                    }
+                    Err(err) => act.retry_later(index, ctx, err.to_string()),
+                }
+            }),
+        );
+    }
+
+    fn delivered(&mut self, index: usize, ctx: &mut Context<Self>) {
+        let hook = &mut self.hooks[index];
+        hook.backlog.pop_front();
+        hook.failures = 0;
+        self.send_next(index, ctx);
+    }
+
+    fn retry_later(&mut self, index: usize, ctx: &mut Context<Self>, reason: String) {
+        let hook = &mut self.hooks[index];
+        hook.failures += 1;
+        if hook.failures >= self.max_tries {
+            warn!(
+                "Posting to {} failed {} times, {}; dropping the event.",
+                hook.name, hook.failures, reason
+            );
+            return self.delivered(index, ctx);
+        }
+        let delay = FIRST_RETRY
+            .checked_mul(1 << (hook.failures - 1).min(16))
+            .map_or(MAX_RETRY, |delay| delay.min(MAX_RETRY));
+        warn!(
+            "Posting to {} failed, {}; retrying in {:?}.",
+            hook.name, reason, delay
+        );
+        ctx.run_later(delay, move |act, ctx| act.send_next(index, ctx));
+    }
+}
+
+impl Actor for Webhooks {
+    type Context = Context<Self>;
+}
+
+impl Handler<Event> for Webhooks {
+    type Result = ();
+    fn handle(&mut self, msg: Event, ctx: &mut Context<Self>) -> Self::Result {
+        for index in 0..self.hooks.len() {
+            let post = match &self.hooks[index].target {
+                Target::Url(url) => Post::json(url.clone(), &msg),
+                Target::Chat(_) => continue,
+            };
+            self.queue(index, post, ctx);
+        }
+        let alert = match &msg {
+            Event::AlertFired(alert) | Event::AlertResolved(alert) => alert,
+            Event::SeriesCreated { .. } => return,
+        };
+        let plots = self.chat_plots
+            && self.hooks.iter().any(|hook| match &hook.target {
+                Target::Chat(chat) => chat.takes_plots(),
+                Target::Url(_) => false,
+            });
+        let (text, snapshot) = {
+            let series = self.series.read().unpoisoned();
+            let serie = series.get(&alert.series).map(Series::lock);
+            let text = self.messages.text(alert, serie.as_deref());
+            // Plots of private series are not shown to the chats.
+            let snapshot = serie
+                .filter(|serie| plots && !serie.meta.private)
+                .and_then(|serie| alert.snapshot(&serie));
+            (text, snapshot)
+        };
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return self.post_to_chats(&text, None, ctx),
+        };
+        let (name, timezone) = (snapshot.name.clone(), self.timezone);
+        let rendered = web::block(move || snapshot.render_png(timezone));
+        // Later events wait for the plot so the chats get them in order.
+        ctx.wait(
+            actix::fut::wrap_future::<_, Self>(rendered).map(move |png, act, ctx| {
+                let png = png
+                    .map_err(|err| warn!("Plotting series {} for the chats failed, {}", name, err))
+                    .ok();
+                act.post_to_chats(&text, png.as_deref(), ctx);
+            }),
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// Renames or deletes a series from its page, after asking for confirmation.
+(function () {
+	'use strict';
+	const admin = document.getElementById('series-admin');
+	if (!admin) {
+		return;
+	}
+	const series = admin.dataset.series;
+	const status = document.getElementById('series-admin-status');
+	const base = document.body.dataset.base || '';
+	const api = base + '/api/v1/series/' + encodeURIComponent(series);
+
+	function submit(request, done) {
+		request.then(function (response) {
+			return response.text().then(function (message) {
+				if (response.ok) {
+					done();
+				} else {
+					status.textContent = message || response.statusText;
+				}
+			});
+		}).catch(function (err) {
+			status.textContent = 'The request failed, ' + err.message;
+		});
+	}
+
+	admin.querySelector('[name=rename]').addEventListener('click', function () {
+		const name = window.prompt('Rename series ' + series + ' to', series);
+		if (!name || name === series) {
+			return;
+		}
+		if (!window.confirm('Rename series ' + series + ' to ' + name + '? Clients still sending values to ' + series + ' will create a new series.')) {
+			return;
+		}
+		submit(window.apiFetch(api + '/rename', {
+			method: 'POST',
+			headers: {'Content-Type': 'application/json'},
+			body: JSON.stringify({name: name})
+		}), function () {
+			window.location.href = base + '/series/' + encodeURIComponent(name);
+		});
+	});
+
+	admin.querySelector('[name=delete]').addEventListener('click', function () {
+		if (!window.confirm('Delete series ' + series + '? It is kept in the trash for a while and can be restored from there.')) {
+			return;
+		}
+		submit(window.apiFetch(api, {method: 'DELETE'}), function () {
+			window.location.href = base + '/';
+		});
+	});
+}());
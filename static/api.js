@@ -0,0 +1,34 @@
+// Sends changes with the API key kept in this browser. When the server asks
+// for a key, the user is asked for it once and the change is sent again.
+// Changes made with the sign in cookie carry the token of the page.
+(function () {
+	'use strict';
+	const storageKey = 'sts-rs-api-key';
+	const csrfToken = document.querySelector('meta[name="csrf-token"]');
+
+	function send(url, options) {
+		const headers = Object.assign({}, options.headers);
+		if (csrfToken) {
+			headers['X-Csrf-Token'] = csrfToken.content;
+		}
+		const apiKey = window.localStorage.getItem(storageKey);
+		if (apiKey) {
+			headers['X-Api-Key'] = apiKey;
+		}
+		return fetch(url, Object.assign({}, options, {headers: headers}));
+	}
+
+	window.apiFetch = function (url, options) {
+		return send(url, options).then(function (response) {
+			if (response.status !== 401) {
+				return response;
+			}
+			const apiKey = window.prompt('This change needs an API key');
+			if (!apiKey) {
+				return response;
+			}
+			window.localStorage.setItem(storageKey, apiKey);
+			return send(url, options);
+		});
+	};
+}());
@@ -0,0 +1,84 @@
+// Creates, changes and deletes dashboards through the dashboards API.
+(function () {
+	'use strict';
+	const form = document.getElementById('dashboard-form');
+	const status = document.getElementById('dashboard-status');
+
+	function dashboardUrl(slug) {
+		return '/api/v1/dashboards/' + encodeURIComponent(slug);
+	}
+
+	function fail(response) {
+		return response.text().then(function (message) { status.textContent = message; });
+	}
+
+	function edit(slug) {
+		fetch(dashboardUrl(slug)).then(function (response) {
+			if (!response.ok) {
+				return fail(response);
+			}
+			return response.json().then(function (dashboard) {
+				form.elements.slug.value = slug;
+				form.elements.title.value = dashboard.title;
+				Array.prototype.forEach.call(form.elements.series.options, function (option) {
+					option.selected = dashboard.series.indexOf(option.value) >= 0;
+				});
+				form.elements.columns.value = dashboard.columns === null ? '' : dashboard.columns;
+				form.elements.window.value = dashboard.window || '';
+				form.elements.refresh.value = dashboard.refresh === null ? '' : dashboard.refresh;
+				form.scrollIntoView();
+			});
+		});
+	}
+
+	Array.prototype.forEach.call(document.getElementsByClassName('dashboard-edit'), function (button) {
+		button.addEventListener('click', function () { edit(button.dataset.slug); });
+	});
+
+	Array.prototype.forEach.call(document.getElementsByClassName('dashboard-delete'), function (button) {
+		button.addEventListener('click', function () {
+			if (!window.confirm('Delete dashboard ' + button.dataset.slug + '?')) {
+				return;
+			}
+			window.apiFetch(dashboardUrl(button.dataset.slug), {method: 'DELETE'}).then(function (response) {
+				if (response.ok) {
+					window.location.reload();
+				} else {
+					return fail(response);
+				}
+			});
+		});
+	});
+
+	form.addEventListener('submit', function (event) {
+		event.preventDefault();
+		const number = function (key) {
+			return form.elements[key].value === '' ? null : Number(form.elements[key].value);
+		};
+		const slug = form.elements.slug.value;
+		const dashboard = {
+			title: form.elements.title.value,
+			series: Array.prototype.filter.call(form.elements.series.options, function (option) {
+				return option.selected;
+			}).map(function (option) { return option.value; }),
+			columns: number('columns'),
+			window: form.elements.window.value || null,
+			refresh: number('refresh')
+		};
+		window.apiFetch(dashboardUrl(slug), {
+			method: 'PUT',
+			headers: {'Content-Type': 'application/json'},
+			body: JSON.stringify(dashboard)
+		}).then(function (response) {
+			if (response.ok) {
+				window.location.href = '/dashboards/' + slug;
+			} else {
+				return fail(response);
+			}
+		});
+	});
+
+	if (window.location.hash) {
+		edit(window.location.hash.slice(1));
+	}
+}());
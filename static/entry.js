@@ -0,0 +1,36 @@
+// Adds a value to a series from the form on its page, through the same
+// endpoint as other clients.
+(function () {
+	'use strict';
+	const form = document.getElementById('series-entry');
+	if (!form) {
+		return;
+	}
+	const status = document.getElementById('series-entry-status');
+
+	form.addEventListener('submit', function (event) {
+		event.preventDefault();
+		// A datetime-local value is read as a time of this device.
+		const time = form.elements.time.value ? new Date(form.elements.time.value) : new Date();
+		const datum = {
+			timeStamp: Math.floor(time.getTime() / 1000),
+			value: Number(form.elements.value.value)
+		};
+		const base = document.body.dataset.base || '';
+		window.apiFetch(base + '/' + encodeURIComponent(form.dataset.series), {
+			method: 'POST',
+			headers: {'Content-Type': 'application/json'},
+			body: JSON.stringify(datum)
+		}).then(function (response) {
+			return response.text().then(function (message) {
+				status.textContent = message;
+				if (response.ok) {
+					form.elements.value.value = '';
+					form.elements.time.value = '';
+				}
+			});
+		}).catch(function (err) {
+			status.textContent = 'Adding the value failed, ' + err.message;
+		});
+	});
+}());
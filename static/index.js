@@ -0,0 +1,25 @@
+// Shows the plot of the series under the pointer next to the list.
+(function () {
+	'use strict';
+	const images = document.getElementById('images-list');
+	if (!images) {
+		return;
+	}
+
+	function showImage(serieName) {
+		const graphs = document.getElementsByClassName('graph');
+		for (let i = 0; i < graphs.length; i++) {
+			const shown = graphs[i].id === serieName;
+			graphs[i].style.display = shown ? 'block' : 'none';
+			graphs[i].style.background = shown
+				? "url('plot/" + encodeURIComponent(serieName) + '.svg?theme=' + images.dataset.theme + "')"
+				: 'none';
+		}
+	}
+
+	document.querySelectorAll('h3[data-series]').forEach(function (heading) {
+		heading.addEventListener('mouseover', function () {
+			showImage(heading.dataset.series);
+		});
+	});
+}());
@@ -0,0 +1,80 @@
+// Updates the latest values, plots and tables of a page as new values come
+// in on the live stream, for the elements within a `data-live` container.
+(function () {
+	'use strict';
+	// Plots are redrawn at most this often, in milliseconds.
+	const REDRAW_INTERVAL = 5000;
+	const page = document.querySelector('[data-live]');
+	if (!page || !window.EventSource) {
+		return;
+	}
+	const followed = page.querySelectorAll('[data-series]');
+	const names = Array.prototype.map.call(followed, function (element) {
+		return element.dataset.series;
+	}).filter(function (name, index, all) { return all.indexOf(name) === index; });
+	if (!names.length) {
+		return;
+	}
+
+	const timeFormat = new Intl.DateTimeFormat('sv-SE', {timeZone: page.dataset.tz || 'UTC',
+		year: 'numeric', month: '2-digit', day: '2-digit',
+		hour: '2-digit', minute: '2-digit', second: '2-digit'});
+
+	// Written like the pages written by the server, `2020/09/13 12:26:40`.
+	function formatTime(timeStamp) {
+		return timeFormat.format(new Date(timeStamp * 1000)).replace(/-/g, '/');
+	}
+
+	function redraw(image) {
+		if (image.dataset.pending) {
+			return;
+		}
+		image.dataset.pending = 'true';
+		const last = Number(image.dataset.drawn || 0);
+		setTimeout(function () {
+			const url = new URL(image.src, window.location.href);
+			url.searchParams.set('drawn', Date.now());
+			image.src = url.toString();
+			image.dataset.drawn = Date.now();
+			delete image.dataset.pending;
+		}, Math.max(0, last + REDRAW_INTERVAL - Date.now()));
+	}
+
+	function update(element, datum) {
+		const text = function (selector, value) {
+			Array.prototype.forEach.call(element.querySelectorAll(selector), function (target) {
+				target.textContent = value;
+			});
+		};
+		text('.live-value', String(datum.value));
+		text('.live-time', formatTime(datum.timeStamp));
+		Array.prototype.forEach.call(element.querySelectorAll('img.live-plot'), redraw);
+		const recent = element.querySelector('.live-recent');
+		if (recent) {
+			const row = recent.insertRow(0);
+			row.insertCell().textContent = formatTime(datum.timeStamp);
+			row.insertCell().textContent = String(datum.value);
+			while (recent.rows.length > Number(element.dataset.recent || 20)) {
+				recent.deleteRow(-1);
+			}
+		}
+	}
+
+	const base = document.body.dataset.base || '';
+	const source = new EventSource(base + '/api/v1/stream?series=' + encodeURIComponent(names.join(',')));
+	source.addEventListener('value', function (event) {
+		const datum = JSON.parse(event.data);
+		Array.prototype.forEach.call(followed, function (element) {
+			if (element.dataset.series === datum.series) {
+				update(element, datum);
+			}
+		});
+	});
+	// The browser reconnects by itself, unless the server refused the stream.
+	source.addEventListener('error', function () {
+		const refresh = Number(page.dataset.refresh || 0);
+		if (source.readyState === EventSource.CLOSED && refresh > 0) {
+			setTimeout(function () { window.location.reload(); }, refresh * 1000);
+		}
+	});
+}());
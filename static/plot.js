@@ -0,0 +1,502 @@
+// Interactive chart of one or more series, fed by the JSON data API.
+(function () {
+	'use strict';
+	const SVG = 'http://www.w3.org/2000/svg';
+	const COLORS = ['#e6194b', '#3cb44b', '#4363d8', '#f58231', '#911eb4',
+		'#46f0f0', '#f032e6', '#bcf60c', '#008080', '#9a6324'];
+	const THRESHOLD_COLORS = ['#ff7f0e', '#d62728', '#9467bd'];
+	const plot = document.getElementById('plot');
+	// The routes of the namespace of the series start with the base.
+	const base = document.body.dataset.base || '';
+	const tooltip = document.getElementById('plot-tooltip');
+	const names = plot.dataset.series.split(',');
+	const secondary = plot.dataset.secondary ? plot.dataset.secondary.split(',') : [];
+	const width = plot.clientWidth;
+	const height = plot.clientHeight;
+	const margin = {top: 10, right: secondary.length ? 60 : 20, bottom: 40, left: 60};
+	const inner = {width: width - margin.left - margin.right, height: height - margin.top - margin.bottom};
+	let series = [];
+	let full = {from: 0, to: 1};
+	let view = {from: 0, to: 1};
+
+	function element(name, attributes, parent) {
+		const node = document.createElementNS(SVG, name);
+		Object.keys(attributes).forEach(function (key) { node.setAttribute(key, attributes[key]); });
+		if (parent) {
+			parent.appendChild(node);
+		}
+		return node;
+	}
+
+	// The Swedish locale writes times like `2020-09-13 12:26:40`.
+	const timeFormat = new Intl.DateTimeFormat('sv-SE', {timeZone: plot.dataset.tz || 'UTC',
+		year: 'numeric', month: '2-digit', day: '2-digit',
+		hour: '2-digit', minute: '2-digit', second: '2-digit'});
+
+	function formatTime(timeStamp) {
+		return timeFormat.format(new Date(timeStamp * 1000));
+	}
+
+	function formatValue(value) {
+		return Math.abs(value) >= 1e5 || (value !== 0 && Math.abs(value) < 1e-3) ?
+			value.toExponential(2) : String(Math.round(value * 1000) / 1000);
+	}
+
+	// About `count` round numbers between `min` and `max`.
+	function ticks(min, max, count) {
+		const raw = (max - min) / count;
+		const magnitude = Math.pow(10, Math.floor(Math.log10(raw)));
+		const step = [1, 2, 5, 10].map(function (m) { return m * magnitude; })
+			.find(function (s) { return s >= raw; });
+		const result = [];
+		for (let tick = Math.ceil(min / step) * step; tick <= max; tick += step) {
+			result.push(tick);
+		}
+		return result;
+	}
+
+	// Index of the first datum at or after `timeStamp`.
+	function search(data, timeStamp) {
+		let low = 0;
+		let high = data.length;
+		while (low < high) {
+			const middle = (low + high) >> 1;
+			if (data[middle].timeStamp < timeStamp) {
+				low = middle + 1;
+			} else {
+				high = middle;
+			}
+		}
+		return low;
+	}
+
+	// Smooths the values like the plot settings ask, `y` is the position on
+	// the axis, the logarithm of the value on a logarithmic scale.
+	function prepare(data, settings) {
+		const count = Math.max(settings.smoothing || 1, 1);
+		const result = [];
+		let sum = 0;
+		data.forEach(function (datum, index) {
+			sum += datum.value;
+			if (index >= count) {
+				sum -= data[index - count].value;
+			}
+			const value = sum / Math.min(index + 1, count);
+			if (!settings.log_scale) {
+				result.push({timeStamp: datum.timeStamp, value: value, y: value});
+			} else if (value > 0) {
+				result.push({timeStamp: datum.timeStamp, value: value, y: Math.log10(value)});
+			}
+		});
+		return result;
+	}
+
+	// Thresholds at their position on the axis, dropping those a
+	// logarithmic scale cannot show.
+	function levels(thresholds, settings) {
+		return thresholds.map(function (threshold, index) {
+			return Object.assign({}, threshold, {
+				y: settings.log_scale ? Math.log10(threshold.value) : threshold.value,
+				color: threshold.color || THRESHOLD_COLORS[index % THRESHOLD_COLORS.length]
+			});
+		}).filter(function (level) { return isFinite(level.y); });
+	}
+
+	// The settings of the first series on an axis apply to the axis.
+	function axisSettings(onSecondary) {
+		const first = series.find(function (s) { return s.secondary === onSecondary; });
+		return first ? first.settings : {};
+	}
+
+	function valueRange(onSecondary) {
+		const settings = axisSettings(onSecondary);
+		const scale = function (value) { return settings.log_scale ? Math.log10(value) : value; };
+		let min = Infinity;
+		let max = -Infinity;
+		series.filter(function (s) { return s.secondary === onSecondary; }).forEach(function (s) {
+			const start = Math.max(search(s.data, view.from) - 1, 0);
+			const end = Math.min(search(s.data, view.to) + 1, s.data.length);
+			for (let i = start; i < end; i++) {
+				min = Math.min(min, s.data[i].y);
+				max = Math.max(max, s.data[i].y);
+			}
+			s.levels.forEach(function (level) {
+				min = Math.min(min, level.y);
+				max = Math.max(max, level.y);
+			});
+		});
+		if (min > max) {
+			min = -1;
+			max = 1;
+		} else {
+			const offset = max > min ? (max - min) * 0.01 : 1;
+			min -= offset;
+			max += offset;
+		}
+		min = settings.y_min === null || settings.y_min === undefined ? min : scale(settings.y_min);
+		max = settings.y_max === null || settings.y_max === undefined ? max : scale(settings.y_max);
+		return {min: min, max: min < max ? max : min + 1, log: Boolean(settings.log_scale)};
+	}
+
+	function axisLabel(tick, range) {
+		return range.log ? formatValue(Number(Math.pow(10, tick).toPrecision(3))) : formatValue(tick);
+	}
+
+	function x(timeStamp) {
+		return margin.left + (timeStamp - view.from) / (view.to - view.from) * inner.width;
+	}
+
+	function timeAt(offsetX) {
+		return view.from + (offsetX - margin.left) / inner.width * (view.to - view.from);
+	}
+
+	const svg = element('svg', {width: width, height: height}, plot);
+	element('defs', {}, svg).innerHTML =
+		'<clipPath id="plot-area"><rect x="' + margin.left + '" y="' + margin.top +
+		'" width="' + inner.width + '" height="' + inner.height + '"/></clipPath>';
+	const layer = element('g', {}, svg);
+	const guide = element('line', {y1: margin.top, y2: margin.top + inner.height,
+		'class': 'guide', visibility: 'hidden'}, svg);
+
+	// The kind of the first series decides whether the plot is a histogram,
+	// which has no time axis to zoom, pan or hover.
+	function histogram() {
+		return series.length > 0 && series[0].settings.kind === 'histogram';
+	}
+
+	// Translucent bars of the distribution of the values of every series,
+	// with a number of bins following Sturges' rule.
+	function drawHistogram() {
+		const values = series.map(function (s) { return s.data.map(function (d) { return d.y; }); });
+		const all = [].concat.apply([], values);
+		if (!all.length) {
+			return;
+		}
+		const min = Math.min.apply(null, all);
+		const max = Math.max.apply(null, all);
+		const most = Math.max.apply(null, values.map(function (v) { return v.length; }));
+		const bins = Math.min(Math.max(Math.ceil(Math.log2(most)) + 1, 1), 100);
+		const binWidth = max > min ? (max - min) / bins : 1;
+		const counts = values.map(function (v) {
+			const count = new Array(bins).fill(0);
+			v.forEach(function (value) { count[Math.min(Math.floor((value - min) / binWidth), bins - 1)]++; });
+			return count;
+		});
+		const highest = Math.max.apply(null, [].concat.apply([], counts)) + 1;
+		const bx = function (value) { return margin.left + (value - min) / (binWidth * bins) * inner.width; };
+		const by = function (count) { return margin.top + (1 - count / highest) * inner.height; };
+		const range = {log: Boolean(series[0].settings.log_scale)};
+		ticks(min, min + binWidth * bins, 5).forEach(function (tick) {
+			element('line', {x1: bx(tick), x2: bx(tick), y1: margin.top, y2: margin.top + inner.height,
+				'class': 'grid'}, layer);
+			element('text', {x: bx(tick), y: height - margin.bottom + 15, 'text-anchor': 'middle',
+				'font-size': 11}, layer).textContent = axisLabel(tick, range);
+		});
+		ticks(0, highest, 6).forEach(function (tick) {
+			element('line', {x1: margin.left, x2: margin.left + inner.width, y1: by(tick), y2: by(tick),
+				'class': 'grid'}, layer);
+			element('text', {x: margin.left - 5, y: by(tick) + 4, 'text-anchor': 'end',
+				'font-size': 11}, layer).textContent = formatValue(tick);
+		});
+		element('rect', {x: margin.left, y: margin.top, width: inner.width, height: inner.height,
+			'class': 'frame'}, layer);
+		counts.forEach(function (count, index) {
+			count.forEach(function (c, bin) {
+				element('rect', {x: bx(min + bin * binWidth), y: by(c), width: inner.width / bins,
+					height: by(0) - by(c), fill: series[index].color,
+					'fill-opacity': series.length > 1 ? 0.5 : 0.8}, layer);
+			});
+		});
+		if (series.length > 1) {
+			series.forEach(function (s, index) {
+				const top = margin.top + 10 + index * 15;
+				element('rect', {x: margin.left + 10, y: top - 4, width: 20, height: 8, fill: s.color}, layer);
+				element('text', {x: margin.left + 35, y: top + 4, 'font-size': 11}, layer)
+					.textContent = s.name;
+			});
+		}
+	}
+
+	function draw() {
+		layer.innerHTML = '';
+		if (histogram()) {
+			drawHistogram();
+			return;
+		}
+		const ranges = {primary: valueRange(false), secondary: valueRange(true)};
+		const y = function (value, onSecondary) {
+			const range = onSecondary ? ranges.secondary : ranges.primary;
+			return margin.top + (range.max - value) / (range.max - range.min) * inner.height;
+		};
+		ticks(view.from, view.to, 5).forEach(function (tick) {
+			element('line', {x1: x(tick), x2: x(tick), y1: margin.top, y2: margin.top + inner.height,
+				'class': 'grid'}, layer);
+			element('text', {x: x(tick), y: height - margin.bottom + 15, 'text-anchor': 'middle',
+				'font-size': 11}, layer).textContent = formatTime(tick);
+		});
+		ticks(ranges.primary.min, ranges.primary.max, 6).forEach(function (tick) {
+			element('line', {x1: margin.left, x2: margin.left + inner.width, y1: y(tick, false),
+				y2: y(tick, false), 'class': 'grid'}, layer);
+			element('text', {x: margin.left - 5, y: y(tick, false) + 4, 'text-anchor': 'end',
+				'font-size': 11}, layer).textContent = axisLabel(tick, ranges.primary);
+		});
+		if (secondary.length) {
+			ticks(ranges.secondary.min, ranges.secondary.max, 6).forEach(function (tick) {
+				element('text', {x: margin.left + inner.width + 5, y: y(tick, true) + 4,
+					'font-size': 11}, layer).textContent = axisLabel(tick, ranges.secondary);
+			});
+		}
+		element('rect', {x: margin.left, y: margin.top, width: inner.width, height: inner.height,
+			'class': 'frame'}, layer);
+		series.forEach(function (s) {
+			s.annotations.filter(function (annotation) {
+				return annotation.timeStamp >= view.from && annotation.timeStamp <= view.to;
+			}).forEach(function (annotation) {
+				const ax = x(annotation.timeStamp);
+				element('line', {x1: ax, x2: ax, y1: margin.top, y2: margin.top + inner.height,
+					'class': 'annotation'}, layer);
+				element('text', {x: ax + 3, y: margin.top + 12, 'font-size': 10, 'class': 'annotation'},
+					layer).textContent = annotation.label;
+			});
+		});
+		const legend = [];
+		series.forEach(function (s) {
+			const range = s.secondary ? ranges.secondary : ranges.primary;
+			s.levels.forEach(function (level) {
+				const top = y(level.y, s.secondary);
+				if (level.shade) {
+					const edge = y(level.below ? range.min : range.max, s.secondary);
+					element('rect', {x: margin.left, y: Math.min(top, edge), width: inner.width,
+						height: Math.abs(edge - top), fill: level.color, 'fill-opacity': 0.1,
+						'clip-path': 'url(#plot-area)'}, layer);
+				}
+				element('line', {x1: margin.left, x2: margin.left + inner.width, y1: top, y2: top,
+					stroke: level.color, 'stroke-dasharray': '6 4', 'clip-path': 'url(#plot-area)'}, layer);
+				legend.push({color: level.color, text: level.name + ' ' + level.value, dashed: true});
+			});
+		});
+		series.forEach(function (s) {
+			const start = Math.max(search(s.data, view.from) - 1, 0);
+			const end = Math.min(search(s.data, view.to) + 1, s.data.length);
+			const scatter = s.settings.kind === 'scatter';
+			const points = [];
+			for (let i = start; i < end; i++) {
+				const px = x(s.data[i].timeStamp);
+				const py = y(s.data[i].y, s.secondary);
+				points.push(px.toFixed(1) + ',' + py.toFixed(1));
+				if (scatter || (s.settings.points && s.settings.points !== 'none')) {
+					element('circle', {cx: px, cy: py, r: 3, fill: s.color,
+						'clip-path': 'url(#plot-area)'}, layer);
+				}
+			}
+			if (!scatter) {
+				element('polyline', {points: points.join(' '), fill: 'none', stroke: s.color,
+					'clip-path': 'url(#plot-area)'}, layer);
+			}
+		});
+		if (series.length > 1 || legend.length) {
+			series.map(function (s) {
+				return {color: s.color, text: s.name + (s.secondary ? ' (right)' : '')};
+			}).concat(legend).forEach(function (entry, index) {
+				const top = margin.top + 10 + index * 15;
+				element('line', {x1: margin.left + 10, x2: margin.left + 30, y1: top, y2: top,
+					stroke: entry.color, 'stroke-width': 2,
+					'stroke-dasharray': entry.dashed ? '6 4' : 'none'}, layer);
+				element('text', {x: margin.left + 35, y: top + 4, 'font-size': 11}, layer)
+					.textContent = entry.text;
+			});
+		}
+	}
+
+	function showTooltip(event) {
+		if (histogram()) {
+			return;
+		}
+		const rect = svg.getBoundingClientRect();
+		const offsetX = event.clientX - rect.left;
+		if (offsetX < margin.left || offsetX > margin.left + inner.width) {
+			hideTooltip();
+			return;
+		}
+		const timeStamp = timeAt(offsetX);
+		const lines = [];
+		let nearest = null;
+		series.forEach(function (s) {
+			const index = search(s.data, timeStamp);
+			const candidates = [s.data[index - 1], s.data[index]].filter(Boolean);
+			if (!candidates.length) {
+				return;
+			}
+			const datum = candidates.reduce(function (best, d) {
+				return Math.abs(d.timeStamp - timeStamp) < Math.abs(best.timeStamp - timeStamp) ? d : best;
+			});
+			if (nearest === null || Math.abs(datum.timeStamp - timeStamp) < Math.abs(nearest - timeStamp)) {
+				nearest = datum.timeStamp;
+			}
+			lines.push('<span style="color: ' + s.color + '">' + s.name + '</span>: ' + formatValue(datum.value));
+		});
+		if (nearest === null) {
+			hideTooltip();
+			return;
+		}
+		guide.setAttribute('x1', x(nearest));
+		guide.setAttribute('x2', x(nearest));
+		guide.setAttribute('visibility', 'visible');
+		tooltip.innerHTML = formatTime(nearest) + '<br>' + lines.join('<br>');
+		tooltip.style.left = (offsetX + 15) + 'px';
+		tooltip.style.top = (event.clientY - rect.top + 15) + 'px';
+		tooltip.style.display = 'block';
+	}
+
+	function hideTooltip() {
+		guide.setAttribute('visibility', 'hidden');
+		tooltip.style.display = 'none';
+	}
+
+	svg.addEventListener('wheel', function (event) {
+		if (histogram()) {
+			return;
+		}
+		event.preventDefault();
+		const rect = svg.getBoundingClientRect();
+		const center = timeAt(event.clientX - rect.left);
+		const factor = event.deltaY < 0 ? 0.8 : 1.25;
+		const span = Math.max((view.to - view.from) * factor, 1);
+		const ratio = (center - view.from) / (view.to - view.from);
+		view.from = Math.max(center - span * ratio, full.from);
+		view.to = Math.min(view.from + span, full.to);
+		draw();
+		showTooltip(event);
+	});
+
+	let dragStart = null;
+	svg.addEventListener('mousedown', function (event) {
+		if (histogram()) {
+			return;
+		}
+		dragStart = {clientX: event.clientX, from: view.from, to: view.to};
+	});
+	window.addEventListener('mouseup', function () { dragStart = null; });
+	svg.addEventListener('mousemove', function (event) {
+		if (dragStart !== null) {
+			const span = dragStart.to - dragStart.from;
+			let shift = (dragStart.clientX - event.clientX) / inner.width * span;
+			shift = Math.min(Math.max(shift, full.from - dragStart.from), full.to - dragStart.to);
+			view.from = dragStart.from + shift;
+			view.to = dragStart.to + shift;
+			draw();
+		}
+		showTooltip(event);
+	});
+	svg.addEventListener('mouseleave', hideTooltip);
+	svg.addEventListener('dblclick', function () {
+		view = {from: full.from, to: full.to};
+		draw();
+	});
+
+	const query = ['from', 'to'].filter(function (key) { return plot.dataset[key]; })
+		.map(function (key) { return key + '=' + plot.dataset[key]; }).join('&');
+	function load(url) {
+		return fetch(url).then(function (response) {
+			if (!response.ok) {
+				throw new Error(url + ' responded with ' + response.status);
+			}
+			return response.json();
+		});
+	}
+
+	function seriesUrl(name, resource) {
+		return base + '/api/v1/series/' + encodeURIComponent(name) + '/' + resource;
+	}
+
+	Promise.all(names.map(function (name) {
+		return Promise.all([
+			load(seriesUrl(name, 'data') + (query ? '?' + query : '')),
+			load(seriesUrl(name, 'plot-settings')),
+			load(seriesUrl(name, 'thresholds')),
+			load(seriesUrl(name, 'annotations'))
+		]);
+	})).then(function (loaded) {
+		const data = loaded.map(function (pair) { return pair[0]; });
+		series = names.map(function (name, index) {
+			const settings = Object.assign({}, loaded[index][1]);
+			if (plot.dataset.log) {
+				settings.log_scale = plot.dataset.log === 'true';
+			}
+			if (plot.dataset.kind) {
+				settings.kind = plot.dataset.kind;
+			}
+			return {name: name, data: prepare(data[index], settings), settings: settings,
+				levels: levels(loaded[index][2], settings),
+				annotations: loaded[index][3],
+				color: settings.color || COLORS[index % COLORS.length],
+				secondary: secondary.indexOf(name) >= 0};
+		});
+		showSettings(loaded[0][1]);
+		const timeStamps = [].concat.apply([], data.map(function (d) {
+			return d.length ? [d[0].timeStamp, d[d.length - 1].timeStamp] : [];
+		}));
+		full.from = plot.dataset.from ? Number(plot.dataset.from) : Math.min.apply(null, timeStamps);
+		full.to = plot.dataset.to ? Number(plot.dataset.to) : Math.max.apply(null, timeStamps);
+		if (!isFinite(full.from) || !isFinite(full.to)) {
+			full = {from: 0, to: 1};
+		}
+		if (full.to <= full.from) {
+			full.to = full.from + 1;
+		}
+		view = {from: full.from, to: full.to};
+		draw();
+	}).catch(function (err) {
+		plot.textContent = 'Loading the data failed, ' + err.message;
+	});
+
+	// The settings form is only there for plots of a single series.
+	const form = document.getElementById('plot-settings');
+
+	function showSettings(settings) {
+		if (!form) {
+			return;
+		}
+		Object.keys(settings).forEach(function (key) {
+			const input = form.elements[key];
+			if (input && input.type === 'checkbox') {
+				input.checked = settings[key];
+			} else if (input) {
+				input.value = settings[key] === null ? '' : settings[key];
+			}
+		});
+	}
+
+	if (form) {
+		form.addEventListener('submit', function (event) {
+			event.preventDefault();
+			const number = function (key) {
+				return form.elements[key].value === '' ? null : Number(form.elements[key].value);
+			};
+			const settings = {
+				kind: form.elements.kind.value,
+				color: form.elements.color.value || null,
+				points: form.elements.points.value,
+				y_min: number('y_min'),
+				y_max: number('y_max'),
+				log_scale: form.elements.log_scale.checked,
+				smoothing: number('smoothing'),
+				width: number('width'),
+				height: number('height'),
+				theme: form.elements.theme.value || null
+			};
+			const status = document.getElementById('plot-settings-status');
+			window.apiFetch(seriesUrl(names[0], 'plot-settings'), {
+				method: 'PUT',
+				headers: {'Content-Type': 'application/json'},
+				body: JSON.stringify(settings)
+			}).then(function (response) {
+				if (response.ok) {
+					window.location.reload();
+				} else {
+					return response.text().then(function (message) { status.textContent = message; });
+				}
+			});
+		});
+	}
+}());
@@ -0,0 +1,16 @@
+// Switches the pages between the automatic, dark and light theme. The
+// choice is kept in a cookie, so the server renders the plots to match.
+(function () {
+	'use strict';
+	const toggle = document.getElementById('theme-toggle');
+	if (!toggle) {
+		return;
+	}
+	const themes = ['auto', 'dark', 'light'];
+
+	toggle.addEventListener('click', function () {
+		const next = themes[(themes.indexOf(toggle.dataset.theme) + 1) % themes.length];
+		document.cookie = 'theme=' + next + '; path=/; max-age=31536000; SameSite=Lax';
+		window.location.reload();
+	});
+}());